@@ -0,0 +1,624 @@
+//! Tamper-evident audit log of tool calls
+//!
+//! Every tool invocation is appended to a JSON Lines file. Each entry holds
+//! the hash of the previous one (`prev_hash`) and its own `hash`, computed
+//! over the entry's other fields, so editing, removing or reordering lines
+//! breaks the chain. [`verify`] walks the chain; `moxie audit verify` runs
+//! it from the command line.
+//!
+//! Hashes are computed over the compact JSON of the entry without `hash`,
+//! with object keys sorted, so they don't depend on field order in the file.
+//! With a secret (`security.audit_secret_env`) they are HMAC-SHA256, so the
+//! chain can't be recomputed after an edit without it; otherwise plain
+//! SHA-256. The last entry's position and hash are also kept, with a MAC,
+//! in a `.head` file next to the log, so dropping entries from the end is
+//! caught too.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Replaces the values of secret-looking arguments
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Argument names are redacted if they end with one of these (lowercase,
+/// `-` read as `_`), so `access_token` is but `max_tokens` is not
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "apikey",
+    "authorization",
+    "credential",
+    "credentials",
+    "private_key",
+    "connection_string",
+];
+
+/// Errors from the audit log
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("Audit log I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Line {line}: not a valid audit entry: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+
+    #[error("Line {line}: {reason}")]
+    Tampered { line: usize, reason: String },
+
+    /// The `.head` file is missing, modified or ahead of the log
+    #[error("Head file: {0}")]
+    Head(String),
+}
+
+/// What a tool call did, as recorded by the chat engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolAudit {
+    /// Caller identity (`key:<id>` or `ip:<address>`), if known
+    pub caller: Option<String>,
+    pub conversation_id: String,
+    pub persona: Option<String>,
+    pub tool: String,
+    /// Plugin providing the tool, if any
+    pub plugin: Option<String>,
+    /// Arguments, with secrets replaced by `[REDACTED]`
+    pub arguments: Value,
+    pub status: AuditStatus,
+    /// Error message for failed calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// `None` when the tool did not need confirmation
+    pub confirmation: Option<Confirmation>,
+}
+
+/// Outcome of a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditStatus {
+    /// The tool ran and reported success
+    Success,
+    /// The tool ran and reported a failure
+    Failure,
+    /// The tool could not run (unknown tool, not allowed, plugin error)
+    Error,
+    /// The user declined the call
+    Declined,
+    /// The reply was cancelled while the tool ran
+    Cancelled,
+}
+
+/// The user's answer to a confirmation request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confirmation {
+    Approved,
+    Declined,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, from 1
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub call: ToolAudit,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Filters for [`AuditLog::query`]
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub caller: Option<String>,
+    pub conversation_id: Option<String>,
+    pub tool: Option<String>,
+    pub status: Option<AuditStatus>,
+    /// Only entries at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum entries returned, newest first (default 100, at most 1000)
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        let call = &entry.call;
+        self.caller
+            .as_ref()
+            .is_none_or(|c| call.caller.as_ref() == Some(c))
+            && self
+                .conversation_id
+                .as_ref()
+                .is_none_or(|c| *c == call.conversation_id)
+            && self.tool.as_ref().is_none_or(|t| *t == call.tool)
+            && self.status.is_none_or(|s| s == call.status)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+/// End of the chain: the last entry written
+struct Tail {
+    seq: u64,
+    hash: String,
+}
+
+/// Contents of the `.head` file: the tail and a MAC over it
+#[derive(Serialize, Deserialize)]
+struct Head {
+    seq: u64,
+    hash: String,
+    mac: String,
+}
+
+/// Append-only, hash-chained audit log file
+pub struct AuditLog {
+    path: PathBuf,
+    secret: Option<Vec<u8>>,
+    tail: Mutex<Tail>,
+}
+
+impl AuditLog {
+    /// Open (or create) the log, continuing the chain of existing entries
+    ///
+    /// Entries are keyed with `secret` if given. Only the last entry is
+    /// read; use [`verify`] to check the whole file.
+    pub fn open(path: impl Into<PathBuf>, secret: Option<Vec<u8>>) -> Result<Self, AuditError> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tail = match std::fs::read_to_string(&path) {
+            Ok(content) => match content
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+                .last()
+            {
+                Some((i, line)) => {
+                    let entry: AuditEntry =
+                        serde_json::from_str(line).map_err(|source| AuditError::Parse {
+                            line: i + 1,
+                            source,
+                        })?;
+                    Tail {
+                        seq: entry.seq,
+                        hash: entry.hash,
+                    }
+                }
+                None => Tail::genesis(),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Tail::genesis(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            secret,
+            tail: Mutex::new(tail),
+        })
+    }
+
+    /// File the log is written to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a tool call to the log
+    pub async fn record(&self, call: ToolAudit) -> Result<AuditEntry, AuditError> {
+        let mut tail = self.tail.lock().await;
+
+        let mut entry = AuditEntry {
+            seq: tail.seq + 1,
+            timestamp: Utc::now(),
+            call,
+            prev_hash: tail.hash.clone(),
+            hash: String::new(),
+        };
+        let mut value = serde_json::to_value(&entry).expect("audit entries serialize");
+        entry.hash = entry_hash(&mut value, self.secret.as_deref());
+        value["hash"] = Value::String(entry.hash.clone());
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(format!("{}\n", value).as_bytes()).await?;
+        file.flush().await?;
+
+        tail.seq = entry.seq;
+        tail.hash = entry.hash.clone();
+        self.write_head(&tail).await?;
+        Ok(entry)
+    }
+
+    /// Replace the `.head` file, via a temporary file so it's never partial
+    async fn write_head(&self, tail: &Tail) -> Result<(), AuditError> {
+        let head = Head {
+            seq: tail.seq,
+            hash: tail.hash.clone(),
+            mac: head_mac(tail.seq, &tail.hash, self.secret.as_deref()),
+        };
+        let path = head_path(&self.path);
+        let mut temp = path.clone().into_os_string();
+        temp.push(".tmp");
+        tokio::fs::write(&temp, serde_json::to_vec(&head).expect("heads serialize")).await?;
+        tokio::fs::rename(&temp, &path).await?;
+        Ok(())
+    }
+
+    /// Entries matching `query`, newest first
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, AuditError> {
+        let limit = query.limit.unwrap_or(100).min(1000);
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = Vec::new();
+        let lines: Vec<&str> = content.lines().collect();
+        for (i, line) in lines.into_iter().enumerate().rev() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: AuditEntry =
+                serde_json::from_str(line).map_err(|source| AuditError::Parse {
+                    line: i + 1,
+                    source,
+                })?;
+            if query.matches(&entry) {
+                entries.push(entry);
+                if entries.len() == limit {
+                    break;
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+impl Tail {
+    fn genesis() -> Self {
+        Self {
+            seq: 0,
+            hash: GENESIS_HASH.to_string(),
+        }
+    }
+}
+
+/// Hash of an entry, ignoring its `hash` field
+fn entry_hash(entry: &mut Value, secret: Option<&[u8]>) -> String {
+    let hash = entry.as_object_mut().and_then(|o| o.remove("hash"));
+    let digest = digest(entry.to_string().as_bytes(), secret);
+    if let (Some(object), Some(hash)) = (entry.as_object_mut(), hash) {
+        object.insert("hash".to_string(), hash);
+    }
+    digest
+}
+
+fn head_mac(seq: u64, hash: &str, secret: Option<&[u8]>) -> String {
+    digest(format!("head:{}:{}", seq, hash).as_bytes(), secret)
+}
+
+/// HMAC-SHA256 with `secret`, or plain SHA-256 without one
+fn digest(data: &[u8], secret: Option<&[u8]>) -> String {
+    match secret {
+        Some(secret) => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+            mac.update(data);
+            hex::encode(mac.finalize().into_bytes())
+        }
+        None => hex::encode(Sha256::digest(data)),
+    }
+}
+
+/// `<log>.head`, where the end of the chain is anchored
+fn head_path(log: &Path) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(".head");
+    PathBuf::from(path)
+}
+
+/// Check the hash chain of an audit log file against its `.head` file
+///
+/// `secret` must be the one the log was written with. Returns the number of
+/// entries, or the first line that doesn't fit the chain.
+pub fn verify(path: &Path, secret: Option<&[u8]>) -> Result<u64, AuditError> {
+    let content = std::fs::read_to_string(path)?;
+    let head = match std::fs::read_to_string(head_path(path)) {
+        Ok(head) => Some(
+            serde_json::from_str::<Head>(&head)
+                .map_err(|e| AuditError::Head(format!("not valid: {}", e)))?,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(head) = head
+        .as_ref()
+        .filter(|h| h.mac != head_mac(h.seq, &h.hash, secret))
+    {
+        return Err(AuditError::Head(format!(
+            "modified, or written with another secret (records entry {})",
+            head.seq
+        )));
+    }
+    let mut tail = Tail::genesis();
+
+    for (i, line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let tampered = |reason: String| AuditError::Tampered {
+            line: line_no,
+            reason,
+        };
+
+        let mut value: Value = serde_json::from_str(line).map_err(|source| AuditError::Parse {
+            line: line_no,
+            source,
+        })?;
+        let entry: AuditEntry =
+            serde_json::from_value(value.clone()).map_err(|source| AuditError::Parse {
+                line: line_no,
+                source,
+            })?;
+
+        if entry.seq != tail.seq + 1 {
+            return Err(tampered(format!(
+                "expected entry {}, found {}",
+                tail.seq + 1,
+                entry.seq
+            )));
+        }
+        if entry.prev_hash != tail.hash {
+            return Err(tampered(format!(
+                "entry {} does not follow the previous entry",
+                entry.seq
+            )));
+        }
+        if entry_hash(&mut value, secret) != entry.hash {
+            return Err(tampered(format!(
+                "entry {} was modified after it was written",
+                entry.seq
+            )));
+        }
+        if let Some(head) = head.as_ref().filter(|h| h.seq == entry.seq) {
+            if head.hash != entry.hash {
+                return Err(tampered(format!(
+                    "entry {} is not the one the head file records",
+                    entry.seq
+                )));
+            }
+        }
+
+        tail = Tail {
+            seq: entry.seq,
+            hash: entry.hash,
+        };
+    }
+
+    // The head is written just after each entry, so the log may be one
+    // entry past it after a crash, but never behind it
+    match head {
+        None if tail.seq > 0 => Err(AuditError::Head(
+            "missing, but the log has entries".to_string(),
+        )),
+        Some(head) if head.seq > tail.seq => Err(AuditError::Head(format!(
+            "records entry {}, but the log ends at entry {}",
+            head.seq, tail.seq
+        ))),
+        Some(head) if tail.seq > head.seq + 1 => Err(AuditError::Head(format!(
+            "records entry {}, but the log goes on to entry {}",
+            head.seq, tail.seq
+        ))),
+        _ => Ok(tail.seq),
+    }
+}
+
+/// Copy of tool arguments with secret-looking values replaced
+pub fn redact(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let value = if is_secret(key) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact(value)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(redact).collect()),
+        other => other.clone(),
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase().replace('-', "_");
+    SECRET_KEYS.iter().any(|secret| key.ends_with(secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn call(tool: &str, status: AuditStatus) -> ToolAudit {
+        ToolAudit {
+            caller: Some("key:abc".to_string()),
+            conversation_id: "conv".to_string(),
+            persona: None,
+            tool: tool.to_string(),
+            plugin: Some("moxie.filesystem".to_string()),
+            arguments: json!({"path": "/tmp/report.txt"}),
+            status,
+            error: None,
+            duration_ms: 3,
+            confirmation: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_survives_reopen_and_verifies() {
+        let dir = std::env::temp_dir().join(format!("moxie-audit-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("logs/audit.jsonl");
+
+        let log = AuditLog::open(&path, None).unwrap();
+        let first = log
+            .record(call("read_file", AuditStatus::Success))
+            .await
+            .unwrap();
+        assert_eq!((first.seq, first.prev_hash.as_str()), (1, GENESIS_HASH));
+
+        let log = AuditLog::open(&path, None).unwrap();
+        let second = log
+            .record(call("write_file", AuditStatus::Declined))
+            .await
+            .unwrap();
+        assert_eq!((second.seq, &second.prev_hash), (2, &first.hash));
+
+        assert_eq!(verify(&path, None).unwrap(), 2);
+
+        let declined = AuditQuery {
+            status: Some(AuditStatus::Declined),
+            ..AuditQuery::default()
+        };
+        let found = log.query(&declined).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].call.tool, "write_file");
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_verify_detects_tampering() {
+        let dir = std::env::temp_dir().join(format!("moxie-audit-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("audit.jsonl");
+        let secret = b"audit-secret".to_vec();
+        let log = AuditLog::open(&path, Some(secret.clone())).unwrap();
+        for tool in ["read_file", "write_file", "delete_file"] {
+            log.record(call(tool, AuditStatus::Success)).await.unwrap();
+        }
+        let original = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+        let verify = |path: &Path| verify(path, Some(&secret));
+        assert_eq!(verify(&path).unwrap(), 3);
+
+        // Edited entry
+        std::fs::write(&path, original.replace("delete_file", "read_file")).unwrap();
+        let error = verify(&path).unwrap_err();
+        assert!(
+            matches!(error, AuditError::Tampered { line: 3, .. }),
+            "{}",
+            error
+        );
+
+        // Removed entry
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        let error = verify(&path).unwrap_err();
+        assert!(
+            matches!(error, AuditError::Tampered { line: 2, .. }),
+            "{}",
+            error
+        );
+
+        // Removed last entries
+        std::fs::write(&path, format!("{}\n", lines[0])).unwrap();
+        let error = verify(&path).unwrap_err();
+        assert!(matches!(error, AuditError::Head(_)), "{}", error);
+
+        // Removed last entries and head file
+        std::fs::remove_file(head_path(&path)).unwrap();
+        let error = verify(&path).unwrap_err();
+        assert!(matches!(error, AuditError::Head(_)), "{}", error);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_verify_detects_recomputed_chain() {
+        let dir = std::env::temp_dir().join(format!("moxie-audit-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("audit.jsonl");
+        let secret = b"audit-secret".to_vec();
+        let log = AuditLog::open(&path, Some(secret.clone())).unwrap();
+        for tool in ["read_file", "delete_file"] {
+            log.record(call(tool, AuditStatus::Success)).await.unwrap();
+        }
+        let original = std::fs::read_to_string(&path).unwrap();
+
+        // Rewrite the whole log from the edited entries, as someone without
+        // the secret would: every link and the head are consistent
+        let forged = dir.join("forged.jsonl");
+        let forger = AuditLog::open(&forged, None).unwrap();
+        for line in original.lines() {
+            let entry: AuditEntry = serde_json::from_str(line).unwrap();
+            let mut call = entry.call;
+            call.tool = call.tool.replace("delete_file", "read_file");
+            forger.record(call).await.unwrap();
+        }
+        let original_head = std::fs::read_to_string(head_path(&path)).unwrap();
+        std::fs::rename(&forged, &path).unwrap();
+        std::fs::rename(head_path(&forged), head_path(&path)).unwrap();
+        assert_eq!(verify(&path, None).unwrap(), 2);
+
+        let error = verify(&path, Some(&secret)).unwrap_err();
+        assert!(matches!(error, AuditError::Head(_)), "{}", error);
+
+        // With the real head put back, the forged entries don't hash right
+        std::fs::write(head_path(&path), original_head).unwrap();
+        let error = verify(&path, Some(&secret)).unwrap_err();
+        assert!(
+            matches!(error, AuditError::Tampered { line: 1, .. }),
+            "{}",
+            error
+        );
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_redact_secrets() {
+        let arguments = json!({
+            "query": "SELECT 1",
+            "max_tokens": 10,
+            "Api-Key": "sk-123",
+            "connection": {"host": "db", "password": "hunter2"},
+            "headers": [{"Authorization": "Bearer x"}]
+        });
+
+        assert_eq!(
+            redact(&arguments),
+            json!({
+                "query": "SELECT 1",
+                "max_tokens": 10,
+                "Api-Key": REDACTED,
+                "connection": {"host": "db", "password": REDACTED},
+                "headers": [{"Authorization": REDACTED}]
+            })
+        );
+    }
+}
@@ -0,0 +1,669 @@
+//! API key storage
+//!
+//! Keys are random tokens shown once at creation. Only a SHA-256 hash is
+//! stored, alongside a short prefix so keys can be told apart in listings.
+//!
+//! Keys with allowed origins are *publishable*: they are meant to be embedded
+//! in web pages (e.g. for the chat widget), so they only work from those
+//! origins, only carry the `chat` scope and can't replace the system prompt.
+//! Since anyone can read them from the page, they must also list the plugins
+//! they may use.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+
+use crate::config::validation::check_origin;
+use crate::config::ValidationIssue;
+
+/// Prefix of every generated key, so leaked keys are easy to grep for
+pub const KEY_PREFIX: &str = "moxie_";
+
+/// Number of characters of the key kept in plain text for display
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// How stale `last_used_at` may get before a use is written again, so
+/// busy keys don't cost a write per request
+const LAST_USED_INTERVAL: chrono::Duration = chrono::Duration::minutes(1);
+
+/// How often [`ApiKeyStore::issued`] may re-read the key hashes for an
+/// unknown key, to pick up keys created by another process (`moxie keys
+/// create` next to a running server)
+const ISSUED_REFRESH: Duration = Duration::from_secs(10);
+
+/// What a key is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    /// Send chat messages
+    #[serde(rename = "chat")]
+    Chat,
+    /// List available tools
+    #[serde(rename = "tools:read")]
+    ToolsRead,
+    /// Read Prometheus metrics
+    #[serde(rename = "metrics")]
+    Metrics,
+    /// Manage API keys; implies every other scope
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Chat, Scope::ToolsRead, Scope::Metrics, Scope::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Chat => "chat",
+            Scope::ToolsRead => "tools:read",
+            Scope::Metrics => "metrics",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| {
+                KeyError::Invalid(format!(
+                    "unknown scope '{}', expected one of: chat, tools:read, metrics, admin",
+                    s
+                ))
+            })
+    }
+}
+
+/// A stored API key (never includes the secret)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+
+    /// First characters of the secret, e.g. "moxie_1a2b3c"
+    pub prefix: String,
+
+    pub scopes: Vec<Scope>,
+
+    /// Personas this key may use (empty = any)
+    pub allowed_personas: Vec<String>,
+
+    /// Plugins whose tools this key may use, by ID or config key (empty = all,
+    /// or none for publishable keys)
+    pub allowed_plugins: Vec<String>,
+
+    /// Browser origins this key works from; non-empty makes it publishable
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Check whether the key grants a scope (`admin` grants all)
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Whether the key is meant to be embedded in web pages
+    pub fn is_publishable(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+
+    /// Whether requests from `origin` may use this key
+    pub fn allows_origin(&self, origin: Option<&str>) -> bool {
+        !self.is_publishable()
+            || origin.is_some_and(|o| self.allowed_origins.iter().any(|a| a == o))
+    }
+
+    /// Whether the key may set its own system prompt
+    pub fn allows_custom_prompts(&self) -> bool {
+        self.allowed_personas.is_empty() && !self.is_publishable()
+    }
+}
+
+/// Parameters for a new key
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub allowed_personas: Vec<String>,
+    #[serde(default)]
+    pub allowed_plugins: Vec<String>,
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+/// Errors from the key store
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("API key not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid API key: {0}")]
+    Invalid(String),
+}
+
+/// Row layout of the `api_keys` table
+type KeyRow = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+);
+
+const SELECT_KEY: &str = "SELECT id, name, prefix, scopes, allowed_personas, allowed_plugins, \
+                          allowed_origins, created_at, last_used_at, revoked_at FROM api_keys";
+
+/// SQLite-backed API key store
+#[derive(Clone)]
+pub struct ApiKeyStore {
+    pool: SqlitePool,
+    issued: Arc<Mutex<IssuedHashes>>,
+}
+
+/// Hashes of every key issued here, for [`ApiKeyStore::issued`]
+struct IssuedHashes {
+    hashes: HashSet<String>,
+    loaded_at: Instant,
+}
+
+impl ApiKeyStore {
+    /// Create the store on an existing pool (usually the memory store's)
+    pub async fn new(pool: SqlitePool) -> Result<Self, sqlx::Error> {
+        let store = Self {
+            pool,
+            issued: Arc::new(Mutex::new(IssuedHashes {
+                hashes: HashSet::new(),
+                loaded_at: Instant::now(),
+            })),
+        };
+        store.init_schema().await?;
+        store.load_issued().await?;
+        Ok(store)
+    }
+
+    /// Re-read the hashes of all keys issued here
+    async fn load_issued(&self) -> Result<(), sqlx::Error> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT key_hash FROM api_keys")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut issued = self.issued.lock().unwrap();
+        issued.hashes.extend(rows.into_iter().map(|(hash,)| hash));
+        Ok(())
+    }
+
+    async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_keys (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                prefix TEXT NOT NULL,
+                key_hash TEXT NOT NULL UNIQUE,
+                scopes TEXT NOT NULL,
+                allowed_personas TEXT NOT NULL DEFAULT '[]',
+                allowed_plugins TEXT NOT NULL DEFAULT '[]',
+                allowed_origins TEXT NOT NULL DEFAULT '[]',
+                created_at TEXT NOT NULL,
+                last_used_at TEXT,
+                revoked_at TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Databases created before publishable keys lack the column
+        let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info('api_keys')")
+            .fetch_all(&self.pool)
+            .await?;
+        if !columns.iter().any(|(name,)| name == "allowed_origins") {
+            sqlx::query(
+                "ALTER TABLE api_keys ADD COLUMN allowed_origins TEXT NOT NULL DEFAULT '[]'",
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Create a key, returning it together with the secret
+    ///
+    /// The secret cannot be recovered later.
+    pub async fn create(&self, new_key: NewApiKey) -> Result<(ApiKey, String), KeyError> {
+        if new_key.name.trim().is_empty() {
+            return Err(KeyError::Invalid("name must not be empty".to_string()));
+        }
+        if new_key.scopes.is_empty() {
+            return Err(KeyError::Invalid(
+                "at least one scope is required".to_string(),
+            ));
+        }
+
+        let mut scopes = new_key.scopes;
+        scopes.sort_unstable();
+        scopes.dedup();
+
+        if !new_key.allowed_origins.is_empty() {
+            if scopes != [Scope::Chat] {
+                return Err(KeyError::Invalid(
+                    "keys with allowed origins are public and can only have the chat scope"
+                        .to_string(),
+                ));
+            }
+            if new_key.allowed_plugins.is_empty() {
+                return Err(KeyError::Invalid(
+                    "keys with allowed origins are public and must list their allowed plugins"
+                        .to_string(),
+                ));
+            }
+
+            let mut issues = Vec::new();
+            for origin in &new_key.allowed_origins {
+                check_origin("allowed_origins", origin, &mut issues);
+                if origin == "*" {
+                    issues.push(ValidationIssue::new(
+                        "allowed_origins",
+                        "\"*\" is not allowed; list the sites that embed the key",
+                    ));
+                }
+            }
+            if let Some(issue) = issues.into_iter().next() {
+                return Err(KeyError::Invalid(issue.message));
+            }
+        }
+
+        let secret = generate_secret();
+
+        let key = ApiKey {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: new_key.name.trim().to_string(),
+            prefix: secret[..DISPLAY_PREFIX_LEN].to_string(),
+            scopes,
+            allowed_personas: new_key.allowed_personas,
+            allowed_plugins: new_key.allowed_plugins,
+            allowed_origins: new_key.allowed_origins,
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys
+                (id, name, prefix, key_hash, scopes, allowed_personas, allowed_plugins,
+                 allowed_origins, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&key.id)
+        .bind(&key.name)
+        .bind(&key.prefix)
+        .bind(hash_secret(&secret))
+        .bind(join_scopes(&key.scopes))
+        .bind(serde_json::to_string(&key.allowed_personas).unwrap_or_default())
+        .bind(serde_json::to_string(&key.allowed_plugins).unwrap_or_default())
+        .bind(serde_json::to_string(&key.allowed_origins).unwrap_or_default())
+        .bind(key.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        self.issued.lock().unwrap().hashes.insert(hash_secret(&secret));
+
+        Ok((key, secret))
+    }
+
+    /// Look up an active key by its secret and record the use, at most
+    /// once a minute
+    pub async fn authenticate(&self, secret: &str) -> Result<Option<ApiKey>, KeyError> {
+        let row: Option<KeyRow> = sqlx::query_as(&format!(
+            "{} WHERE key_hash = ? AND revoked_at IS NULL",
+            SELECT_KEY
+        ))
+        .bind(hash_secret(secret))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let mut key = from_row(row)?;
+        let now = Utc::now();
+        if key
+            .last_used_at
+            .is_none_or(|last_used| now - last_used >= LAST_USED_INTERVAL)
+        {
+            sqlx::query("UPDATE api_keys SET last_used_at = ? WHERE id = ?")
+                .bind(now.to_rfc3339())
+                .bind(&key.id)
+                .execute(&self.pool)
+                .await?;
+            key.last_used_at = Some(now);
+        }
+
+        Ok(Some(key))
+    }
+
+    /// Whether a key with this secret was issued here (even if revoked)
+    ///
+    /// Answered from memory, so a multi-tenant server can ask every tenant.
+    /// Keys created by another process are seen once the hashes are re-read,
+    /// at most every few seconds. Unlike
+    /// [`authenticate`](Self::authenticate) this doesn't record a use.
+    pub async fn issued(&self, secret: &str) -> Result<bool, KeyError> {
+        let hash = hash_secret(secret);
+        {
+            let mut issued = self.issued.lock().unwrap();
+            if issued.hashes.contains(&hash) {
+                return Ok(true);
+            }
+            if issued.loaded_at.elapsed() < ISSUED_REFRESH {
+                return Ok(false);
+            }
+            // Claimed before reading, so concurrent misses read once
+            issued.loaded_at = Instant::now();
+        }
+
+        self.load_issued().await?;
+        Ok(self.issued.lock().unwrap().hashes.contains(&hash))
+    }
+
+    /// List all keys, newest first (including revoked ones)
+    pub async fn list(&self) -> Result<Vec<ApiKey>, KeyError> {
+        let rows: Vec<KeyRow> = sqlx::query_as(&format!("{} ORDER BY created_at DESC", SELECT_KEY))
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter().map(from_row).collect()
+    }
+
+    /// Get a key by ID
+    pub async fn get(&self, id: &str) -> Result<ApiKey, KeyError> {
+        let row: Option<KeyRow> = sqlx::query_as(&format!("{} WHERE id = ?", SELECT_KEY))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => from_row(row),
+            None => Err(KeyError::NotFound(id.to_string())),
+        }
+    }
+
+    /// Revoke a key; revoked keys stay listed but no longer authenticate
+    pub async fn revoke(&self, id: &str) -> Result<ApiKey, KeyError> {
+        let mut key = self.get(id).await?;
+        if key.is_revoked() {
+            return Ok(key);
+        }
+
+        let now = Utc::now();
+        sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ?")
+            .bind(now.to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        key.revoked_at = Some(now);
+
+        Ok(key)
+    }
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{}{}", KEY_PREFIX, hex::encode(bytes))
+}
+
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+fn join_scopes(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(Scope::as_str)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, KeyError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| KeyError::Invalid(format!("bad timestamp '{}': {}", value, e)))
+}
+
+fn from_row(row: KeyRow) -> Result<ApiKey, KeyError> {
+    let (id, name, prefix, scopes, personas, plugins, origins, created_at, last_used_at, revoked_at) =
+        row;
+
+    Ok(ApiKey {
+        id,
+        name,
+        prefix,
+        scopes: scopes
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(Scope::from_str)
+            .collect::<Result<_, _>>()?,
+        allowed_personas: serde_json::from_str(&personas).unwrap_or_default(),
+        allowed_plugins: serde_json::from_str(&plugins).unwrap_or_default(),
+        allowed_origins: serde_json::from_str(&origins).unwrap_or_default(),
+        created_at: parse_time(&created_at)?,
+        last_used_at: last_used_at.as_deref().map(parse_time).transpose()?,
+        revoked_at: revoked_at.as_deref().map(parse_time).transpose()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryStore;
+
+    async fn store() -> ApiKeyStore {
+        let memory = MemoryStore::new_in_memory_async().await.unwrap();
+        ApiKeyStore::new(memory.pool().clone()).await.unwrap()
+    }
+
+    fn new_key(scopes: Vec<Scope>) -> NewApiKey {
+        NewApiKey {
+            name: "website".to_string(),
+            scopes,
+            allowed_personas: vec!["tech_support".to_string()],
+            allowed_plugins: Vec::new(),
+            allowed_origins: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_authenticate() {
+        let store = store().await;
+        let (key, secret) = store.create(new_key(vec![Scope::Chat])).await.unwrap();
+
+        assert!(secret.starts_with(KEY_PREFIX));
+        assert!(secret.starts_with(&key.prefix));
+
+        let found = store.authenticate(&secret).await.unwrap().unwrap();
+        assert_eq!(found.id, key.id);
+        assert_eq!(found.scopes, vec![Scope::Chat]);
+        assert_eq!(found.allowed_personas, vec!["tech_support"]);
+        assert!(found.last_used_at.is_some());
+
+        assert!(store.authenticate("moxie_wrong").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_last_used_is_throttled() {
+        let store = store().await;
+        let (_, secret) = store.create(new_key(vec![Scope::Chat])).await.unwrap();
+
+        let first = store.authenticate(&secret).await.unwrap().unwrap();
+        let second = store.authenticate(&secret).await.unwrap().unwrap();
+        assert_eq!(second.last_used_at, first.last_used_at);
+
+        let stale = first.last_used_at.unwrap() - chrono::Duration::minutes(2);
+        sqlx::query("UPDATE api_keys SET last_used_at = ?")
+            .bind(stale.to_rfc3339())
+            .execute(&store.pool)
+            .await
+            .unwrap();
+        let third = store.authenticate(&secret).await.unwrap().unwrap();
+        assert!(third.last_used_at.unwrap() > first.last_used_at.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_secret_is_not_stored() {
+        let store = store().await;
+        let (_, secret) = store.create(new_key(vec![Scope::Chat])).await.unwrap();
+
+        let (hash,): (String,) = sqlx::query_as("SELECT key_hash FROM api_keys")
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_ne!(hash, secret);
+        assert_eq!(hash.len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_revoked_key_does_not_authenticate() {
+        let store = store().await;
+        let (key, secret) = store.create(new_key(vec![Scope::Chat])).await.unwrap();
+
+        let revoked = store.revoke(&key.id).await.unwrap();
+        assert!(revoked.is_revoked());
+        assert!(store.authenticate(&secret).await.unwrap().is_none());
+        assert_eq!(store.list().await.unwrap().len(), 1);
+
+        assert!(matches!(
+            store.revoke("missing").await,
+            Err(KeyError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_issued_keys_are_indexed() {
+        let store = store().await;
+        let (key, secret) = store.create(new_key(vec![Scope::Chat])).await.unwrap();
+        store.revoke(&key.id).await.unwrap();
+        assert!(store.issued(&secret).await.unwrap());
+        assert!(!store.issued("moxie_nope").await.unwrap());
+
+        // Another process (here: another store on the same database)
+        // creates a key; it's seen once the hashes may be re-read
+        let other = ApiKeyStore::new(store.pool.clone()).await.unwrap();
+        let (_, later) = other.create(new_key(vec![Scope::Chat])).await.unwrap();
+        assert!(!store.issued(&later).await.unwrap());
+        if let Some(past) = Instant::now().checked_sub(ISSUED_REFRESH) {
+            store.issued.lock().unwrap().loaded_at = past;
+            assert!(store.issued(&later).await.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!("tools:read".parse::<Scope>().unwrap(), Scope::ToolsRead);
+        assert!("tools:write".parse::<Scope>().is_err());
+
+        let key = ApiKey {
+            id: "1".to_string(),
+            name: "admin".to_string(),
+            prefix: "moxie_000000".to_string(),
+            scopes: vec![Scope::Admin],
+            allowed_personas: Vec::new(),
+            allowed_plugins: Vec::new(),
+            allowed_origins: Vec::new(),
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+        };
+        assert!(key.has_scope(Scope::Chat));
+        assert!(key.has_scope(Scope::ToolsRead));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_scopes_are_removed() {
+        let store = store().await;
+        let (key, _) = store
+            .create(new_key(vec![Scope::Metrics, Scope::Chat, Scope::Metrics]))
+            .await
+            .unwrap();
+        assert_eq!(key.scopes, vec![Scope::Chat, Scope::Metrics]);
+    }
+
+    #[tokio::test]
+    async fn test_publishable_keys() {
+        let store = store().await;
+        let publishable = |scopes, origin: &str| NewApiKey {
+            allowed_plugins: vec!["faq".to_string()],
+            allowed_origins: vec![origin.to_string()],
+            ..new_key(scopes)
+        };
+
+        let (key, _) = store
+            .create(publishable(vec![Scope::Chat], "https://shop.example"))
+            .await
+            .unwrap();
+        let key = store.get(&key.id).await.unwrap();
+        assert!(key.is_publishable());
+        assert!(key.allows_origin(Some("https://shop.example")));
+        assert!(!key.allows_origin(Some("https://evil.example")));
+        assert!(!key.allows_origin(None));
+        assert!(!key.allows_custom_prompts());
+
+        for (scopes, origin) in [
+            (vec![Scope::Chat, Scope::Admin], "https://shop.example"),
+            (vec![Scope::Chat], "*"),
+            (vec![Scope::Chat], "https://shop.example/page"),
+        ] {
+            assert!(matches!(
+                store.create(publishable(scopes, origin)).await,
+                Err(KeyError::Invalid(_))
+            ));
+        }
+
+        // Publishable keys must list their plugins
+        let all_plugins = NewApiKey {
+            allowed_plugins: Vec::new(),
+            ..publishable(vec![Scope::Chat], "https://shop.example")
+        };
+        assert!(matches!(
+            store.create(all_plugins).await,
+            Err(KeyError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,415 @@
+//! Authentication middleware
+//!
+//! `authenticate` resolves the caller's key and user and stores a
+//! [`Principal`] in the request extensions; `require_scope` guards route
+//! groups by scope. Handlers read the principal to apply persona and plugin
+//! restrictions and to scope conversations to the user.
+
+use std::borrow::Cow;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::keys::{ApiKey, KeyError, Scope};
+use super::user_token::{valid_user_id, UserTokenError};
+use crate::audit::REDACTED;
+use crate::core::Owner;
+use crate::routes::ApiError;
+use crate::AppState;
+
+/// Header accepted as an alternative to `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Header naming the user, trusted from secret (non-publishable) keys
+pub const USER_HEADER: &str = "x-moxie-user";
+
+/// Header carrying a signed user token
+pub const USER_TOKEN_HEADER: &str = "x-moxie-user-token";
+
+/// Query parameters carrying credentials on WebSocket upgrades
+const SECRET_PARAMS: &[&str] = &["api_key", "user_token"];
+
+/// The authenticated caller of a request
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// The key used, or `None` when keys are not required and none was sent
+    pub key: Option<ApiKey>,
+
+    /// The user the request acts for, if it names one
+    pub user_id: Option<String>,
+}
+
+impl Principal {
+    /// Caller without a key, allowed only when `require_api_key` is off
+    pub fn anonymous() -> Self {
+        Self {
+            key: None,
+            user_id: None,
+        }
+    }
+
+    pub fn key_id(&self) -> Option<&str> {
+        self.key.as_ref().map(|k| k.id.as_str())
+    }
+
+    /// Whose conversations the caller may use: the user's, else the key's
+    pub fn owner(&self) -> Owner<'_> {
+        Owner::new(self.user_id.as_deref(), self.key_id())
+    }
+
+    /// Anonymous callers may chat, list tools and read metrics, but never
+    /// administer
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match &self.key {
+            Some(key) => key.has_scope(scope),
+            None => scope != Scope::Admin,
+        }
+    }
+
+    /// Resolve the persona for a chat request
+    ///
+    /// Keys limited to certain personas and publishable keys can't replace
+    /// the system prompt; persona-limited keys get their first allowed
+    /// persona when the request doesn't name one.
+    pub fn persona_for(
+        &self,
+        requested: Option<String>,
+        overrides_prompt: bool,
+    ) -> Result<Option<String>, AuthError> {
+        let Some(key) = &self.key else {
+            return Ok(requested);
+        };
+
+        if overrides_prompt && !key.allows_custom_prompts() {
+            return Err(AuthError::Forbidden(
+                "This API key cannot override the system prompt".to_string(),
+            ));
+        }
+
+        let allowed = &key.allowed_personas;
+        if allowed.is_empty() {
+            return Ok(requested);
+        }
+
+        match requested {
+            Some(persona) if allowed.iter().any(|p| p.eq_ignore_ascii_case(&persona)) => {
+                Ok(Some(persona))
+            }
+            Some(persona) => Err(AuthError::Forbidden(format!(
+                "This API key cannot use persona '{}'",
+                persona
+            ))),
+            None => Ok(allowed.first().cloned()),
+        }
+    }
+
+    /// Plugins whose tools the caller may use (`None` = all)
+    ///
+    /// Publishable keys without a plugin list get no tools.
+    pub fn allowed_plugins(&self) -> Option<Vec<String>> {
+        self.key
+            .as_ref()
+            .filter(|k| !k.allowed_plugins.is_empty() || k.is_publishable())
+            .map(|k| k.allowed_plugins.clone())
+    }
+
+    /// Whether the caller may send raw message lists (including system
+    /// messages), which would bypass persona restrictions
+    pub fn allows_custom_prompts(&self) -> bool {
+        self.key.as_ref().is_none_or(ApiKey::allows_custom_prompts)
+    }
+}
+
+/// Authentication and authorization failures
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Missing API key")]
+    MissingKey,
+
+    #[error("Invalid or revoked API key")]
+    InvalidKey,
+
+    #[error("This API key lacks the '{0}' scope")]
+    MissingScope(Scope),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error(transparent)]
+    UserToken(#[from] UserTokenError),
+
+    #[error("Key store error: {0}")]
+    Store(#[from] KeyError),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// Extract the key from `Authorization: Bearer <key>` or `X-API-Key`
+pub fn extract_key(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+
+    bearer
+        .or_else(|| headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+}
+
+/// The key sent with a request, from the headers or, for WebSocket
+/// upgrades, the `api_key` query parameter
+pub fn request_key<'a>(headers: &'a HeaderMap, uri: &'a Uri) -> Option<Cow<'a, str>> {
+    extract_key(headers)
+        .map(Cow::Borrowed)
+        .or_else(|| websocket_param(headers, uri, "api_key"))
+}
+
+/// Extract a percent-decoded query parameter of a WebSocket upgrade
+///
+/// Browsers cannot set headers on WebSocket connections.
+fn websocket_param<'a>(headers: &'a HeaderMap, uri: &'a Uri, name: &str) -> Option<Cow<'a, str>> {
+    let upgrade = headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if !upgrade {
+        return None;
+    }
+
+    let value = query_params(uri.query()?)
+        .find(|(param, _)| *param == name)
+        .map(|(_, value)| value)?;
+    match value {
+        Cow::Borrowed(value) => Some(Cow::Borrowed(value.trim())),
+        Cow::Owned(value) => Some(Cow::Owned(value.trim().to_string())),
+    }
+    .filter(|v| !v.is_empty())
+}
+
+/// Percent-decoded name and value of each `name=value` pair of a query
+fn query_params(query: &str) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+    let decode = |part| percent_encoding::percent_decode_str(part).decode_utf8().ok();
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter_map(move |(name, value)| Some((decode(name)?, decode(value)?)))
+}
+
+/// `uri` with the values of credential query parameters (`api_key`,
+/// `user_token`) replaced, for logs and trace spans
+pub fn redact_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+
+    let query: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_secret_param(name) => format!("{}={}", name, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+fn is_secret_param(name: &str) -> bool {
+    let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+    SECRET_PARAMS.contains(&name.as_ref())
+}
+
+/// The user a request acts for
+///
+/// A signed user token (`X-Moxie-User-Token`, or `?user_token=` on WebSocket
+/// upgrades) is accepted from anyone; the plain `X-Moxie-User` header only
+/// from secret keys, whose holders are trusted servers.
+fn request_user(
+    state: &AppState,
+    headers: &HeaderMap,
+    uri: &Uri,
+    key: Option<&ApiKey>,
+) -> Result<Option<String>, AuthError> {
+    let header_value = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    let token = header_value(USER_TOKEN_HEADER)
+        .map(Cow::Borrowed)
+        .or_else(|| websocket_param(headers, uri, "user_token"));
+    if let Some(token) = token {
+        let tokens = state.user_tokens.as_ref().ok_or(UserTokenError::Disabled)?;
+        return Ok(Some(tokens.verify(&token)?));
+    }
+
+    let Some(user_id) = header_value(USER_HEADER) else {
+        return Ok(None);
+    };
+    if key.is_none_or(ApiKey::is_publishable) {
+        return Err(AuthError::Forbidden(
+            "Only secret API keys can set X-Moxie-User; send a signed user token instead"
+                .to_string(),
+        ));
+    }
+    if !valid_user_id(user_id) {
+        return Err(AuthError::Forbidden(format!(
+            "Invalid user ID '{}'",
+            user_id.escape_debug()
+        )));
+    }
+    Ok(Some(user_id.to_string()))
+}
+
+/// Resolve the caller and attach a [`Principal`] to the request
+///
+/// A key that is sent must be valid even when keys are optional, and a
+/// publishable key must come from one of its origins. WebSocket upgrades may
+/// pass the key as `?api_key=`. A user named by the request must be vouched
+/// for (see [`request_user`]).
+pub async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let secret = request_key(request.headers(), request.uri()).map(Cow::into_owned);
+    let key = match secret {
+        Some(secret) => {
+            let key = state
+                .keys
+                .authenticate(&secret)
+                .await?
+                .ok_or(AuthError::InvalidKey)?;
+
+            let origin = request
+                .headers()
+                .get(header::ORIGIN)
+                .and_then(|v| v.to_str().ok());
+            if !key.allows_origin(origin) {
+                return Err(AuthError::Forbidden(format!(
+                    "This API key cannot be used from origin '{}'",
+                    origin.unwrap_or("none")
+                )));
+            }
+
+            Some(key)
+        }
+        None if state.security.require_api_key => return Err(AuthError::MissingKey),
+        None => None,
+    };
+
+    let user_id = request_user(&state, request.headers(), request.uri(), key.as_ref())?;
+    request.extensions_mut().insert(Principal { key, user_id });
+    Ok(next.run(request).await)
+}
+
+/// Reject requests whose principal lacks `scope`
+///
+/// Use with `middleware::from_fn_with_state(scope, require_scope)` after
+/// [`authenticate`].
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let allowed = request
+        .extensions()
+        .get::<Principal>()
+        .is_some_and(|p| p.has_scope(scope));
+
+    if !allowed {
+        return Err(AuthError::MissingScope(scope));
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn principal(personas: &[&str]) -> Principal {
+        Principal {
+            key: Some(ApiKey {
+                id: "1".to_string(),
+                name: "website".to_string(),
+                prefix: "moxie_000000".to_string(),
+                scopes: vec![Scope::Chat],
+                allowed_personas: personas.iter().map(|p| p.to_string()).collect(),
+                allowed_plugins: Vec::new(),
+                allowed_origins: Vec::new(),
+                created_at: Utc::now(),
+                last_used_at: None,
+                revoked_at: None,
+            }),
+            user_id: None,
+        }
+    }
+
+    #[test]
+    fn test_extract_key() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(extract_key(&headers), None);
+
+        headers.insert(API_KEY_HEADER, "moxie_abc".parse().unwrap());
+        assert_eq!(extract_key(&headers), Some("moxie_abc"));
+
+        headers.insert(header::AUTHORIZATION, "Bearer moxie_def".parse().unwrap());
+        assert_eq!(extract_key(&headers), Some("moxie_def"));
+    }
+
+    #[test]
+    fn test_websocket_key_is_decoded() {
+        let uri: Uri = "/v2/ws?lang=en&api%5Fkey=moxie%5Fabc%2B".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        assert_eq!(request_key(&headers, &uri), None);
+
+        headers.insert(header::UPGRADE, "websocket".parse().unwrap());
+        assert_eq!(request_key(&headers, &uri).as_deref(), Some("moxie_abc+"));
+    }
+
+    #[test]
+    fn test_redact_uri() {
+        let uri: Uri = "/v2/ws?lang=en&api_key=moxie_abc&user%5Ftoken=t.sig"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            redact_uri(&uri),
+            "/v2/ws?lang=en&api_key=[REDACTED]&user%5Ftoken=[REDACTED]"
+        );
+
+        let plain: Uri = "/v2/chat".parse().unwrap();
+        assert_eq!(redact_uri(&plain), "/v2/chat");
+    }
+
+    #[test]
+    fn test_persona_restrictions() {
+        let open = principal(&[]);
+        assert_eq!(open.persona_for(None, true).unwrap(), None);
+
+        let limited = principal(&["tech_support"]);
+        assert_eq!(
+            limited.persona_for(None, false).unwrap().as_deref(),
+            Some("tech_support")
+        );
+        assert!(limited
+            .persona_for(Some("data_entry".into()), false)
+            .is_err());
+        assert!(limited
+            .persona_for(Some("tech_support".into()), true)
+            .is_err());
+        assert!(!limited.allows_custom_prompts());
+    }
+
+    #[test]
+    fn test_anonymous_is_not_admin() {
+        let anonymous = Principal::anonymous();
+        assert!(anonymous.has_scope(Scope::Chat));
+        assert!(!anonymous.has_scope(Scope::Admin));
+    }
+}
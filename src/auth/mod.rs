@@ -0,0 +1,21 @@
+//! API key authentication
+//!
+//! Every route except `/health` goes through [`authenticate`]. Keys carry
+//! scopes (`chat`, `tools:read`, `metrics`, `admin`) and can be limited to certain
+//! personas and plugins. Keys are managed with `moxie-ai keys` or the
+//! `/v2/admin/keys` endpoints.
+//!
+//! A request may also name the user it acts for, which decides whose
+//! conversations it can see: secret keys may set `X-Moxie-User`, and anyone
+//! may send a [signed user token](user_token) instead.
+
+mod keys;
+mod middleware;
+pub mod user_token;
+
+pub use keys::{ApiKey, ApiKeyStore, KeyError, NewApiKey, Scope, KEY_PREFIX};
+pub use middleware::{
+    authenticate, extract_key, redact_uri, request_key, require_scope, AuthError, Principal,
+    API_KEY_HEADER, USER_HEADER, USER_TOKEN_HEADER,
+};
+pub use user_token::{UserTokenError, UserTokens};
@@ -0,0 +1,160 @@
+//! Signed user tokens
+//!
+//! A site's backend vouches for its signed-in user by signing a short-lived
+//! token with the secret named by `security.user_token_secret_env`. The
+//! widget (or any client) sends it in `X-Moxie-User-Token`, so even a
+//! publishable key can't act as another user.
+//!
+//! ```text
+//! token   = base64url(payload) "." base64url(HMAC-SHA256(secret, base64url(payload)))
+//! payload = {"sub": "<user id>", "exp": <unix seconds>}
+//! ```
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Longest accepted user ID
+pub const MAX_USER_ID_LEN: usize = 256;
+
+/// Why a user token was rejected
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum UserTokenError {
+    #[error("Malformed user token")]
+    Malformed,
+
+    #[error("User token signature does not match")]
+    BadSignature,
+
+    #[error("User token expired")]
+    Expired,
+
+    #[error("User tokens are not enabled")]
+    Disabled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    exp: i64,
+}
+
+/// Signs and verifies user tokens with a shared secret
+#[derive(Clone)]
+pub struct UserTokens {
+    secret: Vec<u8>,
+}
+
+impl std::fmt::Debug for UserTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserTokens").finish_non_exhaustive()
+    }
+}
+
+impl UserTokens {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+        }
+    }
+
+    /// Issue a token for `user_id`, valid until `expires_at`
+    pub fn sign(&self, user_id: &str, expires_at: DateTime<Utc>) -> String {
+        let claims = Claims {
+            sub: user_id.to_string(),
+            exp: expires_at.timestamp(),
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap_or_default());
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// The user ID of a valid, unexpired token
+    pub fn verify(&self, token: &str) -> Result<String, UserTokenError> {
+        self.verify_at(token, Utc::now())
+    }
+
+    fn verify_at(&self, token: &str, now: DateTime<Utc>) -> Result<String, UserTokenError> {
+        let (payload, signature) = token
+            .trim()
+            .split_once('.')
+            .ok_or(UserTokenError::Malformed)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| UserTokenError::Malformed)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| UserTokenError::BadSignature)?;
+
+        let claims: Claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(UserTokenError::Malformed)?;
+        if claims.exp <= now.timestamp() {
+            return Err(UserTokenError::Expired);
+        }
+        if !valid_user_id(&claims.sub) {
+            return Err(UserTokenError::Malformed);
+        }
+        Ok(claims.sub)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// User IDs are opaque, but must be non-empty printable text
+pub fn valid_user_id(user_id: &str) -> bool {
+    !user_id.is_empty()
+        && user_id.len() <= MAX_USER_ID_LEN
+        && !user_id.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let tokens = UserTokens::new("0123456789abcdef0123456789abcdef");
+        let now = Utc::now();
+        let token = tokens.sign("user-42", now + Duration::minutes(5));
+
+        assert_eq!(tokens.verify_at(&token, now).unwrap(), "user-42");
+        assert_eq!(
+            tokens.verify_at(&token, now + Duration::minutes(6)),
+            Err(UserTokenError::Expired)
+        );
+
+        // Signed with another secret, or tampered with
+        let other = UserTokens::new("another secret of enough length!!");
+        assert_eq!(
+            other.verify_at(&token, now),
+            Err(UserTokenError::BadSignature)
+        );
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(r#"{"sub":"admin","exp":9999999999}"#),
+            signature
+        );
+        assert_eq!(
+            tokens.verify_at(&forged, now),
+            Err(UserTokenError::BadSignature)
+        );
+        assert_eq!(
+            tokens.verify_at("user-42", now),
+            Err(UserTokenError::Malformed)
+        );
+    }
+}
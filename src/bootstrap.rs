@@ -0,0 +1,341 @@
+//! Shared startup code
+//!
+//! The server and every CLI command load configuration and build the memory
+//! store, plugins and chat engine the same way, so a `moxie tools list` shows
+//! exactly the tools `moxie serve` would offer.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::audit::AuditLog;
+use crate::auth::{ApiKeyStore, UserTokens};
+use crate::config::client::{FilesystemPluginConfig, LlmConfig, SecurityConfig, WidgetConfig};
+use crate::config::{ClientConfig, Config, ConfigValidator};
+use crate::core::{ArtifactStore, ChatEngine, MemoryStore};
+use crate::plugins::api::ApiPlugin;
+use crate::plugins::database::DatabasePlugin;
+use crate::plugins::email::EmailPlugin;
+use crate::plugins::filesystem::FilesystemPlugin;
+use crate::plugins::office::OfficePlugin;
+use crate::plugins::{self, PluginContext, PluginLoader, SharedPluginLoader};
+use crate::rate_limit::RateLimiter;
+use crate::telemetry::TelemetryAgent;
+use crate::AppState;
+
+/// Environment variable pointing at the client configuration file
+pub const CONFIG_ENV: &str = "MOXIE_CONFIG";
+
+/// Environment variable for the data directory
+pub const DATA_DIR_ENV: &str = "MOXIE_DATA_DIR";
+
+/// Environment variable for the directory of client configs to host
+pub const CLIENTS_DIR_ENV: &str = "MOXIE_CLIENTS_DIR";
+
+/// Data directory used when none is given
+pub const DEFAULT_DATA_DIR: &str = "./data";
+
+/// Shortest accepted secret for signing user tokens
+const MIN_USER_TOKEN_SECRET_LEN: usize = 32;
+
+/// Loaded configuration, ready to build application components from
+#[derive(Debug, Clone)]
+pub struct Bootstrap {
+    /// Server configuration from the environment
+    pub config: Config,
+
+    /// Client configuration, if a config file was given
+    pub client: Option<ClientConfig>,
+
+    /// Directory for the database and plugin data
+    pub data_dir: PathBuf,
+}
+
+impl Bootstrap {
+    /// Load configuration from the environment and an optional client config file
+    ///
+    /// The client config is validated against the built-in plugins; any
+    /// problem aborts startup with the full list of issues.
+    pub fn load(config_path: Option<&Path>, data_dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let config = Config::from_env()?;
+
+        let client = match config_path {
+            Some(path) => {
+                let validator = ConfigValidator::new(plugins::builtin_manifests());
+                let client = validator
+                    .validate_file(path)
+                    .with_context(|| format!("Invalid client config {}", path.display()))?;
+                tracing::info!("Loaded client config for {}", client.client.name);
+                Some(client)
+            }
+            None => None,
+        };
+
+        let data_dir = data_dir.unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR));
+
+        Ok(Self {
+            config,
+            client,
+            data_dir,
+        })
+    }
+
+    /// Open the conversation memory store
+    pub async fn memory_store(&self) -> anyhow::Result<MemoryStore> {
+        let path = self.data_dir.join("moxie.db");
+        MemoryStore::new(&path)
+            .await
+            .with_context(|| format!("Failed to open memory store at {}", path.display()))
+    }
+
+    /// Register and initialize the plugins enabled for this deployment
+    ///
+    /// Without a client config, only a read-only filesystem plugin rooted at
+    /// the current directory is loaded.
+    pub async fn plugin_loader(&self) -> anyhow::Result<PluginLoader> {
+        let mut loader = PluginLoader::new().with_context(PluginContext {
+            data_dir: self.data_dir.join("plugins"),
+            ..PluginContext::default()
+        });
+
+        match &self.client {
+            Some(client) => {
+                for name in &client.plugins.enabled {
+                    let manifest = plugins::builtin_manifests()
+                        .into_iter()
+                        .find(|m| m.matches(name))
+                        .with_context(|| format!("Unknown plugin '{}'", name))?;
+                    let section = client
+                        .plugins
+                        .section(manifest.config_key())
+                        .unwrap_or(Value::Null);
+
+                    match manifest.id.as_str() {
+                        FilesystemPlugin::ID => loader
+                            .register_with_config(FilesystemPlugin::default_plugin(), section)?,
+                        ApiPlugin::ID => {
+                            loader.register_with_config(ApiPlugin::default_plugin(), section)?
+                        }
+                        DatabasePlugin::ID => loader
+                            .register_with_config(DatabasePlugin::default_plugin(), section)?,
+                        OfficePlugin::ID => loader
+                            .register_with_config(OfficePlugin::default_plugin(), section)?,
+                        EmailPlugin::ID => {
+                            loader.register_with_config(EmailPlugin::default_plugin(), section)?
+                        }
+                        other => anyhow::bail!("Plugin '{}' cannot be loaded", other),
+                    }
+                }
+            }
+            None => {
+                let fs_config = FilesystemPluginConfig {
+                    allowed_paths: vec![std::env::current_dir().unwrap_or_default()],
+                    ..FilesystemPluginConfig::default()
+                };
+                let section = serde_json::to_value(&fs_config)?;
+                loader.register_with_config(FilesystemPlugin::new(fs_config.into()), section)?;
+            }
+        }
+
+        loader.init_all().await?;
+        tracing::info!("📦 Loaded {} plugin(s)", loader.len());
+
+        Ok(loader)
+    }
+
+    /// Build the chat engine with memory, plugins and the audit log
+    pub async fn chat_engine(&self) -> anyhow::Result<ChatEngine> {
+        let memory = Arc::new(self.memory_store().await?);
+        let plugins: SharedPluginLoader = Arc::new(RwLock::new(self.plugin_loader().await?));
+        let audit_log = match self.audit_log_path() {
+            Some(path) => Some(Arc::new(
+                AuditLog::open(&path, self.audit_secret()?).with_context(|| {
+                    format!("Failed to open audit log at {}", path.display())
+                })?,
+            )),
+            None => None,
+        };
+
+        Ok(ChatEngine::new(self.config.clone(), plugins, memory)
+            .with_audit_log(audit_log)
+            .with_tool_call_logging(self.security().log_tool_calls))
+    }
+
+    /// Where tool calls are audited: `security.audit_log_path`, or
+    /// `audit.jsonl` in the data directory when only `log_tool_calls` is set
+    pub fn audit_log_path(&self) -> Option<PathBuf> {
+        let security = self.security();
+        match security.audit_log_path {
+            Some(path) => Some(path),
+            None if security.log_tool_calls => Some(self.data_dir.join("audit.jsonl")),
+            None => None,
+        }
+    }
+
+    /// Open the API key store (in the same database as conversations)
+    pub async fn key_store(&self) -> anyhow::Result<ApiKeyStore> {
+        let memory = self.memory_store().await?;
+        Ok(ApiKeyStore::new(memory.pool().clone()).await?)
+    }
+
+    /// Build the shared state for the HTTP server
+    pub async fn app_state(&self) -> anyhow::Result<AppState> {
+        let security = self.security();
+        let limits = RateLimiter::from_config(&security.rate_limits);
+
+        let chat_engine = self.chat_engine().await?;
+        let pool = chat_engine.memory().pool().clone();
+        let keys = ApiKeyStore::new(pool.clone()).await?;
+        let artifacts = ArtifactStore::new(pool).await?;
+        let chat_engine = chat_engine
+            .with_tool_limiter(limits.tool_executions.clone())
+            .with_confirmation_required(security.require_confirmation_for.clone())
+            .with_artifacts(Some(Arc::new(artifacts)));
+
+        Ok(AppState {
+            config: self.config.clone(),
+            chat_engine: Arc::new(chat_engine),
+            keys,
+            security: Arc::new(security),
+            limits: Arc::new(limits),
+            llm: Arc::new(self.llm()),
+            widget: Arc::new(self.widget()),
+            sessions: Arc::default(),
+            user_tokens: self.user_tokens()?.map(Arc::new),
+        })
+    }
+
+    /// Secret keying the audit log, if `security.audit_secret_env` is set
+    ///
+    /// Fails if the variable is unset or empty.
+    pub fn audit_secret(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(name) = self.security().audit_secret_env else {
+            return Ok(None);
+        };
+        let secret = std::env::var(&name)
+            .with_context(|| format!("{} (security.audit_secret_env) is not set", name))?;
+        if secret.is_empty() {
+            anyhow::bail!("{} must not be empty to key the audit log", name);
+        }
+        Ok(Some(secret.into_bytes()))
+    }
+
+    /// Verifier for user tokens, if `security.user_token_secret_env` is set
+    ///
+    /// Fails if the variable is unset or holds a short secret.
+    pub fn user_tokens(&self) -> anyhow::Result<Option<UserTokens>> {
+        let Some(name) = self.security().user_token_secret_env else {
+            return Ok(None);
+        };
+        let secret = std::env::var(&name)
+            .with_context(|| format!("{} (security.user_token_secret_env) is not set", name))?;
+        if secret.len() < MIN_USER_TOKEN_SECRET_LEN {
+            anyhow::bail!(
+                "{} must hold at least {} characters to sign user tokens",
+                name,
+                MIN_USER_TOKEN_SECRET_LEN
+            );
+        }
+        Ok(Some(UserTokens::new(secret)))
+    }
+
+    /// Telemetry agent for the dashboard, if enabled in the client config
+    pub async fn telemetry_agent(
+        &self,
+        memory: Arc<MemoryStore>,
+    ) -> anyhow::Result<Option<TelemetryAgent>> {
+        let Some(client) = &self.client else {
+            return Ok(None);
+        };
+        TelemetryAgent::new(&client.telemetry, &client.client.name, &self.data_dir, memory)
+            .await
+            .context("Failed to start the telemetry agent")
+    }
+
+    /// Security settings from the client config, or the defaults
+    pub fn security(&self) -> SecurityConfig {
+        self.client
+            .as_ref()
+            .map(|c| c.security.clone())
+            .unwrap_or_default()
+    }
+
+    /// LLM settings from the client config, or the defaults
+    pub fn llm(&self) -> LlmConfig {
+        self.client
+            .as_ref()
+            .map(|c| c.llm.clone())
+            .unwrap_or_default()
+    }
+
+    /// Widget settings, titled with the client name unless configured
+    pub fn widget(&self) -> WidgetConfig {
+        match &self.client {
+            Some(client) => WidgetConfig {
+                title: client
+                    .widget
+                    .title
+                    .clone()
+                    .or_else(|| Some(client.client.name.clone())),
+                ..client.widget.clone()
+            },
+            None => WidgetConfig::default(),
+        }
+    }
+
+    /// Default provider for new chats (client config, then "ollama")
+    pub fn default_provider(&self) -> String {
+        self.llm().provider
+    }
+
+    /// Default model for new chats (client config, then "llama3.2")
+    pub fn default_model(&self) -> String {
+        self.llm().model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_plugins_from_client_config() {
+        let dir = std::env::temp_dir().join(format!("moxie-bootstrap-{}", uuid::Uuid::new_v4()));
+        let config_path = dir.join("client.toml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &config_path,
+            format!(
+                "[client]\nname = \"Test\"\n\n[plugins]\nenabled = [\"filesystem\"]\n\n\
+                 [plugins.filesystem]\nallowed_paths = [{:?}]\nallow_write = true\n",
+                dir.display().to_string()
+            ),
+        )
+        .unwrap();
+
+        let bootstrap = Bootstrap::load(Some(&config_path), Some(dir.clone())).unwrap();
+        let loader = bootstrap.plugin_loader().await.unwrap();
+
+        assert_eq!(loader.list_active(), vec![FilesystemPlugin::ID.to_string()]);
+        assert!(loader.all_tools().iter().any(|t| t.name == "write_file"));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_invalid_client_config_is_rejected() {
+        let dir = std::env::temp_dir().join(format!("moxie-bootstrap-{}", uuid::Uuid::new_v4()));
+        let config_path = dir.join("client.toml");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&config_path, "[client]\nname = \"Test\"\n\n[plugins]\nenabled = [\"nope\"]\n")
+            .unwrap();
+
+        let error = Bootstrap::load(Some(&config_path), None).unwrap_err();
+        assert!(format!("{:#}", error).contains("plugins.enabled[0]"));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
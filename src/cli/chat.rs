@@ -0,0 +1,143 @@
+//! Interactive terminal chat
+//!
+//! Talks to the same `ChatEngine` as the HTTP API, so conversations started
+//! here are stored in the memory store and can be continued later (from the
+//! terminal with `--conversation` or through `/v2/chat`).
+
+use std::io::Write;
+
+use clap::Args;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::bootstrap::Bootstrap;
+use crate::core::ChatRequest;
+
+#[derive(Debug, Args)]
+pub struct ChatArgs {
+    /// Continue an existing conversation
+    #[arg(long)]
+    pub conversation: Option<String>,
+
+    /// Persona to use (e.g. business_analyst)
+    #[arg(long)]
+    pub persona: Option<String>,
+
+    /// Provider to use (defaults to the client config, then ollama)
+    #[arg(long)]
+    pub provider: Option<String>,
+
+    /// Model to use (defaults to the client config, then llama3.2)
+    #[arg(long)]
+    pub model: Option<String>,
+}
+
+/// REPL commands, typed instead of a message
+#[derive(Debug, PartialEq, Eq)]
+enum ReplCommand {
+    Exit,
+    New,
+    Id,
+    Help,
+}
+
+fn parse_command(line: &str) -> Option<ReplCommand> {
+    match line {
+        "/exit" | "/quit" => Some(ReplCommand::Exit),
+        "/new" => Some(ReplCommand::New),
+        "/id" => Some(ReplCommand::Id),
+        "/help" => Some(ReplCommand::Help),
+        _ => None,
+    }
+}
+
+pub async fn run(bootstrap: &Bootstrap, args: ChatArgs) -> anyhow::Result<()> {
+    let engine = bootstrap.chat_engine().await?;
+    let provider = args.provider.unwrap_or_else(|| bootstrap.default_provider());
+    let model = args.model.unwrap_or_else(|| bootstrap.default_model());
+    let mut conversation_id = args.conversation;
+
+    println!("Moxie chat ({} / {}). Type /help for commands.", provider, model);
+    if let Some(id) = &conversation_id {
+        println!("Continuing conversation {}", id);
+    }
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        print!("you> ");
+        std::io::stdout().flush()?;
+
+        let Some(line) = lines.next_line().await? else {
+            println!();
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match parse_command(line) {
+            Some(ReplCommand::Exit) => break,
+            Some(ReplCommand::New) => {
+                conversation_id = None;
+                println!("Started a new conversation.");
+                continue;
+            }
+            Some(ReplCommand::Id) => {
+                match &conversation_id {
+                    Some(id) => println!("{}", id),
+                    None => println!("No conversation yet."),
+                }
+                continue;
+            }
+            Some(ReplCommand::Help) => {
+                println!("/new   start a new conversation");
+                println!("/id    show the current conversation ID");
+                println!("/exit  quit");
+                continue;
+            }
+            None => {}
+        }
+
+        let request = ChatRequest {
+            message: line.to_string(),
+            conversation_id: conversation_id.clone(),
+            system_prompt: None,
+            persona: args.persona.clone(),
+            provider: provider.clone(),
+            model: model.clone(),
+            ..ChatRequest::default()
+        };
+
+        match engine.chat(request).await {
+            Ok(response) => {
+                for tool in &response.tool_calls {
+                    let status = if tool.success { "ok" } else { "failed" };
+                    println!("  [tool {} {}]", tool.name, status);
+                }
+                println!("moxie> {}\n", response.message);
+                conversation_id = Some(response.conversation_id);
+            }
+            Err(e) => eprintln!("error: {}\n", e),
+        }
+    }
+
+    if let Some(id) = conversation_id {
+        println!("Conversation saved: {}", id);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("/exit"), Some(ReplCommand::Exit));
+        assert_eq!(parse_command("/quit"), Some(ReplCommand::Exit));
+        assert_eq!(parse_command("/new"), Some(ReplCommand::New));
+        assert_eq!(parse_command("hello /new"), None);
+    }
+}
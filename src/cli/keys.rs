@@ -0,0 +1,116 @@
+//! `moxie keys` - manage API keys
+//!
+//! Works directly on the database, so it's how the first admin key is made.
+
+use clap::Subcommand;
+
+use crate::auth::{ApiKey, ApiKeyStore, NewApiKey, Scope};
+use crate::bootstrap::Bootstrap;
+
+#[derive(Debug, Subcommand)]
+pub enum KeysCommand {
+    /// Create a key and print its secret (shown only once)
+    Create {
+        /// Name to recognise the key by (e.g. "acme website")
+        name: String,
+
+        /// Scope to grant: chat, tools:read, metrics or admin (repeatable)
+        #[arg(long = "scope", required = true)]
+        scopes: Vec<Scope>,
+
+        /// Limit the key to this persona (repeatable)
+        #[arg(long = "persona")]
+        personas: Vec<String>,
+
+        /// Limit the key to this plugin's tools, by ID or key (repeatable)
+        #[arg(long = "plugin")]
+        plugins: Vec<String>,
+
+        /// Make a publishable key for web pages on this origin (repeatable);
+        /// requires --plugin
+        #[arg(long = "origin")]
+        origins: Vec<String>,
+    },
+
+    /// List keys (secrets are never shown)
+    List {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Revoke a key
+    Revoke {
+        /// Key ID
+        id: String,
+    },
+}
+
+pub async fn run(bootstrap: &Bootstrap, command: KeysCommand) -> anyhow::Result<()> {
+    let store = bootstrap.key_store().await?;
+
+    match command {
+        KeysCommand::Create {
+            name,
+            scopes,
+            personas,
+            plugins,
+            origins,
+        } => {
+            let new_key = NewApiKey {
+                name,
+                scopes,
+                allowed_personas: personas,
+                allowed_plugins: plugins,
+                allowed_origins: origins,
+            };
+            create(&store, new_key).await
+        }
+        KeysCommand::List { json } => {
+            let keys = store.list().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&keys)?);
+            } else {
+                for key in &keys {
+                    println!("{}", describe(key));
+                }
+            }
+            Ok(())
+        }
+        KeysCommand::Revoke { id } => {
+            let key = store.revoke(&id).await?;
+            println!("Revoked {} ({})", key.id, key.name);
+            Ok(())
+        }
+    }
+}
+
+async fn create(store: &ApiKeyStore, new_key: NewApiKey) -> anyhow::Result<()> {
+    let (key, secret) = store.create(new_key).await?;
+
+    println!("Created {}", describe(&key));
+    println!("\n{}\n", secret);
+    println!("Store this key now; it cannot be shown again.");
+    Ok(())
+}
+
+fn describe(key: &ApiKey) -> String {
+    let scopes = key
+        .scopes
+        .iter()
+        .map(Scope::as_str)
+        .collect::<Vec<_>>()
+        .join(",");
+    let status = if key.is_revoked() {
+        " [revoked]"
+    } else if key.is_publishable() {
+        " [publishable]"
+    } else {
+        ""
+    };
+
+    format!(
+        "{}  {}...  {:<20} {}{}",
+        key.id, key.prefix, key.name, scopes, status
+    )
+}
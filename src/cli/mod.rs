@@ -0,0 +1,686 @@
+//! Command-line interface
+//!
+//! ```text
+//! moxie serve                          Run the HTTP API (default)
+//! moxie chat                           Interactive chat in the terminal
+//! moxie config validate [FILE]         Check a client config
+//! moxie config schema                  Print the JSON Schema for client configs
+//! moxie tools list                     List tools from the enabled plugins
+//! moxie personas list                  List built-in and file personas
+//! moxie conversations export           Export stored conversations
+//! moxie keys create|list|revoke         Manage API keys
+//! moxie audit verify [FILE]            Check the audit log's hash chain
+//! moxie plugin new NAME                Scaffold a plugin from the template
+//! ```
+//!
+//! All commands share the same configuration loading (`--config`, `--data-dir`
+//! and the environment) through [`Bootstrap`]. With `--clients-dir`, `serve`
+//! hosts every client config in the directory (see [`crate::tenants`]) and
+//! the other commands act on the one named by `--client`.
+
+mod chat;
+mod keys;
+mod scaffold;
+
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::Router;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use tokio_util::sync::CancellationToken;
+use tower_http::trace::TraceLayer;
+
+use crate::audit::{self, AuditError};
+use crate::auth;
+use crate::bootstrap::{Bootstrap, CLIENTS_DIR_ENV, CONFIG_ENV, DATA_DIR_ENV, DEFAULT_DATA_DIR};
+use crate::config::{prompts_builtin, schema, ConfigError, ConfigValidator, PromptManager};
+use crate::conversation::{Message, Role};
+use crate::core::{MemoryStore, Owner};
+use crate::plugins;
+use crate::routes;
+use crate::tenants::{self, TenantRegistry};
+use crate::AppState;
+
+pub use scaffold::PluginScaffold;
+
+/// Moxie - bold AI assistant platform
+#[derive(Debug, Parser)]
+#[command(name = "moxie", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options shared by every command
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Client configuration file (TOML)
+    #[arg(long, short, global = true, env = CONFIG_ENV)]
+    pub config: Option<PathBuf>,
+
+    /// Directory for the database and plugin data
+    #[arg(long, global = true, env = DATA_DIR_ENV)]
+    pub data_dir: Option<PathBuf>,
+
+    /// Directory of client configs (*.toml) to host together
+    #[arg(long, global = true, env = CLIENTS_DIR_ENV, conflicts_with = "config")]
+    pub clients_dir: Option<PathBuf>,
+
+    /// Client of --clients-dir to act on (all of them for serve when omitted)
+    #[arg(long, global = true, requires = "clients_dir")]
+    pub client: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP API server
+    Serve(ServeArgs),
+
+    /// Chat with Moxie in the terminal
+    Chat(chat::ChatArgs),
+
+    /// Client configuration tools
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Inspect the tools offered by enabled plugins
+    #[command(subcommand)]
+    Tools(ToolsCommand),
+
+    /// Inspect available personas
+    #[command(subcommand)]
+    Personas(PersonasCommand),
+
+    /// Manage stored conversations
+    #[command(subcommand)]
+    Conversations(ConversationsCommand),
+
+    /// Manage API keys
+    #[command(subcommand)]
+    Keys(keys::KeysCommand),
+
+    /// Tool call audit log
+    #[command(subcommand)]
+    Audit(AuditCommand),
+
+    /// Plugin development helpers
+    #[command(subcommand)]
+    Plugin(PluginCommand),
+}
+
+/// Default for `serve --shutdown-timeout`
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+
+/// How long connections left open after draining (idle keep-alives) may stay
+const CONNECTION_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to bind (overrides HOST)
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Port to listen on (overrides PORT)
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Seconds to wait for running replies on shutdown before cancelling them
+    #[arg(long, env = "MOXIE_SHUTDOWN_TIMEOUT", default_value_t = DEFAULT_SHUTDOWN_TIMEOUT)]
+    pub shutdown_timeout: u64,
+
+    /// Client serving requests that name no client (with --clients-dir)
+    #[arg(long, env = "MOXIE_DEFAULT_CLIENT")]
+    pub default_client: Option<String>,
+}
+
+impl Default for ServeArgs {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            default_client: None,
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate a client config and report every problem found
+    Validate {
+        /// Config file (defaults to --config)
+        file: Option<PathBuf>,
+    },
+
+    /// Print the JSON Schema for client configs (for editor autocompletion)
+    Schema {
+        /// Write to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditCommand {
+    /// Check that no audit log entry was changed, removed or reordered
+    Verify {
+        /// Audit log (defaults to the configured one)
+        file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ToolsCommand {
+    /// List tools from the enabled plugins
+    List {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PersonasCommand {
+    /// List built-in personas and those in the prompts directory
+    List {
+        /// Directory containing persona TOML files
+        #[arg(long, default_value = "configs/prompts")]
+        prompts_dir: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConversationsCommand {
+    /// Export conversations from the memory store
+    Export {
+        /// Only export this conversation
+        #[arg(long)]
+        id: Option<String>,
+
+        /// Only export the conversations of this user
+        #[arg(long)]
+        user: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Write to a file instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Markdown,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PluginCommand {
+    /// Create a new plugin from plugins/template/mod.rs.template
+    New(scaffold::NewPluginArgs),
+}
+
+impl Cli {
+    /// Log filter used when RUST_LOG is not set
+    ///
+    /// The server logs verbosely; interactive commands only show warnings so
+    /// their output stays readable.
+    pub fn default_log_filter(&self) -> &'static str {
+        match self.command {
+            None | Some(Command::Serve(_)) => "moxie_ai=debug,tower_http=debug",
+            Some(_) => "moxie_ai=warn",
+        }
+    }
+
+    /// Run the selected command (`serve` when none is given)
+    pub async fn run(self) -> anyhow::Result<()> {
+        let global = self.global;
+        let command = self.command.unwrap_or(Command::Serve(ServeArgs::default()));
+
+        match command {
+            Command::Serve(args) => serve(&global, args).await,
+            Command::Chat(args) => chat::run(&load(&global)?, args).await,
+            Command::Config(ConfigCommand::Validate { file }) => {
+                let file = file
+                    .or(global.config)
+                    .context("No config file given (pass a FILE or --config)")?;
+                validate_config(&file)
+            }
+            Command::Config(ConfigCommand::Schema { output }) => {
+                let schema = schema::client_config_schema(&plugins::builtin_manifests());
+                write_output(output.as_deref(), &serde_json::to_string_pretty(&schema)?)
+            }
+            Command::Tools(ToolsCommand::List { json }) => list_tools(&load(&global)?, json).await,
+            Command::Personas(PersonasCommand::List { prompts_dir }) => {
+                list_personas(&prompts_dir).await
+            }
+            Command::Conversations(ConversationsCommand::Export {
+                id,
+                user,
+                format,
+                output,
+            }) => {
+                let bootstrap = load(&global)?;
+                let memory = bootstrap.memory_store().await?;
+                let owner = user.as_deref().map_or(Owner::Any, Owner::User);
+                let exported = export_conversations(&memory, id.as_deref(), owner, format).await?;
+                write_output(output.as_deref(), &exported)
+            }
+            Command::Keys(command) => keys::run(&load(&global)?, command).await,
+            Command::Audit(AuditCommand::Verify { file }) => {
+                let bootstrap = load(&global)?;
+                let file = match file {
+                    Some(file) => file,
+                    None => bootstrap.audit_log_path().context(
+                        "No audit log configured (pass a FILE or set security.audit_log_path)",
+                    )?,
+                };
+                verify_audit_log(&file, bootstrap.audit_secret()?.as_deref())
+            }
+            Command::Plugin(PluginCommand::New(args)) => {
+                let path = PluginScaffold::from_args(&args).write(&args.output)?;
+                println!("Created {}", path.display());
+                Ok(())
+            }
+        }
+    }
+}
+
+fn load(global: &GlobalArgs) -> anyhow::Result<Bootstrap> {
+    match (&global.clients_dir, &global.client) {
+        (Some(dir), Some(id)) => tenants::load_tenant(dir, &data_dir(global), id),
+        (Some(_), None) => anyhow::bail!("Choose a client of --clients-dir with --client"),
+        _ => Bootstrap::load(global.config.as_deref(), global.data_dir.clone()),
+    }
+}
+
+fn data_dir(global: &GlobalArgs) -> PathBuf {
+    global
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR))
+}
+
+async fn serve(global: &GlobalArgs, args: ServeArgs) -> anyhow::Result<()> {
+    if let (Some(dir), None) = (&global.clients_dir, &global.client) {
+        let registry = TenantRegistry::load(dir, &data_dir(global))
+            .await?
+            .with_default(args.default_client.clone())?;
+        let registry = Arc::new(registry);
+        let hosted = registry
+            .tenants()
+            .map(|tenant| (&tenant.bootstrap, tenant.state.clone()))
+            .collect();
+        return run_server(hosted, registry.clone().router(), args).await;
+    }
+
+    let bootstrap = load(global)?;
+    let state = bootstrap.app_state().await?;
+    let app = routes::router(state.clone())
+        .layer(routes::cors_layer(&state.security.cors_allowed_origins));
+    run_server(vec![(&bootstrap, state)], app, args).await
+}
+
+/// Serve `app` until shut down, then wind down the hosted clients
+///
+/// The address comes from the first client's server config; all clients
+/// share the environment, so it is the same for each.
+async fn run_server(
+    hosted: Vec<(&Bootstrap, AppState)>,
+    app: Router,
+    args: ServeArgs,
+) -> anyhow::Result<()> {
+    let config = &hosted.first().context("No client to serve")?.0.config;
+    let host = args.host.unwrap_or_else(|| config.host.clone());
+    let port = args.port.unwrap_or(config.port);
+    let addr: SocketAddr = format!("{}:{}", host, port).parse()?;
+
+    let engines: Vec<_> = hosted.iter().map(|(_, s)| s.chat_engine.clone()).collect();
+    let stop = CancellationToken::new();
+
+    // The process-wide counters would mix the clients' usage
+    let shared = hosted.len() > 1;
+    let mut telemetry = Vec::new();
+    for (bootstrap, state) in &hosted {
+        let memory = state.chat_engine.memory().clone();
+        if let Some(agent) = bootstrap.telemetry_agent(memory).await? {
+            let agent = agent.with_process_totals(!shared);
+            telemetry.push(tokio::spawn(agent.run(stop.clone())));
+        }
+    }
+
+    if hosted.iter().any(|(_, s)| !s.security.require_api_key) {
+        tracing::warn!("API keys are not required; anyone who can reach the server can use it");
+    }
+
+    // The default span, but without the key of WebSocket URLs
+    let app = app.layer(TraceLayer::new_for_http().make_span_with(
+        |request: &axum::extract::Request| {
+            tracing::debug_span!(
+                "request",
+                method = %request.method(),
+                uri = %auth::redact_uri(request.uri()),
+                version = ?request.version(),
+            )
+        },
+    ));
+
+    tracing::info!("🔥 Moxie API running at http://{}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(stop.clone().cancelled_owned());
+    let mut server = tokio::spawn(async move { server.await });
+
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        () = shutdown_signal() => {}
+    }
+
+    // Stop accepting connections, then let running replies finish
+    let deadline = Duration::from_secs(args.shutdown_timeout);
+    tracing::info!(
+        "Shutting down; waiting up to {}s for {} running reply(s)",
+        deadline.as_secs(),
+        engines.iter().map(|e| e.running()).sum::<usize>()
+    );
+    stop.cancel();
+    futures::future::join_all(engines.iter().map(|e| e.drain(deadline))).await;
+
+    match tokio::time::timeout(CONNECTION_GRACE, &mut server).await {
+        Ok(result) => result??,
+        Err(_) => {
+            tracing::warn!("Closing connections still open after shutdown");
+            server.abort();
+        }
+    }
+
+    // The agents send (or buffer) a last report
+    let reports = futures::future::join_all(telemetry);
+    if tokio::time::timeout(CONNECTION_GRACE, reports).await.is_err() {
+        tracing::warn!("Telemetry agent did not finish its last report");
+    }
+
+    for engine in &engines {
+        engine.plugins().write().await.shutdown_all().await?;
+        engine.memory().pool().close().await;
+    }
+    tracing::info!("Shutdown complete");
+
+    Ok(())
+}
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+fn validate_config(file: &Path) -> anyhow::Result<()> {
+    let validator = ConfigValidator::new(plugins::builtin_manifests());
+
+    match validator.validate_file(file) {
+        Ok(config) => {
+            println!(
+                "✅ {} is valid ({}, {} plugin(s) enabled)",
+                file.display(),
+                config.client.name,
+                config.plugins.enabled.len()
+            );
+            Ok(())
+        }
+        Err(ConfigError::Validation(issues)) => {
+            eprintln!("❌ {} has {} problem(s):", file.display(), issues.len());
+            for issue in &issues {
+                eprintln!("  - {}", issue);
+            }
+            std::process::exit(1);
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", file.display())),
+    }
+}
+
+fn verify_audit_log(file: &Path, secret: Option<&[u8]>) -> anyhow::Result<()> {
+    match audit::verify(file, secret) {
+        Ok(entries) => {
+            println!("✅ {}: {} entries, hash chain intact", file.display(), entries);
+            Ok(())
+        }
+        Err(e @ (AuditError::Tampered { .. } | AuditError::Parse { .. } | AuditError::Head(_))) => {
+            eprintln!("❌ {}: {}", file.display(), e);
+            std::process::exit(1);
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", file.display())),
+    }
+}
+
+async fn list_tools(bootstrap: &Bootstrap, json: bool) -> anyhow::Result<()> {
+    let loader = bootstrap.plugin_loader().await?;
+    let mut tools = loader.all_tools();
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&tools)?);
+        return Ok(());
+    }
+
+    for tool in &tools {
+        let confirm = if tool.requires_confirmation {
+            " (requires confirmation)"
+        } else {
+            ""
+        };
+        println!(
+            "{:<24} {:<20} {}{}",
+            tool.name,
+            tool.plugin_id.as_deref().unwrap_or("-"),
+            tool.description,
+            confirm
+        );
+    }
+
+    Ok(())
+}
+
+async fn list_personas(prompts_dir: &Path) -> anyhow::Result<()> {
+    println!("Built-in:");
+    for (name, description) in prompts_builtin::PERSONAS {
+        println!("  {:<20} {}", name, description);
+    }
+
+    let mut manager = PromptManager::new(prompts_dir);
+    let Ok(mut names) = manager.list_available().await else {
+        return Ok(());
+    };
+    names.sort();
+
+    if !names.is_empty() {
+        println!("\nFrom {}:", prompts_dir.display());
+    }
+    for name in names {
+        match manager.load(&name).await {
+            Ok(template) => println!("  {:<20} {}", name, template.persona.description),
+            Err(e) => println!("  {:<20} (invalid: {})", name, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Render the stored conversations of `owner` as JSON or Markdown
+pub async fn export_conversations(
+    memory: &MemoryStore,
+    id: Option<&str>,
+    owner: Owner<'_>,
+    format: ExportFormat,
+) -> anyhow::Result<String> {
+    let ids = match id {
+        Some(id) => vec![id.to_string()],
+        None => memory
+            .list_conversations(owner)
+            .await?
+            .into_iter()
+            .map(|c| c.id)
+            .collect(),
+    };
+
+    let mut conversations = Vec::with_capacity(ids.len());
+    for id in ids {
+        let messages = memory.get_conversation(&id, owner).await?;
+        conversations.push((id, messages));
+    }
+
+    Ok(match format {
+        ExportFormat::Json => {
+            let value: Vec<_> = conversations
+                .iter()
+                .map(|(id, messages)| serde_json::json!({ "id": id, "messages": messages }))
+                .collect();
+            serde_json::to_string_pretty(&value)?
+        }
+        ExportFormat::Markdown => conversations
+            .iter()
+            .map(|(id, messages)| conversation_markdown(id, messages))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    })
+}
+
+fn conversation_markdown(id: &str, messages: &[Message]) -> String {
+    let mut out = format!("## Conversation {}\n\n", id);
+    for message in messages {
+        let role = match message.role {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        out.push_str(&format!("**{}:** {}\n\n", role, message.content));
+    }
+    out
+}
+
+fn write_output(path: Option<&Path>, content: &str) -> anyhow::Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, content)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            writeln!(stdout, "{}", content)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_default_command_is_serve() {
+        let cli = Cli::try_parse_from(["moxie"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["moxie", "serve", "--port", "8080"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Serve(ServeArgs { port: Some(8080), .. }))));
+    }
+
+    #[test]
+    fn test_shutdown_timeout_arg() {
+        let cli = Cli::try_parse_from(["moxie", "serve", "--shutdown-timeout", "5"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve(ServeArgs { shutdown_timeout: 5, .. }))
+        ));
+
+        assert!(Cli::try_parse_from(["moxie", "serve", "--shutdown-timeout", "soon"]).is_err());
+        assert!(Cli::try_parse_from(["moxie", "serve", "--shutdown-timeout", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_global_config_after_subcommand() {
+        let cli = Cli::try_parse_from(["moxie", "tools", "list", "--config", "client.toml"]).unwrap();
+        assert_eq!(cli.global.config, Some(PathBuf::from("client.toml")));
+    }
+
+    #[test]
+    fn test_clients_dir_args() {
+        let cli = Cli::try_parse_from(["moxie", "keys", "list", "--clients-dir", "clients.d", "--client", "acme"]).unwrap();
+        assert_eq!(cli.global.client.as_deref(), Some("acme"));
+
+        // One config, or a directory of them
+        assert!(Cli::try_parse_from(["moxie", "--clients-dir", "clients.d", "--config", "client.toml"]).is_err());
+        assert!(Cli::try_parse_from(["moxie", "--client", "acme"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_export_conversations() {
+        let memory = MemoryStore::new_in_memory_async().await.unwrap();
+        memory
+            .save_message(
+                "conv1",
+                Owner::Anonymous,
+                &Message {
+                    role: Role::User,
+                    content: "Hello".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let json = export_conversations(&memory, None, Owner::Any, ExportFormat::Json).await.unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["id"], "conv1");
+        assert_eq!(value[0]["messages"][0]["content"], "Hello");
+
+        let markdown = export_conversations(&memory, Some("conv1"), Owner::Any, ExportFormat::Markdown)
+            .await
+            .unwrap();
+        assert!(markdown.contains("## Conversation conv1"));
+        assert!(markdown.contains("**User:** Hello"));
+    }
+}
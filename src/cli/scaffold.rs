@@ -0,0 +1,158 @@
+//! `moxie plugin new` - scaffold a plugin from the template
+//!
+//! The template is compiled into the binary, so scaffolding works outside a
+//! checkout of this repository.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Args;
+
+/// Plugin template with `{{...}}` placeholders
+const TEMPLATE: &str = include_str!("../../plugins/template/mod.rs.template");
+
+#[derive(Debug, Args)]
+pub struct NewPluginArgs {
+    /// Plugin name (e.g. "Inventory Tracker")
+    pub name: String,
+
+    /// Plugin ID in reverse domain notation (defaults to com.example.<name>)
+    #[arg(long)]
+    pub id: Option<String>,
+
+    /// One-line description of the plugin
+    #[arg(long, default_value = "A custom Moxie plugin")]
+    pub description: String,
+
+    /// Name of the example tool (defaults to <name>_example)
+    #[arg(long)]
+    pub tool: Option<String>,
+
+    /// Directory to create the plugin in
+    #[arg(long, short, default_value = "plugins")]
+    pub output: PathBuf,
+}
+
+/// Values substituted into the plugin template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginScaffold {
+    /// Display name, e.g. "Inventory Tracker"
+    pub name: String,
+    /// Type name prefix, e.g. "InventoryTracker"
+    pub type_name: String,
+    /// Module name, e.g. "inventory_tracker"
+    pub module: String,
+    pub id: String,
+    pub description: String,
+    pub tool_name: String,
+    pub tool_description: String,
+}
+
+impl PluginScaffold {
+    pub fn new(name: &str) -> Self {
+        let words: Vec<String> = name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let module = words.join("_");
+        let type_name = words
+            .iter()
+            .map(|w| {
+                let mut chars = w.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                    None => String::new(),
+                }
+            })
+            .collect();
+
+        Self {
+            name: name.trim().to_string(),
+            type_name,
+            id: format!("com.example.{}", module),
+            description: "A custom Moxie plugin".to_string(),
+            tool_name: format!("{}_example", module),
+            tool_description: "An example tool".to_string(),
+            module,
+        }
+    }
+
+    pub fn from_args(args: &NewPluginArgs) -> Self {
+        let mut scaffold = Self::new(&args.name);
+        if let Some(id) = &args.id {
+            scaffold.id = id.clone();
+        }
+        if let Some(tool) = &args.tool {
+            scaffold.tool_name = tool.clone();
+        }
+        scaffold.description = args.description.clone();
+        scaffold
+    }
+
+    /// Fill in the template
+    pub fn render(&self) -> String {
+        TEMPLATE
+            .replace("{{PLUGIN_NAME}}", &self.name)
+            .replace("{{PLUGIN_DESCRIPTION}}", &self.description)
+            .replace("{{PluginName}}", &self.type_name)
+            .replace("{{plugin_id}}", &self.id)
+            .replace("{{tool_name}}", &self.tool_name)
+            .replace("{{tool_description}}", &self.tool_description)
+    }
+
+    /// Write `<dir>/<module>/mod.rs`, refusing to overwrite an existing plugin
+    pub fn write(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        if self.module.is_empty() {
+            anyhow::bail!("Plugin name must contain letters or digits");
+        }
+
+        let plugin_dir = dir.join(&self.module);
+        let path = plugin_dir.join("mod.rs");
+        if path.exists() {
+            anyhow::bail!("{} already exists", path.display());
+        }
+
+        std::fs::create_dir_all(&plugin_dir)
+            .with_context(|| format!("Failed to create {}", plugin_dir.display()))?;
+        std::fs::write(&path, self.render())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let scaffold = PluginScaffold::new("Inventory tracker");
+        assert_eq!(scaffold.module, "inventory_tracker");
+        assert_eq!(scaffold.type_name, "InventoryTracker");
+        assert_eq!(scaffold.id, "com.example.inventory_tracker");
+        assert_eq!(scaffold.tool_name, "inventory_tracker_example");
+    }
+
+    #[test]
+    fn test_render_replaces_all_placeholders() {
+        let rendered = PluginScaffold::new("Weather").render();
+
+        assert!(!rendered.contains("{{"), "unreplaced placeholder in template");
+        assert!(rendered.contains("pub struct WeatherPlugin"));
+        assert!(rendered.contains("\"com.example.weather\""));
+    }
+
+    #[test]
+    fn test_write_refuses_overwrite() {
+        let dir = std::env::temp_dir().join(format!("moxie-scaffold-{}", uuid::Uuid::new_v4()));
+        let scaffold = PluginScaffold::new("Weather");
+
+        let path = scaffold.write(&dir).unwrap();
+        assert_eq!(path, dir.join("weather").join("mod.rs"));
+        assert!(scaffold.write(&dir).is_err());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
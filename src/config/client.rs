@@ -6,12 +6,15 @@
 //! - LLM provider configuration
 //! - Security settings
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use super::validation::ValidationIssue;
+
 /// Root client configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ClientConfig {
     /// Client information
     pub client: ClientInfo,
@@ -35,6 +38,10 @@ pub struct ClientConfig {
     /// Telemetry settings (for RMM dashboard)
     #[serde(default)]
     pub telemetry: TelemetryConfig,
+
+    /// Embeddable website chat widget
+    #[serde(default)]
+    pub widget: WidgetConfig,
 }
 
 impl ClientConfig {
@@ -46,6 +53,7 @@ impl ClientConfig {
     }
 
     /// Load configuration from a TOML string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(content: &str) -> Result<Self, ConfigError> {
         let config: ClientConfig = toml::from_str(content)?;
         Ok(config)
@@ -53,7 +61,7 @@ impl ClientConfig {
 }
 
 /// Client identification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ClientInfo {
     /// Client name
     pub name: String,
@@ -68,7 +76,7 @@ pub struct ClientInfo {
 }
 
 /// LLM provider configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct LlmConfig {
     /// Provider name: "ollama", "openai", "anthropic"
     #[serde(default = "default_provider")]
@@ -107,7 +115,7 @@ impl Default for LlmConfig {
 }
 
 /// Plugin configuration
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct PluginsConfig {
     /// List of enabled plugins
     #[serde(default)]
@@ -127,29 +135,74 @@ pub struct PluginsConfig {
 
     /// Custom plugin settings (key-value pairs)
     #[serde(default, flatten)]
+    #[schemars(with = "HashMap<String, serde_json::Value>")]
     pub custom: HashMap<String, toml::Value>,
 }
 
+impl PluginsConfig {
+    /// Get a plugin's configuration section (`[plugins.<key>]`) as JSON
+    ///
+    /// Works for both the typed sections and custom plugin sections, so the
+    /// result can be handed to a plugin's `on_init` as `PluginContext::config`.
+    pub fn section(&self, key: &str) -> Option<serde_json::Value> {
+        let value = match key {
+            "office" => serde_json::to_value(self.office.as_ref()?),
+            "filesystem" => serde_json::to_value(self.filesystem.as_ref()?),
+            "database" => serde_json::to_value(self.database.as_ref()?),
+            _ => serde_json::to_value(self.custom.get(key)?),
+        };
+        value.ok()
+    }
+}
+
 /// Office plugin configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OfficePluginConfig {
     #[serde(default = "default_true")]
     pub excel_enabled: bool,
 
+    /// Enable creating Word reports (needs `allow_write`)
     #[serde(default = "default_true")]
     pub word_enabled: bool,
 
+    /// Enable creating PowerPoint decks (needs `allow_write`)
     #[serde(default)]
     pub powerpoint_enabled: bool,
 
+    /// Unused; configure `[plugins.email]` instead
     #[serde(default)]
     pub outlook_enabled: bool,
+
+    /// Paths that documents can be read from and written to
+    #[serde(default)]
+    pub allowed_paths: Vec<PathBuf>,
+
+    /// Paths refused inside the allowed paths (e.g. "**/payroll/**")
+    #[serde(default)]
+    pub deny_patterns: Vec<String>,
+
+    /// Hidden files: "allow" or "deny"; "hide" is refused by validation, as
+    /// the plugin lists no directories
+    #[serde(default)]
+    pub hidden_files: HiddenFiles,
+
+    /// Whether to allow creating documents
+    #[serde(default)]
+    pub allow_write: bool,
+
+    /// Maximum number of rows read from a sheet
+    #[serde(default = "default_office_max_rows")]
+    pub max_rows: usize,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_office_max_rows() -> usize {
+    500
+}
+
 impl Default for OfficePluginConfig {
     fn default() -> Self {
         Self {
@@ -157,38 +210,149 @@ impl Default for OfficePluginConfig {
             word_enabled: true,
             powerpoint_enabled: false,
             outlook_enabled: false,
+            allowed_paths: vec![],
+            deny_patterns: vec![],
+            hidden_files: HiddenFiles::default(),
+            allow_write: false,
+            max_rows: default_office_max_rows(),
         }
     }
 }
 
+/// What happens to hidden files (names starting with `.`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HiddenFiles {
+    /// Treated like any other file
+    #[default]
+    Allow,
+    /// Left out of listings and searches, but readable by explicit path
+    Hide,
+    /// Not accessible at all
+    Deny,
+}
+
 /// Filesystem plugin configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FilesystemPluginConfig {
     /// Allowed file paths
     #[serde(default)]
     pub allowed_paths: Vec<PathBuf>,
 
-    /// Cloud storage providers to enable
+    /// Paths refused inside the allowed paths (e.g. "**/.env", "*.key")
+    #[serde(default)]
+    pub deny_patterns: Vec<String>,
+
+    /// Hidden files: "allow", "hide" (from listings) or "deny"
+    #[serde(default)]
+    pub hidden_files: HiddenFiles,
+
+    /// Cloud storage providers to enable ("s3")
     #[serde(default)]
     pub cloud_providers: Vec<String>,
 
+    /// S3-compatible storage settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3StorageConfig>,
+
     /// Whether write operations are allowed
     #[serde(default)]
     pub allow_write: bool,
+
+    /// Maximum file size to read (in bytes)
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+
+    /// Maximum number of files or lines returned by a search
+    #[serde(default = "default_max_search_results")]
+    pub max_search_results: usize,
+
+    /// How many directories deep a search may go
+    #[serde(default = "default_max_search_depth")]
+    pub max_search_depth: usize,
+}
+
+fn default_max_file_size() -> u64 {
+    10 * 1024 * 1024 // 10 MB
+}
+
+fn default_max_search_results() -> usize {
+    100
+}
+
+fn default_max_search_depth() -> usize {
+    10
 }
 
 impl Default for FilesystemPluginConfig {
     fn default() -> Self {
         Self {
             allowed_paths: vec![],
+            deny_patterns: vec![],
+            hidden_files: HiddenFiles::default(),
             cloud_providers: vec![],
+            s3: None,
             allow_write: false,
+            max_file_size: default_max_file_size(),
+            max_search_results: default_max_search_results(),
+            max_search_depth: default_max_search_depth(),
         }
     }
 }
 
+/// S3-compatible storage settings (`[plugins.filesystem.s3]`)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct S3StorageConfig {
+    /// Server URL, e.g. `http://localhost:9000` for MinIO (default: AWS S3)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+
+    /// Environment variable holding the access key ID
+    #[serde(default = "default_access_key_id_env")]
+    pub access_key_id_env: String,
+
+    /// Environment variable holding the secret access key
+    #[serde(default = "default_secret_access_key_env")]
+    pub secret_access_key_env: String,
+
+    /// Seconds to wait for a response
+    #[serde(default = "default_s3_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl Default for S3StorageConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            region: default_s3_region(),
+            access_key_id_env: default_access_key_id_env(),
+            secret_access_key_env: default_secret_access_key_env(),
+            timeout_secs: default_s3_timeout_secs(),
+        }
+    }
+}
+
+fn default_access_key_id_env() -> String {
+    "AWS_ACCESS_KEY_ID".to_string()
+}
+
+fn default_secret_access_key_env() -> String {
+    "AWS_SECRET_ACCESS_KEY".to_string()
+}
+
+fn default_s3_timeout_secs() -> u64 {
+    30
+}
+
 /// Database plugin configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DatabasePluginConfig {
     /// Database connections
     #[serde(default)]
@@ -197,6 +361,22 @@ pub struct DatabasePluginConfig {
     /// Allowed operations: "read", "write"
     #[serde(default)]
     pub allowed_operations: Vec<String>,
+
+    /// Maximum number of rows a query returns
+    #[serde(default = "default_max_rows")]
+    pub max_rows: usize,
+
+    /// Seconds a statement may run before it is cancelled
+    #[serde(default = "default_statement_timeout")]
+    pub statement_timeout_secs: u64,
+}
+
+fn default_max_rows() -> usize {
+    100
+}
+
+fn default_statement_timeout() -> u64 {
+    30
 }
 
 impl Default for DatabasePluginConfig {
@@ -204,17 +384,19 @@ impl Default for DatabasePluginConfig {
         Self {
             connections: vec![],
             allowed_operations: vec!["read".to_string()],
+            max_rows: default_max_rows(),
+            statement_timeout_secs: default_statement_timeout(),
         }
     }
 }
 
 /// Database connection configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct DatabaseConnection {
     /// Connection name
     pub name: String,
 
-    /// Database type: "sqlite", "postgres", "mysql", "sqlserver"
+    /// Database type: "sqlite" or "postgres"
     #[serde(rename = "type")]
     pub db_type: String,
 
@@ -223,7 +405,7 @@ pub struct DatabaseConnection {
 }
 
 /// Knowledge base configuration
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct KnowledgeConfig {
     /// Whether the knowledge base is enabled
     #[serde(default)]
@@ -235,7 +417,7 @@ pub struct KnowledgeConfig {
 }
 
 /// Knowledge source configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct KnowledgeSource {
     /// Path to the source
     pub path: PathBuf,
@@ -250,27 +432,155 @@ pub struct KnowledgeSource {
 }
 
 /// Security settings
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SecurityConfig {
+    /// Require an API key on every route except `/health`
+    #[serde(default = "default_true")]
+    pub require_api_key: bool,
+
+    /// Origins allowed to call the API from a browser ("*" for any)
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
+
     /// Tools that require user confirmation before execution
     #[serde(default)]
     pub require_confirmation_for: Vec<String>,
 
-    /// Path for audit logging
+    /// Hash-chained JSON Lines audit log of tool calls (setting it enables
+    /// the log; defaults to `audit.jsonl` in the data directory)
     #[serde(default)]
     pub audit_log_path: Option<PathBuf>,
 
-    /// Whether to log all tool calls
+    /// Environment variable holding the secret that keys the audit log's
+    /// hash chain; unset leaves it unkeyed, so edits can be hidden by
+    /// recomputing the chain
+    #[serde(default)]
+    pub audit_secret_env: Option<String>,
+
+    /// Audit all tool calls, and also write them to the server log
     #[serde(default)]
     pub log_tool_calls: bool,
 
+    /// Environment variable holding the secret that signs user tokens
+    /// (`X-Moxie-User-Token`); unset disables user tokens
+    #[serde(default)]
+    pub user_token_secret_env: Option<String>,
+
     /// Maximum tokens per request (rate limiting)
     #[serde(default)]
     pub max_tokens_per_request: Option<u32>,
+
+    /// Request and tool execution rate limits
+    #[serde(default)]
+    pub rate_limits: RateLimitConfig,
+}
+
+impl Default for SecurityConfig {
+    fn default() -> Self {
+        Self {
+            require_api_key: true,
+            cors_allowed_origins: Vec::new(),
+            require_confirmation_for: Vec::new(),
+            audit_log_path: None,
+            audit_secret_env: None,
+            log_tool_calls: false,
+            user_token_secret_env: None,
+            max_tokens_per_request: None,
+            rate_limits: RateLimitConfig::default(),
+        }
+    }
+}
+
+/// Token-bucket rate limits for the chat endpoints
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimitConfig {
+    /// Whether rate limiting is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Use the first `X-Forwarded-For` address as the client IP
+    /// (only enable behind a reverse proxy that sets it)
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+
+    /// Chat requests per API key
+    #[serde(default = "RateLimit::default_per_key")]
+    pub per_key: RateLimit,
+
+    /// Chat requests per client IP
+    #[serde(default = "RateLimit::default_per_ip")]
+    pub per_ip: RateLimit,
+
+    /// Chat requests per user (requests naming a user)
+    #[serde(default = "RateLimit::default_per_user")]
+    pub per_user: RateLimit,
+
+    /// Messages per conversation
+    #[serde(default = "RateLimit::default_per_conversation")]
+    pub per_conversation: RateLimit,
+
+    /// Tool executions per API key (or IP without a key)
+    #[serde(default = "RateLimit::default_tool_executions")]
+    pub tool_executions: RateLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trust_forwarded_for: false,
+            per_key: RateLimit::default_per_key(),
+            per_ip: RateLimit::default_per_ip(),
+            per_user: RateLimit::default_per_user(),
+            per_conversation: RateLimit::default_per_conversation(),
+            tool_executions: RateLimit::default_tool_executions(),
+        }
+    }
+}
+
+/// A single token bucket: `burst` requests at once, refilled at
+/// `requests_per_minute` (0 disables the limit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RateLimit {
+    pub requests_per_minute: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        Self {
+            requests_per_minute,
+            burst,
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.requests_per_minute == 0
+    }
+
+    fn default_per_key() -> Self {
+        Self::new(60, 10)
+    }
+
+    fn default_per_ip() -> Self {
+        Self::new(30, 10)
+    }
+
+    fn default_per_user() -> Self {
+        Self::new(20, 5)
+    }
+
+    fn default_per_conversation() -> Self {
+        Self::new(20, 5)
+    }
+
+    fn default_tool_executions() -> Self {
+        Self::new(60, 20)
+    }
 }
 
 /// Telemetry configuration for RMM dashboard
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TelemetryConfig {
     /// Whether telemetry is enabled
     #[serde(default)]
@@ -299,6 +609,14 @@ pub struct TelemetryConfig {
     /// NEVER send actual conversation content
     #[serde(default)]
     pub send_conversations: bool,
+
+    /// Seconds between reports
+    #[serde(default = "default_telemetry_interval")]
+    pub interval_secs: u64,
+}
+
+fn default_telemetry_interval() -> u64 {
+    300
 }
 
 impl Default for TelemetryConfig {
@@ -311,6 +629,65 @@ impl Default for TelemetryConfig {
             send_usage: true,
             send_errors: true,
             send_conversations: false, // Always false by default
+            interval_secs: default_telemetry_interval(),
+        }
+    }
+}
+
+/// Website chat widget served at `/widget.js`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WidgetConfig {
+    /// Whether `/widget.js` and the widget config endpoint are served
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Header title (defaults to the client name)
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// First message shown in a new conversation
+    #[serde(default)]
+    pub greeting: Option<String>,
+
+    /// Input placeholder text
+    #[serde(default = "default_widget_placeholder")]
+    pub placeholder: String,
+
+    /// Accent color, e.g. "#4f46e5"
+    #[serde(default = "default_widget_color")]
+    pub primary_color: String,
+
+    /// Corner of the page: "right" or "left"
+    #[serde(default = "default_widget_position")]
+    pub position: String,
+
+    /// Persona the widget chats as
+    #[serde(default)]
+    pub persona: Option<String>,
+}
+
+fn default_widget_placeholder() -> String {
+    "Type a message...".to_string()
+}
+
+fn default_widget_color() -> String {
+    "#4f46e5".to_string()
+}
+
+fn default_widget_position() -> String {
+    "right".to_string()
+}
+
+impl Default for WidgetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            title: None,
+            greeting: None,
+            placeholder: default_widget_placeholder(),
+            primary_color: default_widget_color(),
+            position: default_widget_position(),
+            persona: None,
         }
     }
 }
@@ -324,8 +701,16 @@ pub enum ConfigError {
     #[error("TOML parse error: {0}")]
     Toml(#[from] toml::de::Error),
 
-    #[error("Validation error: {0}")]
-    Validation(String),
+    #[error("Validation failed:\n{}", format_issues(.0))]
+    Validation(Vec<ValidationIssue>),
+}
+
+fn format_issues(issues: &[ValidationIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| format!("  - {}", issue))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[cfg(test)]
@@ -347,6 +732,13 @@ enabled = ["filesystem", "database"]
 [plugins.filesystem]
 allowed_paths = ["C:\\Data", "D:\\Reports"]
 allow_write = false
+max_search_results = 50
+deny_patterns = ["**/.env"]
+hidden_files = "hide"
+cloud_providers = ["s3"]
+
+[plugins.filesystem.s3]
+endpoint = "http://localhost:9000"
 
 [plugins.database]
 allowed_operations = ["read"]
@@ -376,8 +768,18 @@ send_conversations = false
         assert_eq!(config.llm.provider, "ollama");
         assert_eq!(config.plugins.enabled, vec!["filesystem", "database"]);
 
+        let section = config.plugins.section("filesystem").unwrap();
+        assert_eq!(section["max_search_results"], 50);
+        assert_eq!(section["s3"]["endpoint"], "http://localhost:9000");
+        assert_eq!(section["s3"]["region"], "us-east-1");
+        assert_eq!(section["deny_patterns"][0], "**/.env");
+        assert_eq!(section["hidden_files"], "hide");
         let fs_config = config.plugins.filesystem.unwrap();
         assert!(!fs_config.allow_write);
+        assert_eq!(fs_config.hidden_files, HiddenFiles::Hide);
+
+        let invalid = SAMPLE_CONFIG.replace(r#"hidden_files = "hide""#, r#"hidden_files = "skip""#);
+        assert!(ClientConfig::from_str(&invalid).is_err());
 
         let db_config = config.plugins.database.unwrap();
         assert_eq!(db_config.connections.len(), 1);
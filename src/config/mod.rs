@@ -2,13 +2,16 @@
 
 pub mod client;
 pub mod prompts;
+pub mod schema;
+pub mod validation;
 
 use std::env;
 
 use serde::{Deserialize, Serialize};
 
-pub use client::ClientConfig;
+pub use client::{ClientConfig, ConfigError};
 pub use prompts::{PromptManager, PromptTemplate, builtin as prompts_builtin};
+pub use validation::{ConfigValidator, ValidationIssue};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -139,7 +139,7 @@ impl PromptManager {
             .map_err(|e| PromptError::IoError(e.to_string()))?
         {
             let path = entry.path();
-            if path.extension().map_or(false, |ext| ext == "toml") {
+            if path.extension().is_some_and(|ext| ext == "toml") {
                 if let Some(stem) = path.file_stem() {
                     prompts.push(stem.to_string_lossy().to_string());
                 }
@@ -175,6 +175,14 @@ pub enum PromptError {
 
 /// Built-in prompts that don't require files
 pub mod builtin {
+    /// Built-in persona names with a short description
+    pub const PERSONAS: &[(&str, &str)] = &[
+        ("default", "General-purpose assistant"),
+        ("business_analyst", "Analyzes business data and suggests next steps"),
+        ("tech_support", "Troubleshoots technical issues step by step"),
+        ("data_entry", "Inputs and manages data, confirming before changes"),
+    ];
+
     /// Default general-purpose assistant prompt
     pub const DEFAULT: &str = "You are Moxie, a helpful AI assistant. You can use tools to help answer questions and complete tasks. Be concise and helpful in your responses.";
 
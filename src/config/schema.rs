@@ -0,0 +1,166 @@
+//! JSON Schema export for client configurations
+//!
+//! Editors with TOML language support (e.g. Taplo / Even Better TOML) can use
+//! a JSON Schema for autocompletion and inline validation. Reference the
+//! exported file from the top of a client config:
+//!
+//! ```toml
+//! #:schema ./moxie.schema.json
+//! [client]
+//! name = "ACME Corporation"
+//! ```
+//!
+//! The core sections come from the `ClientConfig` types; each plugin section
+//! is generated from the plugin's `config_schema`.
+
+use schemars::schema_for;
+use serde_json::{json, Map, Value};
+
+use crate::plugins::{ConfigField, ConfigFieldType, PluginManifest};
+
+use super::client::ClientConfig;
+
+/// JSON Schema for a whole client configuration, including plugin sections
+pub fn client_config_schema(manifests: &[PluginManifest]) -> Value {
+    let mut schema = serde_json::to_value(schema_for!(ClientConfig)).unwrap_or_default();
+    schema["title"] = json!("Moxie client configuration");
+
+    if let Some(plugins) = schema
+        .pointer_mut("/definitions/PluginsConfig/properties")
+        .and_then(Value::as_object_mut)
+    {
+        let mut names: Vec<Value> = Vec::new();
+
+        for manifest in manifests {
+            let key = manifest.config_key().to_string();
+            let plugin = plugin_config_schema(manifest);
+
+            // Typed sections (e.g. `filesystem`) keep their own schema as well
+            let merged = match plugins.remove(&key) {
+                Some(existing) => json!({ "allOf": [existing, plugin] }),
+                None => plugin,
+            };
+            plugins.insert(key.clone(), merged);
+
+            names.push(json!(manifest.id));
+            names.push(json!(key));
+        }
+
+        if let Some(enabled) = plugins.get_mut("enabled") {
+            enabled["items"] = json!({ "type": "string", "enum": names });
+        }
+    }
+
+    schema
+}
+
+/// JSON Schema for one plugin's configuration section
+pub fn plugin_config_schema(manifest: &PluginManifest) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+
+    for field in &manifest.config_schema {
+        properties.insert(field.name.clone(), field_schema(field));
+        if field.required {
+            required.push(json!(field.name));
+        }
+    }
+
+    let mut schema = json!({
+        "type": "object",
+        "title": manifest.name,
+        "description": manifest.description,
+        "properties": properties,
+    });
+
+    if !required.is_empty() {
+        schema["required"] = Value::Array(required);
+    }
+
+    schema
+}
+
+/// JSON Schema for a single config field
+fn field_schema(field: &ConfigField) -> Value {
+    let string_schema = || {
+        let mut schema = json!({ "type": "string" });
+        if let Some(pattern) = &field.validation {
+            schema["pattern"] = json!(format!("^(?:{})$", pattern));
+        }
+        schema
+    };
+
+    let mut schema = match &field.field_type {
+        ConfigFieldType::String | ConfigFieldType::Path => string_schema(),
+        ConfigFieldType::Secret => {
+            let mut schema = string_schema();
+            schema["writeOnly"] = json!(true);
+            schema
+        }
+        ConfigFieldType::Number => json!({ "type": "number" }),
+        ConfigFieldType::Boolean => json!({ "type": "boolean" }),
+        ConfigFieldType::StringArray | ConfigFieldType::PathArray => {
+            json!({ "type": "array", "items": string_schema() })
+        }
+        ConfigFieldType::Select(options) => json!({ "type": "string", "enum": options }),
+        ConfigFieldType::TableArray => json!({ "type": "array", "items": { "type": "object" } }),
+    };
+
+    if !field.label.is_empty() {
+        schema["title"] = json!(field.label);
+    }
+    if !field.description.is_empty() {
+        schema["description"] = json!(field.description);
+    }
+    if let Some(default) = &field.default {
+        schema["default"] = default.clone();
+    }
+
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::{builtin_manifests, ConfigFieldBuilder};
+
+    #[test]
+    fn test_plugin_schema() {
+        let manifest = PluginManifest::new("moxie.sample", "Sample", "A sample plugin")
+            .with_config_field(
+                ConfigFieldBuilder::new(
+                    "mode",
+                    ConfigFieldType::Select(vec!["a".into(), "b".into()]),
+                )
+                .label("Mode")
+                .required()
+                .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("limit", ConfigFieldType::Number)
+                    .default_value(json!(5))
+                    .build(),
+            );
+
+        let schema = plugin_config_schema(&manifest);
+
+        assert_eq!(schema["properties"]["mode"]["enum"], json!(["a", "b"]));
+        assert_eq!(schema["properties"]["mode"]["title"], "Mode");
+        assert_eq!(schema["properties"]["limit"]["default"], 5);
+        assert_eq!(schema["required"], json!(["mode"]));
+    }
+
+    #[test]
+    fn test_client_schema_includes_plugins() {
+        let schema = client_config_schema(&builtin_manifests());
+        let plugins = &schema["definitions"]["PluginsConfig"]["properties"];
+
+        assert!(schema["properties"]["client"].is_object());
+        assert!(plugins["api"]["properties"]["services"].is_object());
+        assert!(plugins["filesystem"]["allOf"].is_array());
+
+        let enabled = plugins["enabled"]["items"]["enum"].as_array().unwrap();
+        assert!(enabled.contains(&json!("moxie.filesystem")));
+        assert!(enabled.contains(&json!("filesystem")));
+    }
+}
@@ -0,0 +1,604 @@
+//! Client configuration validation
+//!
+//! `ClientConfig::from_file` only checks that a file parses. The validator goes
+//! further and reports every problem it can find in one pass, each with the
+//! TOML path it applies to:
+//!
+//! - Type errors in the core sections (`[llm]`, `[security]`, ...)
+//! - Unknown values for fields with a fixed set of options
+//! - Entries in `plugins.enabled` that don't match a registered plugin
+//! - Plugin sections checked against each plugin's `config_schema`
+//!   (types, required fields, `Select` options and validation patterns)
+//!
+//! Plugin defaults from the schema are applied to the returned configuration.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let validator = ConfigValidator::new(plugins::builtin_manifests());
+//! match validator.validate_file(Path::new("configs/example.toml")) {
+//!     Ok(config) => println!("{} is valid", config.client.name),
+//!     Err(e) => eprintln!("{}", e),
+//! }
+//! ```
+
+use serde_json::Value;
+use std::path::Path;
+
+use crate::plugins::PluginManifest;
+
+use super::client::{ClientConfig, ConfigError, HiddenFiles};
+
+/// LLM providers understood by `Provider::from_name`
+const KNOWN_PROVIDERS: &[&str] = &[
+    "ollama",
+    "openai",
+    "gpt",
+    "gpt4",
+    "groq",
+    "local",
+    "vllm",
+    "lmstudio",
+    "localai",
+    "anthropic",
+    "claude",
+];
+
+/// Database types accepted in `[[plugins.database.connections]]`
+const DATABASE_TYPES: &[&str] = &["sqlite", "postgres"];
+
+/// Operations accepted in `plugins.database.allowed_operations`
+const DATABASE_OPERATIONS: &[&str] = &["read", "write"];
+
+/// Source types accepted in `[[knowledge.sources]]`
+const KNOWLEDGE_SOURCE_TYPES: &[&str] = &["directory", "file", "url"];
+
+/// Widget positions
+const WIDGET_POSITIONS: &[&str] = &["right", "left"];
+
+/// A single validation problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// TOML path of the offending value (e.g. `plugins.filesystem.allow_write`)
+    pub path: String,
+
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl ValidationIssue {
+    pub fn new(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// Validates client configurations against the registered plugins
+pub struct ConfigValidator {
+    manifests: Vec<PluginManifest>,
+}
+
+impl ConfigValidator {
+    /// Create a validator that knows about the given plugins
+    pub fn new(manifests: Vec<PluginManifest>) -> Self {
+        Self { manifests }
+    }
+
+    /// Validate a configuration file
+    pub fn validate_file(&self, path: &Path) -> Result<ClientConfig, ConfigError> {
+        let content = std::fs::read_to_string(path)?;
+        self.validate_str(&content)
+    }
+
+    /// Validate a configuration from a TOML string
+    ///
+    /// Returns the parsed configuration with plugin defaults applied, or
+    /// `ConfigError::Validation` listing every problem found.
+    pub fn validate_str(&self, content: &str) -> Result<ClientConfig, ConfigError> {
+        let mut table: toml::Table = toml::from_str(content)?;
+        let mut issues = Vec::new();
+
+        self.validate_plugins(&mut table, &mut issues);
+
+        // Typed deserialization catches type errors in the core sections.
+        // serde_path_to_error gives us the path of the first failure.
+        let config = match ClientConfig::deserialize_with_path(toml::Value::Table(table)) {
+            Ok(config) => Some(config),
+            Err((path, message)) => {
+                // Plugin sections may already have reported this field
+                if !issues.iter().any(|issue| issue.path == path) {
+                    issues.push(ValidationIssue::new(path, message));
+                }
+                None
+            }
+        };
+
+        if let Some(config) = &config {
+            validate_core(config, &mut issues);
+        }
+
+        match config {
+            Some(config) if issues.is_empty() => Ok(config),
+            _ => Err(ConfigError::Validation(issues)),
+        }
+    }
+
+    /// Check `plugins.enabled` and each enabled plugin's section, applying defaults
+    fn validate_plugins(&self, table: &mut toml::Table, issues: &mut Vec<ValidationIssue>) {
+        let Some(toml::Value::Table(plugins)) = table.get_mut("plugins") else {
+            return;
+        };
+
+        let enabled: Vec<(usize, String)> = match plugins.get("enabled") {
+            Some(toml::Value::Array(items)) => items
+                .iter()
+                .enumerate()
+                .filter_map(|(i, item)| match item.as_str() {
+                    Some(name) => Some((i, name.to_string())),
+                    None => {
+                        issues.push(ValidationIssue::new(
+                            format!("plugins.enabled[{}]", i),
+                            "expected a plugin ID string",
+                        ));
+                        None
+                    }
+                })
+                .collect(),
+            // Type errors are reported by typed deserialization
+            _ => return,
+        };
+
+        let mut seen: Vec<&str> = Vec::new();
+
+        for (i, name) in &enabled {
+            let path = format!("plugins.enabled[{}]", i);
+
+            let Some(manifest) = self.manifests.iter().find(|m| m.matches(name)) else {
+                issues.push(ValidationIssue::new(
+                    path,
+                    format!(
+                        "unknown plugin '{}' (registered: {})",
+                        name,
+                        self.registered_ids().join(", ")
+                    ),
+                ));
+                continue;
+            };
+
+            if seen.contains(&manifest.id.as_str()) {
+                issues.push(ValidationIssue::new(
+                    path,
+                    format!("plugin '{}' is enabled more than once", manifest.id),
+                ));
+                continue;
+            }
+            seen.push(&manifest.id);
+
+            let key = manifest.config_key();
+            let section = match plugins.get(key) {
+                Some(value) => match serde_json::to_value(value) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        issues.push(ValidationIssue::new(
+                            format!("plugins.{}", key),
+                            e.to_string(),
+                        ));
+                        continue;
+                    }
+                },
+                None => Value::Null,
+            };
+
+            match manifest.validate_config(&section) {
+                Ok(resolved) => {
+                    if let Ok(toml::Value::Table(resolved)) = toml::Value::try_from(&resolved) {
+                        if !resolved.is_empty() {
+                            plugins.insert(key.to_string(), toml::Value::Table(resolved));
+                        }
+                    }
+                }
+                Err(errors) => {
+                    for error in errors {
+                        let path = if error.field == key {
+                            format!("plugins.{}", key)
+                        } else {
+                            format!("plugins.{}.{}", key, error.field)
+                        };
+                        issues.push(ValidationIssue::new(path, error.message));
+                    }
+                }
+            }
+        }
+    }
+
+    fn registered_ids(&self) -> Vec<&str> {
+        self.manifests.iter().map(|m| m.id.as_str()).collect()
+    }
+}
+
+impl ClientConfig {
+    /// Deserialize from a TOML value, reporting the path of the first error
+    fn deserialize_with_path(value: toml::Value) -> Result<Self, (String, String)> {
+        serde_path_to_error::deserialize(value).map_err(|e| {
+            let path = e.path().to_string();
+            let path = if path == "." { String::new() } else { path };
+            (path, e.into_inner().message().to_string())
+        })
+    }
+}
+
+/// Semantic checks on the typed core sections
+fn validate_core(config: &ClientConfig, issues: &mut Vec<ValidationIssue>) {
+    if config.client.name.trim().is_empty() {
+        issues.push(ValidationIssue::new("client.name", "must not be empty"));
+    }
+
+    check_one_of(
+        "llm.provider",
+        &config.llm.provider.to_lowercase(),
+        KNOWN_PROVIDERS,
+        issues,
+    );
+
+    if config.llm.model.trim().is_empty() {
+        issues.push(ValidationIssue::new("llm.model", "must not be empty"));
+    }
+
+    if let Some(endpoint) = &config.llm.endpoint {
+        check_url("llm.endpoint", endpoint, issues);
+    }
+
+    if let Some(database) = &config.plugins.database {
+        for (i, op) in database.allowed_operations.iter().enumerate() {
+            check_one_of(
+                &format!("plugins.database.allowed_operations[{}]", i),
+                op,
+                DATABASE_OPERATIONS,
+                issues,
+            );
+        }
+
+        for (i, connection) in database.connections.iter().enumerate() {
+            check_one_of(
+                &format!("plugins.database.connections[{}].type", i),
+                &connection.db_type,
+                DATABASE_TYPES,
+                issues,
+            );
+        }
+
+        if database.max_rows == 0 {
+            issues.push(ValidationIssue::new(
+                "plugins.database.max_rows",
+                "must be at least 1",
+            ));
+        }
+        if database.statement_timeout_secs == 0 {
+            issues.push(ValidationIssue::new(
+                "plugins.database.statement_timeout_secs",
+                "must be at least 1",
+            ));
+        }
+    }
+
+    // The office plugin lists no directories, so "hide" would hide nothing
+    if config
+        .plugins
+        .office
+        .as_ref()
+        .is_some_and(|office| office.hidden_files == HiddenFiles::Hide)
+    {
+        let path = "plugins.office.hidden_files";
+        // The plugin's schema reports it too when the plugin is enabled
+        if !issues.iter().any(|issue| issue.path == path) {
+            issues.push(ValidationIssue::new(
+                path,
+                "\"hide\" is not supported by the office plugin; use \"allow\" or \"deny\"",
+            ));
+        }
+    }
+
+    for (i, source) in config.knowledge.sources.iter().enumerate() {
+        check_one_of(
+            &format!("knowledge.sources[{}].type", i),
+            &source.source_type,
+            KNOWLEDGE_SOURCE_TYPES,
+            issues,
+        );
+    }
+
+    for (i, origin) in config.security.cors_allowed_origins.iter().enumerate() {
+        check_origin(&format!("security.cors_allowed_origins[{}]", i), origin, issues);
+    }
+
+    let limits = &config.security.rate_limits;
+    for (name, limit) in [
+        ("per_key", limits.per_key),
+        ("per_ip", limits.per_ip),
+        ("per_user", limits.per_user),
+        ("per_conversation", limits.per_conversation),
+        ("tool_executions", limits.tool_executions),
+    ] {
+        if !limit.is_disabled() && limit.burst == 0 {
+            issues.push(ValidationIssue::new(
+                format!("security.rate_limits.{}.burst", name),
+                "must be at least 1 (set requests_per_minute = 0 to disable the limit)",
+            ));
+        }
+    }
+
+    let color = config.widget.primary_color.strip_prefix('#').unwrap_or_default();
+    if !matches!(color.len(), 3 | 6) || !color.chars().all(|c| c.is_ascii_hexdigit()) {
+        issues.push(ValidationIssue::new(
+            "widget.primary_color",
+            format!(
+                "'{}' is not a hex color (expected e.g. \"#4f46e5\")",
+                config.widget.primary_color
+            ),
+        ));
+    }
+    check_one_of(
+        "widget.position",
+        &config.widget.position,
+        WIDGET_POSITIONS,
+        issues,
+    );
+
+    if config.telemetry.enabled {
+        match &config.telemetry.dashboard_url {
+            Some(url) => check_url("telemetry.dashboard_url", url, issues),
+            None => issues.push(ValidationIssue::new(
+                "telemetry.dashboard_url",
+                "required when telemetry is enabled",
+            )),
+        }
+        if config.telemetry.interval_secs < 10 {
+            issues.push(ValidationIssue::new(
+                "telemetry.interval_secs",
+                "must be at least 10 seconds",
+            ));
+        }
+    }
+}
+
+fn check_one_of(path: &str, value: &str, allowed: &[&str], issues: &mut Vec<ValidationIssue>) {
+    if !allowed.contains(&value) {
+        issues.push(ValidationIssue::new(
+            path,
+            format!(
+                "invalid value '{}', expected one of: {}",
+                value,
+                allowed.join(", ")
+            ),
+        ));
+    }
+}
+
+fn check_url(path: &str, value: &str, issues: &mut Vec<ValidationIssue>) {
+    if !(value.starts_with("http://") || value.starts_with("https://")) {
+        issues.push(ValidationIssue::new(
+            path,
+            format!("'{}' is not an http(s) URL", value),
+        ));
+    }
+}
+
+/// Origins are scheme + host (+ port), so no path and no trailing slash
+pub(crate) fn check_origin(path: &str, value: &str, issues: &mut Vec<ValidationIssue>) {
+    if value == "*" {
+        return;
+    }
+
+    let host = value
+        .strip_prefix("http://")
+        .or_else(|| value.strip_prefix("https://"));
+    match host {
+        Some(host) if !host.is_empty() && !host.contains('/') => {}
+        _ => issues.push(ValidationIssue::new(
+            path,
+            format!(
+                "'{}' is not an origin (expected e.g. https://example.com or \"*\")",
+                value
+            ),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::builtin_manifests;
+
+    fn validator() -> ConfigValidator {
+        ConfigValidator::new(builtin_manifests())
+    }
+
+    fn issues(content: &str) -> Vec<ValidationIssue> {
+        match validator().validate_str(content) {
+            Err(ConfigError::Validation(issues)) => issues,
+            other => panic!("expected validation error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn paths(issues: &[ValidationIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_valid_config_applies_defaults() {
+        let config = validator()
+            .validate_str(
+                r#"
+[client]
+name = "ACME"
+
+[plugins]
+enabled = ["filesystem"]
+
+[plugins.filesystem]
+allowed_paths = ["/srv/data"]
+"#,
+            )
+            .unwrap();
+
+        let section = config.plugins.section("filesystem").unwrap();
+        assert_eq!(section["allow_write"], false);
+        assert_eq!(section["max_file_size"], 10485760);
+    }
+
+    #[test]
+    fn test_example_configs_are_valid() {
+        for file in [
+            "configs/example.toml",
+            "configs/api_example.toml",
+            "configs/pos_example.toml",
+        ] {
+            let content = std::fs::read_to_string(file).unwrap();
+            // The API examples only contain the plugin section
+            let content = if content.contains("[client]") {
+                content
+            } else {
+                format!(
+                    "[client]\nname = \"Test\"\n\n[plugins]\nenabled = [\"moxie.api\"]\n\n{}",
+                    content
+                )
+            };
+            if let Err(e) = validator().validate_str(&content) {
+                panic!("{} should be valid: {}", file, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_enabled_plugin() {
+        let issues = issues(
+            r#"
+[client]
+name = "ACME"
+
+[plugins]
+enabled = ["filesystem", "teleporter"]
+
+[plugins.filesystem]
+allowed_paths = ["/srv"]
+"#,
+        );
+
+        assert_eq!(paths(&issues), vec!["plugins.enabled[1]"]);
+        assert!(issues[0].message.contains("moxie.filesystem"));
+    }
+
+    #[test]
+    fn test_reports_all_plugin_errors_with_paths() {
+        let issues = issues(
+            r#"
+[client]
+name = "ACME"
+
+[plugins]
+enabled = ["moxie.filesystem"]
+
+[plugins.filesystem]
+allow_write = "yes"
+max_file_size = "big"
+"#,
+        );
+
+        assert_eq!(
+            paths(&issues),
+            vec![
+                "plugins.filesystem.allowed_paths",
+                "plugins.filesystem.allow_write",
+                "plugins.filesystem.max_file_size",
+            ]
+        );
+        assert_eq!(issues[0].message, "missing required field");
+    }
+
+    #[test]
+    fn test_core_type_error_has_path() {
+        let issues = issues(
+            r#"
+[client]
+name = "ACME"
+
+[telemetry]
+enabled = "maybe"
+"#,
+        );
+
+        assert_eq!(paths(&issues), vec!["telemetry.enabled"]);
+    }
+
+    #[test]
+    fn test_semantic_checks() {
+        let issues = issues(
+            r#"
+[client]
+name = "ACME"
+
+[llm]
+provider = "skynet"
+
+[plugins.database]
+allowed_operations = ["read", "drop"]
+
+[plugins.office]
+hidden_files = "hide"
+
+[[plugins.database.connections]]
+name = "prod"
+type = "oracle"
+connection_string_env = "PROD_DB"
+
+[security]
+cors_allowed_origins = ["https://acme.example", "*", "https://acme.example/chat"]
+
+[security.rate_limits]
+per_ip = { requests_per_minute = 10, burst = 0 }
+
+[telemetry]
+enabled = true
+interval_secs = 0
+
+[widget]
+primary_color = "indigo"
+position = "top"
+"#,
+        );
+
+        assert_eq!(
+            paths(&issues),
+            vec![
+                "llm.provider",
+                "plugins.database.allowed_operations[1]",
+                "plugins.database.connections[0].type",
+                "plugins.office.hidden_files",
+                "security.cors_allowed_origins[2]",
+                "security.rate_limits.per_ip.burst",
+                "widget.primary_color",
+                "widget.position",
+                "telemetry.dashboard_url",
+                "telemetry.interval_secs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_is_toml_error() {
+        let result = validator().validate_str("[client\nname = ");
+        assert!(matches!(result, Err(ConfigError::Toml(_))));
+    }
+}
@@ -0,0 +1,226 @@
+//! Files produced by tools, offered to users as downloads
+//!
+//! When a tool result carries an [`Artifact`], the chat engine records it
+//! here with the conversation and owner it was made for. The server serves it
+//! from `/v2/artifacts/:id`, under the same ownership rules as conversations.
+
+use std::path::PathBuf;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use super::memory::Owner;
+use crate::plugins::Artifact;
+
+/// A downloadable file, as returned to clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactInfo {
+    pub id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size: u64,
+    /// Where to download it
+    pub url: String,
+}
+
+/// A recorded artifact and the file behind it
+#[derive(Debug, Clone)]
+pub struct StoredArtifact {
+    pub info: ArtifactInfo,
+    pub path: PathBuf,
+}
+
+/// Artifact store errors
+#[derive(Debug, thiserror::Error)]
+pub enum ArtifactError {
+    #[error("Artifact file {0} is missing: {1}")]
+    Missing(PathBuf, std::io::Error),
+
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+type ArtifactRow = (String, String, String, i64, String, Option<String>, Option<String>);
+
+/// SQLite-backed record of tool artifacts
+#[derive(Clone)]
+pub struct ArtifactStore {
+    pool: SqlitePool,
+}
+
+impl ArtifactStore {
+    /// Create the store on an existing pool (usually the memory store's)
+    pub async fn new(pool: SqlitePool) -> Result<Self, sqlx::Error> {
+        let store = Self { pool };
+        store.init_schema().await?;
+        Ok(store)
+    }
+
+    async fn init_schema(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS artifacts (
+                id TEXT PRIMARY KEY,
+                conversation_id TEXT NOT NULL,
+                user_id TEXT,
+                key_id TEXT,
+                name TEXT NOT NULL,
+                mime_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Databases created before anonymous conversations belonged to keys
+        let columns: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('artifacts')")
+                .fetch_all(&self.pool)
+                .await?;
+        if !columns.iter().any(|(name,)| name == "key_id") {
+            sqlx::query("ALTER TABLE artifacts ADD COLUMN key_id TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Record a file a tool wrote in a conversation of `owner`
+    pub async fn register(
+        &self,
+        artifact: &Artifact,
+        conversation_id: &str,
+        owner: Owner<'_>,
+    ) -> Result<ArtifactInfo, ArtifactError> {
+        let metadata = tokio::fs::metadata(&artifact.path)
+            .await
+            .map_err(|e| ArtifactError::Missing(artifact.path.clone(), e))?;
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let name = artifact
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| id.clone());
+
+        sqlx::query(
+            r#"
+            INSERT INTO artifacts
+                (id, conversation_id, user_id, key_id, name, mime_type, size, path, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&id)
+        .bind(conversation_id)
+        .bind(owner.user_id())
+        .bind(owner.key_id())
+        .bind(&name)
+        .bind(&artifact.mime_type)
+        .bind(metadata.len() as i64)
+        .bind(artifact.path.to_string_lossy().as_ref())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(ArtifactInfo {
+            url: download_url(&id),
+            id,
+            name,
+            mime_type: artifact.mime_type.clone(),
+            size: metadata.len(),
+        })
+    }
+
+    /// An artifact, if it exists and `owner` may see it
+    pub async fn get(
+        &self,
+        id: &str,
+        owner: Owner<'_>,
+    ) -> Result<Option<StoredArtifact>, sqlx::Error> {
+        let row: Option<ArtifactRow> = sqlx::query_as(
+            "SELECT id, name, mime_type, size, path, user_id, key_id FROM artifacts WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .filter(|(.., user_id, key_id)| owner.allows(user_id.as_deref(), key_id.as_deref()))
+            .map(|(id, name, mime_type, size, path, ..)| StoredArtifact {
+                info: ArtifactInfo {
+                    url: download_url(&id),
+                    id,
+                    name,
+                    mime_type,
+                    size: size as u64,
+                },
+                path: PathBuf::from(path),
+            }))
+    }
+}
+
+fn download_url(id: &str) -> String {
+    format!("/v2/artifacts/{}", id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::MemoryStore;
+
+    #[tokio::test]
+    async fn test_register_and_get() {
+        let memory = MemoryStore::new_in_memory_async().await.unwrap();
+        let store = ArtifactStore::new(memory.pool().clone()).await.unwrap();
+        let path =
+            std::env::temp_dir().join(format!("moxie-artifact-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "report").unwrap();
+        let artifact = Artifact {
+            path: path.clone(),
+            mime_type: "text/plain".to_string(),
+        };
+
+        let info = store
+            .register(&artifact, "c1", Owner::User("alice"))
+            .await
+            .unwrap();
+        assert_eq!(info.size, 6);
+        assert_eq!(info.url, format!("/v2/artifacts/{}", info.id));
+
+        let stored = store
+            .get(&info.id, Owner::User("alice"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.info, info);
+        assert_eq!(stored.path, path);
+        assert!(store
+            .get(&info.id, Owner::User("bob"))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(store
+            .get(&info.id, Owner::Anonymous)
+            .await
+            .unwrap()
+            .is_none());
+
+        let info = store
+            .register(&artifact, "c2", Owner::Key("key-a"))
+            .await
+            .unwrap();
+        assert!(store.get(&info.id, Owner::Key("key-a")).await.unwrap().is_some());
+        assert!(store.get(&info.id, Owner::Key("key-b")).await.unwrap().is_none());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            store.register(&artifact, "c1", Owner::Anonymous).await,
+            Err(ArtifactError::Missing(..))
+        ));
+    }
+}
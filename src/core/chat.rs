@@ -7,21 +7,47 @@
 //! 4. Executes tool calls and feeds results back to the LLM
 //! 5. Returns the final response
 //! 6. Saves the conversation to memory
+//!
+//! Callers that keep their own history (such as the OpenAI-compatible API)
+//! pass it in `ChatRequest::history`; nothing is loaded from or saved to
+//! memory for those requests.
+//!
+//! A run stops when its request's `cancel` token fires or
+//! [`ChatEngine::cancel`] is called for its conversation. The provider
+//! request or tool in progress is dropped, and the partial reply is stored
+//! with the status `cancelled`.
+//!
+//! On shutdown, [`ChatEngine::drain`] refuses new replies and waits for the
+//! running ones, cancelling those still running at the deadline.
 
+use futures::StreamExt;
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use std::sync::Arc;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
 
+use crate::audit::{self, AuditLog, AuditStatus, Confirmation, ToolAudit};
 use crate::config::{Config, prompts_builtin};
 use crate::conversation::{Message, Role};
-use crate::plugins::{PluginError, PluginRegistry, ToolDefinition, ToolResult};
-use crate::providers::{Provider, ProviderError};
+use crate::metrics::{self, ActiveConversation};
+use crate::plugins::{PluginError, SharedPluginLoader, ToolDefinition, ToolResult};
+use crate::providers::{ChatOptions, Provider, ProviderError};
+use crate::rate_limit::{KeyedLimiter, RateLimited};
 
-use super::memory::MemoryStore;
+use super::artifacts::{ArtifactInfo, ArtifactStore};
+use super::memory::{MemoryError, MemoryStore, Owner};
+use super::stream::{ChatEvent, ToolCallFilter, ToolConfirmer};
 
 /// Maximum number of tool call iterations to prevent infinite loops
 const MAX_TOOL_ITERATIONS: usize = 10;
 
+/// How long cancelled replies get to stop during [`ChatEngine::drain`]
+const CANCEL_GRACE: Duration = Duration::from_secs(5);
+
 /// A tool call requested by the LLM
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
@@ -57,6 +83,66 @@ pub struct ChatRequest {
     /// Model to use (provider-specific)
     #[serde(default = "default_model")]
     pub model: String,
+
+    /// Restrict tools to these plugins (IDs or config keys); `None` allows all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_plugins: Option<Vec<String>>,
+
+    /// Caller identity for the tool execution rate limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caller: Option<String>,
+
+    /// The user chatting; conversations of other users can't be continued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+
+    /// The API key used; without a user, conversations belong to the key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+
+    /// Conversation so far, supplied by the caller instead of memory
+    ///
+    /// When set, the request is stateless: memory is neither read nor
+    /// written. `message` may then be empty, e.g. when the history ends
+    /// with tool results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<Message>>,
+
+    /// Tools implemented by the caller
+    ///
+    /// The model is offered these next to the plugin tools; calls to them
+    /// end the request and are returned in `ChatResponse::pending_tool_calls`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub client_tools: Vec<ToolDefinition>,
+
+    /// Generation options passed to the provider
+    #[serde(skip)]
+    pub options: ChatOptions,
+
+    /// Stops the run when cancelled, e.g. when the client disconnects
+    #[serde(skip)]
+    pub cancel: CancellationToken,
+}
+
+impl Default for ChatRequest {
+    fn default() -> Self {
+        Self {
+            message: String::new(),
+            conversation_id: None,
+            system_prompt: None,
+            persona: None,
+            provider: default_provider(),
+            model: default_model(),
+            allowed_plugins: None,
+            caller: None,
+            user_id: None,
+            key_id: None,
+            history: None,
+            client_tools: Vec::new(),
+            options: ChatOptions::default(),
+            cancel: CancellationToken::new(),
+        }
+    }
 }
 
 fn default_provider() -> String {
@@ -79,6 +165,14 @@ pub struct ChatResponse {
     /// Tools that were called during this response
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tool_calls: Vec<ToolCallSummary>,
+
+    /// Client tool calls the caller must execute (see `ChatRequest::client_tools`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pending_tool_calls: Vec<ToolCall>,
+
+    /// Files tools produced during this response, ready to download
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<ArtifactInfo>,
 }
 
 /// Summary of a tool call for the response
@@ -100,23 +194,84 @@ pub enum ChatError {
     #[error("Memory error: {0}")]
     Memory(String),
 
+    #[error("Conversation '{0}' not found")]
+    ConversationNotFound(String),
+
     #[error("Max tool iterations exceeded")]
     MaxIterationsExceeded,
+
+    #[error("Cancelled")]
+    Cancelled,
+
+    #[error("The server is shutting down")]
+    ShuttingDown,
+
+    #[error(transparent)]
+    RateLimited(#[from] RateLimited),
+}
+
+impl From<MemoryError> for ChatError {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::NotFound(id) => ChatError::ConversationNotFound(id),
+            MemoryError::Database(e) => ChatError::Memory(e.to_string()),
+        }
+    }
 }
 
 /// The core chat engine
 pub struct ChatEngine {
     config: Config,
-    plugins: Arc<PluginRegistry>,
+    plugins: SharedPluginLoader,
     memory: Arc<MemoryStore>,
     system_prompt: String,
+    tool_limiter: Option<Arc<KeyedLimiter>>,
+    confirm_tools: Vec<String>,
+    audit_log: Option<Arc<AuditLog>>,
+    artifacts: Option<Arc<ArtifactStore>>,
+    log_tool_calls: bool,
+
+    /// Running replies by run ID
+    running: Mutex<HashMap<u64, Run>>,
+    next_run: AtomicU64,
+
+    /// Set by [`drain`](Self::drain); new replies are refused
+    draining: AtomicBool,
+    /// Notified when the last running reply ends
+    idle: Notify,
+}
+
+/// A reply being generated
+struct Run {
+    /// The conversation it can be cancelled through; stateless replies,
+    /// which don't prove they own the conversation they name, have none
+    conversation_id: Option<String>,
+    cancel: CancellationToken,
+}
+
+/// Unregisters a run from [`ChatEngine::running`] when it ends
+struct RunGuard<'a> {
+    running: &'a Mutex<HashMap<u64, Run>>,
+    idle: &'a Notify,
+    run: u64,
+    _active: ActiveConversation<'static>,
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap();
+        running.remove(&self.run);
+        if running.is_empty() {
+            self.idle.notify_waiters();
+        }
+    }
 }
 
 impl ChatEngine {
     /// Create a new chat engine
     pub fn new(
         config: Config,
-        plugins: Arc<PluginRegistry>,
+        plugins: SharedPluginLoader,
         memory: Arc<MemoryStore>,
     ) -> Self {
         Self {
@@ -124,6 +279,15 @@ impl ChatEngine {
             plugins,
             memory,
             system_prompt: default_system_prompt(),
+            tool_limiter: None,
+            confirm_tools: Vec::new(),
+            audit_log: None,
+            artifacts: None,
+            log_tool_calls: false,
+            running: Mutex::new(HashMap::new()),
+            next_run: AtomicU64::new(0),
+            draining: AtomicBool::new(false),
+            idle: Notify::new(),
         }
     }
 
@@ -133,6 +297,38 @@ impl ChatEngine {
         self
     }
 
+    /// Limit tool executions per caller (see `ChatRequest::caller`)
+    pub fn with_tool_limiter(mut self, limiter: Option<Arc<KeyedLimiter>>) -> Self {
+        self.tool_limiter = limiter;
+        self
+    }
+
+    /// Ask for confirmation before these tools run, in addition to tools
+    /// marked `requires_confirmation`; without a confirmer (plain HTTP
+    /// chat) such calls are refused instead of run
+    pub fn with_confirmation_required(mut self, tools: Vec<String>) -> Self {
+        self.confirm_tools = tools;
+        self
+    }
+
+    /// Append every tool call to this audit log
+    pub fn with_audit_log(mut self, audit_log: Option<Arc<AuditLog>>) -> Self {
+        self.audit_log = audit_log;
+        self
+    }
+
+    /// Record files that tools produce here, so users can download them
+    pub fn with_artifacts(mut self, artifacts: Option<Arc<ArtifactStore>>) -> Self {
+        self.artifacts = artifacts;
+        self
+    }
+
+    /// Also write each tool call to the server log
+    pub fn with_tool_call_logging(mut self, enabled: bool) -> Self {
+        self.log_tool_calls = enabled;
+        self
+    }
+
     /// Resolve a persona name to a system prompt
     /// Supports built-in personas and can be extended to load from files
     fn resolve_persona(&self, persona: &str) -> String {
@@ -150,19 +346,150 @@ impl ChatEngine {
         }
     }
 
+    /// Cancel the running replies in a conversation
+    ///
+    /// Returns false if no reply is running.
+    pub fn cancel(&self, conversation_id: &str) -> bool {
+        let running = self.running.lock().unwrap();
+        let mut cancelled = false;
+        for run in running
+            .values()
+            .filter(|run| run.conversation_id.as_deref() == Some(conversation_id))
+        {
+            run.cancel.cancel();
+            cancelled = true;
+        }
+        cancelled
+    }
+
+    /// Number of replies being generated
+    pub fn running(&self) -> usize {
+        self.running.lock().unwrap().len()
+    }
+
+    /// Refuse new replies and wait for the running ones to finish
+    ///
+    /// Replies still running after `deadline` are cancelled, which stores
+    /// their partial text. Returns how many were cancelled.
+    pub async fn drain(&self, deadline: Duration) -> usize {
+        self.draining.store(true, Ordering::SeqCst);
+        if tokio::time::timeout(deadline, self.wait_idle()).await.is_ok() {
+            return 0;
+        }
+
+        let cancelled: Vec<_> = self
+            .running
+            .lock()
+            .unwrap()
+            .values()
+            .map(|run| run.cancel.clone())
+            .collect();
+        for cancel in &cancelled {
+            cancel.cancel();
+        }
+        tracing::warn!("Cancelled {} reply(s) still running at shutdown", cancelled.len());
+
+        // Cancelled runs only need to store what they have
+        if tokio::time::timeout(CANCEL_GRACE, self.wait_idle()).await.is_err() {
+            tracing::error!("{} reply(s) did not stop after cancellation", self.running());
+        }
+        cancelled.len()
+    }
+
+    async fn wait_idle(&self) {
+        loop {
+            let idle = self.idle.notified();
+            if self.running.lock().unwrap().is_empty() {
+                return;
+            }
+            idle.await;
+        }
+    }
+
+    fn track(
+        &self,
+        conversation_id: Option<&str>,
+        cancel: &CancellationToken,
+    ) -> Result<RunGuard<'_>, ChatError> {
+        let mut running = self.running.lock().unwrap();
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(ChatError::ShuttingDown);
+        }
+        let run = self.next_run.fetch_add(1, Ordering::Relaxed);
+        running.insert(
+            run,
+            Run {
+                conversation_id: conversation_id.map(str::to_string),
+                cancel: cancel.clone(),
+            },
+        );
+        Ok(RunGuard {
+            running: &self.running,
+            idle: &self.idle,
+            run,
+            _active: metrics::global().conversation_started(),
+        })
+    }
+
     /// Process a chat request and return a response
     pub async fn chat(&self, request: ChatRequest) -> Result<ChatResponse, ChatError> {
+        self.run(request, None, None).await
+    }
+
+    /// Process a chat request, sending events as the reply is generated
+    ///
+    /// Returns the same response as [`chat`](Self::chat) once finished.
+    /// Events are dropped if the receiver goes away.
+    pub async fn chat_stream(
+        &self,
+        request: ChatRequest,
+        events: mpsc::Sender<ChatEvent>,
+    ) -> Result<ChatResponse, ChatError> {
+        self.run(request, Some(events), None).await
+    }
+
+    /// Process a chat request with events and user confirmation of tools
+    ///
+    /// Tools that require confirmation only run if `confirmer` approves.
+    /// The other entry points have no confirmer, so such tools fail there
+    /// without running.
+    pub async fn chat_interactive(
+        &self,
+        request: ChatRequest,
+        events: mpsc::Sender<ChatEvent>,
+        confirmer: &dyn ToolConfirmer,
+    ) -> Result<ChatResponse, ChatError> {
+        self.run(request, Some(events), Some(confirmer)).await
+    }
+
+    async fn run(
+        &self,
+        request: ChatRequest,
+        events: Option<mpsc::Sender<ChatEvent>>,
+        confirmer: Option<&dyn ToolConfirmer>,
+    ) -> Result<ChatResponse, ChatError> {
         // Get or create conversation ID
         let conversation_id = request
             .conversation_id
+            .clone()
             .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
-        // Load conversation history from memory
-        let history = self
-            .memory
-            .get_conversation(&conversation_id)
-            .await
-            .map_err(|e| ChatError::Memory(e.to_string()))?;
+        // Someone else's conversation can't be continued (or cancelled by
+        // starting a reply in it)
+        let stateless = request.history.is_some();
+        let owner = Owner::new(request.user_id.as_deref(), request.key_id.as_deref());
+        if !stateless {
+            self.memory.check_owner(&conversation_id, owner).await?;
+        }
+
+        let cancel = request.cancel.clone();
+        let _running = self.track((!stateless).then_some(conversation_id.as_str()), &cancel)?;
+
+        // Load conversation history from memory, unless the caller sent it
+        let history = match &request.history {
+            Some(history) => history.clone(),
+            None => self.memory.get_conversation(&conversation_id, owner).await?,
+        };
 
         // Build messages array
         let mut messages = Vec::new();
@@ -178,29 +505,39 @@ impl ChatEngine {
 
         messages.push(Message {
             role: Role::System,
-            content: self.build_system_prompt(&system_prompt),
+            content: self
+                .build_system_prompt(
+                    &system_prompt,
+                    request.allowed_plugins.as_deref(),
+                    &request.client_tools,
+                )
+                .await,
         });
 
         // Add conversation history
         messages.extend(history);
 
         // Add new user message
-        messages.push(Message {
-            role: Role::User,
-            content: request.message.clone(),
-        });
+        if !request.message.is_empty() {
+            messages.push(Message {
+                role: Role::User,
+                content: request.message.clone(),
+            });
 
-        // Save user message to memory
-        self.memory
-            .save_message(&conversation_id, &messages.last().unwrap())
-            .await
-            .map_err(|e| ChatError::Memory(e.to_string()))?;
+            // Save user message to memory
+            if !stateless {
+                self.memory
+                    .save_message(&conversation_id, owner, messages.last().unwrap())
+                    .await?;
+            }
+        }
 
         // Create provider
         let provider = Provider::from_name(&request.provider, &self.config)?;
 
         // Tool calling loop
         let mut tool_calls_made = Vec::new();
+        let mut artifacts = Vec::new();
         let mut iterations = 0;
 
         loop {
@@ -209,25 +546,153 @@ impl ChatEngine {
                 return Err(ChatError::MaxIterationsExceeded);
             }
 
-            // Get response from LLM
-            let response = provider.chat(&messages, &request.model).await?;
+            // Get response from LLM; a cancelled request is dropped
+            let response = match &events {
+                Some(events) => {
+                    self.stream_reply(&provider, &messages, &request, events)
+                        .await?
+                }
+                None => tokio::select! {
+                    response = provider.chat_with_options(&messages, &request.model, &request.options) => response?,
+                    _ = cancel.cancelled() => return self.cancelled(&conversation_id, owner, stateless, "").await,
+                },
+            };
+
+            if cancel.is_cancelled() {
+                return self
+                    .cancelled(&conversation_id, owner, stateless, &response.content)
+                    .await;
+            }
 
             // Check if the response contains tool calls
             if let Some(tool_calls) = self.extract_tool_calls(&response.content) {
+                // Client tools are executed by the caller. Plugin tools
+                // requested in the same reply are left for the next turn.
+                let pending: Vec<ToolCall> = tool_calls
+                    .iter()
+                    .filter(|call| request.client_tools.iter().any(|t| t.name == call.name))
+                    .cloned()
+                    .collect();
+
+                if !pending.is_empty() {
+                    if !stateless {
+                        self.memory
+                            .save_message(&conversation_id, owner, &response)
+                            .await?;
+                    }
+
+                    return Ok(ChatResponse {
+                        message: ToolCallFilter::strip(&response.content),
+                        conversation_id,
+                        tool_calls: tool_calls_made,
+                        pending_tool_calls: pending,
+                        artifacts,
+                    });
+                }
+
                 // Execute each tool call
                 for tool_call in tool_calls {
-                    let result = self.plugins.execute(&tool_call.name, tool_call.arguments.clone()).await;
+                    if let (Some(limiter), Some(caller)) = (&self.tool_limiter, &request.caller) {
+                        limiter.check(caller)?;
+                    }
+
+                    let needs_confirmation = self.needs_confirmation(&tool_call.name).await;
+                    let confirmation = match confirmer {
+                        Some(confirmer) if needs_confirmation => {
+                            let approved = tokio::select! {
+                                approved = confirmer.confirm(&tool_call) => approved,
+                                _ = cancel.cancelled() => false,
+                            };
+                            Some(if approved {
+                                Confirmation::Approved
+                            } else {
+                                Confirmation::Declined
+                            })
+                        }
+                        _ => None,
+                    };
+                    let declined = confirmation == Some(Confirmation::Declined);
+                    let unconfirmable = needs_confirmation && confirmer.is_none();
+
+                    send(
+                        &events,
+                        ChatEvent::ToolCall {
+                            name: tool_call.name.clone(),
+                            arguments: tool_call.arguments.clone(),
+                        },
+                    )
+                    .await;
+
+                    let started = Instant::now();
+                    let result = if declined {
+                        Ok(ToolResult::failure("The user declined to run this tool"))
+                    } else if unconfirmable {
+                        Ok(ToolResult::failure(format!(
+                            "{} requires confirmation; use an interactive transport",
+                            tool_call.name
+                        )))
+                    } else {
+                        self.execute_tool(&tool_call, request.allowed_plugins.as_deref(), &cancel)
+                            .await
+                    };
+                    let status = match &result {
+                        _ if declined => AuditStatus::Declined,
+                        Ok(result) if result.success => AuditStatus::Success,
+                        Ok(_) => AuditStatus::Failure,
+                        Err(PluginError::Cancelled(_)) => AuditStatus::Cancelled,
+                        Err(_) => AuditStatus::Error,
+                    };
+                    let error = match &result {
+                        Ok(result) => result.error.clone(),
+                        Err(e) => Some(e.to_string()),
+                    };
+                    self.audit(ToolAudit {
+                        caller: request.caller.clone(),
+                        conversation_id: conversation_id.clone(),
+                        persona: request.persona.clone(),
+                        tool: tool_call.name.clone(),
+                        plugin: self.plugin_for_tool(&tool_call.name).await,
+                        arguments: audit::redact(&tool_call.arguments),
+                        status,
+                        error,
+                        duration_ms: started.elapsed().as_millis() as u64,
+                        confirmation,
+                    })
+                    .await;
+
+                    if cancel.is_cancelled() {
+                        return self
+                            .cancelled(&conversation_id, owner, stateless, &response.content)
+                            .await;
+                    }
 
                     let tool_result = match result {
                         Ok(r) => r,
                         Err(e) => ToolResult::failure(e.to_string()),
                     };
 
+                    send(
+                        &events,
+                        ChatEvent::ToolResult {
+                            name: tool_call.name.clone(),
+                            success: tool_result.success,
+                        },
+                    )
+                    .await;
+
                     tool_calls_made.push(ToolCallSummary {
                         name: tool_call.name.clone(),
                         success: tool_result.success,
                     });
 
+                    for artifact in self
+                        .register_artifacts(&tool_result, &conversation_id, owner)
+                        .await
+                    {
+                        send(&events, ChatEvent::Artifact(artifact.clone())).await;
+                        artifacts.push(artifact);
+                    }
+
                     // Add tool call and result to messages
                     messages.push(Message {
                         role: Role::Assistant,
@@ -254,22 +719,166 @@ impl ChatEngine {
 
             // No tool calls - this is the final response
             // Save assistant message to memory
-            self.memory
-                .save_message(&conversation_id, &response)
-                .await
-                .map_err(|e| ChatError::Memory(e.to_string()))?;
+            if !stateless {
+                self.memory
+                    .save_message(&conversation_id, owner, &response)
+                    .await?;
+            }
 
             return Ok(ChatResponse {
                 message: response.content,
                 conversation_id,
                 tool_calls: tool_calls_made,
+                pending_tool_calls: Vec::new(),
+                artifacts,
             });
         }
     }
 
+    /// Store the partial reply of a cancelled run
+    async fn cancelled(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
+        stateless: bool,
+        partial: &str,
+    ) -> Result<ChatResponse, ChatError> {
+        if !stateless {
+            let message = Message {
+                role: Role::Assistant,
+                content: ToolCallFilter::strip(partial),
+            };
+            self.memory
+                .save_cancelled(conversation_id, owner, &message)
+                .await?;
+        }
+
+        Err(ChatError::Cancelled)
+    }
+
+    /// Stream one LLM reply as delta events, hiding tool call blocks
+    ///
+    /// On cancellation the stream is dropped and the text so far returned.
+    async fn stream_reply(
+        &self,
+        provider: &Provider,
+        messages: &[Message],
+        request: &ChatRequest,
+        events: &mpsc::Sender<ChatEvent>,
+    ) -> Result<Message, ChatError> {
+        let mut filter = ToolCallFilter::new();
+        let mut content = String::new();
+
+        let mut deltas = tokio::select! {
+            deltas = provider.chat_stream(messages, &request.model, &request.options) => deltas?,
+            _ = request.cancel.cancelled() => {
+                return Ok(Message {
+                    role: Role::Assistant,
+                    content,
+                })
+            }
+        };
+
+        while let Some(delta) = tokio::select! {
+            delta = deltas.next() => delta,
+            _ = request.cancel.cancelled() => None,
+        } {
+            let delta = delta?;
+            content.push_str(&delta);
+            send_delta(events, filter.push(&delta)).await;
+        }
+        send_delta(events, filter.finish()).await;
+
+        Ok(Message {
+            role: Role::Assistant,
+            content,
+        })
+    }
+
+    async fn needs_confirmation(&self, tool: &str) -> bool {
+        self.confirm_tools.iter().any(|t| t == tool)
+            || self.plugins.read().await.requires_confirmation(tool)
+    }
+
+    async fn plugin_for_tool(&self, tool: &str) -> Option<String> {
+        self.plugins
+            .read()
+            .await
+            .find_plugin_for_tool(tool)
+            .map(str::to_string)
+    }
+
+    /// Record the files a tool produced; without a store none are offered
+    async fn register_artifacts(
+        &self,
+        result: &ToolResult,
+        conversation_id: &str,
+        owner: Owner<'_>,
+    ) -> Vec<ArtifactInfo> {
+        let Some(store) = &self.artifacts else {
+            return Vec::new();
+        };
+
+        let mut registered = Vec::new();
+        for artifact in &result.artifacts {
+            match store.register(artifact, conversation_id, owner).await {
+                Ok(info) => registered.push(info),
+                Err(e) => tracing::error!("Failed to record artifact: {}", e),
+            }
+        }
+        registered
+    }
+
+    /// Record a tool call in the audit log and, if enabled, the server log
+    ///
+    /// A failed audit write is logged but does not fail the reply.
+    async fn audit(&self, call: ToolAudit) {
+        if self.log_tool_calls {
+            tracing::info!(
+                tool = %call.tool,
+                conversation_id = %call.conversation_id,
+                caller = call.caller.as_deref().unwrap_or("-"),
+                status = ?call.status,
+                duration_ms = call.duration_ms,
+                "Tool call"
+            );
+        }
+        if let Some(audit_log) = &self.audit_log {
+            if let Err(e) = audit_log.record(call).await {
+                tracing::error!("Failed to write audit log {}: {}", audit_log.path().display(), e);
+            }
+        }
+    }
+
+    /// Execute a tool call, refusing tools outside the allowed plugins
+    async fn execute_tool(
+        &self,
+        tool_call: &ToolCall,
+        allowed_plugins: Option<&[String]>,
+        cancel: &CancellationToken,
+    ) -> Result<ToolResult, PluginError> {
+        let loader = self.plugins.read().await;
+
+        if let Some(allowed) = allowed_plugins {
+            if !loader.tool_in(&tool_call.name, allowed) {
+                return Err(PluginError::ToolNotFound(tool_call.name.clone()));
+            }
+        }
+
+        loader
+            .execute_cancellable(&tool_call.name, tool_call.arguments.clone(), cancel)
+            .await
+    }
+
     /// Build the system prompt with tool information
-    fn build_system_prompt(&self, base_prompt: &str) -> String {
-        let tools = self.plugins.all_tools();
+    async fn build_system_prompt(
+        &self,
+        base_prompt: &str,
+        allowed_plugins: Option<&[String]>,
+        client_tools: &[ToolDefinition],
+    ) -> String {
+        let mut tools = self.tools_for(allowed_plugins).await;
+        tools.extend(client_tools.iter().cloned());
 
         if tools.is_empty() {
             return base_prompt.to_string();
@@ -323,8 +932,36 @@ impl ChatEngine {
     }
 
     /// Get all available tools
-    pub fn available_tools(&self) -> Vec<ToolDefinition> {
-        self.plugins.all_tools()
+    pub async fn available_tools(&self) -> Vec<ToolDefinition> {
+        self.plugins.read().await.all_tools()
+    }
+
+    /// Get the tools from the allowed plugins (`None` = all plugins)
+    pub async fn tools_for(&self, allowed_plugins: Option<&[String]>) -> Vec<ToolDefinition> {
+        match allowed_plugins {
+            Some(allowed) => self.plugins.read().await.tools_for(allowed),
+            None => self.available_tools().await,
+        }
+    }
+
+    /// Get the memory store backing this engine
+    pub fn memory(&self) -> &Arc<MemoryStore> {
+        &self.memory
+    }
+
+    /// Get the audit log tool calls are written to, if any
+    pub fn audit_log(&self) -> Option<&Arc<AuditLog>> {
+        self.audit_log.as_ref()
+    }
+
+    /// Get the store tool artifacts are recorded in, if any
+    pub fn artifacts(&self) -> Option<&Arc<ArtifactStore>> {
+        self.artifacts.as_ref()
+    }
+
+    /// Get the plugin loader backing this engine
+    pub fn plugins(&self) -> &SharedPluginLoader {
+        &self.plugins
     }
 }
 
@@ -332,6 +969,18 @@ fn default_system_prompt() -> String {
     prompts_builtin::DEFAULT.to_string()
 }
 
+async fn send(events: &Option<mpsc::Sender<ChatEvent>>, event: ChatEvent) {
+    if let Some(events) = events {
+        let _ = events.send(event).await;
+    }
+}
+
+async fn send_delta(events: &mpsc::Sender<ChatEvent>, content: String) {
+    if !content.is_empty() {
+        let _ = events.send(ChatEvent::Delta { content }).await;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
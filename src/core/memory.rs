@@ -1,6 +1,14 @@
 //! Conversation memory storage using SQLite
 //!
 //! Provides persistent storage for conversation history.
+//!
+//! Replies cut short by cancellation are stored with the status
+//! `cancelled`, so transcripts show where a reply was interrupted.
+//!
+//! Conversations belong to the user that started them, or without a user to
+//! the API key that did. Every read and write of a conversation names an
+//! [`Owner`]; a conversation of another owner is reported as not found, so
+//! conversation IDs can't be probed.
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -17,9 +25,95 @@ pub struct StoredMessage {
     pub conversation_id: String,
     pub role: String,
     pub content: String,
+
+    /// `complete`, or `cancelled` for a reply that was interrupted
+    pub status: String,
+
     pub created_at: DateTime<Utc>,
 }
 
+/// Status of a message that was stored in full
+pub const STATUS_COMPLETE: &str = "complete";
+
+/// Status of a partial reply whose generation was cancelled
+pub const STATUS_CANCELLED: &str = "cancelled";
+
+/// Summary of a stored conversation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub id: String,
+
+    /// The user the conversation belongs to, if any
+    pub user_id: Option<String>,
+
+    /// The API key that started it, when it has no user
+    pub key_id: Option<String>,
+
+    pub message_count: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Whose conversations an operation may touch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner<'a> {
+    /// Every conversation, for the CLI and administrative access
+    Any,
+    /// Conversations of callers with neither a user nor a key, which only
+    /// exist when `require_api_key` is off
+    Anonymous,
+    /// The conversations an API key started without naming a user
+    Key(&'a str),
+    /// The conversations of one user
+    User(&'a str),
+}
+
+impl<'a> Owner<'a> {
+    /// The owner for a caller's user ID and API key ID
+    ///
+    /// The user wins, so a user's conversations follow them across keys.
+    pub fn new(user_id: Option<&'a str>, key_id: Option<&'a str>) -> Self {
+        match (user_id, key_id) {
+            (Some(user_id), _) => Self::User(user_id),
+            (None, Some(key_id)) => Self::Key(key_id),
+            (None, None) => Self::Anonymous,
+        }
+    }
+
+    /// User ID given to conversations this owner creates
+    pub(crate) fn user_id(self) -> Option<&'a str> {
+        match self {
+            Self::User(user_id) => Some(user_id),
+            Self::Any | Self::Anonymous | Self::Key(_) => None,
+        }
+    }
+
+    /// Key ID given to conversations this owner creates
+    pub(crate) fn key_id(self) -> Option<&'a str> {
+        match self {
+            Self::Key(key_id) => Some(key_id),
+            Self::Any | Self::Anonymous | Self::User(_) => None,
+        }
+    }
+
+    /// Whether a conversation of `user_id` started with `key_id` is visible
+    /// to this owner
+    pub fn allows(self, user_id: Option<&str>, key_id: Option<&str>) -> bool {
+        self == Self::Any || (self.user_id(), self.key_id()) == (user_id, key_id)
+    }
+}
+
+/// Memory store errors
+#[derive(Debug, thiserror::Error)]
+pub enum MemoryError {
+    /// The conversation belongs to someone else
+    #[error("Conversation '{0}' not found")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
 impl From<StoredMessage> for Message {
     fn from(stored: StoredMessage) -> Self {
         let role = match stored.role.as_str() {
@@ -35,6 +129,43 @@ impl From<StoredMessage> for Message {
     }
 }
 
+type MessageRow = (i64, String, String, String, String, String);
+
+fn stored_message(
+    (id, conversation_id, role, content, status, created_at): MessageRow,
+) -> StoredMessage {
+    StoredMessage {
+        id,
+        conversation_id,
+        role,
+        content,
+        status,
+        created_at: timestamp(&created_at),
+    }
+}
+
+type ConversationRow = (String, Option<String>, Option<String>, i64, String, String);
+
+fn conversation_summary(
+    (id, user_id, key_id, message_count, created_at, updated_at): ConversationRow,
+) -> ConversationSummary {
+    ConversationSummary {
+        id,
+        user_id,
+        key_id,
+        message_count: message_count as u64,
+        created_at: timestamp(&created_at),
+        updated_at: timestamp(&updated_at),
+    }
+}
+
+/// Parse a SQLite `datetime('now')` value (UTC)
+fn timestamp(value: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(&format!("{}Z", value))
+        .map(|dt| dt.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
 /// Memory store for conversation persistence
 pub struct MemoryStore {
     pool: SqlitePool,
@@ -81,12 +212,30 @@ impl MemoryStore {
         Ok(store)
     }
 
+    /// Check that the database can be read and written
+    ///
+    /// The test write is rolled back.
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR IGNORE INTO conversations (id) VALUES ('__health_check__')")
+            .execute(&mut *tx)
+            .await?;
+        tx.rollback().await
+    }
+
+    /// Get the underlying connection pool, for stores sharing the database
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
     /// Initialize the database schema
     async fn init_schema(&self) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS conversations (
                 id TEXT PRIMARY KEY,
+                user_id TEXT,
+                key_id TEXT,
                 created_at TEXT NOT NULL DEFAULT (datetime('now')),
                 updated_at TEXT NOT NULL DEFAULT (datetime('now'))
             )
@@ -102,6 +251,7 @@ impl MemoryStore {
                 conversation_id TEXT NOT NULL,
                 role TEXT NOT NULL,
                 content TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'complete',
                 created_at TEXT NOT NULL DEFAULT (datetime('now')),
                 FOREIGN KEY (conversation_id) REFERENCES conversations(id)
             )
@@ -110,6 +260,42 @@ impl MemoryStore {
         .execute(&self.pool)
         .await?;
 
+        // Databases created before message statuses existed
+        let columns: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('messages')")
+                .fetch_all(&self.pool)
+                .await?;
+        if !columns.iter().any(|(name,)| name == "status") {
+            sqlx::query("ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'complete'")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Databases created before conversations had owners
+        let columns: Vec<(String,)> =
+            sqlx::query_as("SELECT name FROM pragma_table_info('conversations')")
+                .fetch_all(&self.pool)
+                .await?;
+        if !columns.iter().any(|(name,)| name == "user_id") {
+            sqlx::query("ALTER TABLE conversations ADD COLUMN user_id TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+        if !columns.iter().any(|(name,)| name == "key_id") {
+            sqlx::query("ALTER TABLE conversations ADD COLUMN key_id TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_conversations_user
+            ON conversations(user_id, updated_at)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         sqlx::query(
             r#"
             CREATE INDEX IF NOT EXISTS idx_messages_conversation
@@ -122,21 +308,68 @@ impl MemoryStore {
         Ok(())
     }
 
-    /// Save a message to a conversation
+    /// Fail with [`MemoryError::NotFound`] if the conversation exists but
+    /// isn't visible to `owner`
+    pub async fn check_owner(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
+    ) -> Result<(), MemoryError> {
+        let row: Option<(Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT user_id, key_id FROM conversations WHERE id = ?")
+                .bind(conversation_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match row {
+            Some((user_id, key_id)) if !owner.allows(user_id.as_deref(), key_id.as_deref()) => {
+                Err(MemoryError::NotFound(conversation_id.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Save a message to a conversation, creating it for `owner`
     pub async fn save_message(
         &self,
         conversation_id: &str,
+        owner: Owner<'_>,
+        message: &Message,
+    ) -> Result<i64, MemoryError> {
+        self.insert_message(conversation_id, owner, message, STATUS_COMPLETE)
+            .await
+    }
+
+    /// Save the partial reply of a cancelled generation
+    pub async fn save_cancelled(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
         message: &Message,
-    ) -> Result<i64, sqlx::Error> {
-        // Ensure conversation exists
+    ) -> Result<i64, MemoryError> {
+        self.insert_message(conversation_id, owner, message, STATUS_CANCELLED)
+            .await
+    }
+
+    async fn insert_message(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
+        message: &Message,
+        status: &str,
+    ) -> Result<i64, MemoryError> {
+        // Ensure conversation exists; the first writer owns it
         sqlx::query(
             r#"
-            INSERT OR IGNORE INTO conversations (id) VALUES (?)
+            INSERT OR IGNORE INTO conversations (id, user_id, key_id) VALUES (?, ?, ?)
             "#,
         )
         .bind(conversation_id)
+        .bind(owner.user_id())
+        .bind(owner.key_id())
         .execute(&self.pool)
         .await?;
+        self.check_owner(conversation_id, owner).await?;
 
         // Update conversation timestamp
         sqlx::query(
@@ -157,13 +390,14 @@ impl MemoryStore {
 
         let result = sqlx::query(
             r#"
-            INSERT INTO messages (conversation_id, role, content)
-            VALUES (?, ?, ?)
+            INSERT INTO messages (conversation_id, role, content, status)
+            VALUES (?, ?, ?, ?)
             "#,
         )
         .bind(conversation_id)
         .bind(role_str)
         .bind(&message.content)
+        .bind(status)
         .execute(&self.pool)
         .await?;
 
@@ -174,7 +408,9 @@ impl MemoryStore {
     pub async fn get_conversation(
         &self,
         conversation_id: &str,
-    ) -> Result<Vec<Message>, sqlx::Error> {
+        owner: Owner<'_>,
+    ) -> Result<Vec<Message>, MemoryError> {
+        self.check_owner(conversation_id, owner).await?;
         let rows: Vec<(String, String)> = sqlx::query_as(
             r#"
             SELECT role, content
@@ -205,8 +441,10 @@ impl MemoryStore {
     pub async fn get_recent_messages(
         &self,
         conversation_id: &str,
+        owner: Owner<'_>,
         limit: usize,
-    ) -> Result<Vec<Message>, sqlx::Error> {
+    ) -> Result<Vec<Message>, MemoryError> {
+        self.check_owner(conversation_id, owner).await?;
         let rows: Vec<(String, String)> = sqlx::query_as(
             r#"
             SELECT role, content
@@ -237,66 +475,142 @@ impl MemoryStore {
             .collect())
     }
 
-    /// Search messages by content
+    /// Search the messages of `owner`'s conversations by content
     pub async fn search_messages(
         &self,
         query: &str,
+        owner: Owner<'_>,
         limit: usize,
     ) -> Result<Vec<StoredMessage>, sqlx::Error> {
-        let rows: Vec<(i64, String, String, String, String)> = sqlx::query_as(
+        let rows: Vec<MessageRow> = sqlx::query_as(
             r#"
-            SELECT id, conversation_id, role, content, created_at
-            FROM messages
-            WHERE content LIKE ?
-            ORDER BY created_at DESC
+            SELECT m.id, m.conversation_id, m.role, m.content, m.status, m.created_at
+            FROM messages m
+            JOIN conversations c ON c.id = m.conversation_id
+            WHERE m.content LIKE ? AND (? OR (c.user_id IS ? AND c.key_id IS ?))
+            ORDER BY m.created_at DESC
             LIMIT ?
             "#,
         )
         .bind(format!("%{}%", query))
+        .bind(owner == Owner::Any)
+        .bind(owner.user_id())
+        .bind(owner.key_id())
         .bind(limit as i64)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows
-            .into_iter()
-            .map(|(id, conversation_id, role, content, created_at)| StoredMessage {
-                id,
-                conversation_id,
-                role,
-                content,
-                created_at: DateTime::parse_from_rfc3339(&format!("{}Z", created_at))
-                    .map(|dt| dt.with_timezone(&Utc))
-                    .unwrap_or_else(|_| Utc::now()),
-            })
-            .collect())
+        Ok(rows.into_iter().map(stored_message).collect())
+    }
+
+    /// Get all messages in a conversation with their metadata
+    pub async fn get_messages(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
+    ) -> Result<Vec<StoredMessage>, MemoryError> {
+        self.check_owner(conversation_id, owner).await?;
+        let rows: Vec<MessageRow> = sqlx::query_as(
+            r#"
+            SELECT id, conversation_id, role, content, status, created_at
+            FROM messages
+            WHERE conversation_id = ?
+            ORDER BY created_at ASC, id ASC
+            "#,
+        )
+        .bind(conversation_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(stored_message).collect())
+    }
+
+    /// Messages with an ID above `after_id`, oldest first, of all users
+    pub async fn get_messages_after(
+        &self,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<StoredMessage>, sqlx::Error> {
+        let rows: Vec<MessageRow> = sqlx::query_as(
+            r#"
+            SELECT id, conversation_id, role, content, status, created_at
+            FROM messages
+            WHERE id > ?
+            ORDER BY id ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(after_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(stored_message).collect())
+    }
+
+    /// ID of the newest message (0 when there are none)
+    pub async fn last_message_id(&self) -> Result<i64, sqlx::Error> {
+        let (id,): (i64,) = sqlx::query_as("SELECT COALESCE(MAX(id), 0) FROM messages")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id)
+    }
+
+    /// Number of stored conversations and messages
+    pub async fn counts(&self) -> Result<(u64, u64), sqlx::Error> {
+        let (conversations, messages): (i64, i64) = sqlx::query_as(
+            "SELECT (SELECT COUNT(*) FROM conversations), (SELECT COUNT(*) FROM messages)",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((conversations as u64, messages as u64))
     }
 
     /// Delete a conversation and all its messages
-    pub async fn delete_conversation(&self, conversation_id: &str) -> Result<(), sqlx::Error> {
+    ///
+    /// Returns whether the conversation existed.
+    pub async fn delete_conversation(
+        &self,
+        conversation_id: &str,
+        owner: Owner<'_>,
+    ) -> Result<bool, MemoryError> {
+        self.check_owner(conversation_id, owner).await?;
+
         sqlx::query("DELETE FROM messages WHERE conversation_id = ?")
             .bind(conversation_id)
             .execute(&self.pool)
             .await?;
 
-        sqlx::query("DELETE FROM conversations WHERE id = ?")
+        let result = sqlx::query("DELETE FROM conversations WHERE id = ?")
             .bind(conversation_id)
             .execute(&self.pool)
             .await?;
 
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
 
-    /// Get all conversation IDs
-    pub async fn list_conversations(&self) -> Result<Vec<String>, sqlx::Error> {
-        let rows: Vec<(String,)> = sqlx::query_as(
+    /// The conversations of `owner`, most recently updated first
+    pub async fn list_conversations(
+        &self,
+        owner: Owner<'_>,
+    ) -> Result<Vec<ConversationSummary>, MemoryError> {
+        let rows: Vec<ConversationRow> = sqlx::query_as(
             r#"
-            SELECT id FROM conversations ORDER BY updated_at DESC
+            SELECT c.id, c.user_id, c.key_id, COUNT(m.id), c.created_at, c.updated_at
+            FROM conversations c
+            LEFT JOIN messages m ON m.conversation_id = c.id
+            WHERE ? OR (c.user_id IS ? AND c.key_id IS ?)
+            GROUP BY c.id
+            ORDER BY c.updated_at DESC, c.id
             "#,
         )
+        .bind(owner == Owner::Any)
+        .bind(owner.user_id())
+        .bind(owner.key_id())
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(rows.into_iter().map(|(id,)| id).collect())
+        Ok(rows.into_iter().map(conversation_summary).collect())
     }
 }
 
@@ -314,6 +628,7 @@ mod tests {
         store
             .save_message(
                 conversation_id,
+                Owner::Anonymous,
                 &Message {
                     role: Role::User,
                     content: "Hello".to_string(),
@@ -325,6 +640,7 @@ mod tests {
         store
             .save_message(
                 conversation_id,
+                Owner::Anonymous,
                 &Message {
                     role: Role::Assistant,
                     content: "Hi there!".to_string(),
@@ -334,7 +650,7 @@ mod tests {
             .unwrap();
 
         // Retrieve messages
-        let messages = store.get_conversation(conversation_id).await.unwrap();
+        let messages = store.get_conversation(conversation_id, Owner::Anonymous).await.unwrap();
         assert_eq!(messages.len(), 2);
         assert_eq!(messages[0].content, "Hello");
         assert_eq!(messages[1].content, "Hi there!");
@@ -347,6 +663,7 @@ mod tests {
         store
             .save_message(
                 "conv1",
+                Owner::Anonymous,
                 &Message {
                     role: Role::User,
                     content: "How do I read a file?".to_string(),
@@ -358,6 +675,7 @@ mod tests {
         store
             .save_message(
                 "conv2",
+                Owner::Anonymous,
                 &Message {
                     role: Role::User,
                     content: "What's the weather?".to_string(),
@@ -366,7 +684,7 @@ mod tests {
             .await
             .unwrap();
 
-        let results = store.search_messages("file", 10).await.unwrap();
+        let results = store.search_messages("file", Owner::Anonymous, 10).await.unwrap();
         assert_eq!(results.len(), 1);
         assert!(results[0].content.contains("file"));
     }
@@ -378,6 +696,7 @@ mod tests {
         store
             .save_message(
                 "conv1",
+                Owner::Anonymous,
                 &Message {
                     role: Role::User,
                     content: "Message 1".to_string(),
@@ -389,6 +708,7 @@ mod tests {
         store
             .save_message(
                 "conv2",
+                Owner::Anonymous,
                 &Message {
                     role: Role::User,
                     content: "Message 2".to_string(),
@@ -397,7 +717,109 @@ mod tests {
             .await
             .unwrap();
 
-        let conversations = store.list_conversations().await.unwrap();
+        let conversations = store.list_conversations(Owner::Anonymous).await.unwrap();
         assert_eq!(conversations.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_cancelled_messages_are_marked() {
+        let store = MemoryStore::new_in_memory_async().await.unwrap();
+
+        let message = |role, content: &str| Message {
+            role,
+            content: content.to_string(),
+        };
+        store
+            .save_message("conv1", Owner::Anonymous, &message(Role::User, "Write a poem"))
+            .await
+            .unwrap();
+        store
+            .save_cancelled("conv1", Owner::Anonymous, &message(Role::Assistant, "Roses are"))
+            .await
+            .unwrap();
+
+        let messages = store.get_messages("conv1", Owner::Anonymous).await.unwrap();
+        let statuses: Vec<&str> = messages.iter().map(|m| m.status.as_str()).collect();
+        assert_eq!(statuses, vec![STATUS_COMPLETE, STATUS_CANCELLED]);
+        assert_eq!(messages[1].content, "Roses are");
+    }
+
+    #[tokio::test]
+    async fn test_conversations_belong_to_their_user() {
+        let store = MemoryStore::new_in_memory_async().await.unwrap();
+        let message = |content: &str| Message {
+            role: Role::User,
+            content: content.to_string(),
+        };
+        let alice = Owner::User("alice");
+        let bob = Owner::User("bob");
+
+        store.save_message("a1", alice, &message("Hi")).await.unwrap();
+        store.save_message("a2", alice, &message("Hello")).await.unwrap();
+        store.save_message("b1", bob, &message("Hey")).await.unwrap();
+        store.save_message("anon", Owner::Anonymous, &message("Yo")).await.unwrap();
+
+        // Someone else's conversation reads and writes as not found
+        assert_eq!(store.get_conversation("a1", alice).await.unwrap().len(), 1);
+        for owner in [bob, Owner::Anonymous] {
+            let error = store.get_conversation("a1", owner).await.unwrap_err();
+            assert!(matches!(error, MemoryError::NotFound(id) if id == "a1"));
+            assert!(store.save_message("a1", owner, &message("Mine now")).await.is_err());
+            assert!(store.delete_conversation("a1", owner).await.is_err());
+        }
+        assert!(store.get_conversation("anon", alice).await.is_err());
+        assert_eq!(store.get_messages("a1", Owner::Any).await.unwrap().len(), 1);
+
+        let ids = |conversations: Vec<ConversationSummary>| {
+            let mut ids: Vec<String> = conversations.into_iter().map(|c| c.id).collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(store.list_conversations(alice).await.unwrap()), ["a1", "a2"]);
+        assert_eq!(ids(store.list_conversations(Owner::Anonymous).await.unwrap()), ["anon"]);
+        assert_eq!(store.list_conversations(Owner::Any).await.unwrap().len(), 4);
+        assert_eq!(store.search_messages("H", bob, 10).await.unwrap().len(), 1);
+
+        assert!(store.delete_conversation("a2", alice).await.unwrap());
+        assert!(!store.delete_conversation("a2", alice).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_conversations_belong_to_their_key() {
+        let store = MemoryStore::new_in_memory_async().await.unwrap();
+        let message = |content: &str| Message {
+            role: Role::User,
+            content: content.to_string(),
+        };
+        let site_a = Owner::new(None, Some("key-a"));
+        let site_b = Owner::new(None, Some("key-b"));
+        assert_eq!(site_a, Owner::Key("key-a"));
+        assert_eq!(Owner::new(Some("alice"), Some("key-a")), Owner::User("alice"));
+
+        store.save_message("ka", site_a, &message("Hi")).await.unwrap();
+        store.save_message("kb", site_b, &message("Hey")).await.unwrap();
+        store.save_message("open", Owner::Anonymous, &message("Yo")).await.unwrap();
+
+        // Neither key sees the other's conversations, nor the keyless ones
+        for (id, strangers) in [
+            ("ka", [site_b, Owner::Anonymous]),
+            ("kb", [site_a, Owner::Anonymous]),
+            ("open", [site_a, site_b]),
+        ] {
+            for owner in strangers {
+                assert!(store.get_conversation(id, owner).await.is_err());
+                assert!(store.save_message(id, owner, &message("Mine now")).await.is_err());
+                assert!(store.delete_conversation(id, owner).await.is_err());
+            }
+        }
+        assert_eq!(store.get_conversation("ka", site_a).await.unwrap().len(), 1);
+        assert!(store.get_conversation("ka", Owner::User("alice")).await.is_err());
+
+        let listed = store.list_conversations(site_a).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].id.as_str(), listed[0].key_id.as_deref()), ("ka", Some("key-a")));
+        let found = store.search_messages("H", site_b, 10).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].conversation_id, "kb");
+    }
 }
@@ -2,8 +2,12 @@
 //!
 //! This module contains the central orchestration logic for Moxie's AI capabilities.
 
+mod artifacts;
 mod chat;
 mod memory;
+mod stream;
 
-pub use chat::{ChatEngine, ChatRequest, ChatResponse};
-pub use memory::{MemoryStore, StoredMessage};
+pub use artifacts::{ArtifactError, ArtifactInfo, ArtifactStore, StoredArtifact};
+pub use chat::{ChatEngine, ChatError, ChatRequest, ChatResponse, ToolCall};
+pub use memory::{ConversationSummary, MemoryError, MemoryStore, Owner, StoredMessage};
+pub use stream::{ChatEvent, ToolCallFilter, ToolConfirmer};
@@ -0,0 +1,186 @@
+//! Streaming chat events
+//!
+//! While streaming, the model's text is forwarded as it arrives. Tool calls
+//! are written by the model as ```` ```tool_call ```` blocks in that same
+//! text; [`ToolCallFilter`] keeps them out of the visible output.
+//!
+//! Interactive transports can also pass a [`ToolConfirmer`] to ask the user
+//! before tools that require confirmation run.
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::artifacts::ArtifactInfo;
+use super::chat::ToolCall;
+
+/// An event emitted while a streaming chat is processed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// Text from the assistant
+    Delta { content: String },
+
+    /// A plugin tool is about to be executed
+    ToolCall { name: String, arguments: Value },
+
+    /// A plugin tool finished
+    ToolResult { name: String, success: bool },
+
+    /// A tool produced a file the user can download
+    Artifact(ArtifactInfo),
+}
+
+impl ChatEvent {
+    /// The event's `type` tag, e.g. for server-sent event names
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ChatEvent::Delta { .. } => "delta",
+            ChatEvent::ToolCall { .. } => "tool_call",
+            ChatEvent::ToolResult { .. } => "tool_result",
+            ChatEvent::Artifact(_) => "artifact",
+        }
+    }
+}
+
+/// Asks the user whether a tool may run
+#[async_trait]
+pub trait ToolConfirmer: Send + Sync {
+    /// Whether `call` may run; declined calls are reported to the model as
+    /// failed
+    async fn confirm(&self, call: &ToolCall) -> bool;
+}
+
+const BLOCK_START: &str = "```tool_call";
+const BLOCK_END: &str = "```";
+
+/// Removes tool call blocks from streamed text
+///
+/// Text that could be the start of a block is held back until the next
+/// delta shows whether it is one.
+#[derive(Debug, Default)]
+pub struct ToolCallFilter {
+    buffer: String,
+    in_block: bool,
+}
+
+impl ToolCallFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a delta and return the text that can be shown
+    pub fn push(&mut self, delta: &str) -> String {
+        self.buffer.push_str(delta);
+        let mut visible = String::new();
+
+        loop {
+            if self.in_block {
+                // The block body is JSON; wait for its closing fence
+                match self.buffer.find(BLOCK_END) {
+                    Some(end) => {
+                        self.buffer.drain(..end + BLOCK_END.len());
+                        self.in_block = false;
+                    }
+                    None => return visible,
+                }
+            } else {
+                match self.buffer.find(BLOCK_START) {
+                    Some(start) => {
+                        visible.push_str(&self.buffer[..start]);
+                        self.buffer.drain(..start + BLOCK_START.len());
+                        self.in_block = true;
+                    }
+                    None => {
+                        let keep = partial_marker_len(&self.buffer);
+                        let split = self.buffer.len() - keep;
+                        visible.push_str(&self.buffer[..split]);
+                        self.buffer.drain(..split);
+                        return visible;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flush held-back text at the end of the stream
+    ///
+    /// An unterminated tool call block is dropped.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.buffer);
+        if std::mem::take(&mut self.in_block) {
+            String::new()
+        } else {
+            rest
+        }
+    }
+
+    /// Remove all tool call blocks from a complete text
+    pub fn strip(content: &str) -> String {
+        let mut filter = Self::new();
+        let mut visible = filter.push(content);
+        visible.push_str(&filter.finish());
+        visible.trim().to_string()
+    }
+}
+
+/// Length of the longest suffix of `text` that starts a tool call marker
+fn partial_marker_len(text: &str) -> usize {
+    (1..BLOCK_START.len().min(text.len() + 1))
+        .rev()
+        .find(|&len| {
+            let start = text.len() - len;
+            text.is_char_boundary(start) && BLOCK_START.starts_with(&text[start..])
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_all(deltas: &[&str]) -> String {
+        let mut filter = ToolCallFilter::new();
+        let mut visible: String = deltas.iter().map(|d| filter.push(d)).collect();
+        visible.push_str(&filter.finish());
+        visible
+    }
+
+    #[test]
+    fn test_plain_text_passes_through() {
+        let mut filter = ToolCallFilter::new();
+        assert_eq!(filter.push("Hello "), "Hello ");
+        assert_eq!(filter.push("world"), "world");
+        assert_eq!(filter.finish(), "");
+    }
+
+    #[test]
+    fn test_tool_call_split_across_deltas() {
+        let visible = filter_all(&[
+            "Let me check. ``",
+            "`tool",
+            "_call\n{\"name\": \"read_file\", ",
+            "\"arguments\": {}}\n`",
+            "``\nDone.",
+        ]);
+        assert_eq!(visible, "Let me check. \nDone.");
+    }
+
+    #[test]
+    fn test_partial_marker_is_released() {
+        let mut filter = ToolCallFilter::new();
+        assert_eq!(filter.push("Use ``"), "Use ");
+        assert_eq!(filter.push("`rust"), "```rust");
+        assert_eq!(filter.push(" code `"), " code ");
+        assert_eq!(filter.finish(), "`");
+    }
+
+    #[test]
+    fn test_unterminated_block_is_dropped() {
+        assert_eq!(filter_all(&["Hi ```tool_call\n{\"name\":"]), "Hi ");
+        assert_eq!(
+            ToolCallFilter::strip("Sure.\n```tool_call\n{}\n```"),
+            "Sure."
+        );
+    }
+}
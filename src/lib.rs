@@ -0,0 +1,54 @@
+//! Moxie - Bold AI Chatbot API
+//!
+//! Provides a unified API for integrating AI chatbots into websites.
+//! Moxie is a self-hosted AI assistant platform with a plugin-based architecture
+//! for deep system integration.
+//!
+//! The `moxie-ai` binary is a thin command-line wrapper around this library;
+//! custom plugins can depend on it through `moxie_ai::plugins::prelude`.
+
+use std::sync::Arc;
+
+pub mod audit;
+pub mod auth;
+pub mod bootstrap;
+pub mod cli;
+pub mod config;
+pub mod conversation;
+pub mod core;
+pub mod metrics;
+pub mod plugins;
+pub mod providers;
+pub mod rate_limit;
+pub mod routes;
+pub mod telemetry;
+pub mod tenants;
+
+use auth::{ApiKeyStore, UserTokens};
+use config::client::{LlmConfig, SecurityConfig, WidgetConfig};
+use config::Config;
+use core::ChatEngine;
+use rate_limit::RateLimiter;
+use routes::SessionRegistry;
+
+/// Application state shared across handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Config,
+    pub chat_engine: Arc<ChatEngine>,
+    pub keys: ApiKeyStore,
+    pub security: Arc<SecurityConfig>,
+    pub limits: Arc<RateLimiter>,
+
+    /// Default provider and model for requests that don't name one
+    pub llm: Arc<LlmConfig>,
+
+    /// Website widget settings
+    pub widget: Arc<WidgetConfig>,
+
+    /// Running and recently finished WebSocket chat sessions
+    pub sessions: Arc<SessionRegistry>,
+
+    /// Verifier for signed user tokens, if enabled
+    pub user_tokens: Option<Arc<UserTokens>>,
+}
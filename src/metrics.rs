@@ -0,0 +1,344 @@
+//! Prometheus metrics
+//!
+//! Metrics live in one process-wide registry, so providers and plugins can
+//! record them without access to the application state. `GET /metrics`
+//! renders the registry in the Prometheus text format.
+//!
+//! Label values are bounded: routes are the matched route templates, not
+//! the request paths.
+//!
+//! [`Metrics::totals`] sums the counters for the telemetry agent.
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    core::Collector, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+
+/// Buckets for LLM calls and tools, which can take minutes
+const SLOW_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// The process-wide metrics
+pub fn global() -> &'static Metrics {
+    &METRICS
+}
+
+/// All metrics exported by Moxie
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    llm_requests: IntCounterVec,
+    llm_duration: HistogramVec,
+    llm_tokens: IntCounterVec,
+    tool_executions: IntCounterVec,
+    tool_duration: HistogramVec,
+    server_errors: IntCounterVec,
+    active_conversations: IntGauge,
+    db_connections: IntGauge,
+    db_idle_connections: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("moxie".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let metric = IntCounterVec::new(Opts::new(name, help), labels).expect("valid metric");
+            registry
+                .register(Box::new(metric.clone()))
+                .expect("metric registered once");
+            metric
+        };
+        let histogram = |name: &str, help: &str, labels: &[&str], buckets: Option<&[f64]>| {
+            let mut opts = HistogramOpts::new(name, help);
+            if let Some(buckets) = buckets {
+                opts = opts.buckets(buckets.to_vec());
+            }
+            let metric = HistogramVec::new(opts, labels).expect("valid metric");
+            registry
+                .register(Box::new(metric.clone()))
+                .expect("metric registered once");
+            metric
+        };
+        let gauge = |name: &str, help: &str| {
+            let metric = IntGauge::new(name, help).expect("valid metric");
+            registry
+                .register(Box::new(metric.clone()))
+                .expect("metric registered once");
+            metric
+        };
+
+        Self {
+            http_requests: counter(
+                "http_requests_total",
+                "HTTP requests by route and status",
+                &["method", "route", "status"],
+            ),
+            http_duration: histogram(
+                "http_request_duration_seconds",
+                "HTTP request latency by route",
+                &["method", "route"],
+                None,
+            ),
+            llm_requests: counter(
+                "llm_requests_total",
+                "LLM calls by provider, model and outcome (ok or error)",
+                &["provider", "model", "outcome"],
+            ),
+            llm_duration: histogram(
+                "llm_request_duration_seconds",
+                "LLM call latency, until the last streamed token",
+                &["provider", "model"],
+                Some(SLOW_BUCKETS),
+            ),
+            llm_tokens: counter(
+                "llm_tokens_total",
+                "Tokens reported by the provider, by kind (prompt or completion)",
+                &["provider", "model", "kind"],
+            ),
+            tool_executions: counter(
+                "tool_executions_total",
+                "Tool executions by outcome (success, failure, error or cancelled)",
+                &["plugin", "tool", "outcome"],
+            ),
+            tool_duration: histogram(
+                "tool_duration_seconds",
+                "Tool execution latency",
+                &["plugin", "tool"],
+                Some(SLOW_BUCKETS),
+            ),
+            server_errors: counter(
+                "server_errors_total",
+                "Server-side API errors by error code",
+                &["code"],
+            ),
+            active_conversations: gauge(
+                "active_conversations",
+                "Conversations with a reply being generated",
+            ),
+            db_connections: gauge("db_pool_connections", "Open SQLite pool connections"),
+            db_idle_connections: gauge("db_pool_idle_connections", "Idle SQLite pool connections"),
+            registry,
+        }
+    }
+
+    /// Record a finished HTTP request
+    pub fn observe_http(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record a finished LLM call
+    pub fn observe_llm(&self, provider: &str, model: &str, elapsed: Duration, ok: bool) {
+        let outcome = if ok { "ok" } else { "error" };
+        self.llm_requests
+            .with_label_values(&[provider, model, outcome])
+            .inc();
+        self.llm_duration
+            .with_label_values(&[provider, model])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record token usage reported by a provider
+    pub fn record_tokens(&self, provider: &str, model: &str, prompt: u64, completion: u64) {
+        self.llm_tokens
+            .with_label_values(&[provider, model, "prompt"])
+            .inc_by(prompt);
+        self.llm_tokens
+            .with_label_values(&[provider, model, "completion"])
+            .inc_by(completion);
+    }
+
+    /// Record a tool execution; `outcome` is success, failure, error or cancelled
+    pub fn observe_tool(&self, plugin: &str, tool: &str, outcome: &str, elapsed: Duration) {
+        self.tool_executions
+            .with_label_values(&[plugin, tool, outcome])
+            .inc();
+        self.tool_duration
+            .with_label_values(&[plugin, tool])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record a server-side API error (only its code)
+    pub fn record_error(&self, code: &str) {
+        self.server_errors.with_label_values(&[code]).inc();
+    }
+
+    /// Track a conversation with a reply in progress until the guard drops
+    pub fn conversation_started(&self) -> ActiveConversation<'_> {
+        self.active_conversations.inc();
+        ActiveConversation(&self.active_conversations)
+    }
+
+    /// Update the connection pool gauges
+    pub fn set_db_pool(&self, connections: u32, idle: usize) {
+        self.db_connections.set(connections.into());
+        self.db_idle_connections.set(idle as i64);
+    }
+
+    /// Counter totals since the process started
+    pub fn totals(&self) -> Totals {
+        let llm_outcomes = sum_by(&self.llm_requests, "outcome");
+        let tokens = sum_by(&self.llm_tokens, "kind");
+        let count = |map: &BTreeMap<String, u64>, key: &str| map.get(key).copied().unwrap_or(0);
+
+        Totals {
+            llm_requests: llm_outcomes.values().sum(),
+            llm_errors: count(&llm_outcomes, "error"),
+            prompt_tokens: count(&tokens, "prompt"),
+            completion_tokens: count(&tokens, "completion"),
+            tool_executions: sum_by(&self.tool_executions, "outcome").values().sum(),
+            active_conversations: self.active_conversations.get(),
+            server_errors: sum_by(&self.server_errors, "code"),
+        }
+    }
+
+    /// All metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Totals of the counters, see [`Metrics::totals`]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Totals {
+    pub llm_requests: u64,
+    pub llm_errors: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub tool_executions: u64,
+    pub active_conversations: i64,
+    /// Server-side API errors by error code
+    pub server_errors: BTreeMap<String, u64>,
+}
+
+/// Sum a counter over all labels but `label`
+fn sum_by(counter: &IntCounterVec, label: &str) -> BTreeMap<String, u64> {
+    let mut sums = BTreeMap::new();
+    for family in counter.collect() {
+        for metric in family.get_metric() {
+            let Some(pair) = metric.get_label().iter().find(|p| p.get_name() == label) else {
+                continue;
+            };
+            *sums.entry(pair.get_value().to_string()).or_default() +=
+                metric.get_counter().get_value() as u64;
+        }
+    }
+    sums
+}
+
+/// Decrements the active conversation gauge when dropped
+pub struct ActiveConversation<'a>(&'a IntGauge);
+
+impl Drop for ActiveConversation<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// Middleware recording request counts and latencies per route
+///
+/// Use as a route layer so the matched route is known.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    global().observe_http(&method, &route, response.status().as_u16(), started.elapsed());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_recorded_metrics() {
+        let metrics = Metrics::new();
+        metrics.observe_http("GET", "/v2/tools", 200, Duration::from_millis(5));
+        metrics.record_tokens("ollama", "llama3.2", 12, 34);
+        metrics.observe_tool("filesystem", "read_file", "success", Duration::from_millis(2));
+        let active = metrics.conversation_started();
+
+        let text = metrics.render();
+        assert!(text.contains(
+            r#"moxie_http_requests_total{method="GET",route="/v2/tools",status="200"} 1"#
+        ));
+        assert!(text.contains(
+            r#"moxie_llm_tokens_total{kind="completion",model="llama3.2",provider="ollama"} 34"#
+        ));
+        assert!(text.contains(
+            r#"moxie_tool_executions_total{outcome="success",plugin="filesystem",tool="read_file"} 1"#
+        ));
+        assert!(text.contains("moxie_active_conversations 1"));
+
+        drop(active);
+        assert!(metrics.render().contains("moxie_active_conversations 0"));
+    }
+
+    #[test]
+    fn test_render_includes_failures() {
+        let metrics = Metrics::new();
+        metrics.observe_llm("ollama", "llama3.2", Duration::from_millis(5), false);
+        metrics.observe_tool("database", "run_query", "failure", Duration::from_millis(2));
+        metrics.observe_tool("database", "run_query", "error", Duration::from_millis(2));
+        metrics.record_error("provider_unavailable");
+
+        let text = metrics.render();
+        assert!(text.contains(
+            r#"moxie_llm_requests_total{model="llama3.2",outcome="error",provider="ollama"} 1"#
+        ));
+        assert!(text.contains(
+            r#"moxie_tool_executions_total{outcome="failure",plugin="database",tool="run_query"} 1"#
+        ));
+        assert!(text.contains(
+            r#"moxie_tool_executions_total{outcome="error",plugin="database",tool="run_query"} 1"#
+        ));
+        assert!(text.contains(
+            r#"moxie_tool_duration_seconds_count{plugin="database",tool="run_query"} 2"#
+        ));
+    }
+
+    #[test]
+    fn test_totals_sum_counters() {
+        let metrics = Metrics::new();
+        metrics.observe_llm("ollama", "llama3.2", Duration::from_millis(5), true);
+        metrics.observe_llm("groq", "llama-3.3", Duration::from_millis(5), false);
+        metrics.record_tokens("ollama", "llama3.2", 12, 34);
+        metrics.record_tokens("groq", "llama-3.3", 1, 2);
+        metrics.record_error("storage_error");
+        metrics.record_error("storage_error");
+
+        let totals = metrics.totals();
+        assert_eq!((totals.llm_requests, totals.llm_errors), (2, 1));
+        assert_eq!((totals.prompt_tokens, totals.completion_tokens), (13, 36));
+        assert_eq!(totals.server_errors.get("storage_error"), Some(&2));
+    }
+}
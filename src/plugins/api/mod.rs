@@ -347,7 +347,7 @@ impl ApiPlugin {
                         };
                         header_params.insert(name.clone(), value_str);
                     }
-                    "body" | _ => {
+                    _ => {
                         body_params.insert(name.clone(), value.clone());
                     }
                 }
@@ -380,10 +380,8 @@ impl ApiPlugin {
 
         // Add body for POST/PUT/PATCH
         match endpoint.method {
-            HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH => {
-                if !body_params.is_empty() {
-                    request = request.json(&Value::Object(body_params));
-                }
+            HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH if !body_params.is_empty() => {
+                request = request.json(&Value::Object(body_params));
             }
             _ => {}
         }
@@ -491,7 +489,7 @@ impl Plugin for ApiPlugin {
         .with_category(PluginCategory::Cloud)
         .with_keywords(vec!["api", "rest", "http", "integration", "custom"])
         .with_config_field(
-            ConfigFieldBuilder::new("services", ConfigFieldType::StringArray)
+            ConfigFieldBuilder::new("services", ConfigFieldType::TableArray)
                 .label("API Services")
                 .description("Configured API services")
                 .build(),
@@ -0,0 +1,407 @@
+//! SQL checks for the database plugin
+//!
+//! Every statement is parsed with its connection's dialect before it runs;
+//! nothing is decided by looking at keywords. `run_query` accepts a single
+//! SELECT, WITH, VALUES or an EXPLAIN of one of those, and gets a LIMIT added
+//! when it has none; a LIMIT or FETCH that isn't a plain number is refused.
+//! `execute_statement` accepts a single INSERT, UPDATE or DELETE. Functions
+//! that sleep, touch the server's files or settings, signal other sessions,
+//! reach other servers or run SQL given as text (which is never parsed
+//! here) are refused anywhere in a statement.
+
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{Expr, ObjectName, Query, SetExpr, Statement, Value, Visit, Visitor};
+use sqlparser::dialect::{Dialect, GenericDialect, PostgreSqlDialect, SQLiteDialect};
+use sqlparser::parser::Parser;
+
+/// Functions refused in any statement
+const FORBIDDEN_FUNCTIONS: &[&str] = &[
+    // Postgres: sleeping, server files, other sessions, settings, other servers
+    "pg_sleep",
+    "pg_sleep_for",
+    "pg_sleep_until",
+    "pg_read_file",
+    "pg_read_binary_file",
+    "pg_ls_dir",
+    "pg_ls_logdir",
+    "pg_ls_waldir",
+    "pg_ls_tmpdir",
+    "pg_stat_file",
+    "lo_import",
+    "lo_export",
+    "lo_from_bytea",
+    "lo_put",
+    "lo_unlink",
+    "pg_terminate_backend",
+    "pg_cancel_backend",
+    "pg_reload_conf",
+    "pg_rotate_logfile",
+    "set_config",
+    "current_setting",
+    "pg_advisory_lock",
+    "pg_advisory_xact_lock",
+    "pg_try_advisory_lock",
+    "nextval",
+    "setval",
+    "dblink",
+    "dblink_connect",
+    "dblink_exec",
+    "dblink_send_query",
+    // Postgres: running SQL passed as text
+    "ts_stat",
+    "ts_rewrite",
+    // SQLite: extensions and file access (sqlite3 shell functions)
+    "load_extension",
+    "readfile",
+    "writefile",
+    "edit",
+    "fts3_tokenizer",
+];
+
+/// Whether a function runs SQL passed as text, like every Postgres
+/// `*_to_xml*` export (`query_to_xml`, `cursor_to_xml`, `table_to_xml`,
+/// `database_to_xml` and their `xmlschema` variants)
+fn runs_sql_text(function: &str) -> bool {
+    function.contains("_to_xml")
+}
+
+/// Why a statement was refused
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SqlError {
+    #[error("Could not parse SQL: {0}")]
+    Parse(String),
+
+    #[error("No SQL statement given")]
+    Empty,
+
+    #[error("Only one statement can run at a time")]
+    MultipleStatements,
+
+    #[error("{0} statements are not allowed here")]
+    NotAllowed(String),
+
+    #[error("SELECT INTO and locking clauses (FOR UPDATE) are not allowed")]
+    NotReadOnly,
+
+    #[error("Function {0} is not allowed")]
+    ForbiddenFunction(String),
+
+    #[error("LIMIT and FETCH must be a plain row count")]
+    Limit,
+}
+
+/// Check a query for `run_query` and return the SQL to run
+///
+/// A LIMIT of `limit` is added when the query has none, and a larger
+/// LIMIT or FETCH count is lowered to it. Counts computed by an expression
+/// (or negative, which SQLite reads as "no limit") can't be capped and are
+/// refused.
+pub fn check_query(sql: &str, db_type: &str, limit: usize) -> Result<String, SqlError> {
+    let mut statement = parse_single(sql, db_type)?;
+
+    match &mut statement {
+        Statement::Query(query) => apply_limit(query, limit)?,
+        Statement::Explain { statement, .. } if matches!(**statement, Statement::Query(_)) => {}
+        other => return Err(SqlError::NotAllowed(statement_kind(other))),
+    }
+
+    check_tree(&statement, false)?;
+    Ok(statement.to_string())
+}
+
+/// Check a statement for `execute_statement` and return the SQL to run
+pub fn check_write(sql: &str, db_type: &str) -> Result<String, SqlError> {
+    let statement = parse_single(sql, db_type)?;
+
+    match &statement {
+        Statement::Insert(_) | Statement::Update { .. } | Statement::Delete(_) => {}
+        other => return Err(SqlError::NotAllowed(statement_kind(other))),
+    }
+
+    check_tree(&statement, true)?;
+    Ok(statement.to_string())
+}
+
+fn dialect(db_type: &str) -> Box<dyn Dialect> {
+    match db_type {
+        "sqlite" => Box::new(SQLiteDialect {}),
+        "postgres" => Box::new(PostgreSqlDialect {}),
+        _ => Box::new(GenericDialect {}),
+    }
+}
+
+fn parse_single(sql: &str, db_type: &str) -> Result<Statement, SqlError> {
+    let mut statements = Parser::parse_sql(dialect(db_type).as_ref(), sql)
+        .map_err(|e| SqlError::Parse(e.to_string()))?;
+
+    match statements.len() {
+        0 => Err(SqlError::Empty),
+        1 => Ok(statements.remove(0)),
+        _ => Err(SqlError::MultipleStatements),
+    }
+}
+
+/// The leading keyword of a statement, e.g. "DROP"
+fn statement_kind(statement: &Statement) -> String {
+    statement
+        .to_string()
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_uppercase()
+}
+
+fn apply_limit(query: &mut Query, limit: usize) -> Result<(), SqlError> {
+    let capped = || Some(Expr::Value(Value::Number(limit.to_string(), false)));
+
+    if let Some(fetch) = &mut query.fetch {
+        if fetch.percent {
+            return Err(SqlError::Limit);
+        }
+        // `FETCH FIRST ROW ONLY` is one row
+        if let Some(quantity) = &fetch.quantity {
+            if row_count(quantity)? > limit {
+                fetch.quantity = capped();
+            }
+        }
+    }
+
+    match &query.limit {
+        None if query.fetch.is_none() => query.limit = capped(),
+        Some(count) if row_count(count)? > limit => query.limit = capped(),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The value of a literal, non-negative row count
+fn row_count(expr: &Expr) -> Result<usize, SqlError> {
+    match expr {
+        Expr::Value(Value::Number(n, _)) => n.parse().map_err(|_| SqlError::Limit),
+        _ => Err(SqlError::Limit),
+    }
+}
+
+/// Walk the whole statement, including subqueries and CTEs
+fn check_tree(statement: &Statement, allow_write: bool) -> Result<(), SqlError> {
+    match statement.visit(&mut TreeCheck {
+        allow_write,
+        depth: 0,
+    }) {
+        ControlFlow::Break(error) => Err(error),
+        ControlFlow::Continue(()) => Ok(()),
+    }
+}
+
+struct TreeCheck {
+    allow_write: bool,
+    depth: usize,
+}
+
+impl TreeCheck {
+    fn check_function(name: &ObjectName) -> ControlFlow<SqlError> {
+        let function = name
+            .0
+            .last()
+            .map(|ident| ident.value.to_lowercase())
+            .unwrap_or_default();
+        if FORBIDDEN_FUNCTIONS.contains(&function.as_str()) || runs_sql_text(&function) {
+            ControlFlow::Break(SqlError::ForbiddenFunction(function))
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+impl Visitor for TreeCheck {
+    type Break = SqlError;
+
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<SqlError> {
+        // The top-level statement was checked by the caller; nested ones
+        // (e.g. an INSERT inside a CTE) must be queries unless writing
+        self.depth += 1;
+        if self.depth > 1 && !self.allow_write && !matches!(statement, Statement::Query(_)) {
+            return ControlFlow::Break(SqlError::NotAllowed(statement_kind(statement)));
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn post_visit_statement(&mut self, _statement: &Statement) -> ControlFlow<SqlError> {
+        self.depth -= 1;
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<SqlError> {
+        if !self.allow_write {
+            let select_into =
+                matches!(&*query.body, SetExpr::Select(select) if select.into.is_some());
+            if select_into || !query.locks.is_empty() {
+                return ControlFlow::Break(SqlError::NotReadOnly);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<SqlError> {
+        match expr {
+            Expr::Function(function) => Self::check_function(&function.name),
+            _ => ControlFlow::Continue(()),
+        }
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<SqlError> {
+        // Table-valued functions, e.g. `FROM pg_ls_dir('.')`
+        Self::check_function(relation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries() {
+        assert_eq!(
+            check_query("SELECT * FROM products", "sqlite", 11).unwrap(),
+            "SELECT * FROM products LIMIT 11"
+        );
+        assert_eq!(
+            check_query("select id from products limit 5", "postgres", 11).unwrap(),
+            "SELECT id FROM products LIMIT 5"
+        );
+        assert_eq!(
+            check_query("SELECT id FROM products LIMIT 500", "postgres", 11).unwrap(),
+            "SELECT id FROM products LIMIT 11"
+        );
+        assert_eq!(
+            check_query("SELECT id FROM products FETCH FIRST 500 ROWS ONLY", "postgres", 11)
+                .unwrap(),
+            "SELECT id FROM products FETCH FIRST 11 ROWS ONLY"
+        );
+        assert!(check_query(
+            "WITH cheap AS (SELECT * FROM products WHERE price < 5) SELECT count(*) FROM cheap",
+            "postgres",
+            11
+        )
+        .is_ok());
+        assert!(check_query("EXPLAIN SELECT * FROM products", "postgres", 11).is_ok());
+        assert!(check_query("EXPLAIN QUERY PLAN SELECT * FROM products", "sqlite", 11).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_writes_and_tricks() {
+        let rejected = |sql: &str| check_query(sql, "postgres", 11).unwrap_err();
+
+        assert_eq!(
+            rejected("DELETE FROM products"),
+            SqlError::NotAllowed("DELETE".into())
+        );
+        assert_eq!(
+            rejected("  /* harmless */ DROP TABLE products"),
+            SqlError::NotAllowed("DROP".into())
+        );
+        assert_eq!(
+            rejected("SELECT 1; DELETE FROM products"),
+            SqlError::MultipleStatements
+        );
+        assert_eq!(
+            rejected("EXPLAIN ANALYZE DELETE FROM products"),
+            SqlError::NotAllowed("EXPLAIN".into())
+        );
+        assert_eq!(
+            rejected("SELECT * INTO copy FROM products"),
+            SqlError::NotReadOnly
+        );
+        assert_eq!(
+            rejected("SELECT * FROM products FOR UPDATE"),
+            SqlError::NotReadOnly
+        );
+        assert_eq!(
+            rejected("SELECT name FROM products WHERE id = (SELECT pg_sleep(60))"),
+            SqlError::ForbiddenFunction("pg_sleep".into())
+        );
+        assert_eq!(
+            rejected("SELECT * FROM pg_catalog.pg_ls_dir('.')"),
+            SqlError::ForbiddenFunction("pg_ls_dir".into())
+        );
+        assert_eq!(
+            check_query("SELECT load_extension('evil')", "sqlite", 11).unwrap_err(),
+            SqlError::ForbiddenFunction("load_extension".into())
+        );
+        assert_eq!(
+            check_query("SELECT * FROM products LIMIT -1", "sqlite", 11).unwrap_err(),
+            SqlError::Limit
+        );
+        assert_eq!(
+            rejected("SELECT * FROM products LIMIT (SELECT 1e9)"),
+            SqlError::Limit
+        );
+        assert_eq!(rejected("SELECT * FROM products LIMIT 1e9"), SqlError::Limit);
+        assert_eq!(
+            rejected("SELECT * FROM products FETCH FIRST 50 PERCENT ROWS ONLY"),
+            SqlError::Limit
+        );
+        assert_eq!(rejected(""), SqlError::Empty);
+        assert!(matches!(rejected("SELEC 1"), SqlError::Parse(_)));
+    }
+
+    #[test]
+    fn test_rejects_functions_running_sql_text() {
+        let rejected = |sql: &str| check_query(sql, "postgres", 11).unwrap_err();
+        let forbidden = |name: &str| SqlError::ForbiddenFunction(name.into());
+
+        assert_eq!(
+            rejected("SELECT query_to_xml('SELECT pg_read_file(''/etc/passwd'')', true, false, '')"),
+            forbidden("query_to_xml")
+        );
+        assert_eq!(
+            rejected("SELECT query_to_xml_and_xmlschema('SELECT 1', true, false, '')"),
+            forbidden("query_to_xml_and_xmlschema")
+        );
+        assert_eq!(
+            rejected("SELECT cursor_to_xml('c', 10, true, false, '')"),
+            forbidden("cursor_to_xml")
+        );
+        assert_eq!(
+            rejected("SELECT * FROM pg_catalog.table_to_xml('products', true, false, '')"),
+            forbidden("table_to_xml")
+        );
+        assert_eq!(
+            rejected("SELECT Database_To_Xml(true, false, '')"),
+            forbidden("database_to_xml")
+        );
+        assert_eq!(
+            rejected("SELECT * FROM ts_stat('SELECT pg_sleep(60)::text::tsvector')"),
+            forbidden("ts_stat")
+        );
+        assert_eq!(
+            rejected("SELECT set_config('statement_timeout', '0', false)"),
+            forbidden("set_config")
+        );
+        assert_eq!(
+            rejected("SELECT current_setting('data_directory')"),
+            forbidden("current_setting")
+        );
+        // Other XML functions are fine
+        assert!(check_query("SELECT xmlcomment('x')", "postgres", 11).is_ok());
+    }
+
+    #[test]
+    fn test_writes() {
+        assert!(check_write("UPDATE products SET price = 3 WHERE id = 1", "sqlite").is_ok());
+        assert!(check_write("INSERT INTO products (name) VALUES ('x')", "postgres").is_ok());
+        assert_eq!(
+            check_write("DROP TABLE products", "postgres").unwrap_err(),
+            SqlError::NotAllowed("DROP".into())
+        );
+        assert_eq!(
+            check_write("DELETE FROM products; DROP TABLE products", "postgres").unwrap_err(),
+            SqlError::MultipleStatements
+        );
+        assert_eq!(
+            check_write("DELETE FROM products WHERE id = pg_sleep(10)", "postgres").unwrap_err(),
+            SqlError::ForbiddenFunction("pg_sleep".into())
+        );
+    }
+}
@@ -0,0 +1,1135 @@
+//! Database plugin for SQL databases
+//!
+//! Lets the AI inspect and query the databases configured as named
+//! connections. SQLite and Postgres are supported. Connection strings are read
+//! from environment variables so they never appear in the config file, and
+//! each connection is opened on first use.
+//!
+//! # Tools
+//!
+//! - `list_tables` - List the tables and views of a connection
+//! - `describe_table` - Show the columns of a table
+//! - `run_query` - Run a query and return its rows as JSON
+//! - `execute_statement` - Run a statement that changes data (if "write" is allowed)
+//!
+//! SQL from the AI is parsed and checked by [`guard`] before it runs. Queries
+//! also run in a read-only transaction with a statement timeout: Postgres
+//! cancels the statement itself, and SQLite statements are interrupted by a
+//! progress handler once the timeout has passed.
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [plugins.database]
+//! allowed_operations = ["read"]  # Add "write" for execute_statement
+//! max_rows = 100
+//! statement_timeout_secs = 30
+//!
+//! [[plugins.database.connections]]
+//! name = "production"
+//! type = "postgres"  # "sqlite" or "postgres"
+//! connection_string_env = "PROD_DB_CONNECTION"
+//! ```
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow, PgValueRef, Postgres};
+use sqlx::sqlite::{
+    Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow, SqliteValueRef,
+};
+use sqlx::{Column, Database, Decode, Row, TypeInfo, ValueRef};
+use std::any::Any;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::plugins::manifest::{
+    ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest,
+};
+use crate::plugins::traits::{Plugin, PluginContext};
+use crate::plugins::{LegacyPlugin, PluginError, ToolDefinition, ToolResult};
+
+pub mod guard;
+
+/// Database types the plugin can connect to
+const SUPPORTED_TYPES: &[&str] = &["sqlite", "postgres"];
+
+/// Pool size for each connection
+const MAX_POOL_CONNECTIONS: u32 = 4;
+
+/// Extra time the client-side timeout leaves the database to cancel a
+/// statement itself, so the connection is free again when the tool returns
+const TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+/// SQLite virtual machine steps between checks of a statement's deadline
+const SQLITE_PROGRESS_STEPS: i32 = 1000;
+
+/// A named database connection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionConfig {
+    /// Name the AI uses to pick the connection
+    pub name: String,
+
+    /// Database type: "sqlite" or "postgres"
+    #[serde(rename = "type")]
+    pub db_type: String,
+
+    /// Environment variable containing the connection string
+    pub connection_string_env: String,
+}
+
+/// Configuration for the database plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    /// Configured connections
+    #[serde(default)]
+    pub connections: Vec<ConnectionConfig>,
+
+    /// Allowed operations: "read", "write"
+    #[serde(default = "default_allowed_operations")]
+    pub allowed_operations: Vec<String>,
+
+    /// Maximum number of rows a query returns
+    #[serde(default = "default_max_rows")]
+    pub max_rows: usize,
+
+    /// Seconds a statement may run before it is cancelled
+    #[serde(default = "default_statement_timeout")]
+    pub statement_timeout_secs: u64,
+}
+
+fn default_allowed_operations() -> Vec<String> {
+    vec!["read".to_string()]
+}
+
+fn default_max_rows() -> usize {
+    100
+}
+
+fn default_statement_timeout() -> u64 {
+    30
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            connections: vec![],
+            allowed_operations: default_allowed_operations(),
+            max_rows: default_max_rows(),
+            statement_timeout_secs: default_statement_timeout(),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Parse configuration from a JSON Value
+    pub fn from_value(value: &Value) -> Result<Self, PluginError> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_value(value.clone())
+            .map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Whether statements that change data are allowed
+    pub fn allows_write(&self) -> bool {
+        self.allowed_operations.iter().any(|op| op == "write")
+    }
+
+    fn validate(&self) -> Result<(), PluginError> {
+        for (i, connection) in self.connections.iter().enumerate() {
+            if !SUPPORTED_TYPES.contains(&connection.db_type.as_str()) {
+                return Err(PluginError::ConfigError(format!(
+                    "Connection '{}' has unsupported type '{}' (supported: {})",
+                    connection.name,
+                    connection.db_type,
+                    SUPPORTED_TYPES.join(", ")
+                )));
+            }
+            if self.connections[..i]
+                .iter()
+                .any(|c| c.name == connection.name)
+            {
+                return Err(PluginError::ConfigError(format!(
+                    "Duplicate connection name '{}'",
+                    connection.name
+                )));
+            }
+        }
+        if self.max_rows == 0 {
+            return Err(PluginError::ConfigError(
+                "max_rows must be at least 1".to_string(),
+            ));
+        }
+        if self.statement_timeout_secs == 0 {
+            return Err(PluginError::ConfigError(
+                "statement_timeout_secs must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An open connection pool
+enum Pool {
+    /// SQLite queries go through connections opened read-only; `writer`
+    /// exists only when "write" is allowed
+    Sqlite {
+        reader: SqlitePool,
+        writer: Option<SqlitePool>,
+    },
+    Postgres(PgPool),
+}
+
+impl Pool {
+    fn db_type(&self) -> &'static str {
+        match self {
+            Pool::Sqlite { .. } => "sqlite",
+            Pool::Postgres(_) => "postgres",
+        }
+    }
+}
+
+/// A table column: name, type, nullable, default, primary key
+type ColumnInfo = (String, String, bool, Option<String>, bool);
+
+/// A configured connection, opened on first use
+struct Connection {
+    config: ConnectionConfig,
+    pool: OnceCell<Pool>,
+}
+
+/// Database plugin for SQL access
+pub struct DatabasePlugin {
+    config: DatabaseConfig,
+    connections: Vec<Connection>,
+}
+
+impl DatabasePlugin {
+    /// Plugin ID constant
+    pub const ID: &'static str = "moxie.database";
+
+    /// Create a new database plugin with the given configuration
+    pub fn new(config: DatabaseConfig) -> Self {
+        let connections = config
+            .connections
+            .iter()
+            .map(|c| Connection {
+                config: c.clone(),
+                pool: OnceCell::new(),
+            })
+            .collect();
+
+        Self {
+            config,
+            connections,
+        }
+    }
+
+    /// Create with default configuration
+    pub fn default_plugin() -> Self {
+        Self::new(DatabaseConfig::default())
+    }
+
+    /// Get the pool for a named connection, connecting if needed
+    async fn pool(&self, name: &str) -> Result<&Pool, String> {
+        let connection = self
+            .connections
+            .iter()
+            .find(|c| c.config.name == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = self
+                    .connections
+                    .iter()
+                    .map(|c| c.config.name.as_str())
+                    .collect();
+                format!(
+                    "Unknown connection '{}' (available: {})",
+                    name,
+                    names.join(", ")
+                )
+            })?;
+
+        connection
+            .pool
+            .get_or_try_init(|| self.connect(&connection.config))
+            .await
+    }
+
+    async fn connect(&self, config: &ConnectionConfig) -> Result<Pool, String> {
+        let url = std::env::var(&config.connection_string_env).map_err(|_| {
+            format!(
+                "Connection '{}' is not available: {} is not set",
+                config.name, config.connection_string_env
+            )
+        })?;
+        let failed = |e: sqlx::Error| format!("Could not connect to '{}': {}", config.name, e);
+
+        let pool = match config.db_type.as_str() {
+            "sqlite" => {
+                let options = SqliteConnectOptions::from_str(&url).map_err(failed)?;
+                let writer = self.config.allows_write().then(|| {
+                    sqlite_pool_options()
+                        .max_connections(1)
+                        .connect_lazy_with(options.clone())
+                });
+                sqlite_pool_options()
+                    .max_connections(MAX_POOL_CONNECTIONS)
+                    .connect_with(options.read_only(true))
+                    .await
+                    .map(|reader| Pool::Sqlite { reader, writer })
+            }
+            "postgres" => PgPoolOptions::new()
+                .max_connections(MAX_POOL_CONNECTIONS)
+                .connect(&url)
+                .await
+                .map(Pool::Postgres),
+            other => return Err(format!("Unsupported database type '{}'", other)),
+        }
+        .map_err(failed)?;
+
+        tracing::info!("Connected to database '{}'", config.name);
+        Ok(pool)
+    }
+
+    /// List the tables and views of a connection
+    async fn list_tables(&self, connection: &str) -> Result<ToolResult, PluginError> {
+        let pool = match self.pool(connection).await {
+            Ok(pool) => pool,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+
+        let tables: Result<Vec<(String, String)>, sqlx::Error> = match pool {
+            Pool::Sqlite { reader: pool, .. } => {
+                sqlx::query_as(
+                    "SELECT name, type FROM sqlite_master \
+                     WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' \
+                     ORDER BY name",
+                )
+                .fetch_all(pool)
+                .await
+            }
+            Pool::Postgres(pool) => {
+                sqlx::query_as(
+                    "SELECT CASE WHEN table_schema = 'public' THEN table_name::text \
+                            ELSE table_schema || '.' || table_name END, \
+                            CASE table_type WHEN 'VIEW' THEN 'view' ELSE 'table' END \
+                     FROM information_schema.tables \
+                     WHERE table_schema NOT IN ('pg_catalog', 'information_schema') \
+                     ORDER BY table_schema, table_name",
+                )
+                .fetch_all(pool)
+                .await
+            }
+        };
+
+        let tables = match tables {
+            Ok(tables) => tables,
+            Err(e) => return Ok(ToolResult::failure(format!("Query failed: {}", e))),
+        };
+
+        Ok(ToolResult::success(json!({
+            "connection": connection,
+            "count": tables.len(),
+            "tables": tables
+                .into_iter()
+                .map(|(name, kind)| json!({"name": name, "type": kind}))
+                .collect::<Vec<_>>()
+        })))
+    }
+
+    /// Show the columns of a table
+    async fn describe_table(
+        &self,
+        connection: &str,
+        table: &str,
+    ) -> Result<ToolResult, PluginError> {
+        let pool = match self.pool(connection).await {
+            Ok(pool) => pool,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+
+        let columns: Result<Vec<ColumnInfo>, sqlx::Error> = match pool {
+            Pool::Sqlite { reader: pool, .. } => {
+                sqlx::query_as(
+                    "SELECT name, type, \"notnull\" = 0, dflt_value, pk > 0 \
+                         FROM pragma_table_info(?1) ORDER BY cid",
+                )
+                .bind(table)
+                .fetch_all(pool)
+                .await
+            }
+            Pool::Postgres(pool) => {
+                let (schema, name) = table.split_once('.').unwrap_or(("public", table));
+                sqlx::query_as(
+                    "SELECT c.column_name::text, c.data_type::text, c.is_nullable = 'YES', \
+                                c.column_default::text, \
+                                EXISTS (SELECT 1 FROM information_schema.table_constraints t \
+                                        JOIN information_schema.key_column_usage k \
+                                          ON k.constraint_name = t.constraint_name \
+                                         AND k.table_schema = t.table_schema \
+                                         AND k.table_name = t.table_name \
+                                        WHERE t.constraint_type = 'PRIMARY KEY' \
+                                          AND t.table_schema = c.table_schema \
+                                          AND t.table_name = c.table_name \
+                                          AND k.column_name = c.column_name) \
+                         FROM information_schema.columns c \
+                         WHERE c.table_schema = $1 AND c.table_name = $2 \
+                         ORDER BY c.ordinal_position",
+                )
+                .bind(schema)
+                .bind(name)
+                .fetch_all(pool)
+                .await
+            }
+        };
+
+        let columns = match columns {
+            Ok(columns) => columns,
+            Err(e) => return Ok(ToolResult::failure(format!("Query failed: {}", e))),
+        };
+        if columns.is_empty() {
+            return Ok(ToolResult::failure(format!("Table not found: {}", table)));
+        }
+
+        Ok(ToolResult::success(json!({
+            "connection": connection,
+            "table": table,
+            "columns": columns
+                .into_iter()
+                .map(|(name, data_type, nullable, default, primary_key)| json!({
+                    "name": name,
+                    "type": data_type,
+                    "nullable": nullable,
+                    "default": default,
+                    "primary_key": primary_key
+                }))
+                .collect::<Vec<_>>()
+        })))
+    }
+
+    /// Run a query and return at most `limit` rows (capped at `max_rows`)
+    async fn run_query(
+        &self,
+        connection: &str,
+        sql: &str,
+        limit: Option<usize>,
+    ) -> Result<ToolResult, PluginError> {
+        let pool = match self.pool(connection).await {
+            Ok(pool) => pool,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+
+        let limit = limit
+            .unwrap_or(self.config.max_rows)
+            .clamp(1, self.config.max_rows);
+        // One row more than returned tells whether the result was truncated
+        let sql = match guard::check_query(sql, pool.db_type(), limit + 1) {
+            Ok(sql) => sql,
+            Err(e) => return Ok(ToolResult::failure(format!("Query rejected: {}", e))),
+        };
+
+        let timeout = self.statement_timeout();
+        let query = query_rows(pool, &sql, limit, timeout);
+        let (rows, truncated) = match tokio::time::timeout(timeout + TIMEOUT_GRACE, query).await {
+            Ok(Ok(rows)) => rows,
+            Ok(Err(e)) if is_timeout(&e) => return Ok(ToolResult::failure(self.timed_out())),
+            Ok(Err(e)) => return Ok(ToolResult::failure(format!("Query failed: {}", e))),
+            Err(_) => return Ok(ToolResult::failure(self.timed_out())),
+        };
+
+        Ok(ToolResult::success(json!({
+            "connection": connection,
+            "row_count": rows.len(),
+            "truncated": truncated,
+            "rows": rows
+        })))
+    }
+
+    /// Run a statement that changes data
+    async fn execute_statement(
+        &self,
+        connection: &str,
+        sql: &str,
+    ) -> Result<ToolResult, PluginError> {
+        if !self.config.allows_write() {
+            return Ok(ToolResult::failure(
+                "Write operations are disabled for this plugin",
+            ));
+        }
+
+        let pool = match self.pool(connection).await {
+            Ok(pool) => pool,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+
+        let sql = match guard::check_write(sql, pool.db_type()) {
+            Ok(sql) => sql,
+            Err(e) => return Ok(ToolResult::failure(format!("Statement rejected: {}", e))),
+        };
+
+        let timeout = self.statement_timeout();
+        match tokio::time::timeout(timeout + TIMEOUT_GRACE, execute(pool, &sql, timeout)).await {
+            Ok(Ok(rows_affected)) => Ok(ToolResult::success(json!({
+                "connection": connection,
+                "rows_affected": rows_affected
+            }))),
+            Ok(Err(e)) if is_timeout(&e) => Ok(ToolResult::failure(self.timed_out())),
+            Ok(Err(e)) => Ok(ToolResult::failure(format!("Statement failed: {}", e))),
+            Err(_) => Ok(ToolResult::failure(self.timed_out())),
+        }
+    }
+
+    fn statement_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.statement_timeout_secs)
+    }
+
+    fn timed_out(&self) -> String {
+        format!(
+            "Statement cancelled after {} seconds",
+            self.config.statement_timeout_secs
+        )
+    }
+
+    /// Build tools list based on configuration
+    fn build_tools(&self) -> Vec<ToolDefinition> {
+        let names: Vec<_> = self
+            .connections
+            .iter()
+            .map(|c| c.config.name.as_str())
+            .collect();
+        let connection = json!({
+            "type": "string",
+            "enum": names,
+            "description": "Name of the database connection"
+        });
+
+        let mut tools = vec![
+            ToolDefinition::new("list_tables", "List the tables and views in a database")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "connection": connection
+                    },
+                    "required": ["connection"]
+                }))
+                .from_plugin(Self::ID),
+            ToolDefinition::new("describe_table", "Show the columns of a database table")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "connection": connection,
+                        "table": {
+                            "type": "string",
+                            "description": "The table name (schema.table for Postgres schemas other than public)"
+                        }
+                    },
+                    "required": ["connection", "table"]
+                }))
+                .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "run_query",
+                "Run a SQL query and return the rows as JSON. Cast unusual column types to text.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "connection": connection,
+                    "sql": {
+                        "type": "string",
+                        "description": "A single SELECT, WITH, EXPLAIN or VALUES query"
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": format!("Maximum rows to return (at most {})", self.config.max_rows)
+                    }
+                },
+                "required": ["connection", "sql"]
+            }))
+            .from_plugin(Self::ID),
+        ];
+
+        if self.config.allows_write() {
+            tools.push(
+                ToolDefinition::new(
+                    "execute_statement",
+                    "Run a SQL statement that changes data (INSERT, UPDATE, DELETE, ...)",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "connection": connection,
+                        "sql": {
+                            "type": "string",
+                            "description": "The statement to run"
+                        }
+                    },
+                    "required": ["connection", "sql"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            );
+        }
+
+        tools
+    }
+}
+
+/// Run a checked query in a read-only transaction that is rolled back
+async fn query_rows(
+    pool: &Pool,
+    sql: &str,
+    limit: usize,
+    timeout: Duration,
+) -> Result<(Vec<Value>, bool), sqlx::Error> {
+    match pool {
+        Pool::Sqlite { reader, .. } => {
+            let mut tx = reader.begin().await?;
+            interrupt_after(&mut tx, timeout).await?;
+            let rows = collect_rows(sqlx::query(sql).fetch(&mut *tx), limit, sqlite_row).await;
+            tx.rollback().await?;
+            rows
+        }
+        Pool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("SET TRANSACTION READ ONLY")
+                .execute(&mut *tx)
+                .await?;
+            set_statement_timeout(&mut tx, timeout).await?;
+            let rows = collect_rows(sqlx::query(sql).fetch(&mut *tx), limit, postgres_row).await;
+            tx.rollback().await?;
+            rows
+        }
+    }
+}
+
+/// Run a checked statement and commit it
+async fn execute(pool: &Pool, sql: &str, timeout: Duration) -> Result<u64, sqlx::Error> {
+    match pool {
+        Pool::Sqlite { writer, .. } => {
+            let writer = writer.as_ref().ok_or_else(|| {
+                sqlx::Error::Configuration("connection was opened without write access".into())
+            })?;
+            let mut conn = writer.acquire().await?;
+            interrupt_after(&mut conn, timeout).await?;
+            let result = sqlx::query(sql).execute(&mut *conn).await?;
+            Ok(result.rows_affected())
+        }
+        Pool::Postgres(pool) => {
+            let mut tx = pool.begin().await?;
+            set_statement_timeout(&mut tx, timeout).await?;
+            let result = sqlx::query(sql).execute(&mut *tx).await?;
+            tx.commit().await?;
+            Ok(result.rows_affected())
+        }
+    }
+}
+
+/// SQLite pool options shared by readers and writers
+///
+/// Connections go back to the pool without the deadline of their last
+/// statement.
+fn sqlite_pool_options() -> SqlitePoolOptions {
+    SqlitePoolOptions::new().after_release(|conn, _| {
+        Box::pin(async move {
+            conn.lock_handle().await?.remove_progress_handler();
+            Ok(true)
+        })
+    })
+}
+
+/// Let SQLite interrupt statements still running after `timeout`
+///
+/// SQLite has no statement timeout of its own; the progress handler is the
+/// counterpart of Postgres' `statement_timeout`.
+async fn interrupt_after(
+    conn: &mut sqlx::SqliteConnection,
+    timeout: Duration,
+) -> Result<(), sqlx::Error> {
+    let deadline = Instant::now() + timeout;
+    conn.lock_handle()
+        .await?
+        .set_progress_handler(SQLITE_PROGRESS_STEPS, move || Instant::now() < deadline);
+    Ok(())
+}
+
+/// Whether the database cancelled a statement for running too long
+fn is_timeout(error: &sqlx::Error) -> bool {
+    const SQLITE_INTERRUPT: &str = "9";
+    const POSTGRES_QUERY_CANCELED: &str = "57014";
+
+    match error {
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code == SQLITE_INTERRUPT || code == POSTGRES_QUERY_CANCELED),
+        _ => false,
+    }
+}
+
+/// Let Postgres cancel the statement itself, besides the client-side timeout
+async fn set_statement_timeout(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    timeout: Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "SET LOCAL statement_timeout = {}",
+        timeout.as_millis()
+    ))
+    .execute(&mut **tx)
+    .await
+    .map(|_| ())
+}
+
+/// Collect up to `limit` rows, reporting whether more were available
+async fn collect_rows<R>(
+    mut rows: BoxStream<'_, Result<R, sqlx::Error>>,
+    limit: usize,
+    to_json: fn(&R) -> Value,
+) -> Result<(Vec<Value>, bool), sqlx::Error> {
+    let mut collected = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        if collected.len() == limit {
+            return Ok((collected, true));
+        }
+        collected.push(to_json(&row));
+    }
+    Ok((collected, false))
+}
+
+fn decode<'r, DB: Database, T: Decode<'r, DB>>(raw: <DB as Database>::ValueRef<'r>) -> Option<Value>
+where
+    Value: From<T>,
+{
+    T::decode(raw).ok().map(Value::from)
+}
+
+fn sqlite_row(row: &SqliteRow) -> Value {
+    let mut object = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = row.try_get_raw(i).map(sqlite_value).unwrap_or(Value::Null);
+        object.insert(column.name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
+fn sqlite_value(raw: SqliteValueRef<'_>) -> Value {
+    if raw.is_null() {
+        return Value::Null;
+    }
+    // The storage class of the value itself, not the declared column type
+    let type_name = raw.type_info().name().to_string();
+    let value = match type_name.as_str() {
+        "INTEGER" => decode::<Sqlite, i64>(raw),
+        "REAL" => decode::<Sqlite, f64>(raw),
+        "BLOB" => <Vec<u8> as Decode<Sqlite>>::decode(raw)
+            .ok()
+            .map(|bytes| Value::String(BASE64.encode(bytes))),
+        _ => decode::<Sqlite, String>(raw),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+fn postgres_row(row: &PgRow) -> Value {
+    let mut object = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = row
+            .try_get_raw(i)
+            .map(postgres_value)
+            .unwrap_or(Value::Null);
+        object.insert(column.name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
+fn postgres_value(raw: PgValueRef<'_>) -> Value {
+    if raw.is_null() {
+        return Value::Null;
+    }
+    let type_name = raw.type_info().name().to_string();
+    let value = match type_name.as_str() {
+        "BOOL" => decode::<Postgres, bool>(raw),
+        "INT2" => decode::<Postgres, i16>(raw),
+        "INT4" => decode::<Postgres, i32>(raw),
+        "INT8" => decode::<Postgres, i64>(raw),
+        "FLOAT4" => decode::<Postgres, f32>(raw),
+        "FLOAT8" => decode::<Postgres, f64>(raw),
+        "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" | "CHAR" => decode::<Postgres, String>(raw),
+        "JSON" | "JSONB" => decode::<Postgres, Value>(raw),
+        "UUID" => <uuid::Uuid as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|uuid| Value::String(uuid.to_string())),
+        "TIMESTAMPTZ" => <chrono::DateTime<chrono::Utc> as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|time| Value::String(time.to_rfc3339())),
+        "TIMESTAMP" => <chrono::NaiveDateTime as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|time| Value::String(time.to_string())),
+        "DATE" => <chrono::NaiveDate as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|date| Value::String(date.to_string())),
+        "TIME" => <chrono::NaiveTime as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|time| Value::String(time.to_string())),
+        "BYTEA" => <Vec<u8> as Decode<Postgres>>::decode(raw)
+            .ok()
+            .map(|bytes| Value::String(BASE64.encode(bytes))),
+        other => Some(Value::String(format!("<unsupported type {}>", other))),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+// ============================================================================
+// New Plugin trait implementation
+// ============================================================================
+
+#[async_trait]
+impl Plugin for DatabasePlugin {
+    fn manifest(&self) -> PluginManifest {
+        PluginManifest::new(Self::ID, "Database", "Query SQLite and Postgres databases")
+            .with_version(1, 0, 0)
+            .with_author("Moxie AI")
+            .with_category(PluginCategory::Database)
+            .with_keywords(vec!["database", "sql", "sqlite", "postgres", "query"])
+            .with_config_field(
+                ConfigFieldBuilder::new("connections", ConfigFieldType::TableArray)
+                    .label("Connections")
+                    .description("Named database connections")
+                    .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("allowed_operations", ConfigFieldType::StringArray)
+                    .label("Allowed Operations")
+                    .description("\"read\", and \"write\" to allow changing data")
+                    .default_value(json!(["read"]))
+                    .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("max_rows", ConfigFieldType::Number)
+                    .label("Max Rows")
+                    .description("Maximum number of rows a query returns")
+                    .default_value(json!(100))
+                    .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("statement_timeout_secs", ConfigFieldType::Number)
+                    .label("Statement Timeout")
+                    .description("Seconds a statement may run before it is cancelled")
+                    .default_value(json!(30))
+                    .build(),
+            )
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        let connection = || {
+            params["connection"]
+                .as_str()
+                .ok_or_else(|| PluginError::InvalidParameters("connection is required".into()))
+        };
+        let sql = || {
+            params["sql"]
+                .as_str()
+                .ok_or_else(|| PluginError::InvalidParameters("sql is required".into()))
+        };
+
+        match tool {
+            "list_tables" => self.list_tables(connection()?).await,
+            "describe_table" => {
+                let table = params["table"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("table is required".into()))?;
+                self.describe_table(connection()?, table).await
+            }
+            "run_query" => {
+                let limit = params["limit"].as_u64().map(|n| n as usize);
+                self.run_query(connection()?, sql()?, limit).await
+            }
+            "execute_statement" => self.execute_statement(connection()?, sql()?).await,
+            _ => Err(PluginError::ToolNotFound(tool.to_string())),
+        }
+    }
+
+    async fn on_init(&mut self, ctx: &PluginContext) -> Result<(), PluginError> {
+        // Update config from context if provided
+        if !ctx.config.is_null() {
+            *self = Self::new(DatabaseConfig::from_value(&ctx.config)?);
+        }
+
+        for connection in &self.config.connections {
+            if std::env::var_os(&connection.connection_string_env).is_none() {
+                tracing::warn!(
+                    "Database connection '{}': {} is not set",
+                    connection.name,
+                    connection.connection_string_env
+                );
+            }
+        }
+
+        tracing::info!(
+            "Database plugin initialized with {} connection(s)",
+            self.connections.len()
+        );
+
+        Ok(())
+    }
+
+    async fn on_shutdown(&mut self) -> Result<(), PluginError> {
+        for connection in &self.connections {
+            match connection.pool.get() {
+                Some(Pool::Sqlite { reader, writer }) => {
+                    reader.close().await;
+                    if let Some(writer) = writer {
+                        writer.close().await;
+                    }
+                }
+                Some(Pool::Postgres(pool)) => pool.close().await,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// ============================================================================
+// Legacy Plugin trait implementation (backwards compatibility)
+// ============================================================================
+
+#[async_trait]
+impl LegacyPlugin for DatabasePlugin {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    fn description(&self) -> &str {
+        "Query SQLite and Postgres databases"
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        Plugin::execute(self, tool, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::traits::Plugin as NewPlugin;
+    use std::path::PathBuf;
+
+    /// A SQLite database with a small `products` table, exposed as "shop"
+    async fn test_database(allowed_operations: &[&str]) -> (DatabasePlugin, PathBuf) {
+        let path = std::env::temp_dir().join(format!("moxie-db-{}.sqlite", uuid::Uuid::new_v4()));
+        let options = SqliteConnectOptions::from_str(&path.to_string_lossy())
+            .unwrap()
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await.unwrap();
+        sqlx::query(
+            "CREATE TABLE products (id INTEGER PRIMARY KEY, name TEXT NOT NULL, price REAL, image BLOB)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for i in 1..=5 {
+            sqlx::query("INSERT INTO products (name, price) VALUES (?1, ?2)")
+                .bind(format!("Product {}", i))
+                .bind(i as f64 * 2.5)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool.close().await;
+
+        let env = format!("MOXIE_TEST_DB_{}", uuid::Uuid::new_v4().simple());
+        std::env::set_var(&env, path.to_string_lossy().as_ref());
+
+        let plugin = DatabasePlugin::new(DatabaseConfig {
+            connections: vec![ConnectionConfig {
+                name: "shop".to_string(),
+                db_type: "sqlite".to_string(),
+                connection_string_env: env,
+            }],
+            allowed_operations: allowed_operations.iter().map(|s| s.to_string()).collect(),
+            max_rows: 3,
+            statement_timeout_secs: 5,
+        });
+        (plugin, path)
+    }
+
+    #[test]
+    fn test_manifest() {
+        let plugin = DatabasePlugin::default_plugin();
+        let manifest = NewPlugin::manifest(&plugin);
+
+        assert_eq!(manifest.id, "moxie.database");
+        assert_eq!(manifest.category, PluginCategory::Database);
+        assert_eq!(manifest.config_key(), "database");
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = DatabaseConfig::from_value(&json!({
+            "connections": [{"name": "erp", "type": "mysql", "connection_string_env": "ERP_DB"}]
+        }));
+        assert!(matches!(config, Err(PluginError::ConfigError(e)) if e.contains("mysql")));
+
+        let config = DatabaseConfig::from_value(&json!({
+            "connections": [
+                {"name": "a", "type": "sqlite", "connection_string_env": "A"},
+                {"name": "a", "type": "postgres", "connection_string_env": "B"}
+            ]
+        }));
+        assert!(matches!(config, Err(PluginError::ConfigError(e)) if e.contains("Duplicate")));
+
+        let config = DatabaseConfig::from_value(&json!({})).unwrap();
+        assert_eq!(config.allowed_operations, vec!["read"]);
+        assert_eq!(config.max_rows, 100);
+        assert!(!config.allows_write());
+    }
+
+    #[tokio::test]
+    async fn test_inspect_and_query() {
+        let (plugin, path) = test_database(&["read"]).await;
+
+        let tables = plugin.list_tables("shop").await.unwrap();
+        assert!(tables.success);
+        assert_eq!(
+            tables.output["tables"],
+            json!([{"name": "products", "type": "table"}])
+        );
+
+        let columns = plugin.describe_table("shop", "products").await.unwrap();
+        assert!(columns.success);
+        assert_eq!(columns.output["columns"][0]["name"], "id");
+        assert_eq!(columns.output["columns"][0]["primary_key"], true);
+        assert_eq!(columns.output["columns"][1]["nullable"], false);
+        assert!(
+            !plugin
+                .describe_table("shop", "missing")
+                .await
+                .unwrap()
+                .success
+        );
+
+        // Rows are capped at max_rows
+        let result = plugin
+            .run_query(
+                "shop",
+                "SELECT id, name, price, image FROM products ORDER BY id",
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(result.success);
+        assert_eq!(result.output["row_count"], 3);
+        assert_eq!(result.output["truncated"], true);
+        assert_eq!(
+            result.output["rows"][0],
+            json!({"id": 1, "name": "Product 1", "price": 2.5, "image": null})
+        );
+
+        let result = plugin
+            .run_query("shop", "select count(*) as n from products", Some(10))
+            .await
+            .unwrap();
+        assert_eq!(result.output["rows"], json!([{"n": 5}]));
+        assert_eq!(result.output["truncated"], false);
+
+        let result = plugin
+            .run_query("shop", "DELETE FROM products", None)
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().starts_with("Query rejected"));
+        let result = plugin
+            .run_query("shop", "SELECT 1; DELETE FROM products", None)
+            .await
+            .unwrap();
+        assert!(!result.success);
+        let result = plugin.run_query("other", "SELECT 1", None).await.unwrap();
+        assert!(result.error.unwrap().contains("Unknown connection"));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_statements_are_interrupted() {
+        let (mut plugin, path) = test_database(&["read"]).await;
+        plugin.config.statement_timeout_secs = 1;
+
+        let started = Instant::now();
+        let result = plugin
+            .run_query(
+                "shop",
+                "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n) \
+                 SELECT count(*) FROM n",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.error.unwrap(), "Statement cancelled after 1 seconds");
+        assert!(started.elapsed() < Duration::from_secs(1) + TIMEOUT_GRACE);
+
+        // The connection is usable again, without the expired deadline
+        let tables = plugin.list_tables("shop").await.unwrap();
+        assert!(tables.success);
+        let result = plugin
+            .run_query("shop", "SELECT count(*) AS n FROM products", None)
+            .await
+            .unwrap();
+        assert_eq!(result.output["rows"], json!([{"n": 5}]));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_write_requires_permission() {
+        let (plugin, path) = test_database(&["read"]).await;
+        assert!(!plugin
+            .build_tools()
+            .iter()
+            .any(|t| t.name == "execute_statement"));
+        let result = plugin
+            .execute_statement("shop", "DELETE FROM products")
+            .await
+            .unwrap();
+        assert!(!result.success);
+        std::fs::remove_file(path).ok();
+
+        let (plugin, path) = test_database(&["read", "write"]).await;
+        let tool = plugin
+            .build_tools()
+            .into_iter()
+            .find(|t| t.name == "execute_statement")
+            .unwrap();
+        assert!(tool.requires_confirmation);
+
+        let result = plugin
+            .execute_statement("shop", "DELETE FROM products WHERE price > 5")
+            .await
+            .unwrap();
+        assert!(result.success);
+        assert_eq!(result.output["rows_affected"], 3);
+        std::fs::remove_file(path).ok();
+    }
+}
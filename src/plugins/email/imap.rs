@@ -0,0 +1,598 @@
+//! A small IMAP client
+//!
+//! Implements the commands the email plugin needs (RFC 3501): LOGIN,
+//! EXAMINE, UID SEARCH, UID FETCH and APPEND, over TLS, STARTTLS or plain
+//! TCP. Responses are parsed just far enough to read search results and
+//! fetched message data.
+
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+
+use super::Security;
+
+/// IMAP errors
+#[derive(Debug, thiserror::Error)]
+pub enum ImapError {
+    #[error("IMAP connection failed: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("IMAP TLS failed: {0}")]
+    Tls(#[from] native_tls::Error),
+
+    #[error("IMAP server refused {command}: {message}")]
+    Refused { command: String, message: String },
+
+    #[error("Unexpected IMAP response: {0}")]
+    Protocol(String),
+
+    #[error("IMAP server did not answer in time")]
+    Timeout,
+
+    #[error("IMAP response is larger than {0} bytes")]
+    TooLarge(usize),
+}
+
+/// What to search a mailbox for; empty matches everything
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub text: Option<String>,
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub unseen: bool,
+}
+
+/// A message returned by `UID FETCH`
+#[derive(Debug, Clone, Default)]
+pub struct Fetched {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    /// The fetched body section
+    pub body: Vec<u8>,
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// Part of a command: plain text, or a literal sent once the server asks
+enum Part {
+    Text(String),
+    Literal(Vec<u8>),
+}
+
+/// A connection to an IMAP server
+pub struct ImapClient {
+    stream: BufReader<Box<dyn Stream>>,
+    next_tag: u32,
+    timeout: Duration,
+    /// Largest response accepted, literals included
+    max_size: usize,
+}
+
+impl ImapClient {
+    /// Connect and read the server greeting; responses larger than
+    /// `max_size` bytes are refused
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        security: Security,
+        timeout: Duration,
+        max_size: usize,
+    ) -> Result<Self, ImapError> {
+        let tcp = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| ImapError::Timeout)??;
+        let stream: Box<dyn Stream> = match security {
+            Security::Tls => Box::new(tls(host, tcp).await?),
+            Security::Starttls | Security::None => Box::new(tcp),
+        };
+        let mut client = Self {
+            stream: BufReader::new(stream),
+            next_tag: 0,
+            timeout,
+            max_size,
+        };
+
+        let greeting = tokio::time::timeout(timeout, read_response(&mut client.stream, max_size))
+            .await
+            .map_err(|_| ImapError::Timeout)??;
+        if !greeting.starts_with(b"* OK") {
+            return Err(ImapError::Protocol(text(&greeting)));
+        }
+
+        if security == Security::Starttls {
+            client.command(vec![Part::Text("STARTTLS".into())]).await?;
+            let Self {
+                stream, next_tag, ..
+            } = client;
+            client = Self {
+                stream: BufReader::new(Box::new(tls(host, stream.into_inner()).await?)),
+                next_tag,
+                timeout,
+                max_size,
+            };
+        }
+
+        Ok(client)
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<(), ImapError> {
+        self.command(vec![
+            Part::Text("LOGIN".into()),
+            string(username),
+            string(password),
+        ])
+        .await
+        .map(drop)
+    }
+
+    /// Open a mailbox read-only
+    pub async fn examine(&mut self, mailbox: &str) -> Result<(), ImapError> {
+        self.command(vec![Part::Text("EXAMINE".into()), string(mailbox)])
+            .await
+            .map(drop)
+    }
+
+    /// UIDs of the messages matching `query`, in ascending order
+    pub async fn uid_search(&mut self, query: &SearchQuery) -> Result<Vec<u32>, ImapError> {
+        let mut parts = vec![Part::Text("UID SEARCH".into())];
+        let strings = [&query.text, &query.from, &query.subject];
+        if strings
+            .iter()
+            .any(|s| s.as_ref().is_some_and(|s| !s.is_ascii()))
+        {
+            parts.push(Part::Text("CHARSET UTF-8".into()));
+        }
+        for (key, value) in ["TEXT", "FROM", "SUBJECT"].iter().zip(strings) {
+            if let Some(value) = value {
+                parts.push(Part::Text(key.to_string()));
+                parts.push(string(value));
+            }
+        }
+        if let Some(since) = query.since {
+            parts.push(Part::Text(format!("SINCE {}", since.format("%-d-%b-%Y"))));
+        }
+        if query.unseen {
+            parts.push(Part::Text("UNSEEN".into()));
+        }
+        if parts.len() == 1 {
+            parts.push(Part::Text("ALL".into()));
+        }
+
+        let mut uids = Vec::new();
+        for response in self.command(parts).await? {
+            let items = parse(&response)?;
+            if matches!(items.get(1), Some(Item::Atom(a)) if a.eq_ignore_ascii_case("SEARCH")) {
+                uids.extend(items[2..].iter().filter_map(Item::number));
+            }
+        }
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// Fetch flags and a body section (e.g. `HEADER` or an empty string for
+    /// the whole message) without marking the messages read
+    pub async fn uid_fetch(
+        &mut self,
+        uids: &[u32],
+        section: &str,
+    ) -> Result<Vec<Fetched>, ImapError> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let set = uids
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let responses = self
+            .command(vec![Part::Text(format!(
+                "UID FETCH {} (UID FLAGS BODY.PEEK[{}])",
+                set, section
+            ))])
+            .await?;
+
+        let mut fetched = Vec::new();
+        for response in responses {
+            let items = parse(&response)?;
+            match items.as_slice() {
+                [_, _, Item::Atom(kind), Item::List(data)]
+                    if kind.eq_ignore_ascii_case("FETCH") =>
+                {
+                    fetched.push(fetch_data(data));
+                }
+                _ => {}
+            }
+        }
+        Ok(fetched)
+    }
+
+    /// Store a message in a mailbox with the given flags, e.g. `\Draft`
+    pub async fn append(
+        &mut self,
+        mailbox: &str,
+        flags: &str,
+        message: &[u8],
+    ) -> Result<(), ImapError> {
+        self.command(vec![
+            Part::Text("APPEND".into()),
+            string(mailbox),
+            Part::Text(format!("({})", flags)),
+            Part::Literal(message.to_vec()),
+        ])
+        .await
+        .map(drop)
+    }
+
+    /// End the session; errors are ignored since the work is done
+    pub async fn logout(mut self) {
+        let _ = self.command(vec![Part::Text("LOGOUT".into())]).await;
+    }
+
+    /// Run a command and return its untagged responses
+    async fn command(&mut self, parts: Vec<Part>) -> Result<Vec<Vec<u8>>, ImapError> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, self.run(parts))
+            .await
+            .map_err(|_| ImapError::Timeout)?
+    }
+
+    async fn run(&mut self, parts: Vec<Part>) -> Result<Vec<Vec<u8>>, ImapError> {
+        self.next_tag += 1;
+        let tag = format!("A{}", self.next_tag);
+        let name = match parts.first() {
+            Some(Part::Text(text)) => text.clone(),
+            _ => String::new(),
+        };
+
+        let mut line = tag.clone().into_bytes();
+        for part in parts {
+            line.push(b' ');
+            match part {
+                Part::Text(text) => line.extend_from_slice(text.as_bytes()),
+                Part::Literal(bytes) => {
+                    line.extend_from_slice(format!("{{{}}}\r\n", bytes.len()).as_bytes());
+                    self.stream.write_all(&line).await?;
+                    self.stream.flush().await?;
+                    line.clear();
+
+                    // Wait for the go-ahead before sending the literal
+                    let response = read_response(&mut self.stream, self.max_size).await?;
+                    if !response.starts_with(b"+") {
+                        return Err(refused(&name, &tag, &response));
+                    }
+                    line.extend_from_slice(&bytes);
+                }
+            }
+        }
+        line.extend_from_slice(b"\r\n");
+        self.stream.write_all(&line).await?;
+        self.stream.flush().await?;
+
+        let mut untagged = Vec::new();
+        loop {
+            let response = read_response(&mut self.stream, self.max_size).await?;
+            if let Some(status) = response.strip_prefix(format!("{} ", tag).as_bytes()) {
+                if status.starts_with(b"OK") {
+                    return Ok(untagged);
+                }
+                return Err(refused(&name, &tag, &response));
+            }
+            untagged.push(response);
+        }
+    }
+}
+
+async fn tls<S>(host: &str, stream: S) -> Result<tokio_native_tls::TlsStream<S>, ImapError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+    Ok(connector.connect(host, stream).await?)
+}
+
+/// Read one response line, including any literals it announces, refusing
+/// responses larger than `max_size` before reading them whole
+async fn read_response<R: AsyncBufReadExt + Unpin>(
+    stream: &mut R,
+    max_size: usize,
+) -> Result<Vec<u8>, ImapError> {
+    let mut response = Vec::new();
+    loop {
+        let start = response.len();
+        // One byte more than fits tells a line that is too long
+        let room = (max_size - start) as u64 + 1;
+        if (&mut *stream)
+            .take(room)
+            .read_until(b'\n', &mut response)
+            .await?
+            == 0
+        {
+            return Err(ImapError::Protocol("connection closed".into()));
+        }
+        if response.len() > max_size {
+            return Err(ImapError::TooLarge(max_size));
+        }
+        match literal_length(&response[start..]) {
+            Some(length) => {
+                if length > max_size - response.len() {
+                    return Err(ImapError::TooLarge(max_size));
+                }
+                let mut literal = vec![0; length];
+                stream.read_exact(&mut literal).await?;
+                response.extend_from_slice(&literal);
+            }
+            None => return Ok(response),
+        }
+    }
+}
+
+/// The length of a literal announced at the end of `line` (`{123}\r\n`)
+fn literal_length(line: &[u8]) -> Option<usize> {
+    let line = line.strip_suffix(b"\r\n").unwrap_or(line);
+    let line = line.strip_suffix(b"}")?;
+    let open = line.iter().rposition(|&b| b == b'{')?;
+    std::str::from_utf8(&line[open + 1..]).ok()?.parse().ok()
+}
+
+fn refused(command: &str, tag: &str, response: &[u8]) -> ImapError {
+    let message = text(response);
+    let message = message
+        .strip_prefix(tag)
+        .unwrap_or(&message)
+        .trim()
+        .to_string();
+    // Don't echo credentials back in errors
+    let command = command.split_whitespace().next().unwrap_or_default();
+    ImapError::Refused {
+        command: command.to_string(),
+        message,
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim_end().to_string()
+}
+
+/// A string argument: quoted when it is plain ASCII, otherwise a literal
+fn string(value: &str) -> Part {
+    if value.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        Part::Text(format!(
+            "\"{}\"",
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+    } else {
+        Part::Literal(value.as_bytes().to_vec())
+    }
+}
+
+/// A parsed piece of a response
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Atom(String),
+    Str(Vec<u8>),
+    List(Vec<Item>),
+    Nil,
+}
+
+impl Item {
+    fn number(&self) -> Option<u32> {
+        match self {
+            Item::Atom(atom) => atom.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+fn fetch_data(data: &[Item]) -> Fetched {
+    let mut fetched = Fetched::default();
+    for pair in data.chunks(2) {
+        let [Item::Atom(key), value] = pair else {
+            continue;
+        };
+        let key = key.to_ascii_uppercase();
+        match value {
+            Item::Atom(_) if key == "UID" => fetched.uid = value.number().unwrap_or_default(),
+            Item::List(flags) if key == "FLAGS" => {
+                fetched.flags = flags
+                    .iter()
+                    .filter_map(|flag| match flag {
+                        Item::Atom(flag) => Some(flag.clone()),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            Item::Str(body) if key.starts_with("BODY[") => fetched.body = body.clone(),
+            _ => {}
+        }
+    }
+    fetched
+}
+
+/// Parse a whole response into items
+fn parse(response: &[u8]) -> Result<Vec<Item>, ImapError> {
+    let mut parser = Parser {
+        input: response,
+        pos: 0,
+    };
+    let items = parser.items()?;
+    if parser.pos < response.len() {
+        return Err(ImapError::Protocol(text(response)));
+    }
+    Ok(items)
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn error(&self) -> ImapError {
+        ImapError::Protocol(text(self.input))
+    }
+
+    /// Items up to the end of input or a closing parenthesis
+    fn items(&mut self) -> Result<Vec<Item>, ImapError> {
+        let mut items = Vec::new();
+        loop {
+            while matches!(self.peek(), Some(b' ' | b'\r' | b'\n')) {
+                self.pos += 1;
+            }
+            match self.peek() {
+                None | Some(b')') => return Ok(items),
+                Some(_) => items.push(self.item()?),
+            }
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, ImapError> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let items = self.items()?;
+                if self.peek() != Some(b')') {
+                    return Err(self.error());
+                }
+                self.pos += 1;
+                Ok(Item::List(items))
+            }
+            Some(b'"') => self.quoted(),
+            Some(b'{') => self.literal(),
+            _ => {
+                let atom = self.atom();
+                if atom.is_empty() {
+                    Err(self.error())
+                } else if atom.eq_ignore_ascii_case("NIL") {
+                    Ok(Item::Nil)
+                } else {
+                    Ok(Item::Atom(atom))
+                }
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<Item, ImapError> {
+        self.pos += 1;
+        let mut value = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(Item::Str(value));
+                }
+                Some(b'\\') => {
+                    value.push(*self.input.get(self.pos + 1).ok_or_else(|| self.error())?);
+                    self.pos += 2;
+                }
+                Some(b) => {
+                    value.push(b);
+                    self.pos += 1;
+                }
+                None => return Err(self.error()),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Item, ImapError> {
+        let rest = &self.input[self.pos..];
+        let close = rest
+            .iter()
+            .position(|&b| b == b'}')
+            .ok_or_else(|| self.error())?;
+        let length: usize = std::str::from_utf8(&rest[1..close])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| self.error())?;
+        let start = self.pos + close + 3; // past "}\r\n"
+        let value = self
+            .input
+            .get(start..start + length)
+            .ok_or_else(|| self.error())?;
+        self.pos = start + length;
+        Ok(Item::Str(value.to_vec()))
+    }
+
+    /// An atom; brackets may contain spaces and parentheses, as in
+    /// `BODY[HEADER.FIELDS (FROM)]`
+    fn atom(&mut self) -> String {
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(b) = self.peek() {
+            match b {
+                b'[' => depth += 1,
+                b']' => depth -= 1,
+                b' ' | b'(' | b')' | b'\r' | b'\n' if depth == 0 => break,
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fetch() {
+        let response = b"* 3 FETCH (UID 42 FLAGS (\\Seen \\Answered) BODY[HEADER.FIELDS (FROM SUBJECT)] {23}\r\nSubject: Hi (there)\r\n\r\n)\r\n";
+        assert_eq!(
+            literal_length(b"* 3 FETCH (UID 42 BODY[] {22}\r\n"),
+            Some(22)
+        );
+
+        let items = parse(response).unwrap();
+        let [_, _, Item::Atom(fetch), Item::List(data)] = items.as_slice() else {
+            panic!("unexpected items: {:?}", items);
+        };
+        assert_eq!(fetch, "FETCH");
+        let fetched = fetch_data(data);
+        assert_eq!(fetched.uid, 42);
+        assert_eq!(fetched.flags, vec!["\\Seen", "\\Answered"]);
+        assert_eq!(fetched.body, b"Subject: Hi (there)\r\n\r\n");
+
+        assert_eq!(
+            parse(b"* SEARCH 2 \"a \\\"b\\\"\" NIL\r\n").unwrap(),
+            vec![
+                Item::Atom("*".into()),
+                Item::Atom("SEARCH".into()),
+                Item::Atom("2".into()),
+                Item::Str(b"a \"b\"".to_vec()),
+                Item::Nil,
+            ]
+        );
+        assert!(parse(b"* 1 FETCH (UID 1\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_response_size_limit() {
+        let read = |input: &'static [u8], max_size| async move {
+            read_response(&mut BufReader::new(input), max_size).await
+        };
+
+        let response = read(b"* 1 FETCH (BODY[] {5}\r\nhello)\r\n", 64).await;
+        assert_eq!(response.unwrap(), b"* 1 FETCH (BODY[] {5}\r\nhello)\r\n");
+
+        // An announced literal is refused before it is read
+        let response = read(b"* 1 FETCH (BODY[] {4294967295}\r\n", 64).await;
+        assert!(matches!(response, Err(ImapError::TooLarge(64))));
+
+        // So is a line that doesn't end in time
+        let response = read(&[b'x'; 100], 64).await;
+        assert!(matches!(response, Err(ImapError::TooLarge(64))));
+
+        let response = read(b"* OK", 64).await;
+        assert_eq!(response.unwrap(), b"* OK");
+        assert!(matches!(read(b"", 64).await, Err(ImapError::Protocol(_))));
+    }
+}
@@ -0,0 +1,1268 @@
+//! Email plugin over IMAP and SMTP
+//!
+//! Searches and reads a mailbox over IMAP, saves reply drafts to the drafts
+//! mailbox and sends mail over SMTP. Mailboxes are opened read-only, and
+//! messages are fetched without marking them read. Sending is off unless
+//! `allow_send` is set, and always asks for confirmation.
+//!
+//! Message contents come from outside senders; the model sees them as tool
+//! output, not as instructions.
+//!
+//! # Tools
+//!
+//! - `search_mail` - Find messages by text, sender, subject, date or unread
+//! - `read_message` - Read a message's headers, text and attachment names
+//! - `draft_reply` - Save a reply to a message in the drafts mailbox
+//! - `send_mail` - Send a message (if enabled)
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [plugins.email]
+//! imap_host = "imap.example.com"
+//! smtp_host = "smtp.example.com"
+//! username = "analyst@example.com"
+//! password_env = "MOXIE_EMAIL_PASSWORD"
+//! allow_send = false
+//! ```
+
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use mail_parser::{Address, MessageParser, MimeHeaders};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::time::Duration;
+
+use crate::plugins::manifest::{
+    ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest,
+};
+use crate::plugins::traits::{Plugin, PluginContext};
+use crate::plugins::{LegacyPlugin, PluginError, ToolDefinition, ToolResult};
+
+pub mod imap;
+
+use imap::{ImapClient, ImapError, SearchQuery};
+
+/// Characters of message text returned by `read_message`
+const MAX_BODY_CHARS: usize = 20_000;
+
+/// Header fields fetched for search results
+const SUMMARY_FIELDS: &str = "HEADER.FIELDS (FROM TO SUBJECT DATE)";
+
+/// How a connection to a mail server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// TLS from the start (IMAP 993, SMTP 465)
+    #[default]
+    Tls,
+    /// Plain connection upgraded with STARTTLS (IMAP 143, SMTP 587)
+    Starttls,
+    /// No encryption; only for local servers
+    None,
+}
+
+/// Configuration for the email plugin
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    /// IMAP server; without it, only `send_mail` is available
+    #[serde(default)]
+    pub imap_host: Option<String>,
+
+    #[serde(default = "default_imap_port")]
+    pub imap_port: u16,
+
+    #[serde(default)]
+    pub imap_security: Security,
+
+    /// SMTP server; without it, mail can't be sent
+    #[serde(default)]
+    pub smtp_host: Option<String>,
+
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+
+    #[serde(default = "default_smtp_security")]
+    pub smtp_security: Security,
+
+    /// Login for both servers
+    #[serde(default)]
+    pub username: String,
+
+    #[serde(default)]
+    pub password: String,
+
+    /// Environment variable holding the password, instead of `password`
+    #[serde(default)]
+    pub password_env: Option<String>,
+
+    /// Sender of drafts and sent mail (default: the username)
+    #[serde(default)]
+    pub from_address: Option<String>,
+
+    /// Mailbox searched by default
+    #[serde(default = "default_mailbox")]
+    pub mailbox: String,
+
+    /// Mailbox reply drafts are saved to
+    #[serde(default = "default_drafts_mailbox")]
+    pub drafts_mailbox: String,
+
+    /// Whether to offer `send_mail`
+    #[serde(default)]
+    pub allow_send: bool,
+
+    /// Maximum number of messages returned by a search
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+
+    /// Seconds to wait for a mail server
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Largest message read from the IMAP server, in bytes
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_smtp_security() -> Security {
+    Security::Starttls
+}
+
+fn default_mailbox() -> String {
+    "INBOX".to_string()
+}
+
+fn default_drafts_mailbox() -> String {
+    "Drafts".to_string()
+}
+
+fn default_max_results() -> usize {
+    20
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_message_size() -> usize {
+    25 * 1024 * 1024 // 25 MB
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            imap_host: None,
+            imap_port: default_imap_port(),
+            imap_security: Security::Tls,
+            smtp_host: None,
+            smtp_port: default_smtp_port(),
+            smtp_security: default_smtp_security(),
+            username: String::new(),
+            password: String::new(),
+            password_env: None,
+            from_address: None,
+            mailbox: default_mailbox(),
+            drafts_mailbox: default_drafts_mailbox(),
+            allow_send: false,
+            max_results: default_max_results(),
+            timeout_secs: default_timeout_secs(),
+            max_message_size: default_max_message_size(),
+        }
+    }
+}
+
+// The password must not end up in logs
+impl std::fmt::Debug for EmailConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailConfig")
+            .field("imap_host", &self.imap_host)
+            .field("imap_port", &self.imap_port)
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("password_env", &self.password_env)
+            .field("allow_send", &self.allow_send)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EmailConfig {
+    /// Parse configuration from a JSON Value
+    pub fn from_value(value: &Value) -> Result<Self, PluginError> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_value(value.clone())
+            .map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), PluginError> {
+        if self.imap_host.is_none() && self.smtp_host.is_none() {
+            return Err(PluginError::ConfigError(
+                "set imap_host, smtp_host or both".to_string(),
+            ));
+        }
+        if self.password_env.is_some() && !self.password.is_empty() {
+            return Err(PluginError::ConfigError(
+                "set password or password_env, not both".to_string(),
+            ));
+        }
+        if self.max_results == 0 || self.timeout_secs == 0 || self.max_message_size == 0 {
+            return Err(PluginError::ConfigError(
+                "max_results, timeout_secs and max_message_size must be at least 1".to_string(),
+            ));
+        }
+        self.sender()
+            .map_err(|e| PluginError::ConfigError(format!("from_address: {}", e)))?;
+        Ok(())
+    }
+
+    /// The address mail is sent from
+    fn sender(&self) -> Result<Mailbox, String> {
+        let address = self.from_address.as_deref().unwrap_or(&self.username);
+        parse_mailbox(address)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// Email plugin for IMAP and SMTP mailboxes
+pub struct EmailPlugin {
+    config: EmailConfig,
+}
+
+impl EmailPlugin {
+    /// Plugin ID constant
+    pub const ID: &'static str = "moxie.email";
+
+    /// Create a new email plugin with the given configuration
+    pub fn new(config: EmailConfig) -> Self {
+        Self { config }
+    }
+
+    /// Create with default configuration
+    pub fn default_plugin() -> Self {
+        Self::new(EmailConfig::default())
+    }
+
+    fn imap_enabled(&self) -> bool {
+        self.config.imap_host.is_some()
+    }
+
+    fn send_enabled(&self) -> bool {
+        self.config.smtp_host.is_some() && self.config.allow_send
+    }
+
+    /// Log in and open `mailbox` read-only
+    async fn open(&self, mailbox: &str) -> Result<ImapClient, ImapError> {
+        let config = &self.config;
+        let host = config.imap_host.as_deref().unwrap_or_default();
+        let mut client = ImapClient::connect(
+            host,
+            config.imap_port,
+            config.imap_security,
+            config.timeout(),
+            config.max_message_size,
+        )
+        .await?;
+        client.login(&config.username, &config.password).await?;
+        client.examine(mailbox).await?;
+        Ok(client)
+    }
+
+    async fn search_mail(
+        &self,
+        mailbox: &str,
+        query: SearchQuery,
+        limit: usize,
+    ) -> Result<ToolResult, ImapError> {
+        let mut client = self.open(mailbox).await?;
+        let uids = client.uid_search(&query).await?;
+
+        // Newest first
+        let newest: Vec<u32> = uids.iter().rev().take(limit).copied().collect();
+        let mut fetched = client.uid_fetch(&newest, SUMMARY_FIELDS).await?;
+        client.logout().await;
+        fetched.sort_by_key(|message| std::cmp::Reverse(message.uid));
+
+        let messages: Vec<Value> = fetched
+            .iter()
+            .map(|message| {
+                let headers = MessageParser::default().parse_headers(&message.body);
+                let headers = headers.as_ref();
+                json!({
+                    "uid": message.uid,
+                    "from": addresses(headers.and_then(|h| h.from())).join(", "),
+                    "subject": headers.and_then(|h| h.subject()),
+                    "date": headers.and_then(|h| h.date()).map(|d| d.to_rfc3339()),
+                    "seen": message.flags.iter().any(|f| f.eq_ignore_ascii_case("\\Seen")),
+                })
+            })
+            .collect();
+
+        Ok(ToolResult::success(json!({
+            "mailbox": mailbox,
+            "total": uids.len(),
+            "messages": messages,
+        })))
+    }
+
+    /// The whole message `uid`, if it exists
+    async fn fetch_message(&self, mailbox: &str, uid: u32) -> Result<Option<Vec<u8>>, ImapError> {
+        let mut client = self.open(mailbox).await?;
+        let fetched = client.uid_fetch(&[uid], "").await?;
+        client.logout().await;
+        Ok(fetched
+            .into_iter()
+            .find(|message| message.uid == uid)
+            .map(|message| message.body))
+    }
+
+    async fn read_message(&self, mailbox: &str, uid: u32) -> Result<ToolResult, ImapError> {
+        let Some(raw) = self.fetch_message(mailbox, uid).await? else {
+            return Ok(not_found(mailbox, uid));
+        };
+        let Some(message) = MessageParser::default().parse(&raw) else {
+            return Ok(ToolResult::failure(format!(
+                "Message {} could not be parsed",
+                uid
+            )));
+        };
+
+        let text = message.body_text(0).unwrap_or_default();
+        let truncated = text.chars().count() > MAX_BODY_CHARS;
+        let body: String = text.chars().take(MAX_BODY_CHARS).collect();
+        let attachments: Vec<Value> = message
+            .attachments()
+            .map(|part| {
+                json!({
+                    "name": part.attachment_name(),
+                    "size": part.len(),
+                })
+            })
+            .collect();
+
+        Ok(ToolResult::success(json!({
+            "uid": uid,
+            "mailbox": mailbox,
+            "from": addresses(message.from()),
+            "to": addresses(message.to()),
+            "cc": addresses(message.cc()),
+            "subject": message.subject(),
+            "date": message.date().map(|d| d.to_rfc3339()),
+            "message_id": message.message_id(),
+            "body": body,
+            "truncated": truncated,
+            "attachments": attachments,
+        })))
+    }
+
+    async fn draft_reply(
+        &self,
+        mailbox: &str,
+        uid: u32,
+        text: &str,
+        reply_all: bool,
+    ) -> Result<ToolResult, ImapError> {
+        let Some(raw) = self.fetch_message(mailbox, uid).await? else {
+            return Ok(not_found(mailbox, uid));
+        };
+        let Some(original) = MessageParser::default().parse(&raw) else {
+            return Ok(ToolResult::failure(format!(
+                "Message {} could not be parsed",
+                uid
+            )));
+        };
+
+        let sender = match self.config.sender() {
+            Ok(sender) => sender,
+            Err(e) => return Ok(ToolResult::failure(e)),
+        };
+        let own = sender.email.to_string();
+        let not_own = |address: &String| !address.eq_ignore_ascii_case(&own);
+
+        let mut to = emails(original.reply_to().or(original.from()));
+        let mut cc = Vec::new();
+        if reply_all {
+            to.extend(emails(original.to()).into_iter().filter(not_own));
+            cc = emails(original.cc()).into_iter().filter(not_own).collect();
+        }
+        to.dedup();
+
+        let subject = original.subject().unwrap_or_default();
+        let subject = if subject.to_lowercase().starts_with("re:") {
+            subject.to_string()
+        } else {
+            format!("Re: {}", subject)
+        };
+
+        // Quote the original below the reply
+        let quoted: String = original
+            .body_text(0)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| format!("> {}\n", line))
+            .collect();
+        let body = format!(
+            "{}\n\nOn {}, {} wrote:\n{}",
+            text,
+            original
+                .date()
+                .map(|d| d.to_rfc822())
+                .unwrap_or_else(|| "an earlier date".to_string()),
+            addresses(original.from()).join(", "),
+            quoted
+        );
+
+        let thread = original.message_id().map(|id| {
+            let mut references: Vec<String> = original
+                .references()
+                .as_text_list()
+                .unwrap_or_default()
+                .iter()
+                .map(|id| format!("<{}>", id))
+                .collect();
+            references.push(format!("<{}>", id));
+            (format!("<{}>", id), references.join(" "))
+        });
+
+        let message = match compose(sender, &to, &cc, &subject, body, thread) {
+            Ok(message) => message,
+            Err(e) => return Ok(ToolResult::failure(e)),
+        };
+
+        let config = &self.config;
+        let host = config.imap_host.as_deref().unwrap_or_default();
+        let mut client = ImapClient::connect(
+            host,
+            config.imap_port,
+            config.imap_security,
+            config.timeout(),
+            config.max_message_size,
+        )
+        .await?;
+        client.login(&config.username, &config.password).await?;
+        client
+            .append(&config.drafts_mailbox, "\\Draft", &message.formatted())
+            .await?;
+        client.logout().await;
+
+        Ok(ToolResult::success(json!({
+            "drafts_mailbox": config.drafts_mailbox,
+            "to": to,
+            "cc": cc,
+            "subject": subject,
+        })))
+    }
+
+    async fn send_mail(
+        &self,
+        to: &[String],
+        cc: &[String],
+        subject: &str,
+        body: String,
+    ) -> Result<ToolResult, PluginError> {
+        let message = match self
+            .config
+            .sender()
+            .and_then(|sender| compose(sender, to, cc, subject, body, None))
+        {
+            Ok(message) => message,
+            Err(e) => return Ok(ToolResult::failure(e)),
+        };
+        let message_id = message.headers().get_raw("Message-ID").map(str::to_string);
+
+        let result = match self.smtp() {
+            Ok(transport) => transport.send(message).await.map(drop),
+            Err(e) => Err(e),
+        };
+
+        Ok(match result {
+            Ok(()) => ToolResult::success(json!({
+                "to": to,
+                "cc": cc,
+                "subject": subject,
+                "message_id": message_id,
+            })),
+            Err(e) => ToolResult::failure(format!("Could not send mail: {}", e)),
+        })
+    }
+
+    fn smtp(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
+        let config = &self.config;
+        let host = config.smtp_host.as_deref().unwrap_or_default();
+        let builder = match config.smtp_security {
+            Security::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            Security::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            Security::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let mut builder = builder
+            .port(config.smtp_port)
+            .timeout(Some(config.timeout()));
+        if !config.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.username.clone(),
+                config.password.clone(),
+            ));
+        }
+        Ok(builder.build())
+    }
+
+    /// Build tools list based on configuration
+    fn build_tools(&self) -> Vec<ToolDefinition> {
+        let mut tools = Vec::new();
+
+        if self.imap_enabled() {
+            tools.extend([
+                ToolDefinition::new(
+                    "search_mail",
+                    "Search a mailbox; returns the newest matching messages first",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Text to find in headers or body"
+                        },
+                        "from": {
+                            "type": "string",
+                            "description": "Sender name or address"
+                        },
+                        "subject": {
+                            "type": "string",
+                            "description": "Text in the subject"
+                        },
+                        "since": {
+                            "type": "string",
+                            "description": "Only messages on or after this date (YYYY-MM-DD)"
+                        },
+                        "unread": {
+                            "type": "boolean",
+                            "description": "Only unread messages"
+                        },
+                        "mailbox": {
+                            "type": "string",
+                            "description": format!("Mailbox to search (default: {})", self.config.mailbox)
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": format!("Maximum messages to return (max {})", self.config.max_results)
+                        }
+                    }
+                }))
+                .from_plugin(Self::ID),
+                ToolDefinition::new(
+                    "read_message",
+                    "Read a message's headers, text and attachment names",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "uid": {
+                            "type": "integer",
+                            "description": "Message UID from search_mail"
+                        },
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Mailbox of the message (default: the searched mailbox)"
+                        }
+                    },
+                    "required": ["uid"]
+                }))
+                .from_plugin(Self::ID),
+                ToolDefinition::new(
+                    "draft_reply",
+                    "Save a reply to a message in the drafts mailbox for the user to review and send",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "uid": {
+                            "type": "integer",
+                            "description": "UID of the message to reply to"
+                        },
+                        "body": {
+                            "type": "string",
+                            "description": "Text of the reply; the original is quoted below it"
+                        },
+                        "reply_all": {
+                            "type": "boolean",
+                            "description": "Also address the original recipients (default: false)"
+                        },
+                        "mailbox": {
+                            "type": "string",
+                            "description": "Mailbox of the message (default: the searched mailbox)"
+                        }
+                    },
+                    "required": ["uid", "body"]
+                }))
+                .from_plugin(Self::ID),
+            ]);
+        }
+
+        if self.send_enabled() {
+            tools.push(
+                ToolDefinition::new("send_mail", "Send an email")
+                    .with_parameters(json!({
+                        "type": "object",
+                        "properties": {
+                            "to": {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Recipient addresses"
+                            },
+                            "cc": {
+                                "type": "array",
+                                "items": { "type": "string" }
+                            },
+                            "subject": { "type": "string" },
+                            "body": {
+                                "type": "string",
+                                "description": "Plain text body"
+                            }
+                        },
+                        "required": ["to", "subject", "body"]
+                    }))
+                    .with_confirmation()
+                    .from_plugin(Self::ID),
+            );
+        }
+
+        tools
+    }
+}
+
+/// Build a plain text message
+fn compose(
+    sender: Mailbox,
+    to: &[String],
+    cc: &[String],
+    subject: &str,
+    body: String,
+    thread: Option<(String, String)>,
+) -> Result<Message, String> {
+    if to.is_empty() {
+        return Err("At least one recipient is required".to_string());
+    }
+
+    let domain = sender.email.domain().to_string();
+    let mut builder = Message::builder()
+        .from(sender)
+        .subject(subject)
+        .message_id(Some(format!("<{}@{}>", uuid::Uuid::new_v4(), domain)))
+        .header(ContentType::TEXT_PLAIN);
+    for address in to {
+        builder = builder.to(parse_mailbox(address)?);
+    }
+    for address in cc {
+        builder = builder.cc(parse_mailbox(address)?);
+    }
+    if let Some((in_reply_to, references)) = thread {
+        builder = builder.in_reply_to(in_reply_to).references(references);
+    }
+
+    builder
+        .body(body)
+        .map_err(|e| format!("Could not build message: {}", e))
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+    address
+        .parse()
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))
+}
+
+/// Addresses as `Name <address>`, or just the address
+fn addresses(address: Option<&Address>) -> Vec<String> {
+    address
+        .map(|address| {
+            address
+                .iter()
+                .filter_map(|addr| match (addr.name(), addr.address()) {
+                    (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+                    (None, Some(email)) => Some(email.to_string()),
+                    (name, None) => name.map(str::to_string),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Bare email addresses
+fn emails(address: Option<&Address>) -> Vec<String> {
+    address
+        .map(|address| {
+            address
+                .iter()
+                .filter_map(|addr| addr.address().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn not_found(mailbox: &str, uid: u32) -> ToolResult {
+    ToolResult::failure(format!("Message {} not found in {}", uid, mailbox))
+}
+
+fn strings(value: &Value) -> Result<Vec<String>, PluginError> {
+    match value {
+        Value::Null => Ok(Vec::new()),
+        Value::String(s) => Ok(vec![s.clone()]),
+        other => serde_json::from_value(other.clone())
+            .map_err(|e| PluginError::InvalidParameters(e.to_string())),
+    }
+}
+
+// ============================================================================
+// New Plugin trait implementation
+// ============================================================================
+
+#[async_trait]
+impl Plugin for EmailPlugin {
+    fn manifest(&self) -> PluginManifest {
+        let security =
+            || ConfigFieldType::Select(vec!["tls".into(), "starttls".into(), "none".into()]);
+
+        PluginManifest::new(
+            Self::ID,
+            "Email",
+            "Search, read and draft replies to email over IMAP, and send it over SMTP",
+        )
+        .with_version(1, 0, 0)
+        .with_author("Moxie AI")
+        .with_category(PluginCategory::Communication)
+        .with_keywords(vec!["email", "mail", "imap", "smtp", "inbox"])
+        .with_config_field(
+            ConfigFieldBuilder::new("imap_host", ConfigFieldType::String)
+                .label("IMAP Host")
+                .description("Server to search and read mail on, and save drafts to")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("imap_port", ConfigFieldType::Number)
+                .label("IMAP Port")
+                .default_value(json!(993))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("imap_security", security())
+                .label("IMAP Security")
+                .default_value(json!("tls"))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("smtp_host", ConfigFieldType::String)
+                .label("SMTP Host")
+                .description("Server to send mail through")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("smtp_port", ConfigFieldType::Number)
+                .label("SMTP Port")
+                .default_value(json!(587))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("smtp_security", security())
+                .label("SMTP Security")
+                .default_value(json!("starttls"))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("username", ConfigFieldType::String)
+                .label("Username")
+                .description("Login for both servers")
+                .required()
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("password", ConfigFieldType::Secret)
+                .label("Password")
+                .description("Password or app password for both servers")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("password_env", ConfigFieldType::String)
+                .label("Password Variable")
+                .description("Environment variable holding the password, instead of Password")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("from_address", ConfigFieldType::String)
+                .label("From Address")
+                .description("Sender of drafts and sent mail (default: the username)")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("mailbox", ConfigFieldType::String)
+                .label("Mailbox")
+                .default_value(json!("INBOX"))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("drafts_mailbox", ConfigFieldType::String)
+                .label("Drafts Mailbox")
+                .default_value(json!("Drafts"))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("allow_send", ConfigFieldType::Boolean)
+                .label("Allow Send")
+                .description("Enable sending mail; every message needs confirmation")
+                .default_value(json!(false))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("max_results", ConfigFieldType::Number)
+                .label("Max Results")
+                .description("Maximum number of messages returned by a search")
+                .default_value(json!(20))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("timeout_secs", ConfigFieldType::Number)
+                .label("Timeout")
+                .description("Seconds to wait for a mail server")
+                .default_value(json!(30))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("max_message_size", ConfigFieldType::Number)
+                .label("Max Message Size")
+                .description("Largest message read from the IMAP server, in bytes")
+                .default_value(json!(default_max_message_size()))
+                .build(),
+        )
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        let text = |name: &str| {
+            params[name]
+                .as_str()
+                .filter(|s| !s.trim().is_empty())
+                .map(String::from)
+        };
+        let mailbox = text("mailbox").unwrap_or_else(|| self.config.mailbox.clone());
+        let uid = || {
+            params["uid"]
+                .as_u64()
+                .and_then(|uid| u32::try_from(uid).ok())
+                .ok_or_else(|| PluginError::InvalidParameters("uid is required".into()))
+        };
+
+        let result = match tool {
+            "search_mail" if self.imap_enabled() => {
+                let since = text("since")
+                    .map(|since| {
+                        chrono::NaiveDate::parse_from_str(&since, "%Y-%m-%d").map_err(|_| {
+                            PluginError::InvalidParameters("since must be YYYY-MM-DD".into())
+                        })
+                    })
+                    .transpose()?;
+                let query = SearchQuery {
+                    text: text("query"),
+                    from: text("from"),
+                    subject: text("subject"),
+                    since,
+                    unseen: params["unread"].as_bool().unwrap_or(false),
+                };
+                let max = self.config.max_results;
+                let limit = params["limit"]
+                    .as_u64()
+                    .map_or(max, |limit| (limit as usize).clamp(1, max));
+                self.search_mail(&mailbox, query, limit).await
+            }
+            "read_message" if self.imap_enabled() => self.read_message(&mailbox, uid()?).await,
+            "draft_reply" if self.imap_enabled() => {
+                let body = text("body")
+                    .ok_or_else(|| PluginError::InvalidParameters("body is required".into()))?;
+                let reply_all = params["reply_all"].as_bool().unwrap_or(false);
+                self.draft_reply(&mailbox, uid()?, &body, reply_all).await
+            }
+            "send_mail" if self.send_enabled() => {
+                let subject = text("subject")
+                    .ok_or_else(|| PluginError::InvalidParameters("subject is required".into()))?;
+                let body = text("body")
+                    .ok_or_else(|| PluginError::InvalidParameters("body is required".into()))?;
+                return self
+                    .send_mail(
+                        &strings(&params["to"])?,
+                        &strings(&params["cc"])?,
+                        &subject,
+                        body,
+                    )
+                    .await;
+            }
+            _ => return Err(PluginError::ToolNotFound(tool.to_string())),
+        };
+
+        // Server problems are reported to the model rather than failing the chat
+        Ok(result.unwrap_or_else(|e| ToolResult::failure(e.to_string())))
+    }
+
+    async fn on_init(&mut self, ctx: &PluginContext) -> Result<(), PluginError> {
+        // Update config from context if provided
+        if !ctx.config.is_null() {
+            self.config = EmailConfig::from_value(&ctx.config)?;
+        }
+        if let Some(var) = &self.config.password_env {
+            self.config.password = std::env::var(var)
+                .map_err(|_| PluginError::InitFailed(format!("Email: {} is not set", var)))?;
+        }
+
+        tracing::info!(
+            "Email plugin initialized (IMAP: {}, SMTP: {})",
+            self.config.imap_host.as_deref().unwrap_or("none"),
+            self.config.smtp_host.as_deref().unwrap_or("none")
+        );
+
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// ============================================================================
+// Legacy Plugin trait implementation (backwards compatibility)
+// ============================================================================
+
+#[async_trait]
+impl LegacyPlugin for EmailPlugin {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn description(&self) -> &str {
+        "Searches, reads, drafts and sends email"
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        Plugin::execute(self, tool, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::traits::Plugin as NewPlugin;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    const QUESTION: &str = "From: Ana <ana@example.com>\r\nTo: analyst@example.com, bo@example.com\r\nSubject: Q3 numbers\r\nDate: Mon, 5 Oct 2026 09:00:00 +0000\r\nMessage-ID: <q3@example.com>\r\n\r\nCan you send the Q3 numbers?\r\n";
+    const NEWSLETTER: &str = "From: news@example.com\r\nTo: analyst@example.com\r\nSubject: Weekly digest\r\nDate: Tue, 6 Oct 2026 09:00:00 +0000\r\n\r\nThis week...\r\n";
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// An IMAP server holding messages 7 and 9; records commands and appends
+    async fn fake_imap(log: Log, appended: Log) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let (log, appended) = (log.clone(), appended.clone());
+                tokio::spawn(async move {
+                    let (read, mut write) = socket.into_split();
+                    let mut read = BufReader::new(read);
+                    write.write_all(b"* OK ready\r\n").await.unwrap();
+                    let mut line = String::new();
+                    while read.read_line(&mut line).await.unwrap() > 0 {
+                        let (tag, command) = line.trim_end().split_once(' ').unwrap();
+                        let (tag, command) = (tag.to_string(), command.to_string());
+                        line.clear();
+                        log.lock().unwrap().push(command.clone());
+
+                        let mut reply = String::new();
+                        if command.starts_with("UID SEARCH") {
+                            reply.push_str("* SEARCH 7 9\r\n");
+                        } else if let Some(rest) = command.strip_prefix("UID FETCH ") {
+                            let set = rest.split(' ').next().unwrap();
+                            for (n, uid) in set.split(',').enumerate() {
+                                let (body, flags) = match uid {
+                                    "7" => (QUESTION, "\\Seen"),
+                                    "9" => (NEWSLETTER, ""),
+                                    _ => continue,
+                                };
+                                reply.push_str(&format!(
+                                    "* {} FETCH (UID {} FLAGS ({}) BODY[] {{{}}}\r\n{})\r\n",
+                                    n + 1,
+                                    uid,
+                                    flags,
+                                    body.len(),
+                                    body
+                                ));
+                            }
+                        } else if command.starts_with("APPEND") {
+                            let length: usize = command
+                                .rsplit_once('{')
+                                .and_then(|(_, n)| n.strip_suffix('}'))
+                                .unwrap()
+                                .parse()
+                                .unwrap();
+                            write.write_all(b"+ go ahead\r\n").await.unwrap();
+                            let mut message = vec![0; length + 2];
+                            read.read_exact(&mut message).await.unwrap();
+                            message.truncate(length);
+                            appended
+                                .lock()
+                                .unwrap()
+                                .push(String::from_utf8(message).unwrap());
+                        } else if command == "LOGOUT" {
+                            reply.push_str("* BYE\r\n");
+                        }
+                        reply.push_str(&format!("{} OK done\r\n", tag));
+                        write.write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    /// An SMTP server that accepts one message and records its data
+    async fn fake_smtp(data: Log) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (read, mut write) = socket.into_split();
+            let mut read = BufReader::new(read);
+            write.write_all(b"220 localhost ready\r\n").await.unwrap();
+            let mut line = String::new();
+            while read.read_line(&mut line).await.unwrap() > 0 {
+                let command = line.to_uppercase();
+                line.clear();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250 AUTH PLAIN LOGIN\r\n"
+                } else if command.starts_with("AUTH") {
+                    b"235 authenticated\r\n"
+                } else if command.starts_with("DATA") {
+                    write.write_all(b"354 go ahead\r\n").await.unwrap();
+                    let mut message = String::new();
+                    while read.read_line(&mut line).await.unwrap() > 0 && line != ".\r\n" {
+                        message.push_str(&line);
+                        line.clear();
+                    }
+                    line.clear();
+                    data.lock().unwrap().push(message);
+                    b"250 queued\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+        });
+        port
+    }
+
+    fn local_config() -> EmailConfig {
+        EmailConfig {
+            imap_security: Security::None,
+            smtp_security: Security::None,
+            username: "analyst@example.com".to_string(),
+            password: "secret".to_string(),
+            ..EmailConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let config = EmailConfig::from_value(&json!({
+            "smtp_host": "smtp.example.com",
+            "username": "analyst@example.com",
+            "password": "hunter2",
+            "allow_send": true
+        }))
+        .unwrap();
+        assert_eq!(config.smtp_port, 587);
+        assert_eq!(config.smtp_security, Security::Starttls);
+        assert!(!format!("{:?}", config).contains("hunter2"));
+
+        // Without IMAP only sending is offered, and it needs confirmation
+        let tools = EmailPlugin::new(config).build_tools();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "send_mail");
+        assert!(tools[0].requires_confirmation);
+
+        let config = EmailConfig::from_value(&json!({
+            "imap_host": "imap.example.com",
+            "smtp_host": "smtp.example.com",
+            "username": "analyst@example.com",
+            "password": "hunter2"
+        }))
+        .unwrap();
+        let names: Vec<_> = EmailPlugin::new(config)
+            .build_tools()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, vec!["search_mail", "read_message", "draft_reply"]);
+
+        assert!(EmailConfig::from_value(&json!({"username": "a@example.com"})).is_err());
+        assert!(EmailConfig::from_value(&json!({
+            "imap_host": "imap.example.com",
+            "username": "not an address"
+        }))
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_password_env() {
+        let var = format!("MOXIE_TEST_MAIL_{}", uuid::Uuid::new_v4().simple());
+        let section = json!({
+            "smtp_host": "smtp.example.com",
+            "username": "analyst@example.com",
+            "password_env": var
+        });
+        let ctx = PluginContext {
+            config: section.clone(),
+            ..PluginContext::default()
+        };
+
+        let mut plugin = EmailPlugin::default_plugin();
+        let error = NewPlugin::on_init(&mut plugin, &ctx).await.unwrap_err();
+        assert!(error.to_string().contains(&var));
+
+        std::env::set_var(&var, "hunter2");
+        NewPlugin::on_init(&mut plugin, &ctx).await.unwrap();
+        assert_eq!(plugin.config.password, "hunter2");
+        std::env::remove_var(&var);
+
+        let mut both = section;
+        both["password"] = json!("inline");
+        assert!(EmailConfig::from_value(&both).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_search_read_and_draft() {
+        let (log, appended) = (Log::default(), Log::default());
+        let port = fake_imap(log.clone(), appended.clone()).await;
+        let plugin = EmailPlugin::new(EmailConfig {
+            imap_host: Some("127.0.0.1".to_string()),
+            imap_port: port,
+            ..local_config()
+        });
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_mail",
+            json!({"from": "ana", "since": "2026-10-01", "limit": 5}),
+        )
+        .await
+        .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        let data = result.output;
+        assert_eq!(data["total"], 2);
+        assert_eq!(data["messages"][0]["uid"], 9);
+        assert_eq!(data["messages"][0]["seen"], false);
+        assert_eq!(data["messages"][1]["from"], "Ana <ana@example.com>");
+        assert_eq!(data["messages"][1]["subject"], "Q3 numbers");
+
+        let result = NewPlugin::execute(&plugin, "read_message", json!({"uid": 7}))
+            .await
+            .unwrap();
+        let data = result.output;
+        assert_eq!(data["to"], json!(["analyst@example.com", "bo@example.com"]));
+        assert_eq!(data["message_id"], "q3@example.com");
+        assert!(data["body"].as_str().unwrap().contains("Q3 numbers?"));
+
+        let result = NewPlugin::execute(&plugin, "read_message", json!({"uid": 8}))
+            .await
+            .unwrap();
+        assert!(!result.success);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "draft_reply",
+            json!({"uid": 7, "body": "Attached.", "reply_all": true}),
+        )
+        .await
+        .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        let data = result.output;
+        assert_eq!(data["to"], json!(["ana@example.com", "bo@example.com"]));
+        assert_eq!(data["subject"], "Re: Q3 numbers");
+
+        let draft = appended.lock().unwrap().pop().unwrap();
+        assert!(draft.contains("In-Reply-To: <q3@example.com>"));
+        assert!(draft.contains("> Can you send the Q3 numbers?"));
+
+        // Mailboxes are opened read-only and fetches don't mark messages read
+        let log = log.lock().unwrap();
+        assert!(log.iter().any(|c| c == "EXAMINE \"INBOX\""));
+        assert!(log.iter().any(|c| c.contains("FROM \"ana\" SINCE 1-Oct-2026")));
+        assert!(log.iter().any(|c| c.starts_with("APPEND \"Drafts\" (\\Draft)")));
+        assert!(!log.iter().any(|c| c.contains("SELECT") || c.contains("BODY[")));
+    }
+
+    #[tokio::test]
+    async fn test_send_mail() {
+        let data = Log::default();
+        let port = fake_smtp(data.clone()).await;
+        let plugin = EmailPlugin::new(EmailConfig {
+            smtp_host: Some("127.0.0.1".to_string()),
+            smtp_port: port,
+            allow_send: true,
+            ..local_config()
+        });
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "send_mail",
+            json!({"to": "bo@example.com", "subject": "Q3", "body": "Numbers attached."}),
+        )
+        .await
+        .unwrap();
+        assert!(result.success, "{:?}", result.error);
+
+        let message = data.lock().unwrap().pop().unwrap();
+        assert!(message.contains("To: bo@example.com"));
+        assert!(message.contains("Message-ID: <"));
+        assert!(message.contains("Numbers attached."));
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "send_mail",
+            json!({"to": [], "subject": "Q3", "body": "No one"}),
+        )
+        .await
+        .unwrap();
+        assert!(!result.success);
+    }
+}
@@ -0,0 +1,376 @@
+//! Record of file changes, so they can be undone
+//!
+//! Every change the plugin makes is appended to `journal.jsonl` in the plugin
+//! data directory, with SHA-256 checksums of the file before and after.
+//! Overwritten content is copied to `backups/` and deleted files to `trash/`,
+//! so nothing the plugin touches is lost. A change is only undone
+//! while the file still has the checksum recorded after it, so later edits
+//! (by the plugin or anyone else) are never overwritten. Undoing goes
+//! through the plugin's sandbox, like the change itself did.
+//!
+//! Only the latest changes are kept: once the journal holds more than
+//! [`MAX_CHANGES`] changes, or their backups and trash take more than
+//! [`MAX_KEPT_BYTES`], the oldest are dropped with their copies and can no
+//! longer be undone.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use super::sandbox::{Sandbox, SandboxError};
+
+/// Most changes kept in the journal
+pub const MAX_CHANGES: usize = 500;
+
+/// Most bytes kept in backups and trash
+pub const MAX_KEPT_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Journal errors
+#[derive(Debug, thiserror::Error)]
+pub enum JournalError {
+    #[error("Change '{0}' not found")]
+    NotFound(String),
+
+    #[error("No changes to undo")]
+    Empty,
+
+    #[error("Change '{0}' was already undone")]
+    AlreadyUndone(String),
+
+    #[error("{0} has changed since change '{1}'; undo later changes first")]
+    Modified(PathBuf, String),
+
+    #[error("{0} already exists")]
+    Exists(PathBuf),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("Corrupt journal: {0}")]
+    Corrupt(#[from] serde_json::Error),
+}
+
+/// What a change did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Write,
+    Append,
+    Edit,
+    Copy,
+    Move,
+    CreateDirectory,
+    Delete,
+}
+
+/// One recorded change
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub id: String,
+    pub operation: Operation,
+    pub path: PathBuf,
+    /// Where a copy or move went
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<PathBuf>,
+    /// Checksum of `path` before the change, if it was a file
+    pub before: Option<String>,
+    /// Checksum of the changed file (`destination` for copies and moves)
+    pub after: Option<String>,
+    /// Previous content, or the trashed file for deletions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<PathBuf>,
+    pub timestamp: String,
+    #[serde(default)]
+    pub undone: bool,
+}
+
+impl Change {
+    pub fn new(operation: Operation, path: &Path) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            operation,
+            path: path.to_path_buf(),
+            destination: None,
+            before: None,
+            after: None,
+            backup: None,
+            timestamp: Utc::now().to_rfc3339(),
+            undone: false,
+        }
+    }
+}
+
+/// The change journal, backups and trash of one plugin instance
+pub struct Journal {
+    dir: PathBuf,
+    max_changes: usize,
+    max_kept_bytes: u64,
+    // Serializes rewrites of the journal file
+    lock: Mutex<()>,
+}
+
+impl Journal {
+    /// A journal kept in `dir` (the plugin data directory)
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_changes: MAX_CHANGES,
+            max_kept_bytes: MAX_KEPT_BYTES,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Keep at most `max_changes` changes and `max_kept_bytes` of backups
+    /// and trash, instead of the defaults
+    #[cfg(test)]
+    pub fn with_retention(mut self, max_changes: usize, max_kept_bytes: u64) -> Self {
+        self.max_changes = max_changes;
+        self.max_kept_bytes = max_kept_bytes;
+        self
+    }
+
+    fn file(&self) -> PathBuf {
+        self.dir.join("journal.jsonl")
+    }
+
+    /// Keep the content of `path` in the backups, before it is overwritten
+    pub async fn backup(&self, change: &mut Change, content: &[u8]) -> Result<(), std::io::Error> {
+        let backup = self.dir.join("backups").join(&change.id);
+        fs::create_dir_all(self.dir.join("backups")).await?;
+        fs::write(&backup, content).await?;
+        change.backup = Some(backup);
+        Ok(())
+    }
+
+    /// Keep the content of `path` in the trash under its file name, before
+    /// it is deleted
+    pub async fn trash(&self, change: &mut Change, content: &[u8]) -> Result<(), std::io::Error> {
+        let name = change.path.file_name().unwrap_or_default();
+        let trashed = self.dir.join("trash").join(&change.id);
+        fs::create_dir_all(&trashed).await?;
+        let trashed = trashed.join(name);
+        fs::write(&trashed, content).await?;
+        change.backup = Some(trashed);
+        Ok(())
+    }
+
+    /// Append a change to the journal, then drop the oldest changes beyond
+    /// the retention limits
+    pub async fn record(&self, change: &Change) -> Result<(), std::io::Error> {
+        let _guard = self.lock.lock().await;
+        fs::create_dir_all(&self.dir).await?;
+        let mut line = serde_json::to_string(change).map_err(std::io::Error::other)?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.file())
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        self.prune().await
+    }
+
+    /// Drop the oldest changes, and their backups and trash, until the
+    /// journal is within its limits; the latest change is always kept
+    async fn prune(&self) -> Result<(), std::io::Error> {
+        let mut changes = self.changes().await.map_err(std::io::Error::other)?;
+        let mut kept_bytes = 0;
+        for change in &changes {
+            kept_bytes += kept_size(change).await?;
+        }
+
+        let mut dropped = 0;
+        while changes.len() - dropped > 1
+            && (changes.len() - dropped > self.max_changes || kept_bytes > self.max_kept_bytes)
+        {
+            let change = &changes[dropped];
+            kept_bytes = kept_bytes.saturating_sub(kept_size(change).await?);
+            remove_if_exists(fs::remove_file(self.dir.join("backups").join(&change.id)).await)?;
+            remove_if_exists(fs::remove_dir_all(self.dir.join("trash").join(&change.id)).await)?;
+            dropped += 1;
+        }
+        if dropped == 0 {
+            return Ok(());
+        }
+
+        changes.drain(..dropped);
+        self.save(&changes).await
+    }
+
+    /// Rewrite the journal with `changes`
+    async fn save(&self, changes: &[Change]) -> Result<(), std::io::Error> {
+        let mut text = String::new();
+        for change in changes {
+            text.push_str(&serde_json::to_string(change).map_err(std::io::Error::other)?);
+            text.push('\n');
+        }
+        fs::write(self.file(), text).await
+    }
+
+    /// All recorded changes, oldest first
+    pub async fn changes(&self) -> Result<Vec<Change>, JournalError> {
+        let text = match fs::read_to_string(self.file()).await {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(JournalError::from))
+            .collect()
+    }
+
+    /// Undo change `id`, or the latest change that isn't undone yet, with
+    /// the changed files accessed through `sandbox`
+    pub async fn undo(
+        &self,
+        id: Option<&str>,
+        sandbox: &Arc<Sandbox>,
+    ) -> Result<Change, JournalError> {
+        let _guard = self.lock.lock().await;
+        let mut changes = self.changes().await?;
+        let index = match id {
+            Some(id) => changes
+                .iter()
+                .position(|change| change.id == id)
+                .ok_or_else(|| JournalError::NotFound(id.to_string()))?,
+            None => changes
+                .iter()
+                .rposition(|change| !change.undone)
+                .ok_or(JournalError::Empty)?,
+        };
+
+        let change = &mut changes[index];
+        if change.undone {
+            return Err(JournalError::AlreadyUndone(change.id.clone()));
+        }
+        revert(change, sandbox).await?;
+        change.undone = true;
+        let undone = change.clone();
+
+        self.save(&changes).await?;
+        Ok(undone)
+    }
+}
+
+async fn revert(change: &Change, sandbox: &Arc<Sandbox>) -> Result<(), JournalError> {
+    let modified = |path: &Path| JournalError::Modified(path.to_path_buf(), change.id.clone());
+    let path = &change.path;
+
+    match change.operation {
+        Operation::Write | Operation::Append | Operation::Edit => {
+            if checksum(sandbox, path).await? != change.after {
+                return Err(modified(path));
+            }
+            match &change.backup {
+                Some(backup) => {
+                    let content = fs::read(backup).await?;
+                    sandboxed(sandbox, path, move |sandbox, path| {
+                        sandbox.write(path, &content)
+                    })
+                    .await?;
+                }
+                // The change created the file
+                None => sandboxed(sandbox, path, |sandbox, path| sandbox.remove_file(path)).await?,
+            }
+        }
+        Operation::Copy | Operation::Move => {
+            let destination = change.destination.as_deref().unwrap_or(path);
+            if checksum(sandbox, destination).await? != change.after {
+                return Err(modified(destination));
+            }
+            if change.operation == Operation::Copy {
+                sandboxed(sandbox, destination, |sandbox, path| {
+                    sandbox.remove_file(path)
+                })
+                .await?;
+            } else if exists(sandbox, path).await? {
+                return Err(JournalError::Exists(path.clone()));
+            } else {
+                let to = path.clone();
+                sandboxed(sandbox, destination, move |sandbox, from| {
+                    sandbox.rename(from, &to)
+                })
+                .await?;
+            }
+        }
+        Operation::CreateDirectory => {
+            sandboxed(sandbox, path, |sandbox, path| sandbox.remove_dir(path)).await?
+        }
+        Operation::Delete => {
+            if exists(sandbox, path).await? {
+                return Err(JournalError::Exists(path.clone()));
+            }
+            let trashed = change.backup.as_deref().unwrap_or(path);
+            let content = fs::read(trashed).await?;
+            sandboxed(sandbox, path, move |sandbox, path| {
+                sandbox.write(path, &content)
+            })
+            .await?;
+            fs::remove_file(trashed).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes taken by a change's backup or trashed file, if it is still there
+async fn kept_size(change: &Change) -> Result<u64, std::io::Error> {
+    let Some(backup) = &change.backup else {
+        return Ok(0);
+    };
+    match fs::metadata(backup).await {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Treat removing something that is already gone as done
+fn remove_if_exists(result: Result<(), std::io::Error>) -> Result<(), std::io::Error> {
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Run a sandbox operation on `path`
+async fn sandboxed<T, F>(sandbox: &Arc<Sandbox>, path: &Path, f: F) -> Result<T, SandboxError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Sandbox, &Path) -> Result<T, SandboxError> + Send + 'static,
+{
+    let path = path.to_path_buf();
+    sandbox.run(move |sandbox| f(sandbox, &path)).await
+}
+
+/// Whether anything exists at `path`
+async fn exists(sandbox: &Arc<Sandbox>, path: &Path) -> Result<bool, SandboxError> {
+    sandboxed(sandbox, path, |sandbox, path| sandbox.metadata(path))
+        .await
+        .map(|metadata| metadata.is_some())
+}
+
+/// SHA-256 of a file's content, or `None` if it isn't a file
+async fn checksum(sandbox: &Arc<Sandbox>, path: &Path) -> Result<Option<String>, SandboxError> {
+    sandboxed(sandbox, path, |sandbox, path| {
+        match sandbox.metadata(path)? {
+            Some(metadata) if metadata.is_file() => Ok(Some(digest(&sandbox.read(path)?))),
+            _ => Ok(None),
+        }
+    })
+    .await
+}
+
+/// SHA-256 of some content
+pub fn digest(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
@@ -1,13 +1,25 @@
-//! Filesystem plugin for local file operations
+//! Filesystem plugin for local and cloud file operations
 //!
-//! Provides tools for reading, writing, and listing files on the local filesystem.
-//! Access is restricted to configured allowed paths for security.
+//! Provides tools for reading, writing, listing and searching files on the local
+//! filesystem and, with `cloud_providers = ["s3"]`, in S3-compatible storage
+//! (AWS S3, MinIO) addressed as `s3://bucket/prefix`. Access is restricted to
+//! configured allowed paths for security: local files are opened relative to
+//! the allowed directories, so symbolic links can't lead outside them, and
+//! deny patterns and the hidden-file policy narrow access further.
 //!
 //! # Tools
 //!
 //! - `read_file` - Read contents of a file
 //! - `write_file` - Write content to a file (if enabled)
 //! - `list_directory` - List files in a directory
+//! - `search_files` - Find files by glob pattern, name or date, recursively
+//! - `grep_files` - Find lines in files, with surrounding context
+//! - `file_info` - Size, times and MIME type of a file
+//!
+//! With `allow_write`, files can also be appended to, edited, copied, moved,
+//! deleted (to a trash in the plugin data directory) and directories created.
+//! Each change needs confirmation and is journaled with checksums, so
+//! `undo_change` can revert it. These edit tools work on local files only.
 //!
 //! # Configuration
 //!
@@ -16,46 +28,112 @@
 //! allowed_paths = ["C:\\Data", "D:\\Reports"]
 //! allow_write = false
 //! max_file_size = 10485760  # 10 MB
+//! max_search_results = 100
+//! max_search_depth = 10
+//! deny_patterns = ["**/.env", "*.key", "*.pem"]
+//! hidden_files = "hide"  # "allow", "hide" (from listings) or "deny"
+//!
+//! # Cloud storage; allowed_paths may then include "s3://reports/2024"
+//! cloud_providers = ["s3"]
+//!
+//! [plugins.filesystem.s3]
+//! endpoint = "http://localhost:9000"  # MinIO; omit for AWS
+//! region = "us-east-1"
+//! access_key_id_env = "AWS_ACCESS_KEY_ID"
+//! secret_access_key_env = "AWS_SECRET_ACCESS_KEY"
 //! ```
 
 use async_trait::async_trait;
-use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::any::Any;
 use std::path::{Path, PathBuf};
-use tokio::fs;
+use std::sync::Arc;
+
+use cap_std::fs::Metadata;
 
+mod journal;
+mod s3;
+mod sandbox;
+mod search;
+mod storage;
+
+pub(crate) use journal::{digest, Change, Journal, Operation};
+pub use s3::S3Config;
+use s3::S3Storage;
+pub use sandbox::HiddenFiles;
+pub(crate) use sandbox::{DenyPattern, Sandbox, SandboxError};
+use search::{FileQuery, Grep, Limits, Pattern, SearchError, MAX_FILES_SCANNED};
+use storage::{is_s3_uri, Entry, LocalStorage, S3Location, StorageBackend, StorageError};
+
+use crate::config::client::FilesystemPluginConfig;
 use crate::plugins::manifest::{
-    ConfigField, ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest,
+    ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest,
 };
 use crate::plugins::traits::{Plugin, PluginContext};
 use crate::plugins::{LegacyPlugin, PluginError, ToolDefinition, ToolResult};
 
+/// Changes returned by `list_changes`
+const MAX_LISTED_CHANGES: usize = 20;
+
 /// Configuration for the filesystem plugin
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Built from the typed `[plugins.filesystem]` section, which holds the
+/// defaults.
+#[derive(Debug, Clone)]
 pub struct FilesystemConfig {
     /// Paths that the plugin is allowed to access
     pub allowed_paths: Vec<PathBuf>,
 
     /// Whether to allow write operations
-    #[serde(default)]
     pub allow_write: bool,
 
     /// Maximum file size to read (in bytes)
-    #[serde(default = "default_max_file_size")]
     pub max_file_size: u64,
-}
 
-fn default_max_file_size() -> u64 {
-    10 * 1024 * 1024 // 10 MB
+    /// Maximum number of files or lines returned by a search
+    pub max_search_results: usize,
+
+    /// How many directories deep a search may go
+    pub max_search_depth: usize,
+
+    /// Paths refused even inside the allowed paths, e.g. `**/.env` or
+    /// `*.key` (a pattern without `/` matches a name at any depth); S3 keys
+    /// are matched from the bucket down
+    pub deny_patterns: Vec<String>,
+
+    /// Whether hidden files are treated normally, left out of listings and
+    /// searches, or refused
+    pub hidden_files: HiddenFiles,
+
+    /// Cloud storage the plugin may use; only "s3" is supported
+    pub cloud_providers: Vec<String>,
+
+    /// S3-compatible storage settings, used with "s3" in `cloud_providers`
+    pub s3: Option<S3Config>,
 }
 
+/// Lines of context shown around a `grep_files` match by default, and at most
+const DEFAULT_CONTEXT_LINES: usize = 2;
+const MAX_CONTEXT_LINES: usize = 10;
+
 impl Default for FilesystemConfig {
     fn default() -> Self {
+        FilesystemPluginConfig::default().into()
+    }
+}
+
+impl From<FilesystemPluginConfig> for FilesystemConfig {
+    fn from(config: FilesystemPluginConfig) -> Self {
         Self {
-            allowed_paths: vec![],
-            allow_write: false,
-            max_file_size: default_max_file_size(),
+            allowed_paths: config.allowed_paths,
+            allow_write: config.allow_write,
+            max_file_size: config.max_file_size,
+            max_search_results: config.max_search_results,
+            max_search_depth: config.max_search_depth,
+            deny_patterns: config.deny_patterns,
+            hidden_files: config.hidden_files,
+            cloud_providers: config.cloud_providers,
+            s3: config.s3.map(S3Config::from),
         }
     }
 }
@@ -67,14 +145,73 @@ impl FilesystemConfig {
             return Ok(Self::default());
         }
 
-        serde_json::from_value(value.clone())
-            .map_err(|e| PluginError::ConfigError(e.to_string()))
+        let config: FilesystemPluginConfig = serde_json::from_value(value.clone())
+            .map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        let config = Self::from(config);
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), PluginError> {
+        for pattern in &self.deny_patterns {
+            DenyPattern::new(pattern).map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        }
+
+        if let Some(provider) = self.cloud_providers.iter().find(|p| p.as_str() != "s3") {
+            return Err(PluginError::ConfigError(format!(
+                "Unsupported cloud provider '{}' (supported: s3)",
+                provider
+            )));
+        }
+
+        let s3_paths = self
+            .allowed_paths
+            .iter()
+            .filter(|path| is_s3_uri(&path.to_string_lossy()));
+        for path in s3_paths {
+            let path = path.to_string_lossy();
+            S3Location::parse(&path)
+                .map_err(|e| PluginError::ConfigError(e.to_string()))?;
+            if !self.s3_enabled() {
+                return Err(PluginError::ConfigError(format!(
+                    "allowed path '{}' needs \"s3\" in cloud_providers",
+                    path
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether S3-compatible storage is enabled
+    pub fn s3_enabled(&self) -> bool {
+        self.cloud_providers.iter().any(|p| p == "s3")
     }
 }
 
+/// The sandbox for a configuration; one that allows nothing if the allowed
+/// directories can't be opened
+fn sandbox(config: &FilesystemConfig) -> Arc<Sandbox> {
+    let local: Vec<PathBuf> = config
+        .allowed_paths
+        .iter()
+        .filter(|path| !is_s3_uri(&path.to_string_lossy()))
+        .cloned()
+        .collect();
+    let sandbox =
+        Sandbox::new(&local, &config.deny_patterns, config.hidden_files).unwrap_or_else(|e| {
+            tracing::error!("Filesystem sandbox: {}", e);
+            Sandbox::empty()
+        });
+    Arc::new(sandbox)
+}
+
 /// Filesystem plugin for local file access
 pub struct FilesystemPlugin {
     config: FilesystemConfig,
+    journal: Journal,
+    sandbox: Arc<Sandbox>,
+    local: LocalStorage,
+    s3: Option<S3Storage>,
 }
 
 impl FilesystemPlugin {
@@ -83,7 +220,20 @@ impl FilesystemPlugin {
 
     /// Create a new filesystem plugin with the given configuration
     pub fn new(config: FilesystemConfig) -> Self {
-        Self { config }
+        let sandbox = sandbox(&config);
+        Self {
+            config,
+            journal: Journal::new(PluginContext::default().data_dir.join(Self::ID)),
+            local: LocalStorage::new(sandbox.clone()),
+            sandbox,
+            s3: None,
+        }
+    }
+
+    /// Keep the change journal, backups and trash in `dir`
+    pub fn with_data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.journal = Journal::new(dir);
+        self
     }
 
     /// Create with default configuration
@@ -91,73 +241,67 @@ impl FilesystemPlugin {
         Self::new(FilesystemConfig::default())
     }
 
-    /// Check if a path is within the allowed paths
-    fn is_path_allowed(&self, path: &Path) -> bool {
-        // If no paths are configured, allow nothing
-        if self.config.allowed_paths.is_empty() {
-            return false;
+    /// The backend holding `path`, or why it can't be used
+    fn storage(&self, path: &str) -> Result<&dyn StorageBackend, String> {
+        if !is_s3_uri(path) {
+            self.sandbox
+                .check(Path::new(path))
+                .map_err(|e| e.to_string())?;
+            return Ok(&self.local);
         }
 
-        // Canonicalize the path to prevent directory traversal attacks
-        let canonical = match path.canonicalize() {
-            Ok(p) => p,
-            Err(_) => {
-                // If we can't canonicalize, check parent directory for new files
-                if let Some(parent) = path.parent() {
-                    match parent.canonicalize() {
-                        Ok(p) => p,
-                        Err(_) => return false,
-                    }
-                } else {
-                    return false;
-                }
-            }
-        };
-
-        self.config.allowed_paths.iter().any(|allowed| {
-            if let Ok(allowed_canonical) = allowed.canonicalize() {
-                canonical.starts_with(&allowed_canonical)
-            } else {
-                false
-            }
-        })
+        if !storage::is_s3_allowed(&self.config.allowed_paths, path) {
+            return Err(format!(
+                "Access denied: path '{}' is not in allowed paths",
+                path
+            ));
+        }
+        let location = S3Location::parse(path).map_err(|e| e.to_string())?;
+        self.sandbox
+            .check_key(&location.key, path)
+            .map_err(|e| e.to_string())?;
+        match &self.s3 {
+            Some(s3) => Ok(s3),
+            None => Err("S3 storage is not enabled for this plugin".to_string()),
+        }
     }
 
-    /// Read a file from the filesystem
+    /// Read a file from the filesystem or cloud storage
     async fn read_file(&self, path: &str) -> Result<ToolResult, PluginError> {
-        let path = Path::new(path);
-
-        if !self.is_path_allowed(path) {
-            return Ok(ToolResult::failure(format!(
-                "Access denied: path '{}' is not in allowed paths",
-                path.display()
-            )));
-        }
+        let storage = match self.storage(path) {
+            Ok(storage) => storage,
+            Err(denied) => return Ok(ToolResult::failure(denied)),
+        };
 
         // Check file exists
-        if !path.exists() {
-            return Ok(ToolResult::failure(format!(
-                "File not found: {}",
-                path.display()
-            )));
-        }
+        let entry = match storage.stat(path).await {
+            Ok(Some(entry)) if !entry.is_dir => entry,
+            Ok(_) => return Ok(ToolResult::failure(format!("File not found: {}", path))),
+            Err(e) => return Ok(ToolResult::failure(e.to_string())),
+        };
 
         // Check file size
-        let metadata = fs::metadata(path).await?;
-        if metadata.len() > self.config.max_file_size {
+        if entry.size > self.config.max_file_size {
             return Ok(ToolResult::failure(format!(
                 "File too large: {} bytes (max: {} bytes)",
-                metadata.len(),
-                self.config.max_file_size
+                entry.size, self.config.max_file_size
             )));
         }
 
-        let content = fs::read_to_string(path).await?;
+        let content = match storage
+            .read(path, self.config.max_file_size)
+            .await
+            .map(String::from_utf8)
+        {
+            Ok(Ok(content)) => content,
+            Ok(Err(_)) => return Ok(ToolResult::failure(format!("Not a text file: {}", path))),
+            Err(e) => return Ok(ToolResult::failure(e.to_string())),
+        };
 
         Ok(ToolResult::success(json!({
-            "path": path.to_string_lossy(),
+            "path": entry.path,
             "content": content,
-            "size": metadata.len()
+            "size": entry.size
         })))
     }
 
@@ -169,73 +313,531 @@ impl FilesystemPlugin {
             ));
         }
 
+        // Cloud storage keeps no journal; versioning is the bucket's business
+        if is_s3_uri(path) {
+            let storage = match self.storage(path) {
+                Ok(storage) => storage,
+                Err(denied) => return Ok(ToolResult::failure(denied)),
+            };
+            return Ok(match storage.write(path, content.as_bytes()).await {
+                Ok(()) => ToolResult::success(json!({
+                    "path": path,
+                    "bytes_written": content.len()
+                })),
+                Err(e) => ToolResult::failure(e.to_string()),
+            });
+        }
+
         let path = Path::new(path);
 
-        if !self.is_path_allowed(path) {
-            return Ok(ToolResult::failure(format!(
-                "Access denied: path '{}' is not in allowed paths",
-                path.display()
-            )));
+        if let Err(denied) = self.sandbox.check(path) {
+            return Ok(ToolResult::failure(denied.to_string()));
+        }
+
+        let change = self
+            .rewrite(Operation::Write, path, content.as_bytes())
+            .await?;
+
+        Ok(changed(
+            &change,
+            json!({
+                "path": path.to_string_lossy(),
+                "bytes_written": content.len()
+            }),
+        ))
+    }
+
+    /// A failure if writes are disabled or `path` is not allowed
+    fn write_denied(&self, path: &Path) -> Option<ToolResult> {
+        if !self.config.allow_write {
+            return Some(ToolResult::failure(
+                "Write operations are disabled for this plugin",
+            ));
+        }
+
+        if is_s3_uri(&path.to_string_lossy()) {
+            return Some(ToolResult::failure(
+                "Only write_file is supported for cloud storage",
+            ));
+        }
+
+        if let Err(denied) = self.sandbox.check(path) {
+            return Some(ToolResult::failure(denied.to_string()));
+        }
+
+        None
+    }
+
+    /// Run a sandbox operation on `path`
+    async fn sandboxed<T, F>(&self, path: &Path, f: F) -> Result<T, PluginError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sandbox, &Path) -> Result<T, SandboxError> + Send + 'static,
+    {
+        let path = path.to_path_buf();
+        self.sandbox
+            .run(move |sandbox| f(sandbox, &path))
+            .await
+            .map_err(|e| match e {
+                SandboxError::Io(e) => PluginError::IoError(e),
+                e => PluginError::ExecutionFailed(e.to_string()),
+            })
+    }
+
+    /// Metadata of `path`, if it exists
+    async fn metadata(&self, path: &Path) -> Result<Option<Metadata>, PluginError> {
+        self.sandboxed(path, |sandbox, path| sandbox.metadata(path))
+            .await
+    }
+
+    /// Whether `path` is a file
+    async fn is_file(&self, path: &Path) -> Result<bool, PluginError> {
+        Ok(self
+            .metadata(path)
+            .await?
+            .is_some_and(|metadata| metadata.is_file()))
+    }
+
+    /// Replace the content of `path`, keeping a backup of the old content
+    async fn rewrite(
+        &self,
+        operation: Operation,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<Change, PluginError> {
+        let mut change = Change::new(operation, path);
+        if self.is_file(path).await? {
+            let previous = self
+                .sandboxed(path, |sandbox, path| sandbox.read(path))
+                .await?;
+            change.before = Some(digest(&previous));
+            self.journal.backup(&mut change, &previous).await?;
         }
 
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            if !parent.exists() {
-                fs::create_dir_all(parent).await?;
+        let bytes = content.to_vec();
+        self.sandboxed(path, move |sandbox, path| sandbox.write(path, &bytes))
+            .await?;
+        change.after = Some(digest(content));
+        self.journal.record(&change).await?;
+        Ok(change)
+    }
+
+    /// Read a file that is about to be changed, within the size limit
+    async fn read_for_edit(&self, path: &Path) -> Result<Result<Vec<u8>, ToolResult>, PluginError> {
+        let metadata = match self.metadata(path).await? {
+            Some(metadata) if metadata.is_file() => metadata,
+            _ => {
+                return Ok(Err(ToolResult::failure(format!(
+                    "File not found: {}",
+                    path.display()
+                ))))
             }
+        };
+        let max_size = self.config.max_file_size;
+        if metadata.len() > max_size {
+            return Ok(Err(ToolResult::failure(format!(
+                "File too large: {} bytes (max: {} bytes)",
+                metadata.len(),
+                max_size
+            ))));
         }
+        Ok(self
+            .sandboxed(path, move |sandbox, path| sandbox.read_limited(path, max_size))
+            .await?
+            .ok_or_else(|| ToolResult::failure(StorageError::TooLarge(max_size).to_string())))
+    }
 
-        fs::write(path, content).await?;
+    /// Append content to a file, creating it if needed
+    async fn append_file(&self, path: &str, content: &str) -> Result<ToolResult, PluginError> {
+        let path = Path::new(path);
+        if let Some(denied) = self.write_denied(path) {
+            return Ok(denied);
+        }
 
-        Ok(ToolResult::success(json!({
-            "path": path.to_string_lossy(),
-            "bytes_written": content.len()
-        })))
+        let mut bytes = if self.metadata(path).await?.is_some() {
+            match self.read_for_edit(path).await? {
+                Ok(bytes) => bytes,
+                Err(failure) => return Ok(failure),
+            }
+        } else {
+            Vec::new()
+        };
+        bytes.extend_from_slice(content.as_bytes());
+
+        let change = self.rewrite(Operation::Append, path, &bytes).await?;
+        Ok(changed(
+            &change,
+            json!({
+                "path": path.to_string_lossy(),
+                "bytes_appended": content.len(),
+                "size": bytes.len()
+            }),
+        ))
     }
 
-    /// List files in a directory
-    async fn list_directory(&self, path: &str) -> Result<ToolResult, PluginError> {
+    /// Replace text in a file, or apply a unified diff to it
+    async fn edit_file(&self, path: &str, edit: Edit<'_>) -> Result<ToolResult, PluginError> {
         let path = Path::new(path);
+        if let Some(denied) = self.write_denied(path) {
+            return Ok(denied);
+        }
 
-        if !self.is_path_allowed(path) {
+        let bytes = match self.read_for_edit(path).await? {
+            Ok(bytes) => bytes,
+            Err(failure) => return Ok(failure),
+        };
+        let Ok(text) = String::from_utf8(bytes) else {
             return Ok(ToolResult::failure(format!(
-                "Access denied: path '{}' is not in allowed paths",
+                "{} is not a text file",
                 path.display()
             )));
+        };
+
+        let (edited, details) = match edit {
+            Edit::Replace {
+                old_text,
+                new_text,
+                replace_all,
+            } => {
+                let count = text.matches(old_text).count();
+                if count == 0 {
+                    return Ok(ToolResult::failure(format!(
+                        "old_text not found in {}",
+                        path.display()
+                    )));
+                }
+                if count > 1 && !replace_all {
+                    return Ok(ToolResult::failure(format!(
+                        "old_text occurs {} times in {}; include more surrounding text or set replace_all",
+                        count,
+                        path.display()
+                    )));
+                }
+                (
+                    text.replace(old_text, new_text),
+                    json!({ "replacements": count }),
+                )
+            }
+            Edit::Patch(patch) => {
+                let patched = diffy::Patch::from_str(patch)
+                    .map_err(|e| format!("Invalid patch: {}", e))
+                    .and_then(|patch| {
+                        diffy::apply(&text, &patch)
+                            .map_err(|e| format!("Patch does not apply: {}", e))
+                    });
+                match patched {
+                    Ok(patched) => (patched, json!({ "patched": true })),
+                    Err(e) => return Ok(ToolResult::failure(e)),
+                }
+            }
+        };
+
+        let change = self
+            .rewrite(Operation::Edit, path, edited.as_bytes())
+            .await?;
+        let mut output = details;
+        output["path"] = json!(path.to_string_lossy());
+        Ok(changed(&change, output))
+    }
+
+    /// Copy or move a file to a path that doesn't exist yet
+    async fn transfer(
+        &self,
+        operation: Operation,
+        source: &str,
+        destination: &str,
+    ) -> Result<ToolResult, PluginError> {
+        let (source, destination) = (Path::new(source), Path::new(destination));
+        for path in [source, destination] {
+            if let Some(denied) = self.write_denied(path) {
+                return Ok(denied);
+            }
+        }
+        if !self.is_file(source).await? {
+            return Ok(ToolResult::failure(format!(
+                "File not found: {}",
+                source.display()
+            )));
+        }
+        if self.metadata(destination).await?.is_some() {
+            return Ok(ToolResult::failure(format!(
+                "Destination already exists: {}",
+                destination.display()
+            )));
+        }
+
+        let mut change = Change::new(operation, source);
+        change.destination = Some(destination.to_path_buf());
+        let content = self
+            .sandboxed(source, |sandbox, path| sandbox.read(path))
+            .await?;
+        change.before = Some(digest(&content));
+        let to = destination.to_path_buf();
+        if operation == Operation::Move {
+            self.sandboxed(source, move |sandbox, from| sandbox.rename(from, &to))
+                .await?;
+        } else {
+            self.sandboxed(source, move |sandbox, from| sandbox.copy(from, &to))
+                .await?;
         }
+        change.after = change.before.clone();
+        self.journal.record(&change).await?;
+
+        Ok(changed(
+            &change,
+            json!({
+                "source": source.to_string_lossy(),
+                "destination": destination.to_string_lossy()
+            }),
+        ))
+    }
 
-        if !path.exists() {
+    /// Create a directory whose parent exists
+    async fn create_directory(&self, path: &str) -> Result<ToolResult, PluginError> {
+        let path = Path::new(path);
+        if let Some(denied) = self.write_denied(path) {
+            return Ok(denied);
+        }
+        if self.metadata(path).await?.is_some() {
             return Ok(ToolResult::failure(format!(
-                "Directory not found: {}",
+                "Path already exists: {}",
                 path.display()
             )));
         }
 
-        let mut entries = Vec::new();
-        let mut read_dir = fs::read_dir(path).await?;
+        self.sandboxed(path, |sandbox, path| sandbox.create_dir(path))
+            .await?;
+        let change = Change::new(Operation::CreateDirectory, path);
+        self.journal.record(&change).await?;
+
+        Ok(changed(&change, json!({ "path": path.to_string_lossy() })))
+    }
+
+    /// Move a file to the trash
+    async fn delete_file(&self, path: &str) -> Result<ToolResult, PluginError> {
+        let path = Path::new(path);
+        if let Some(denied) = self.write_denied(path) {
+            return Ok(denied);
+        }
+        if !self.is_file(path).await? {
+            return Ok(ToolResult::failure(format!(
+                "File not found: {}",
+                path.display()
+            )));
+        }
 
-        while let Some(entry) = read_dir.next_entry().await? {
-            let file_type = entry.file_type().await?;
-            let metadata = entry.metadata().await?;
+        let mut change = Change::new(Operation::Delete, path);
+        let content = self
+            .sandboxed(path, |sandbox, path| sandbox.read(path))
+            .await?;
+        change.before = Some(digest(&content));
+        self.journal.trash(&mut change, &content).await?;
+        self.sandboxed(path, |sandbox, path| sandbox.remove_file(path))
+            .await?;
+        self.journal.record(&change).await?;
+
+        Ok(changed(&change, json!({ "path": path.to_string_lossy() })))
+    }
 
-            entries.push(json!({
-                "name": entry.file_name().to_string_lossy(),
-                "path": entry.path().to_string_lossy(),
-                "is_file": file_type.is_file(),
-                "is_dir": file_type.is_dir(),
-                "size": metadata.len()
-            }));
+    /// Undo a change, or the latest one
+    async fn undo_change(&self, id: Option<&str>) -> Result<ToolResult, PluginError> {
+        if !self.config.allow_write {
+            return Ok(ToolResult::failure(
+                "Write operations are disabled for this plugin",
+            ));
         }
 
+        Ok(match self.journal.undo(id, &self.sandbox).await {
+            Ok(change) => ToolResult::success(json!({ "undone": change })),
+            Err(e) => ToolResult::failure(e.to_string()),
+        })
+    }
+
+    /// The most recent changes, newest first
+    async fn list_changes(&self) -> Result<ToolResult, PluginError> {
+        let changes = match self.journal.changes().await {
+            Ok(changes) => changes,
+            Err(e) => return Ok(ToolResult::failure(e.to_string())),
+        };
+        let recent: Vec<&Change> = changes.iter().rev().take(MAX_LISTED_CHANGES).collect();
+
+        Ok(ToolResult::success(json!({
+            "total": changes.len(),
+            "changes": recent
+        })))
+    }
+
+    /// List files in a directory
+    async fn list_directory(&self, path: &str) -> Result<ToolResult, PluginError> {
+        let storage = match self.storage(path) {
+            Ok(storage) => storage,
+            Err(denied) => return Ok(ToolResult::failure(denied)),
+        };
+
+        let entries = match storage.list(path).await {
+            Ok(entries) => self.visible(entries),
+            Err(StorageError::NotFound(_)) => {
+                return Ok(ToolResult::failure(format!(
+                    "Directory not found: {}",
+                    path
+                )))
+            }
+            Err(e) => return Ok(ToolResult::failure(e.to_string())),
+        };
+
+        let entries: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                json!({
+                    "name": entry.name,
+                    "path": entry.path,
+                    "is_file": !entry.is_dir,
+                    "is_dir": entry.is_dir,
+                    "size": entry.size
+                })
+            })
+            .collect();
+
         Ok(ToolResult::success(json!({
-            "path": path.to_string_lossy(),
+            "path": path,
             "count": entries.len(),
             "entries": entries
         })))
     }
 
+    /// The entry at `path`, or why it can't be used
+    async fn stat(&self, path: &str) -> Result<(&dyn StorageBackend, Entry), String> {
+        let storage = self.storage(path)?;
+        match storage.stat(path).await {
+            Ok(Some(entry)) => Ok((storage, entry)),
+            Ok(None) => Err(format!("Path not found: {}", path)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// The entries a search below `path` looks at; a file is its own walk
+    async fn walk(
+        &self,
+        path: &str,
+        limits: Limits,
+    ) -> Result<(&dyn StorageBackend, Vec<Entry>), String> {
+        let (storage, root) = self.stat(path).await?;
+        if !root.is_dir {
+            return Ok((storage, vec![root]));
+        }
+        // One more than scanned, so searches can tell they were cut short
+        match storage
+            .walk(path, limits.max_depth, MAX_FILES_SCANNED + 1)
+            .await
+        {
+            Ok(entries) => Ok((storage, self.visible(entries))),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Leave out S3 entries that are denied or hidden; the sandbox already
+    /// filters local ones
+    fn visible(&self, entries: Vec<Entry>) -> Vec<Entry> {
+        entries
+            .into_iter()
+            .filter(|entry| {
+                !is_s3_uri(&entry.path)
+                    || S3Location::parse(&entry.path)
+                        .is_ok_and(|location| self.sandbox.key_visible(&location.key))
+            })
+            .collect()
+    }
+
+    /// Find files below `path` by name, pattern or date
+    async fn search_files(
+        &self,
+        path: &str,
+        query: FileQuery,
+        limits: Limits,
+    ) -> Result<ToolResult, PluginError> {
+        let entries = match self.walk(path, limits).await {
+            Ok((_, entries)) => entries,
+            Err(failure) => return Ok(ToolResult::failure(failure)),
+        };
+
+        let mut output = search::search_files(path, &entries, &query, limits);
+        output["path"] = json!(path);
+        Ok(ToolResult::success(output))
+    }
+
+    /// Find matching lines in the files below `path`
+    async fn grep_files(
+        &self,
+        path: &str,
+        grep: Grep,
+        limits: Limits,
+    ) -> Result<ToolResult, PluginError> {
+        let (storage, entries) = match self.walk(path, limits).await {
+            Ok(walk) => walk,
+            Err(failure) => return Ok(ToolResult::failure(failure)),
+        };
+
+        let mut matches = Vec::new();
+        let mut files_searched = 0;
+        let mut truncated = entries.len() > MAX_FILES_SCANNED;
+        for entry in entries.iter().take(MAX_FILES_SCANNED) {
+            if !grep.wants(path, entry, limits) {
+                continue;
+            }
+            // Files that can't be read are skipped, like binary ones
+            let Ok(bytes) = storage.read(&entry.path, limits.max_file_size).await else {
+                continue;
+            };
+            let remaining = limits.max_results - matches.len();
+            let Some(lines) = grep.lines(&entry.path, &bytes, remaining + 1) else {
+                continue;
+            };
+
+            files_searched += 1;
+            if lines.len() > remaining {
+                matches.extend(lines.into_iter().take(remaining));
+                truncated = true;
+                break;
+            }
+            matches.extend(lines);
+        }
+
+        Ok(ToolResult::success(json!({
+            "path": path,
+            "files_searched": files_searched,
+            "count": matches.len(),
+            "truncated": truncated,
+            "matches": matches,
+        })))
+    }
+
+    /// Size, times and MIME type of `path`
+    async fn file_info(&self, path: &str) -> Result<ToolResult, PluginError> {
+        Ok(match self.stat(path).await {
+            Ok((_, entry)) => ToolResult::success(search::file_info(&entry)),
+            Err(failure) => ToolResult::failure(failure),
+        })
+    }
+
+    /// Search limits, narrowed by the `max_depth` parameter
+    fn limits(&self, params: &Value) -> Limits {
+        let max_depth = self.config.max_search_depth;
+        Limits {
+            max_depth: params["max_depth"]
+                .as_u64()
+                .map_or(max_depth, |depth| (depth as usize).clamp(1, max_depth)),
+            max_results: self.config.max_search_results,
+            max_file_size: self.config.max_file_size,
+        }
+    }
+
     /// Build tools list based on configuration
     fn build_tools(&self) -> Vec<ToolDefinition> {
+        let list_description = if self.config.s3_enabled() {
+            "List files and directories in a path or an s3://bucket/prefix URI"
+        } else {
+            "List files and directories in a path"
+        };
         let mut tools = vec![
             ToolDefinition::new("read_file", "Read the contents of a file")
                 .with_parameters(json!({
@@ -249,7 +851,7 @@ impl FilesystemPlugin {
                     "required": ["path"]
                 }))
                 .from_plugin(Self::ID),
-            ToolDefinition::new("list_directory", "List files and directories in a path")
+            ToolDefinition::new("list_directory", list_description)
                 .with_parameters(json!({
                     "type": "object",
                     "properties": {
@@ -261,6 +863,91 @@ impl FilesystemPlugin {
                     "required": ["path"]
                 }))
                 .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "search_files",
+                "Find files and directories below a path by glob pattern, name or modification date",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The directory to search"
+                    },
+                    "pattern": {
+                        "type": "string",
+                        "description": "Glob matched against names (e.g. \"*.pdf\"), or against paths below the directory if it contains '/' (e.g. \"2024/**/invoice*\")"
+                    },
+                    "name": {
+                        "type": "string",
+                        "description": "Text the name must contain (case-insensitive)"
+                    },
+                    "modified_after": {
+                        "type": "string",
+                        "description": "Only entries modified on or after this date (YYYY-MM-DD)"
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "description": format!("Directory levels to search (max {})", self.config.max_search_depth)
+                    }
+                },
+                "required": ["path"]
+            }))
+            .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "grep_files",
+                "Find lines containing text in the files below a path",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The directory (or file) to search"
+                    },
+                    "query": {
+                        "type": "string",
+                        "description": "Text to find"
+                    },
+                    "regex": {
+                        "type": "boolean",
+                        "description": "Treat the query as a regular expression (default: false)"
+                    },
+                    "case_sensitive": {
+                        "type": "boolean",
+                        "description": "Match case exactly (default: false)"
+                    },
+                    "include": {
+                        "type": "string",
+                        "description": "Only search files matching this glob (e.g. \"*.csv\")"
+                    },
+                    "context": {
+                        "type": "integer",
+                        "description": format!("Lines shown before and after each match (default {}, max {})", DEFAULT_CONTEXT_LINES, MAX_CONTEXT_LINES)
+                    },
+                    "max_depth": {
+                        "type": "integer",
+                        "description": format!("Directory levels to search (max {})", self.config.max_search_depth)
+                    }
+                },
+                "required": ["path", "query"]
+            }))
+            .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "file_info",
+                "Get the size, modification time and MIME type of a file or directory",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "The file or directory"
+                    }
+                },
+                "required": ["path"]
+            }))
+            .from_plugin(Self::ID),
         ];
 
         if self.config.allow_write {
@@ -283,10 +970,147 @@ impl FilesystemPlugin {
                     .with_confirmation()
                     .from_plugin(Self::ID),
             );
+            tools.extend(self.build_edit_tools());
         }
 
         tools
     }
+
+    /// Tools that change files, offered with `allow_write`
+    fn build_edit_tools(&self) -> Vec<ToolDefinition> {
+        let path = |description: &str| json!({ "type": "string", "description": description });
+
+        vec![
+            ToolDefinition::new("append_file", "Append content to the end of a file, creating it if needed")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": path("The file to append to"),
+                        "content": {
+                            "type": "string",
+                            "description": "The content to append"
+                        }
+                    },
+                    "required": ["path", "content"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "edit_file",
+                "Edit a text file by replacing exact text, or by applying a unified diff",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "path": path("The file to edit"),
+                    "old_text": {
+                        "type": "string",
+                        "description": "Exact text to replace; must occur once unless replace_all is set"
+                    },
+                    "new_text": {
+                        "type": "string",
+                        "description": "Text to put in its place"
+                    },
+                    "replace_all": {
+                        "type": "boolean",
+                        "description": "Replace every occurrence (default: false)"
+                    },
+                    "patch": {
+                        "type": "string",
+                        "description": "A unified diff to apply instead of old_text/new_text"
+                    }
+                },
+                "required": ["path"]
+            }))
+            .with_confirmation()
+            .from_plugin(Self::ID),
+            ToolDefinition::new("copy_file", "Copy a file to a new path")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "source": path("The file to copy"),
+                        "destination": path("The new path; must not exist")
+                    },
+                    "required": ["source", "destination"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            ToolDefinition::new("move_file", "Move or rename a file")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "source": path("The file to move"),
+                        "destination": path("The new path; must not exist")
+                    },
+                    "required": ["source", "destination"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            ToolDefinition::new("create_directory", "Create a directory")
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": path("The directory to create; its parent must exist")
+                    },
+                    "required": ["path"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "delete_file",
+                "Delete a file; it is kept in a trash and can be restored with undo_change",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "path": path("The file to delete")
+                },
+                "required": ["path"]
+            }))
+            .with_confirmation()
+            .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "list_changes",
+                "List recent file changes, newest first, with their IDs for undo_change",
+            )
+            .from_plugin(Self::ID),
+            ToolDefinition::new(
+                "undo_change",
+                "Undo a file change, or the latest one; fails if the file changed since",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "change_id": {
+                        "type": "string",
+                        "description": "ID from list_changes (default: the latest change)"
+                    }
+                }
+            }))
+            .with_confirmation()
+            .from_plugin(Self::ID),
+        ]
+    }
+}
+
+/// How `edit_file` changes a file
+enum Edit<'a> {
+    /// Exact text replacement
+    Replace {
+        old_text: &'a str,
+        new_text: &'a str,
+        replace_all: bool,
+    },
+    /// A unified diff
+    Patch(&'a str),
+}
+
+/// A successful change, with its journal ID and checksums
+fn changed(change: &Change, mut output: Value) -> ToolResult {
+    output["change_id"] = json!(change.id);
+    output["before"] = json!(change.before);
+    output["after"] = json!(change.after);
+    ToolResult::success(output)
 }
 
 // ============================================================================
@@ -299,12 +1123,19 @@ impl Plugin for FilesystemPlugin {
         PluginManifest::new(
             Self::ID,
             "Filesystem",
-            "Read, write, and list files on the local filesystem",
+            "Read, write, list and search files on the local filesystem",
         )
         .with_version(1, 0, 0)
         .with_author("Moxie AI")
         .with_category(PluginCategory::Filesystem)
-        .with_keywords(vec!["files", "filesystem", "read", "write", "directory"])
+        .with_keywords(vec![
+            "files",
+            "filesystem",
+            "read",
+            "write",
+            "directory",
+            "search",
+        ])
         .with_config_field(
             ConfigFieldBuilder::new("allowed_paths", ConfigFieldType::PathArray)
                 .label("Allowed Paths")
@@ -326,6 +1157,43 @@ impl Plugin for FilesystemPlugin {
                 .default_value(json!(10485760))
                 .build(),
         )
+        .with_config_field(
+            ConfigFieldBuilder::new("max_search_results", ConfigFieldType::Number)
+                .label("Max Search Results")
+                .description("Maximum number of files or lines returned by a search")
+                .default_value(json!(100))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("max_search_depth", ConfigFieldType::Number)
+                .label("Max Search Depth")
+                .description("How many directories deep a search may go")
+                .default_value(json!(10))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("deny_patterns", ConfigFieldType::StringArray)
+                .label("Deny Patterns")
+                .description("Files refused even inside the allowed paths (e.g. **/.env, *.key)")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new(
+                "hidden_files",
+                ConfigFieldType::Select(vec!["allow".into(), "hide".into(), "deny".into()]),
+            )
+            .label("Hidden Files")
+            .description("Whether files starting with '.' are accessible (allow), left out of listings and searches (hide), or refused (deny)")
+            .default_value(json!("allow"))
+            .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("cloud_providers", ConfigFieldType::StringArray)
+                .label("Cloud Providers")
+                .description("Cloud storage to enable (\"s3\" for S3-compatible storage such as AWS S3 or MinIO)")
+                .validation("s3")
+                .build(),
+        )
     }
 
     fn tools(&self) -> Vec<ToolDefinition> {
@@ -355,6 +1223,139 @@ impl Plugin for FilesystemPlugin {
                     .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
                 self.list_directory(path).await
             }
+            "search_files" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                let pattern = match params["pattern"].as_str().filter(|p| !p.is_empty()) {
+                    Some(pattern) => match Pattern::new(pattern) {
+                        Ok(pattern) => Some(pattern),
+                        Err(e) => return Ok(ToolResult::failure(e.to_string())),
+                    },
+                    None => None,
+                };
+                let modified_after = params["modified_after"]
+                    .as_str()
+                    .map(|date| {
+                        chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                            PluginError::InvalidParameters(
+                                "modified_after must be YYYY-MM-DD".into(),
+                            )
+                        })
+                    })
+                    .transpose()?;
+                let query = FileQuery {
+                    pattern,
+                    name: params["name"].as_str().map(String::from),
+                    modified_after,
+                };
+                let limits = self.limits(&params);
+                self.search_files(path, query, limits).await
+            }
+            "grep_files" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                let query = params["query"]
+                    .as_str()
+                    .filter(|q| !q.is_empty())
+                    .ok_or_else(|| PluginError::InvalidParameters("query is required".into()))?;
+                let query = if params["regex"].as_bool().unwrap_or(false) {
+                    query.to_string()
+                } else {
+                    regex::escape(query)
+                };
+                let regex = match regex::RegexBuilder::new(&query)
+                    .case_insensitive(!params["case_sensitive"].as_bool().unwrap_or(false))
+                    .build()
+                {
+                    Ok(regex) => regex,
+                    Err(e) => return Ok(ToolResult::failure(SearchError::from(e).to_string())),
+                };
+                let include = match params["include"].as_str().filter(|p| !p.is_empty()) {
+                    Some(include) => match Pattern::new(include) {
+                        Ok(include) => Some(include),
+                        Err(e) => return Ok(ToolResult::failure(e.to_string())),
+                    },
+                    None => None,
+                };
+                let context = params["context"]
+                    .as_u64()
+                    .map_or(DEFAULT_CONTEXT_LINES, |c| {
+                        (c as usize).min(MAX_CONTEXT_LINES)
+                    });
+                let grep = Grep {
+                    regex,
+                    include,
+                    context,
+                };
+                let limits = self.limits(&params);
+                self.grep_files(path, grep, limits).await
+            }
+            "append_file" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                let content = params["content"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("content is required".into()))?;
+                self.append_file(path, content).await
+            }
+            "edit_file" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                let edit = match (params["patch"].as_str(), params["old_text"].as_str()) {
+                    (Some(patch), None) => Edit::Patch(patch),
+                    (None, Some(old_text)) if !old_text.is_empty() => Edit::Replace {
+                        old_text,
+                        new_text: params["new_text"].as_str().ok_or_else(|| {
+                            PluginError::InvalidParameters("new_text is required".into())
+                        })?,
+                        replace_all: params["replace_all"].as_bool().unwrap_or(false),
+                    },
+                    _ => {
+                        return Err(PluginError::InvalidParameters(
+                            "give either old_text and new_text, or patch".into(),
+                        ))
+                    }
+                };
+                self.edit_file(path, edit).await
+            }
+            "copy_file" | "move_file" => {
+                let source = params["source"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("source is required".into()))?;
+                let destination = params["destination"].as_str().ok_or_else(|| {
+                    PluginError::InvalidParameters("destination is required".into())
+                })?;
+                let operation = if tool == "copy_file" {
+                    Operation::Copy
+                } else {
+                    Operation::Move
+                };
+                self.transfer(operation, source, destination).await
+            }
+            "create_directory" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                self.create_directory(path).await
+            }
+            "delete_file" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                self.delete_file(path).await
+            }
+            "list_changes" => self.list_changes().await,
+            "undo_change" => self.undo_change(params["change_id"].as_str()).await,
+            "file_info" => {
+                let path = params["path"]
+                    .as_str()
+                    .ok_or_else(|| PluginError::InvalidParameters("path is required".into()))?;
+                self.file_info(path).await
+            }
             _ => Err(PluginError::ToolNotFound(tool.to_string())),
         }
     }
@@ -364,6 +1365,19 @@ impl Plugin for FilesystemPlugin {
         if !ctx.config.is_null() {
             self.config = FilesystemConfig::from_value(&ctx.config)?;
         }
+        self.journal = Journal::new(&ctx.data_dir);
+        self.sandbox = sandbox(&self.config);
+        self.local = LocalStorage::new(self.sandbox.clone());
+
+        self.s3 = None;
+        if self.config.s3_enabled() {
+            let config = self.config.s3.clone().unwrap_or_default();
+            let storage = config
+                .credentials()
+                .and_then(|credentials| S3Storage::new(&config, credentials))
+                .map_err(|e| PluginError::InitFailed(format!("S3 storage: {}", e)))?;
+            self.s3 = Some(storage);
+        }
 
         tracing::info!(
             "Filesystem plugin initialized with {} allowed paths",
@@ -411,12 +1425,14 @@ mod tests {
     use super::*;
     use crate::plugins::traits::Plugin as NewPlugin;
     use std::env;
+    use tokio::fs;
 
     fn test_config() -> FilesystemConfig {
         FilesystemConfig {
             allowed_paths: vec![env::temp_dir()],
             allow_write: true,
             max_file_size: 1024 * 1024,
+            ..FilesystemConfig::default()
         }
     }
 
@@ -432,7 +1448,8 @@ mod tests {
 
     #[tokio::test]
     async fn test_read_write_file() {
-        let plugin = FilesystemPlugin::new(test_config());
+        let data_dir = env::temp_dir().join(format!("moxie-fs-data-{}", uuid::Uuid::new_v4()));
+        let plugin = FilesystemPlugin::new(test_config()).with_data_dir(&data_dir);
         let test_path = env::temp_dir().join("moxie_test_file.txt");
 
         // Write - use the internal method directly
@@ -455,6 +1472,7 @@ mod tests {
 
         // Cleanup
         fs::remove_file(test_path).await.ok();
+        fs::remove_dir_all(data_dir).await.ok();
     }
 
     #[tokio::test]
@@ -476,6 +1494,7 @@ mod tests {
             allowed_paths: vec![PathBuf::from("/allowed/path")],
             allow_write: true,
             max_file_size: 1024,
+            ..FilesystemConfig::default()
         });
 
         let result = plugin
@@ -485,5 +1504,623 @@ mod tests {
 
         assert!(!result.success);
         assert!(result.error.unwrap().contains("Access denied"));
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "grep_files",
+            json!({"path": "/not/allowed", "query": "secret"}),
+        )
+        .await
+        .unwrap();
+        assert!(!result.success);
+    }
+
+    #[tokio::test]
+    async fn test_deny_patterns_and_hidden_files() {
+        let root = env::temp_dir().join(format!("moxie-deny-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join(".cache")).unwrap();
+        std::fs::write(root.join(".env"), "SECRET=hunter2\n").unwrap();
+        std::fs::write(root.join("server.key"), "SECRET key\n").unwrap();
+        std::fs::write(root.join(".cache/index"), "SECRET index\n").unwrap();
+        std::fs::write(root.join("notes.txt"), "no secrets here\n").unwrap();
+
+        assert!(FilesystemConfig::from_value(&json!({"deny_patterns": ["["]})).is_err());
+        let config = FilesystemConfig::from_value(&json!({
+            "allowed_paths": [root],
+            "allow_write": true,
+            "deny_patterns": ["**/.env", "*.key"],
+            "hidden_files": "hide",
+        }))
+        .unwrap();
+        let plugin = FilesystemPlugin::new(config);
+        let file = |name: &str| root.join(name).to_string_lossy().into_owned();
+
+        let result = plugin.read_file(&file(".env")).await.unwrap();
+        assert!(result.error.unwrap().contains("deny pattern '**/.env'"));
+        let result = plugin
+            .write_file(&file("server.key"), "replaced")
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().contains("Access denied"));
+        // Hidden, but readable by explicit path
+        let result = plugin.read_file(&file(".cache/index")).await.unwrap();
+        assert!(result.success);
+
+        let result = plugin.list_directory(&file("")).await.unwrap();
+        assert_eq!(result.output["count"], 1);
+        assert_eq!(result.output["entries"][0]["name"], "notes.txt");
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "grep_files",
+            json!({"path": root, "query": "SECRET", "case_sensitive": true}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 0);
+        assert_eq!(result.output["files_searched"], 1);
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_search_grep_and_info() {
+        let root = env::temp_dir().join(format!("moxie-search-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("2024/march")).unwrap();
+        std::fs::write(root.join("2024/march/Invoice-0042.pdf"), b"%PDF\0binary").unwrap();
+        std::fs::write(
+            root.join("2024/march/notes.txt"),
+            "intro\nsupplier: Acme\ntotal: 1200 EUR\nthanks\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("readme.md"), "Total is on the invoice\n").unwrap();
+        let plugin = FilesystemPlugin::new(FilesystemConfig {
+            allowed_paths: vec![root.clone()],
+            max_search_results: 2,
+            ..FilesystemConfig::default()
+        });
+        let dir = root.to_string_lossy();
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_files",
+            json!({"path": dir, "pattern": "*.PDF"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 1);
+        let found = result.output["matches"][0]["path"].as_str().unwrap();
+        assert!(found.ends_with("Invoice-0042.pdf"));
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_files",
+            json!({"path": dir, "pattern": "2024/**/*.txt"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 1);
+
+        // Too shallow to reach the invoice
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_files",
+            json!({"path": dir, "name": "invoice", "max_depth": 2}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 0);
+
+        // Results are capped, and binary files are not searched
+        let result = NewPlugin::execute(
+            &plugin,
+            "grep_files",
+            json!({"path": dir, "query": "total", "context": 1}),
+        )
+        .await
+        .unwrap();
+        let output = &result.output;
+        assert_eq!(output["files_searched"], 2);
+        assert_eq!(output["count"], 2);
+        assert_eq!(output["matches"][0]["line"], 3);
+        assert_eq!(output["matches"][0]["before"], json!(["supplier: Acme"]));
+        assert_eq!(output["matches"][0]["after"], json!(["thanks"]));
+        assert_eq!(output["truncated"], false);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "grep_files",
+            json!({"path": dir, "query": "\\d+ EUR", "regex": true, "include": "*.txt"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 1);
+
+        let result = NewPlugin::execute(&plugin, "file_info", json!({"path": found}))
+            .await
+            .unwrap();
+        assert_eq!(result.output["mime_type"], "application/pdf");
+        assert_eq!(result.output["size"], 11);
+        assert!(result.output["modified"].is_string());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_config_reaches_the_plugin() {
+        let root = env::temp_dir().join(format!("moxie-limits-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            std::fs::write(root.join(name), "total\n").unwrap();
+        }
+        let toml = format!(
+            "[client]\nname = \"Test\"\n\n[plugins]\nenabled = [\"filesystem\"]\n\n\
+             [plugins.filesystem]\nallowed_paths = [{:?}]\nmax_search_results = 1\n",
+            root.to_string_lossy()
+        );
+        let client = crate::config::ClientConfig::from_str(&toml).unwrap();
+        let section = client.plugins.section("filesystem").unwrap();
+
+        let config = FilesystemConfig::from_value(&section).unwrap();
+        assert_eq!(config.max_search_results, 1);
+        assert_eq!(config.max_file_size, FilesystemConfig::default().max_file_size);
+
+        let plugin = FilesystemPlugin::new(config);
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_files",
+            json!({"path": root.to_string_lossy(), "pattern": "*.txt"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 1);
+        assert_eq!(result.output["truncated"], true);
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_edit_trash_and_undo() {
+        let root = env::temp_dir().join(format!("moxie-edit-{}", uuid::Uuid::new_v4()));
+        let files = root.join("files");
+        std::fs::create_dir_all(&files).unwrap();
+        let plugin = FilesystemPlugin::new(FilesystemConfig {
+            allowed_paths: vec![files.clone()],
+            allow_write: true,
+            ..FilesystemConfig::default()
+        })
+        .with_data_dir(root.join("data"));
+        let report = files.join("report.txt");
+        let path = report.to_string_lossy().to_string();
+        std::fs::write(&report, "Q1: 100\nQ2: 100\n").unwrap();
+        let run = |tool: &'static str, params: Value| {
+            let plugin = &plugin;
+            async move { NewPlugin::execute(plugin, tool, params).await.unwrap() }
+        };
+
+        let confirmed: Vec<_> = NewPlugin::tools(&plugin)
+            .into_iter()
+            .filter(|t| t.requires_confirmation)
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(
+            confirmed,
+            vec![
+                "write_file",
+                "append_file",
+                "edit_file",
+                "copy_file",
+                "move_file",
+                "create_directory",
+                "delete_file",
+                "undo_change"
+            ]
+        );
+
+        // Ambiguous text is refused
+        let result = run(
+            "edit_file",
+            json!({"path": path, "old_text": "100", "new_text": "120"}),
+        )
+        .await;
+        assert!(!result.success);
+
+        let result = run(
+            "edit_file",
+            json!({"path": path, "old_text": "Q2: 100", "new_text": "Q2: 120"}),
+        )
+        .await;
+        assert!(result.success, "{:?}", result.error);
+        assert_ne!(result.output["before"], result.output["after"]);
+
+        let patch =
+            "--- a/report.txt\n+++ b/report.txt\n@@ -1,2 +1,2 @@\n-Q1: 100\n+Q1: 110\n Q2: 120\n";
+        let result = run("edit_file", json!({"path": path, "patch": patch})).await;
+        assert!(result.success, "{:?}", result.error);
+        assert!(
+            run("append_file", json!({"path": path, "content": "Q3: 130\n"}))
+                .await
+                .success
+        );
+        assert_eq!(
+            std::fs::read_to_string(&report).unwrap(),
+            "Q1: 110\nQ2: 120\nQ3: 130\n"
+        );
+
+        // Deleted files go to the trash and come back on undo
+        let copy = files.join("copy.txt");
+        assert!(
+            run(
+                "copy_file",
+                json!({"source": path, "destination": copy.to_string_lossy()}),
+            )
+            .await
+            .success
+        );
+        let result = run("delete_file", json!({"path": path})).await;
+        assert!(result.success);
+        assert!(!report.exists());
+        assert!(run("undo_change", json!({})).await.success);
+        assert!(report.exists());
+
+        // The copy changed since it was made, so that undo is refused
+        let changes = run("list_changes", json!({})).await.output;
+        assert_eq!(changes["total"], 5);
+        let copy_id = changes["changes"][1]["id"].clone();
+        std::fs::write(&copy, "changed elsewhere").unwrap();
+        let result = run("undo_change", json!({"change_id": copy_id})).await;
+        assert!(result.error.unwrap().contains("has changed"));
+        std::fs::write(&copy, "Q1: 110\nQ2: 120\nQ3: 130\n").unwrap();
+
+        // Undo the copy and the edits in reverse order to get the original back
+        for _ in 0..4 {
+            let result = run("undo_change", json!({})).await;
+            assert!(result.success, "{:?}", result.error);
+        }
+        assert!(!copy.exists());
+        assert_eq!(
+            std::fs::read_to_string(&report).unwrap(),
+            "Q1: 100\nQ2: 100\n"
+        );
+
+        // Undo goes through the sandbox, so a file swapped for a link to
+        // outside the allowed paths is left alone
+        #[cfg(unix)]
+        {
+            let outside = root.join("outside.txt");
+            std::fs::write(&outside, "secret").unwrap();
+            let created = files.join("created.txt");
+            let result = run(
+                "write_file",
+                json!({"path": created.to_string_lossy(), "content": "secret"}),
+            )
+            .await;
+            assert!(result.success);
+            std::fs::remove_file(&created).unwrap();
+            std::os::unix::fs::symlink("../outside.txt", &created).unwrap();
+            assert!(!run("undo_change", json!({})).await.success);
+            assert_eq!(std::fs::read_to_string(&outside).unwrap(), "secret");
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_undo_after_further_changes() {
+        let root = env::temp_dir().join(format!("moxie-undo-{}", uuid::Uuid::new_v4()));
+        let files = root.join("files");
+        std::fs::create_dir_all(&files).unwrap();
+        let plugin = FilesystemPlugin::new(FilesystemConfig {
+            allowed_paths: vec![files.clone()],
+            allow_write: true,
+            ..FilesystemConfig::default()
+        })
+        .with_data_dir(root.join("data"));
+        let report = files.join("report.txt");
+        let path = report.to_string_lossy().to_string();
+        std::fs::write(&report, "draft\n").unwrap();
+        let run = |tool: &'static str, params: Value| {
+            let plugin = &plugin;
+            async move { NewPlugin::execute(plugin, tool, params).await.unwrap() }
+        };
+
+        // A deleted file that was created again is not overwritten by undo
+        let deleted = run("delete_file", json!({"path": path})).await;
+        assert!(deleted.success);
+        std::fs::write(&report, "new\n").unwrap();
+        let result = run("undo_change", json!({})).await;
+        assert!(result.error.unwrap().contains("already exists"));
+        std::fs::remove_file(&report).unwrap();
+        let result = run("undo_change", json!({})).await;
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(std::fs::read_to_string(&report).unwrap(), "draft\n");
+
+        // An edit followed by another one is only undone after the later one
+        let first = run("write_file", json!({"path": path, "content": "first\n"})).await;
+        let first_id = first.output["change_id"].clone();
+        assert!(
+            run("write_file", json!({"path": path, "content": "second\n"}))
+                .await
+                .success
+        );
+        let result = run("undo_change", json!({"change_id": first_id})).await;
+        assert!(result.error.unwrap().contains("has changed"));
+        assert_eq!(std::fs::read_to_string(&report).unwrap(), "second\n");
+        assert!(run("undo_change", json!({})).await.success);
+        assert!(
+            run("undo_change", json!({"change_id": first_id}))
+                .await
+                .success
+        );
+        assert_eq!(std::fs::read_to_string(&report).unwrap(), "draft\n");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_journal_retention() {
+        let root = env::temp_dir().join(format!("moxie-retention-{}", uuid::Uuid::new_v4()));
+        let files = root.join("files");
+        let data = root.join("data");
+        std::fs::create_dir_all(&files).unwrap();
+        let mut plugin = FilesystemPlugin::new(FilesystemConfig {
+            allowed_paths: vec![files.clone()],
+            allow_write: true,
+            ..FilesystemConfig::default()
+        });
+        plugin.journal = Journal::new(&data).with_retention(3, 20);
+        let path = files.join("log.txt").to_string_lossy().to_string();
+        let run = |tool: &'static str, params: Value| {
+            let plugin = &plugin;
+            async move { NewPlugin::execute(plugin, tool, params).await.unwrap() }
+        };
+
+        // Each write backs up the previous 8 bytes; only two backups fit
+        for n in 0..5 {
+            let content = format!("entry {}\n", n);
+            assert!(
+                run("write_file", json!({"path": path, "content": content}))
+                    .await
+                    .success
+            );
+        }
+        let changes = run("list_changes", json!({})).await.output;
+        assert_eq!(changes["total"], 2);
+        let backups = std::fs::read_dir(data.join("backups")).unwrap().count();
+        assert_eq!(backups, 2);
+
+        // The kept changes still undo; the dropped ones are gone
+        assert!(run("undo_change", json!({})).await.success);
+        assert!(run("undo_change", json!({})).await.success);
+        let result = run("undo_change", json!({})).await;
+        assert!(result.error.unwrap().contains("No changes"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "entry 2\n");
+
+        // Deleted files count against the limit too
+        assert!(run("delete_file", json!({"path": path})).await.success);
+        let trashed = std::fs::read_dir(data.join("trash")).unwrap().count();
+        assert_eq!(trashed, 1);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    /// A minimal in-memory S3 server: objects by GET, PUT and HEAD, and
+    /// ListObjectsV2 listings
+    async fn fake_s3(objects: &[(&str, &str)]) -> String {
+        use axum::body::Bytes;
+        use axum::extract::{Query, State};
+        use axum::http::{header, Method, StatusCode, Uri};
+        use axum::response::{IntoResponse, Response};
+        use std::collections::{BTreeMap, BTreeSet, HashMap};
+        use std::sync::{Arc, Mutex};
+
+        type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+        async fn handle(
+            State(objects): State<Objects>,
+            method: Method,
+            uri: Uri,
+            Query(query): Query<HashMap<String, String>>,
+            body: Bytes,
+        ) -> Response {
+            let path = uri.path().trim_start_matches('/').to_string();
+            let mut objects = objects.lock().unwrap();
+
+            if query.get("list-type").is_some_and(|t| t == "2") {
+                let prefix = query.get("prefix").cloned().unwrap_or_default();
+                let bucket = format!("{}/", path);
+                let mut contents = String::new();
+                let mut prefixes = BTreeSet::new();
+                for (key, data) in objects.iter() {
+                    let Some(key) = key.strip_prefix(&bucket) else {
+                        continue;
+                    };
+                    let Some(rest) = key.strip_prefix(&prefix) else {
+                        continue;
+                    };
+                    match rest.find('/') {
+                        Some(slash) if query.contains_key("delimiter") => {
+                            prefixes.insert(format!("{}{}/", prefix, &rest[..slash]));
+                        }
+                        _ => contents.push_str(&format!(
+                            "<Contents><Key>{}</Key><Size>{}</Size>\
+                             <LastModified>2024-03-01T12:00:00.000Z</LastModified></Contents>",
+                            key,
+                            data.len()
+                        )),
+                    }
+                }
+                let prefixes: String = prefixes
+                    .iter()
+                    .map(|p| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", p))
+                    .collect();
+                return format!(
+                    "<ListBucketResult>{}{}</ListBucketResult>",
+                    contents, prefixes
+                )
+                .into_response();
+            }
+
+            match method {
+                Method::PUT => {
+                    objects.insert(path, body.to_vec());
+                    StatusCode::OK.into_response()
+                }
+                Method::GET | Method::HEAD => match objects.get(&path) {
+                    Some(data) => (
+                        [
+                            (header::CONTENT_LENGTH, data.len().to_string()),
+                            (
+                                header::LAST_MODIFIED,
+                                "Fri, 01 Mar 2024 12:00:00 GMT".to_string(),
+                            ),
+                        ],
+                        data.clone(),
+                    )
+                        .into_response(),
+                    None => StatusCode::NOT_FOUND.into_response(),
+                },
+                _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+            }
+        }
+
+        let objects: Objects = Arc::new(Mutex::new(
+            objects
+                .iter()
+                .map(|(key, data)| (key.to_string(), data.as_bytes().to_vec()))
+                .collect(),
+        ));
+        let app = axum::Router::new().fallback(handle).with_state(objects);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn test_s3_storage() {
+        let endpoint = fake_s3(&[
+            (
+                "reports/2024/q1/sales.csv",
+                "region,total\nnorth,1200\nsouth,800\n",
+            ),
+            ("reports/2024/readme.txt", "Quarterly sales by region\n"),
+            ("reports/2024/.env", "REGION=north\n"),
+            ("reports/2024/q1/north.key", "north\n"),
+            ("reports/2025/secret.txt", "north,9999\n"),
+        ])
+        .await;
+
+        // s3:// allowed paths need the provider enabled
+        let config = json!({"allowed_paths": ["s3://reports/2024"]});
+        assert!(FilesystemConfig::from_value(&config).is_err());
+        let config = json!({
+            "allowed_paths": ["s3://reports/2024"],
+            "cloud_providers": ["s3"],
+            "allow_write": true,
+            "deny_patterns": ["*.key"],
+            "hidden_files": "hide",
+        });
+        let mut plugin = FilesystemPlugin::new(FilesystemConfig::from_value(&config).unwrap());
+
+        let result = plugin
+            .read_file("s3://reports/2024/readme.txt")
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().contains("not enabled"));
+
+        let s3 = S3Config {
+            endpoint: Some(endpoint),
+            ..S3Config::default()
+        };
+        let credentials = s3::Credentials {
+            access_key_id: "minio".into(),
+            secret_access_key: "minio123".into(),
+        };
+        plugin.s3 = Some(S3Storage::new(&s3, credentials).unwrap());
+
+        let result = plugin
+            .read_file("s3://reports/2024/q1/sales.csv")
+            .await
+            .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert!(result.output["content"]
+            .as_str()
+            .unwrap()
+            .contains("north,1200"));
+
+        let result = plugin
+            .write_file(
+                "s3://reports/2024/q2/sales.csv",
+                "region,total\nnorth,1500\n",
+            )
+            .await
+            .unwrap();
+        assert!(result.success, "{:?}", result.error);
+
+        let result = plugin.list_directory("s3://reports/2024").await.unwrap();
+        let mut names: Vec<&str> = result.output["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["q1", "q2", "readme.txt"]);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "search_files",
+            json!({"path": "s3://reports/2024", "pattern": "*.csv"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 2);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "grep_files",
+            json!({"path": "s3://reports/2024", "query": "NORTH", "context": 0}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["count"], 2);
+        assert_eq!(result.output["files_searched"], 3);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "file_info",
+            json!({"path": "s3://reports/2024/q1/sales.csv"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["mime_type"], "text/csv");
+        assert_eq!(result.output["size"], 34);
+
+        // Outside the allowed prefix, however it is spelled, or denied
+        for path in [
+            "s3://reports/2025/secret.txt",
+            "s3://reports/2024/../2025/secret.txt",
+            "s3://reports/2024-old/secret.txt",
+            "s3://reports/2024/q1/north.key",
+        ] {
+            let result = plugin.read_file(path).await.unwrap();
+            assert!(result.error.unwrap().contains("Access denied"), "{}", path);
+        }
+
+        // Journaled edits are local only
+        let result = plugin
+            .delete_file("s3://reports/2024/readme.txt")
+            .await
+            .unwrap();
+        assert!(!result.success);
+        let result = plugin
+            .read_file("s3://reports/2024/readme.txt")
+            .await
+            .unwrap();
+        assert!(result.success);
+
+        let result = plugin.list_directory("s3://reports/2024/q3").await.unwrap();
+        assert!(result.error.unwrap().contains("Directory not found"));
     }
 }
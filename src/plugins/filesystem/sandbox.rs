@@ -0,0 +1,862 @@
+//! Confinement of local file access to the allowed paths
+//!
+//! Each allowed directory is opened once, and every file operation is done
+//! relative to that handle (openat-style, via `cap-std`) rather than by path.
+//! The kernel then resolves the rest of the path beneath the root, so a
+//! symbolic link or `..` that leads outside it fails. Absolute symbolic links
+//! are refused even when they point inside the root.
+//!
+//! On top of that, paths can be refused by deny patterns (`**/.env`,
+//! `*.key`) and hidden files (names starting with `.`) can be hidden from
+//! listings or refused entirely. Both apply to the path as given and to the
+//! path its symbolic links lead to, so `link.txt -> .env` is refused too.
+//!
+//! Those checks run before the file is opened, on a path with its links
+//! resolved. The open then goes one component at a time from the root, and
+//! each opened handle is compared with the directory entry it was opened
+//! by: an entry swapped for a link in between (say `a.txt -> .env`) fails
+//! the comparison instead of being followed. Writes go to a new file that
+//! is renamed into place, which replaces a swapped link rather than the file
+//! it points to. On Unix handles are compared by device and inode; elsewhere
+//! by type, size and modification time.
+
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use cap_std::ambient_authority;
+use cap_std::fs::{Dir, File, Metadata, OpenOptions};
+use globset::{GlobBuilder, GlobMatcher};
+
+pub use crate::config::client::HiddenFiles;
+
+/// Sandbox errors
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("Access denied: path '{0}' is not in allowed paths")]
+    NotAllowed(PathBuf),
+
+    #[error("Access denied: '{0}' matches deny pattern '{1}'")]
+    Denied(PathBuf, String),
+
+    #[error("Access denied: '{0}' is a hidden file")]
+    Hidden(PathBuf),
+
+    #[error("Access denied: '{0}' leads outside the allowed paths")]
+    Escape(PathBuf),
+
+    #[error("Access denied: '{0}' changed while it was being opened")]
+    Changed(PathBuf),
+
+    #[error("Invalid deny pattern '{0}': {1}")]
+    Pattern(String, globset::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// An allowed directory, opened
+struct Root {
+    /// As configured, lexically normalized
+    path: PathBuf,
+    /// With symbolic links resolved, e.g. `/private/tmp` for `/tmp`
+    canonical: PathBuf,
+    dir: Dir,
+}
+
+/// A path inside a root
+struct Resolved<'a> {
+    root: &'a Root,
+    /// Relative to the root; `.` for the root itself
+    relative: PathBuf,
+    /// As given, for errors
+    path: PathBuf,
+}
+
+/// The directory holding a resolved path, opened, and the path's last name
+/// (`None` for the root itself)
+struct Parent {
+    dir: Dir,
+    name: Option<OsString>,
+}
+
+/// Symbolic links followed in one path before giving up, as in Linux
+const MAX_LINKS: usize = 40;
+
+/// Local file access confined to the allowed directories
+pub struct Sandbox {
+    roots: Vec<Root>,
+    deny: Vec<DenyPattern>,
+    hidden: HiddenFiles,
+}
+
+/// A compiled deny pattern
+pub struct DenyPattern {
+    pattern: String,
+    matcher: GlobMatcher,
+}
+
+impl Sandbox {
+    /// Open the allowed directories; ones that don't exist are skipped
+    pub fn new(
+        allowed_paths: &[PathBuf],
+        deny_patterns: &[String],
+        hidden: HiddenFiles,
+    ) -> Result<Self, SandboxError> {
+        let mut roots = Vec::new();
+        for path in allowed_paths {
+            let dir = match Dir::open_ambient_dir(path, ambient_authority()) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    tracing::warn!("Skipping allowed path {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            roots.push(Root {
+                path: normalize(path),
+                canonical: path.canonicalize()?,
+                dir,
+            });
+        }
+        // The most specific root wins when roots are nested
+        roots.sort_by_key(|root| std::cmp::Reverse(root.path.components().count()));
+
+        Ok(Self {
+            roots,
+            deny: deny_patterns
+                .iter()
+                .map(|pattern| DenyPattern::new(pattern))
+                .collect::<Result<_, _>>()?,
+            hidden,
+        })
+    }
+
+    /// A sandbox that allows nothing
+    pub fn empty() -> Self {
+        Self {
+            roots: Vec::new(),
+            deny: Vec::new(),
+            hidden: HiddenFiles::Allow,
+        }
+    }
+
+    /// Whether `path` may be accessed; the file doesn't need to exist
+    pub fn check(&self, path: &Path) -> Result<(), SandboxError> {
+        self.resolve(path).map(|_| ())
+    }
+
+    /// Find the root of `path` and the path below it, with symbolic links
+    /// followed
+    fn resolve(&self, path: &Path) -> Result<Resolved<'_>, SandboxError> {
+        self.resolve_links(path, true)
+    }
+
+    /// Like [`Sandbox::resolve`], but a link in the last component is left
+    /// alone, for operations on the link itself (removing, renaming)
+    fn resolve_entry(&self, path: &Path) -> Result<Resolved<'_>, SandboxError> {
+        self.resolve_links(path, false)
+    }
+
+    fn resolve_links(&self, path: &Path, follow_last: bool) -> Result<Resolved<'_>, SandboxError> {
+        if !path.is_absolute() {
+            return Err(SandboxError::NotAllowed(path.to_path_buf()));
+        }
+        let normalized = normalize(path);
+
+        let (root, relative) = self
+            .roots
+            .iter()
+            .find_map(|root| {
+                let relative = normalized
+                    .strip_prefix(&root.path)
+                    .or_else(|_| normalized.strip_prefix(&root.canonical))
+                    .ok()?;
+                Some((root, relative.to_path_buf()))
+            })
+            .ok_or_else(|| SandboxError::NotAllowed(path.to_path_buf()))?;
+
+        if let Some(error) = self.refusal(&relative, path) {
+            return Err(error);
+        }
+        let relative = follow_links(&root.dir, &relative, follow_last, path)?;
+        if let Some(error) = self.refusal(&relative, path) {
+            return Err(error);
+        }
+
+        let relative = if relative.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            relative
+        };
+        Ok(Resolved {
+            root,
+            relative,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Why `relative` is refused by the deny patterns or hidden-file policy
+    fn refusal(&self, relative: &Path, path: &Path) -> Option<SandboxError> {
+        let mut prefix = PathBuf::new();
+        for component in relative.components() {
+            prefix.push(component);
+            let name = component.as_os_str().to_string_lossy();
+
+            if self.hidden == HiddenFiles::Deny && name.starts_with('.') {
+                return Some(SandboxError::Hidden(path.to_path_buf()));
+            }
+
+            let prefix_text = prefix.to_string_lossy().replace('\\', "/");
+            if let Some(deny) = self
+                .deny
+                .iter()
+                .find(|deny| deny.matches(&name, &prefix_text))
+            {
+                return Some(SandboxError::Denied(
+                    path.to_path_buf(),
+                    deny.pattern.clone(),
+                ));
+            }
+        }
+        None
+    }
+
+    /// Whether an object key (e.g. in an S3 bucket) is refused by the deny
+    /// patterns or hidden-file policy; keys are matched like paths below a
+    /// root, and `uri` is what errors report
+    pub fn check_key(&self, key: &str, uri: &str) -> Result<(), SandboxError> {
+        match self.refusal(Path::new(key), Path::new(uri)) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether an object key found by a listing should be shown
+    pub fn key_visible(&self, key: &str) -> bool {
+        self.visible(Path::new(key))
+    }
+
+    /// Whether an entry found by listing `relative` should be shown
+    fn visible(&self, relative: &Path) -> bool {
+        let hidden = relative
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        (self.hidden == HiddenFiles::Allow || !hidden) && self.refusal(relative, relative).is_none()
+    }
+
+    /// Run a sandbox operation on the blocking pool
+    pub async fn run<T, F>(self: &Arc<Self>, f: F) -> Result<T, SandboxError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sandbox) -> Result<T, SandboxError> + Send + 'static,
+    {
+        let sandbox = Arc::clone(self);
+        tokio::task::spawn_blocking(move || f(&sandbox))
+            .await
+            .map_err(|e| SandboxError::Io(std::io::Error::other(e)))?
+    }
+
+    /// The whole content of a file
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, SandboxError> {
+        let mut content = Vec::new();
+        self.resolve(path)?.open_file()?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// The content of a file, or `None` if it is over `max_size` bytes
+    ///
+    /// The size is what reading finds, not what the metadata said earlier,
+    /// so a file that grows meanwhile can't get past the limit.
+    pub fn read_limited(&self, path: &Path, max_size: u64) -> Result<Option<Vec<u8>>, SandboxError> {
+        let mut content = Vec::new();
+        self.resolve(path)?
+            .open_file()?
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut content)?;
+        Ok((content.len() as u64 <= max_size).then_some(content))
+    }
+
+    /// Create or replace a file, creating its parent directories
+    pub fn write(&self, path: &Path, content: &[u8]) -> Result<(), SandboxError> {
+        let resolved = self.resolve(path)?;
+        if let Some(parent) = resolved.relative.parent() {
+            if !parent.as_os_str().is_empty() {
+                resolved.root.dir.create_dir_all(parent)?;
+            }
+        }
+        resolved.replace_file(|file| std::io::Write::write_all(file, content))
+    }
+
+    /// Metadata of a file or directory, if it exists
+    pub fn metadata(&self, path: &Path) -> Result<Option<Metadata>, SandboxError> {
+        let resolved = self.resolve(path)?;
+        let metadata = match resolved.parent() {
+            Ok(Parent {
+                dir,
+                name: Some(name),
+            }) => dir.symlink_metadata(name),
+            Ok(Parent { dir, name: None }) => dir.dir_metadata(),
+            Err(SandboxError::Io(e)) => Err(e),
+            Err(e) => return Err(e),
+        };
+        match metadata {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The visible entries directly inside a directory
+    pub fn read_dir(&self, path: &Path) -> Result<Vec<(PathBuf, Metadata)>, SandboxError> {
+        let resolved = self.resolve(path)?;
+        let mut entries = Vec::new();
+        for entry in resolved.open_dir()?.entries()? {
+            let entry = entry?;
+            let relative = resolved.relative.join(entry.file_name());
+            if self.visible(&relative) {
+                entries.push((path.join(entry.file_name()), entry.metadata()?));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Visible entries below a directory, depth first in name order, at most
+    /// `max_depth` levels down and at most `limit` of them
+    pub fn walk(
+        &self,
+        path: &Path,
+        max_depth: usize,
+        limit: usize,
+    ) -> Result<Vec<(PathBuf, Metadata)>, SandboxError> {
+        let resolved = self.resolve(path)?;
+        let dir = resolved.open_dir()?;
+
+        let mut entries = Vec::new();
+        self.walk_dir(
+            &dir,
+            path,
+            &resolved.relative,
+            max_depth,
+            limit,
+            &mut entries,
+        );
+        Ok(entries)
+    }
+
+    fn walk_dir(
+        &self,
+        dir: &Dir,
+        path: &Path,
+        relative: &Path,
+        depth: usize,
+        limit: usize,
+        entries: &mut Vec<(PathBuf, Metadata)>,
+    ) {
+        if depth == 0 {
+            return;
+        }
+        // Entries that can't be read (e.g. permission denied) are skipped
+        let Ok(read_dir) = dir.entries() else {
+            return;
+        };
+        let mut children: Vec<_> = read_dir.filter_map(Result::ok).collect();
+        children.sort_by_key(|entry| entry.file_name());
+
+        for child in children {
+            if entries.len() == limit {
+                return;
+            }
+            let name = child.file_name();
+            let relative = relative.join(&name);
+            // Symbolic links could lead outside the allowed paths
+            let Ok(file_type) = child.file_type() else {
+                continue;
+            };
+            if file_type.is_symlink() || !self.visible(&relative) {
+                continue;
+            }
+            let Ok(metadata) = child.metadata() else {
+                continue;
+            };
+
+            let is_dir = metadata.is_dir();
+            entries.push((path.join(&name), metadata));
+            if is_dir {
+                if let Ok(child) = child.open_dir() {
+                    self.walk_dir(
+                        &child,
+                        &path.join(&name),
+                        &relative,
+                        depth - 1,
+                        limit,
+                        entries,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Create a directory whose parent exists
+    pub fn create_dir(&self, path: &Path) -> Result<(), SandboxError> {
+        let resolved = self.resolve(path)?;
+        let (dir, name) = resolved.parent()?.named(&resolved)?;
+        dir.create_dir(&name).map_err(SandboxError::from)
+    }
+
+    /// Delete a file
+    pub fn remove_file(&self, path: &Path) -> Result<(), SandboxError> {
+        let resolved = self.resolve_entry(path)?;
+        let (dir, name) = resolved.parent()?.named(&resolved)?;
+        dir.remove_file(&name).map_err(SandboxError::from)
+    }
+
+    /// Delete an empty directory
+    pub fn remove_dir(&self, path: &Path) -> Result<(), SandboxError> {
+        let resolved = self.resolve_entry(path)?;
+        let (dir, name) = resolved.parent()?.named(&resolved)?;
+        dir.remove_dir(&name).map_err(SandboxError::from)
+    }
+
+    /// Copy a file, possibly into another root
+    pub fn copy(&self, from: &Path, to: &Path) -> Result<(), SandboxError> {
+        let (source, destination) = (self.resolve(from)?, self.resolve(to)?);
+        let mut file = source.open_file()?;
+        destination.replace_file(|copy| std::io::copy(&mut file, copy).map(|_| ()))
+    }
+
+    /// Move a file, copying it when the roots are on different filesystems
+    pub fn rename(&self, from: &Path, to: &Path) -> Result<(), SandboxError> {
+        let (source, destination) = (self.resolve_entry(from)?, self.resolve_entry(to)?);
+        let (source_dir, source_name) = source.parent()?.named(&source)?;
+        let (destination_dir, destination_name) = destination.parent()?.named(&destination)?;
+        let renamed = source_dir.rename(&source_name, &destination_dir, &destination_name);
+        match renamed {
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                self.copy(from, to)?;
+                self.remove_file(from)
+            }
+            renamed => renamed.map_err(SandboxError::from),
+        }
+    }
+}
+
+impl Resolved<'_> {
+    /// Open the directory holding this path, one component at a time
+    fn parent(&self) -> Result<Parent, SandboxError> {
+        let mut components: Vec<&OsStr> = self
+            .relative
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .map(Component::as_os_str)
+            .collect();
+        let name = components.pop().map(OsStr::to_os_string);
+
+        let mut dir = self.root.dir.try_clone()?;
+        for component in components {
+            let child = dir.open_dir(component)?;
+            self.verify(&dir, component, &child.dir_metadata()?)?;
+            dir = child;
+        }
+        Ok(Parent { dir, name })
+    }
+
+    /// Fail unless `name` in `dir` is still the entry `opened` came from,
+    /// and not a symbolic link
+    fn verify(&self, dir: &Dir, name: &OsStr, opened: &Metadata) -> Result<(), SandboxError> {
+        let entry = dir.symlink_metadata(name)?;
+        if entry.is_symlink() || !same_file(&entry, opened) {
+            return Err(SandboxError::Changed(self.path.clone()));
+        }
+        Ok(())
+    }
+
+    /// Open the file at this path for reading
+    fn open_file(&self) -> Result<File, SandboxError> {
+        let (dir, name) = self.parent()?.named(self)?;
+        let file = dir.open(&name)?;
+        self.verify(&dir, &name, &file.metadata()?)?;
+        Ok(file)
+    }
+
+    /// Open the directory at this path
+    fn open_dir(&self) -> Result<Dir, SandboxError> {
+        match self.parent()? {
+            Parent {
+                dir,
+                name: Some(name),
+            } => {
+                let child = dir.open_dir(&name)?;
+                self.verify(&dir, &name, &child.dir_metadata()?)?;
+                Ok(child)
+            }
+            Parent { dir, name: None } => Ok(dir),
+        }
+    }
+
+    /// Write a new file with `fill` and rename it over this path
+    ///
+    /// Creating a new file never follows a link, and the rename replaces
+    /// the directory entry itself. A replaced file's permissions are kept.
+    fn replace_file(
+        &self,
+        fill: impl FnOnce(&mut File) -> std::io::Result<()>,
+    ) -> Result<(), SandboxError> {
+        let (dir, name) = self.parent()?.named(self)?;
+        let mut temporary = OsString::from(".");
+        temporary.push(&name);
+        temporary.push(format!(".{}.tmp", uuid::Uuid::new_v4().simple()));
+        let permissions = dir
+            .symlink_metadata(&name)
+            .ok()
+            .filter(Metadata::is_file)
+            .map(|existing| existing.permissions());
+
+        let written = dir
+            .open_with(&temporary, OpenOptions::new().write(true).create_new(true))
+            .and_then(|mut file| {
+                if let Some(permissions) = permissions {
+                    file.set_permissions(permissions)?;
+                }
+                fill(&mut file)
+            })
+            .and_then(|()| dir.rename(&temporary, &dir, &name));
+        if written.is_err() {
+            dir.remove_file(&temporary).ok();
+        }
+        written.map_err(SandboxError::from)
+    }
+}
+
+impl Parent {
+    /// The directory and name, failing for the root itself
+    fn named(self, resolved: &Resolved) -> Result<(Dir, OsString), SandboxError> {
+        match self.name {
+            Some(name) => Ok((self.dir, name)),
+            None => Err(SandboxError::Io(std::io::Error::new(
+                std::io::ErrorKind::IsADirectory,
+                format!("'{}' is an allowed directory", resolved.path.display()),
+            ))),
+        }
+    }
+}
+
+/// Whether two metadata describe the same file
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use cap_std::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+/// Whether two metadata describe the same file, as far as can be told
+/// without file IDs
+#[cfg(not(unix))]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    a.file_type() == b.file_type()
+        && a.len() == b.len()
+        && a.modified().ok() == b.modified().ok()
+}
+
+impl DenyPattern {
+    /// Compile a pattern, matched case-insensitively
+    pub fn new(pattern: &str) -> Result<Self, SandboxError> {
+        let matcher = GlobBuilder::new(&pattern.replace('\\', "/"))
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| SandboxError::Pattern(pattern.to_string(), e))?
+            .compile_matcher();
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matcher,
+        })
+    }
+
+    /// Patterns with a `/` match paths below the root; others match a name at
+    /// any depth
+    fn matches(&self, name: &str, relative: &str) -> bool {
+        if self.pattern.contains('/') {
+            self.matcher.is_match(relative)
+        } else {
+            self.matcher.is_match(name)
+        }
+    }
+}
+
+/// Remove `.` and resolve `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Follow the symbolic links in `relative`, without leaving the root
+///
+/// Components that don't exist (yet) are kept as they are. A link to an
+/// absolute path, or one that climbs above the root, is an escape.
+fn follow_links(
+    dir: &Dir,
+    relative: &Path,
+    follow_last: bool,
+    path: &Path,
+) -> Result<PathBuf, SandboxError> {
+    // Components still to resolve, last first
+    let mut pending: Vec<OsString> = relative
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().to_os_string())
+        .collect();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            if !resolved.pop() {
+                return Err(SandboxError::Escape(path.to_path_buf()));
+            }
+            continue;
+        }
+        if name == "." {
+            continue;
+        }
+
+        let candidate = resolved.join(&name);
+        let is_link = dir
+            .symlink_metadata(&candidate)
+            .is_ok_and(|metadata| metadata.is_symlink());
+        if !is_link || (pending.is_empty() && !follow_last) {
+            resolved = candidate;
+            continue;
+        }
+
+        links += 1;
+        if links > MAX_LINKS {
+            return Err(SandboxError::Escape(path.to_path_buf()));
+        }
+        let target = dir.read_link_contents(&candidate)?;
+        if target.has_root() {
+            return Err(SandboxError::Escape(path.to_path_buf()));
+        }
+        pending.extend(
+            target
+                .components()
+                .rev()
+                .map(|c| c.as_os_str().to_os_string()),
+        );
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("moxie-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn test_deny_patterns_and_hidden_files() {
+        let root = temp_dir("sandbox");
+        std::fs::create_dir_all(root.join("app/.git")).unwrap();
+        std::fs::create_dir_all(root.join("certs")).unwrap();
+        std::fs::write(root.join("app/.env"), "SECRET=1").unwrap();
+        std::fs::write(root.join("app/.git/config"), "[core]").unwrap();
+        std::fs::write(root.join("app/notes.txt"), "notes").unwrap();
+        std::fs::write(root.join("certs/server.KEY"), "key").unwrap();
+        let deny = vec!["**/.env".to_string(), "*.key".to_string()];
+
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &deny, HiddenFiles::Hide).unwrap();
+        assert_eq!(sandbox.read(&root.join("app/notes.txt")).unwrap(), b"notes");
+        let notes = root.join("app/notes.txt");
+        assert_eq!(sandbox.read_limited(&notes, 5).unwrap().unwrap(), b"notes");
+        assert!(sandbox.read_limited(&notes, 4).unwrap().is_none());
+        assert!(matches!(
+            sandbox.read(&root.join("app/.env")),
+            Err(SandboxError::Denied(_, pattern)) if pattern == "**/.env"
+        ));
+        assert!(matches!(
+            sandbox.write(&root.join("certs/server.KEY"), b"replaced"),
+            Err(SandboxError::Denied(_, pattern)) if pattern == "*.key"
+        ));
+        // Hidden, but readable by explicit path
+        assert!(sandbox.read(&root.join("app/.git/config")).is_ok());
+        let names: Vec<PathBuf> = sandbox
+            .walk(&root, 5, 100)
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(names, ["app", "app/notes.txt", "certs"].map(PathBuf::from));
+
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &[], HiddenFiles::Deny).unwrap();
+        assert!(matches!(
+            sandbox.read(&root.join("app/.git/config")),
+            Err(SandboxError::Hidden(_))
+        ));
+        assert!(sandbox.read(&root.join("certs/server.KEY")).is_ok());
+
+        assert!(matches!(
+            sandbox.read(&root.join("app/../../etc/passwd")),
+            Err(SandboxError::NotAllowed(_))
+        ));
+        assert!(sandbox.check(Path::new("app/notes.txt")).is_err());
+        assert!(Sandbox::new(&[], &["[".to_string()], HiddenFiles::Allow).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_escape() {
+        let root = temp_dir("sandbox-root");
+        let outside = temp_dir("sandbox-outside");
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::fs::create_dir(root.join("inside")).unwrap();
+        std::fs::write(root.join("inside/data.txt"), "data").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("secret.txt")).unwrap();
+        std::os::unix::fs::symlink("inside", root.join("alias")).unwrap();
+
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &[], HiddenFiles::Allow).unwrap();
+        for path in ["out/secret.txt", "secret.txt"] {
+            assert!(
+                matches!(sandbox.read(&root.join(path)), Err(SandboxError::Escape(_))),
+                "{}",
+                path
+            );
+        }
+        assert!(sandbox.write(&root.join("out/new.txt"), b"x").is_err());
+        assert!(!outside.join("new.txt").exists());
+        // Relative links that stay inside the root are fine
+        assert_eq!(sandbox.read(&root.join("alias/data.txt")).unwrap(), b"data");
+        // and walks don't follow links at all
+        assert_eq!(sandbox.walk(&root, 5, 100).unwrap().len(), 2);
+
+        // Deny patterns and hidden files apply to where links lead
+        std::fs::write(root.join("inside/.env"), "SECRET=1").unwrap();
+        std::os::unix::fs::symlink("inside/.env", root.join("env.txt")).unwrap();
+        std::os::unix::fs::symlink(".", root.join("here")).unwrap();
+        let deny = vec!["**/.env".to_string()];
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &deny, HiddenFiles::Allow).unwrap();
+        for path in ["env.txt", "alias/.env", "here/inside/.env"] {
+            assert!(
+                matches!(
+                    sandbox.read(&root.join(path)),
+                    Err(SandboxError::Denied(..))
+                ),
+                "{}",
+                path
+            );
+        }
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &[], HiddenFiles::Deny).unwrap();
+        assert!(matches!(
+            sandbox.write(&root.join("env.txt"), b"SECRET=2"),
+            Err(SandboxError::Hidden(_))
+        ));
+        // Removing a link removes the link, not what it points to
+        sandbox.remove_file(&root.join("env.txt")).unwrap();
+        assert_eq!(
+            std::fs::read(root.join("inside/.env")).unwrap(),
+            b"SECRET=1"
+        );
+
+        std::fs::remove_dir_all(root).ok();
+        std::fs::remove_dir_all(outside).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_entries_swapped_after_the_check_are_refused() {
+        let root = temp_dir("sandbox-swap");
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::create_dir(root.join(".secret")).unwrap();
+        std::fs::write(root.join(".env"), "SECRET=1").unwrap();
+        std::fs::write(root.join(".secret/a.txt"), "secret").unwrap();
+        std::fs::write(root.join("a.txt"), "public").unwrap();
+        std::fs::write(root.join("sub/a.txt"), "public").unwrap();
+        let sandbox = Sandbox::new(std::slice::from_ref(&root), &[], HiddenFiles::Deny).unwrap();
+
+        // Checked while they were plain files, swapped before the open
+        let file = sandbox.resolve(&root.join("a.txt")).unwrap();
+        let nested = sandbox.resolve(&root.join("sub/a.txt")).unwrap();
+        std::fs::remove_file(root.join("a.txt")).unwrap();
+        std::os::unix::fs::symlink(".env", root.join("a.txt")).unwrap();
+        std::fs::rename(root.join("sub"), root.join("old")).unwrap();
+        std::os::unix::fs::symlink(".secret", root.join("sub")).unwrap();
+
+        assert!(matches!(file.open_file(), Err(SandboxError::Changed(_))));
+        assert!(matches!(nested.open_file(), Err(SandboxError::Changed(_))));
+        assert!(matches!(
+            nested.replace_file(|f| std::io::Write::write_all(f, b"x")),
+            Err(SandboxError::Changed(_))
+        ));
+        // A write replaces the link, not the file it points to
+        file.replace_file(|f| std::io::Write::write_all(f, b"replaced")).unwrap();
+        assert!(!std::fs::symlink_metadata(root.join("a.txt")).unwrap().is_symlink());
+        assert_eq!(std::fs::read(root.join(".env")).unwrap(), b"SECRET=1");
+        assert_eq!(std::fs::read(root.join(".secret/a.txt")).unwrap(), b"secret");
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    /// Path segments that try to get out of the root in different ways
+    fn segment() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just("..".to_string()),
+            Just(".".to_string()),
+            Just("out".to_string()),
+            Just("inside".to_string()),
+            Just("secret.txt".to_string()),
+            Just(".env".to_string()),
+            Just("".to_string()),
+            "[a-z./\\\\]{1,8}",
+            any::<String>(),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn prop_sandbox_never_leaves_the_root(segments in prop::collection::vec(segment(), 1..8)) {
+            let root = temp_dir("sandbox-prop");
+            let outside = temp_dir("sandbox-prop-outside");
+            std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+            std::fs::create_dir(root.join("inside")).unwrap();
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+            let deny = vec!["**/.env".to_string()];
+            let sandbox = Sandbox::new(std::slice::from_ref(&root), &deny, HiddenFiles::Allow).unwrap();
+
+            let path = root.join(segments.join("/"));
+            if sandbox.check(&path).is_ok() {
+                let normalized = normalize(&path);
+                prop_assert!(normalized.starts_with(&root));
+                prop_assert!(!normalized.components().any(|c| c.as_os_str() == ".env"));
+            }
+            if let Ok(content) = sandbox.read(&path) {
+                prop_assert_ne!(content, b"secret".to_vec());
+            }
+            let _ = sandbox.write(&path, b"written");
+            let _ = sandbox.remove_file(&path);
+            prop_assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 1);
+            prop_assert_eq!(std::fs::read(outside.join("secret.txt")).unwrap(), b"secret".to_vec());
+
+            std::fs::remove_dir_all(&root).ok();
+            std::fs::remove_dir_all(&outside).ok();
+        }
+    }
+}
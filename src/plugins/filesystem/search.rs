@@ -0,0 +1,202 @@
+//! Finding files by name and content
+//!
+//! Searches filter the entries a storage backend walks. Walks are limited in
+//! depth and in entries scanned, and results are capped.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde_json::{json, Value};
+
+use super::storage::Entry;
+
+/// Entries looked at before a search gives up
+pub const MAX_FILES_SCANNED: usize = 20_000;
+
+/// Characters of a matching line returned by `grep_files`
+const MAX_LINE_CHARS: usize = 300;
+
+/// Bytes checked for NUL when deciding whether a file is binary
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Search errors
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error("Invalid pattern: {0}")]
+    Pattern(#[from] globset::Error),
+
+    #[error("Invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+}
+
+/// How far a search may go
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_results: usize,
+    pub max_file_size: u64,
+}
+
+/// A glob matched against file names, or against paths below the root when
+/// it contains a `/` (e.g. `2024/**/*.pdf`)
+pub struct Pattern {
+    matcher: GlobMatcher,
+    whole_path: bool,
+}
+
+impl Pattern {
+    pub fn new(glob: &str) -> Result<Self, SearchError> {
+        let glob = glob.replace('\\', "/");
+        let matcher = GlobBuilder::new(&glob)
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        Ok(Self {
+            whole_path: glob.contains('/'),
+            matcher,
+        })
+    }
+
+    fn matches(&self, root: &str, entry: &Entry) -> bool {
+        if self.whole_path {
+            entry.path.strip_prefix(root).is_some_and(|relative| {
+                let relative = relative.trim_start_matches(['/', '\\']).replace('\\', "/");
+                self.matcher.is_match(relative)
+            })
+        } else {
+            self.matcher.is_match(&entry.name)
+        }
+    }
+}
+
+/// What `search_files` looks for; unset filters match everything
+#[derive(Default)]
+pub struct FileQuery {
+    pub pattern: Option<Pattern>,
+    /// Case-insensitive text in the file name
+    pub name: Option<String>,
+    pub modified_after: Option<NaiveDate>,
+}
+
+/// The entries below `root` matching `query`
+pub fn search_files(root: &str, entries: &[Entry], query: &FileQuery, limits: Limits) -> Value {
+    let name = query.name.as_ref().map(|name| name.to_lowercase());
+    let after: Option<DateTime<Utc>> = query
+        .modified_after
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc());
+
+    let mut matches = Vec::new();
+    let mut truncated = entries.len() > MAX_FILES_SCANNED;
+    for entry in entries.iter().take(MAX_FILES_SCANNED) {
+        if let Some(pattern) = &query.pattern {
+            if !pattern.matches(root, entry) {
+                continue;
+            }
+        }
+        if let Some(name) = &name {
+            if !entry.name.to_lowercase().contains(name.as_str()) {
+                continue;
+            }
+        }
+        if let Some(after) = after {
+            if entry.modified.is_none_or(|modified| modified < after) {
+                continue;
+            }
+        }
+
+        if matches.len() == limits.max_results {
+            truncated = true;
+            break;
+        }
+        matches.push(json!({
+            "path": entry.path,
+            "is_dir": entry.is_dir,
+            "size": entry.size,
+            "modified": entry.modified.map(|m| m.to_rfc3339()),
+        }));
+    }
+
+    json!({
+        "count": matches.len(),
+        "truncated": truncated,
+        "matches": matches,
+    })
+}
+
+/// What `grep_files` looks for
+pub struct Grep {
+    pub regex: Regex,
+    /// Only files matching this pattern are searched
+    pub include: Option<Pattern>,
+    /// Lines shown either side of a match
+    pub context: usize,
+}
+
+impl Grep {
+    /// Whether `entry` is a file this search should read
+    pub fn wants(&self, root: &str, entry: &Entry, limits: Limits) -> bool {
+        !entry.is_dir
+            && entry.size <= limits.max_file_size
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.matches(root, entry))
+    }
+
+    /// Up to `limit` matching lines of a file, with context; `None` for
+    /// binary files
+    pub fn lines(&self, path: &str, bytes: &[u8], limit: usize) -> Option<Vec<Value>> {
+        if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+            return None;
+        }
+
+        let text = String::from_utf8_lossy(bytes);
+        let lines: Vec<&str> = text.lines().collect();
+        let matches = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| self.regex.is_match(line))
+            .take(limit)
+            .map(|(index, line)| {
+                let before = &lines[index.saturating_sub(self.context)..index];
+                let after = &lines[index + 1..(index + 1 + self.context).min(lines.len())];
+                json!({
+                    "path": path,
+                    "line": index + 1,
+                    "text": clip(line),
+                    "before": before.iter().map(|l| clip(l)).collect::<Vec<_>>(),
+                    "after": after.iter().map(|l| clip(l)).collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+        Some(matches)
+    }
+}
+
+/// Size, times and MIME type of a file or directory
+pub fn file_info(entry: &Entry) -> Value {
+    let mime_type = if entry.is_dir {
+        "inode/directory".to_string()
+    } else {
+        mime_guess::from_path(&entry.name)
+            .first_or_octet_stream()
+            .to_string()
+    };
+
+    json!({
+        "path": entry.path,
+        "name": entry.name,
+        "is_file": !entry.is_dir,
+        "is_dir": entry.is_dir,
+        "size": entry.size,
+        "modified": entry.modified.map(|m| m.to_rfc3339()),
+        "created": entry.created.map(|c| c.to_rfc3339()),
+        "readonly": entry.readonly,
+        "mime_type": mime_type,
+    })
+}
+
+fn clip(line: &str) -> String {
+    line.chars().take(MAX_LINE_CHARS).collect()
+}
@@ -0,0 +1,283 @@
+//! Where the filesystem tools read and write
+//!
+//! Paths are either local (`C:\Data\report.csv`) or S3 URIs
+//! (`s3://bucket/prefix/report.csv`). Both kinds are handled by a
+//! [`StorageBackend`], and both are confined by `allowed_paths`: an S3 entry
+//! like `s3://reports/2024` allows every key below that prefix, just as a
+//! local entry allows every file below a directory. Local files are reached
+//! through the [`Sandbox`].
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::sandbox::{Sandbox, SandboxError};
+
+/// URI scheme of S3-compatible storage
+pub const S3_SCHEME: &str = "s3://";
+
+/// Storage errors
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid S3 URI '{0}': expected s3://bucket/key")]
+    InvalidUri(String),
+
+    #[error("{0} is not set")]
+    MissingCredentials(String),
+
+    #[error("File too large (max: {0} bytes)")]
+    TooLarge(u64),
+
+    #[error(transparent)]
+    Sandbox(#[from] SandboxError),
+
+    #[error("S3 request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("S3 returned {status}: {message}")]
+    S3 { status: u16, message: String },
+
+    #[error("Invalid S3 response: {0}")]
+    Xml(#[from] quick_xml::DeError),
+}
+
+/// A file or directory in a backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Full path or URI, usable with the other tools
+    pub path: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub created: Option<DateTime<Utc>>,
+    pub readonly: bool,
+}
+
+/// Files the tools can reach, local or remote
+///
+/// Paths are passed as the user wrote them; callers check them against the
+/// allow-list first.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// The whole content of a file, failing with
+    /// [`StorageError::TooLarge`] past `max_size` bytes
+    async fn read(&self, path: &str, max_size: u64) -> Result<Vec<u8>, StorageError>;
+
+    /// Create or replace a file
+    async fn write(&self, path: &str, content: &[u8]) -> Result<(), StorageError>;
+
+    /// A file or directory, if it exists
+    async fn stat(&self, path: &str) -> Result<Option<Entry>, StorageError>;
+
+    /// The entries directly inside a directory
+    async fn list(&self, path: &str) -> Result<Vec<Entry>, StorageError>;
+
+    /// Entries below a directory, at most `max_depth` levels down and at most
+    /// `limit` of them (the walk stops there)
+    async fn walk(
+        &self,
+        path: &str,
+        max_depth: usize,
+        limit: usize,
+    ) -> Result<Vec<Entry>, StorageError>;
+}
+
+/// Whether `path` is an S3 URI
+pub fn is_s3_uri(path: &str) -> bool {
+    path.get(..S3_SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(S3_SCHEME))
+}
+
+/// An S3 location: a bucket and a normalized key (no leading or trailing `/`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S3Location {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl S3Location {
+    /// Parse `s3://bucket/key`; `.` segments are dropped and `..` is rejected
+    pub fn parse(uri: &str) -> Result<Self, StorageError> {
+        let invalid = || StorageError::InvalidUri(uri.to_string());
+        if !is_s3_uri(uri) {
+            return Err(invalid());
+        }
+
+        let rest = &uri[S3_SCHEME.len()..];
+        let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut segments = Vec::new();
+        for segment in key.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return Err(invalid()),
+                segment => segments.push(segment),
+            }
+        }
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: segments.join("/"),
+        })
+    }
+
+    /// Whether this location is `prefix` or below it
+    pub fn is_within(&self, prefix: &S3Location) -> bool {
+        self.bucket == prefix.bucket
+            && (prefix.key.is_empty()
+                || self.key == prefix.key
+                || self
+                    .key
+                    .strip_prefix(&prefix.key)
+                    .is_some_and(|rest| rest.starts_with('/')))
+    }
+
+    /// The URI of a key in the same bucket
+    pub fn uri(&self, key: &str) -> String {
+        format!("{}{}/{}", S3_SCHEME, self.bucket, key)
+    }
+}
+
+/// Check an S3 URI against the `s3://` entries of the allow-list
+pub fn is_s3_allowed(allowed_paths: &[PathBuf], path: &str) -> bool {
+    let Ok(location) = S3Location::parse(path) else {
+        return false;
+    };
+    allowed_paths
+        .iter()
+        .filter_map(|allowed| S3Location::parse(&allowed.to_string_lossy()).ok())
+        .any(|prefix| location.is_within(&prefix))
+}
+
+/// The local disk, within the sandbox
+pub struct LocalStorage {
+    sandbox: Arc<Sandbox>,
+}
+
+impl LocalStorage {
+    pub fn new(sandbox: Arc<Sandbox>) -> Self {
+        Self { sandbox }
+    }
+
+    /// Run a sandbox operation on `path`
+    async fn run<T, F>(&self, path: &str, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sandbox, &Path) -> Result<T, SandboxError> + Send + 'static,
+    {
+        let owned = PathBuf::from(path);
+        match self.sandbox.run(move |sandbox| f(sandbox, &owned)).await {
+            Err(SandboxError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StorageError::NotFound(path.to_string()))
+            }
+            result => Ok(result?),
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    async fn read(&self, path: &str, max_size: u64) -> Result<Vec<u8>, StorageError> {
+        self.run(path, move |sandbox, path| sandbox.read_limited(path, max_size))
+            .await?
+            .ok_or(StorageError::TooLarge(max_size))
+    }
+
+    async fn write(&self, path: &str, content: &[u8]) -> Result<(), StorageError> {
+        let content = content.to_vec();
+        self.run(path, move |sandbox, path| sandbox.write(path, &content))
+            .await
+    }
+
+    async fn stat(&self, path: &str) -> Result<Option<Entry>, StorageError> {
+        self.run(path, |sandbox, path| {
+            Ok(sandbox
+                .metadata(path)?
+                .map(|metadata| local_entry(path, &metadata)))
+        })
+        .await
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<Entry>, StorageError> {
+        self.run(path, |sandbox, path| {
+            let entries = sandbox.read_dir(path)?;
+            Ok(entries
+                .iter()
+                .map(|(path, metadata)| local_entry(path, metadata))
+                .collect())
+        })
+        .await
+    }
+
+    async fn walk(
+        &self,
+        path: &str,
+        max_depth: usize,
+        limit: usize,
+    ) -> Result<Vec<Entry>, StorageError> {
+        self.run(path, move |sandbox, path| {
+            let entries = sandbox.walk(path, max_depth, limit)?;
+            Ok(entries
+                .iter()
+                .map(|(path, metadata)| local_entry(path, metadata))
+                .collect())
+        })
+        .await
+    }
+}
+
+fn local_entry(path: &Path, metadata: &cap_std::fs::Metadata) -> Entry {
+    let time = |time: std::io::Result<cap_std::time::SystemTime>| {
+        time.ok().map(|time| DateTime::from(time.into_std()))
+    };
+    Entry {
+        path: path.to_string_lossy().into_owned(),
+        name: path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        is_dir: metadata.is_dir(),
+        size: metadata.len(),
+        modified: time(metadata.modified()),
+        created: time(metadata.created()),
+        readonly: metadata.permissions().readonly(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_allow_list() {
+        let location = S3Location::parse("s3://reports/2024/./q1//sales.csv").unwrap();
+        assert_eq!(location.bucket, "reports");
+        assert_eq!(location.key, "2024/q1/sales.csv");
+        assert!(S3Location::parse("s3://reports/2024/../hr/salaries.csv").is_err());
+        assert!(S3Location::parse("s3:///key").is_err());
+
+        let allowed = vec![PathBuf::from("s3://reports/2024"), std::env::temp_dir()];
+        assert!(is_s3_allowed(&allowed, "s3://reports/2024/q1/sales.csv"));
+        assert!(is_s3_allowed(&allowed, "S3://reports/2024"));
+        assert!(!is_s3_allowed(&allowed, "s3://reports/2024-old/sales.csv"));
+        assert!(!is_s3_allowed(&allowed, "s3://reports/2025/sales.csv"));
+        assert!(!is_s3_allowed(&allowed, "s3://other/2024/sales.csv"));
+        assert!(!is_s3_allowed(
+            &allowed,
+            "s3://reports/2024/../hr/salaries.csv"
+        ));
+        assert!(!is_s3_allowed(
+            &allowed,
+            &std::env::temp_dir().to_string_lossy()
+        ));
+    }
+}
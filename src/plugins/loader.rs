@@ -5,13 +5,17 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
-use super::manifest::{PluginManifest, Version};
+use super::manifest::PluginManifest;
 use super::traits::{Plugin, PluginContext, PluginState};
 use super::{PluginError, ToolDefinition, ToolResult};
 use serde_json::Value;
 
+use crate::metrics;
+
 /// Information about a loaded plugin
 pub struct LoadedPlugin {
     /// The plugin instance
@@ -71,7 +75,7 @@ impl PluginLoader {
         let id = manifest.id.clone();
 
         // Validate manifest
-        manifest.validate().map_err(|e| PluginError::InvalidParameters(e))?;
+        manifest.validate().map_err(PluginError::InvalidParameters)?;
 
         // Check for duplicates
         if self.plugins.contains_key(&id) {
@@ -273,6 +277,16 @@ impl PluginLoader {
         self.plugins.get(id).map(|p| p.state)
     }
 
+    /// The state of every registered plugin, in load order
+    pub fn states(&self) -> Vec<(String, PluginState)> {
+        let mut plugins: Vec<_> = self.plugins.iter().collect();
+        plugins.sort_by_key(|(_, p)| p.load_order);
+        plugins
+            .into_iter()
+            .map(|(id, p)| (id.clone(), p.state))
+            .collect()
+    }
+
     /// List all registered plugins (returns owned manifests)
     pub fn list(&self) -> Vec<PluginManifest> {
         self.plugins
@@ -299,6 +313,26 @@ impl PluginLoader {
             .collect()
     }
 
+    /// Get the tools from active plugins matching any of `plugins` (IDs or config keys)
+    pub fn tools_for(&self, plugins: &[String]) -> Vec<ToolDefinition> {
+        self.plugins
+            .values()
+            .filter(|p| p.state == PluginState::Active)
+            .filter(|p| {
+                let manifest = p.plugin.manifest();
+                plugins.iter().any(|name| manifest.matches(name))
+            })
+            .flat_map(|p| p.plugin.tools())
+            .collect()
+    }
+
+    /// Check whether a tool is provided by one of `plugins` (IDs or config keys)
+    pub fn tool_in(&self, tool: &str, plugins: &[String]) -> bool {
+        self.find_plugin_for_tool(tool)
+            .and_then(|id| self.get(id))
+            .is_some_and(|p| plugins.iter().any(|name| p.manifest().matches(name)))
+    }
+
     /// Find which plugin provides a tool
     pub fn find_plugin_for_tool(&self, tool: &str) -> Option<&str> {
         self.plugins
@@ -308,8 +342,36 @@ impl PluginLoader {
             .map(|(id, _)| id.as_str())
     }
 
+    /// Whether a tool or its plugin asks for user confirmation
+    pub fn requires_confirmation(&self, tool: &str) -> bool {
+        self.plugins
+            .values()
+            .filter(|p| p.plugin.has_tool(tool))
+            .any(|p| {
+                p.plugin.manifest().requires_confirmation
+                    || p.plugin
+                        .tools()
+                        .iter()
+                        .any(|t| t.name == tool && t.requires_confirmation)
+            })
+    }
+
     /// Execute a tool
     pub async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        self.execute_cancellable(tool, params, &CancellationToken::new())
+            .await
+    }
+
+    /// Execute a tool, stopping it when `cancel` fires
+    ///
+    /// The plugin's future is dropped on cancellation, and `after_execute`
+    /// is not called.
+    pub async fn execute_cancellable(
+        &self,
+        tool: &str,
+        params: Value,
+        cancel: &CancellationToken,
+    ) -> Result<ToolResult, PluginError> {
         let (id, loaded) = self
             .plugins
             .iter()
@@ -331,8 +393,25 @@ impl PluginLoader {
         // Call before_execute hook
         loaded.plugin.before_execute(tool, &params).await?;
 
-        // Execute the tool
-        let result = loaded.plugin.execute(tool, params).await?;
+        // Execute the tool, timed for metrics and the result metadata
+        let started = Instant::now();
+        let result = tokio::select! {
+            result = loaded.plugin.execute(tool, params) => result,
+            _ = cancel.cancelled() => Err(PluginError::Cancelled(tool.to_string())),
+        };
+        let elapsed = started.elapsed();
+
+        let outcome = match &result {
+            Ok(result) if result.success => "success",
+            Ok(_) => "failure",
+            Err(PluginError::Cancelled(_)) => "cancelled",
+            Err(_) => "error",
+        };
+        metrics::global().observe_tool(id, tool, outcome, elapsed);
+
+        let result = result?
+            .with_duration(elapsed.as_millis() as u64)
+            .with_plugin(id.as_str());
 
         // Call after_execute hook
         loaded.plugin.after_execute(tool, &result).await?;
@@ -372,12 +451,24 @@ mod tests {
 
     struct TestPlugin {
         name: String,
+        shutdowns: Option<Arc<std::sync::Mutex<Vec<String>>>>,
+        fail_shutdown: bool,
     }
 
     impl TestPlugin {
         fn new(name: &str) -> Self {
             Self {
                 name: name.to_string(),
+                shutdowns: None,
+                fail_shutdown: false,
+            }
+        }
+
+        /// Record the plugin's name in `shutdowns` when it shuts down
+        fn recording(name: &str, shutdowns: &Arc<std::sync::Mutex<Vec<String>>>) -> Self {
+            Self {
+                shutdowns: Some(shutdowns.clone()),
+                ..Self::new(name)
             }
         }
     }
@@ -404,6 +495,16 @@ mod tests {
             Ok(ToolResult::success(format!("Executed {} on {}", tool, self.name)))
         }
 
+        async fn on_shutdown(&mut self) -> Result<(), PluginError> {
+            if let Some(shutdowns) = &self.shutdowns {
+                shutdowns.lock().unwrap().push(self.name.clone());
+            }
+            if self.fail_shutdown {
+                return Err(PluginError::ExecutionFailed("shutdown failed".into()));
+            }
+            Ok(())
+        }
+
         fn as_any(&self) -> &dyn std::any::Any {
             self
         }
@@ -445,6 +546,47 @@ mod tests {
         assert!(result.success);
     }
 
+    #[tokio::test]
+    async fn test_shutdown_in_reverse_load_order() {
+        let shutdowns = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut loader = PluginLoader::new();
+        for name in ["first", "second", "third"] {
+            loader.register(TestPlugin::recording(name, &shutdowns)).unwrap();
+        }
+        loader.init_all().await.unwrap();
+
+        loader.shutdown_all().await.unwrap();
+        assert_eq!(*shutdowns.lock().unwrap(), ["third", "second", "first"]);
+        assert!(loader.all_tools().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_continues_past_failures() {
+        let shutdowns = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut loader = PluginLoader::new();
+        loader.register(TestPlugin::recording("first", &shutdowns)).unwrap();
+        loader
+            .register(TestPlugin {
+                fail_shutdown: true,
+                ..TestPlugin::recording("broken", &shutdowns)
+            })
+            .unwrap();
+        loader.init_all().await.unwrap();
+
+        // A plugin failing to shut down doesn't keep the others running
+        loader.shutdown_all().await.unwrap();
+        assert_eq!(*shutdowns.lock().unwrap(), ["broken", "first"]);
+        assert_eq!(loader.get_state("test.broken"), Some(PluginState::Registered));
+
+        // Plugins that aren't running are left alone
+        loader.shutdown_all().await.unwrap();
+        assert_eq!(shutdowns.lock().unwrap().len(), 2);
+        assert!(matches!(
+            loader.shutdown_plugin("test.missing").await,
+            Err(PluginError::PluginNotFound(_))
+        ));
+    }
+
     #[test]
     fn test_all_tools() {
         let mut loader = PluginLoader::new();
@@ -51,7 +51,7 @@ impl Default for Version {
 }
 
 /// Plugin category for organization
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "snake_case")]
 pub enum PluginCategory {
     /// File system and storage
@@ -71,15 +71,10 @@ pub enum PluginCategory {
     /// Cloud services
     Cloud,
     /// Custom/Other
+    #[default]
     Custom,
 }
 
-impl Default for PluginCategory {
-    fn default() -> Self {
-        Self::Custom
-    }
-}
-
 /// Platform requirements for a plugin
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PlatformRequirements {
@@ -108,6 +103,24 @@ pub enum ConfigFieldType {
     PathArray,
     Secret,      // Stored securely, not logged
     Select(Vec<String>),  // Dropdown options
+    TableArray,  // Array of TOML tables (e.g. `[[plugins.api.services]]`)
+}
+
+impl ConfigFieldType {
+    /// Human-readable name of the expected value, used in validation messages
+    pub fn expected(&self) -> &'static str {
+        match self {
+            ConfigFieldType::String => "a string",
+            ConfigFieldType::Number => "a number",
+            ConfigFieldType::Boolean => "a boolean",
+            ConfigFieldType::StringArray => "an array of strings",
+            ConfigFieldType::Path => "a path string",
+            ConfigFieldType::PathArray => "an array of path strings",
+            ConfigFieldType::Secret => "a string",
+            ConfigFieldType::Select(_) => "one of the listed options",
+            ConfigFieldType::TableArray => "an array of tables",
+        }
+    }
 }
 
 /// A configuration field definition
@@ -134,10 +147,121 @@ pub struct ConfigField {
     pub default: Option<serde_json::Value>,
 
     /// Validation pattern (regex for strings)
+    ///
+    /// The pattern must match the whole value; string arrays are checked
+    /// element by element.
     #[serde(default)]
     pub validation: Option<String>,
 }
 
+impl ConfigField {
+    /// Check a single configuration value against this field's type,
+    /// select options and validation pattern
+    pub fn check(&self, value: &serde_json::Value) -> Result<(), String> {
+        use serde_json::Value;
+
+        let strings: Vec<&str> = match (&self.field_type, value) {
+            (ConfigFieldType::String | ConfigFieldType::Path | ConfigFieldType::Secret, Value::String(s)) => {
+                vec![s.as_str()]
+            }
+            (ConfigFieldType::Select(options), Value::String(s)) => {
+                if !options.iter().any(|o| o == s) {
+                    return Err(format!(
+                        "invalid value '{}', expected one of: {}",
+                        s,
+                        options.join(", ")
+                    ));
+                }
+                vec![s.as_str()]
+            }
+            (ConfigFieldType::StringArray | ConfigFieldType::PathArray, Value::Array(items)) => {
+                let mut strings = Vec::with_capacity(items.len());
+                for (i, item) in items.iter().enumerate() {
+                    match item.as_str() {
+                        Some(s) => strings.push(s),
+                        None => {
+                            return Err(format!(
+                                "item {} must be a string, found {}",
+                                i,
+                                json_type_name(item)
+                            ))
+                        }
+                    }
+                }
+                strings
+            }
+            (ConfigFieldType::TableArray, Value::Array(items)) => {
+                if let Some(i) = items.iter().position(|item| !item.is_object()) {
+                    return Err(format!(
+                        "item {} must be a table, found {}",
+                        i,
+                        json_type_name(&items[i])
+                    ));
+                }
+                vec![]
+            }
+            (ConfigFieldType::Number, Value::Number(_)) | (ConfigFieldType::Boolean, Value::Bool(_)) => {
+                vec![]
+            }
+            (field_type, other) => {
+                return Err(format!(
+                    "expected {}, found {}",
+                    field_type.expected(),
+                    json_type_name(other)
+                ))
+            }
+        };
+
+        if let Some(pattern) = &self.validation {
+            let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("invalid validation pattern '{}': {}", pattern, e))?;
+            for s in strings {
+                if !regex.is_match(s) {
+                    // Never echo secrets back in error messages
+                    let shown = match self.field_type {
+                        ConfigFieldType::Secret => "<redacted>",
+                        _ => s,
+                    };
+                    return Err(format!(
+                        "value '{}' does not match pattern '{}'",
+                        shown, pattern
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A problem found while validating a plugin configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFieldError {
+    /// Name of the offending field
+    pub field: String,
+
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigFieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// JSON type name for error messages
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "a table",
+    }
+}
+
 /// Plugin manifest - complete metadata for a plugin
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PluginManifest {
@@ -255,6 +379,70 @@ impl PluginManifest {
         self
     }
 
+    /// Key of this plugin's section in client TOML (`[plugins.<key>]`)
+    ///
+    /// This is the last segment of the ID, so `moxie.filesystem` is configured
+    /// under `[plugins.filesystem]`.
+    pub fn config_key(&self) -> &str {
+        self.id.rsplit('.').next().unwrap_or(&self.id)
+    }
+
+    /// Check whether a `plugins.enabled` entry refers to this plugin
+    ///
+    /// Both the full ID and the config key are accepted.
+    pub fn matches(&self, name: &str) -> bool {
+        self.id == name || self.config_key() == name
+    }
+
+    /// Validate a plugin configuration against the config schema
+    ///
+    /// Every field is checked and all problems are reported. On success the
+    /// configuration is returned with defaults applied for missing fields.
+    pub fn validate_config(
+        &self,
+        config: &serde_json::Value,
+    ) -> Result<serde_json::Value, Vec<ConfigFieldError>> {
+        let mut resolved = match config {
+            serde_json::Value::Object(map) => map.clone(),
+            serde_json::Value::Null => serde_json::Map::new(),
+            other => {
+                return Err(vec![ConfigFieldError {
+                    field: self.config_key().to_string(),
+                    message: format!("expected a table, found {}", json_type_name(other)),
+                }])
+            }
+        };
+        let mut errors = Vec::new();
+
+        for field in &self.config_schema {
+            match resolved.get(&field.name) {
+                Some(value) => {
+                    if let Err(message) = field.check(value) {
+                        errors.push(ConfigFieldError {
+                            field: field.name.clone(),
+                            message,
+                        });
+                    }
+                }
+                None if field.required => errors.push(ConfigFieldError {
+                    field: field.name.clone(),
+                    message: "missing required field".to_string(),
+                }),
+                None => {
+                    if let Some(default) = &field.default {
+                        resolved.insert(field.name.clone(), default.clone());
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(serde_json::Value::Object(resolved))
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Validate the manifest
     pub fn validate(&self) -> Result<(), String> {
         if self.id.is_empty() {
@@ -369,4 +557,81 @@ mod tests {
         assert_eq!(field.name, "allowed_paths");
         assert!(field.required);
     }
+
+    fn validated_manifest() -> PluginManifest {
+        PluginManifest::new("moxie.sample", "Sample", "A sample plugin")
+            .with_config_field(
+                ConfigFieldBuilder::new("endpoint", ConfigFieldType::String)
+                    .required()
+                    .validation("https?://.+")
+                    .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("mode", ConfigFieldType::Select(vec![
+                    "fast".into(),
+                    "safe".into(),
+                ]))
+                .default_value(serde_json::json!("safe"))
+                .build(),
+            )
+            .with_config_field(
+                ConfigFieldBuilder::new("tags", ConfigFieldType::StringArray).build(),
+            )
+    }
+
+    #[test]
+    fn test_config_key() {
+        let manifest = validated_manifest();
+        assert_eq!(manifest.config_key(), "sample");
+        assert!(manifest.matches("sample"));
+        assert!(manifest.matches("moxie.sample"));
+        assert!(!manifest.matches("moxie"));
+    }
+
+    #[test]
+    fn test_validate_config_applies_defaults() {
+        let manifest = validated_manifest();
+        let config = manifest
+            .validate_config(&serde_json::json!({ "endpoint": "https://example.com" }))
+            .unwrap();
+
+        assert_eq!(config["mode"], "safe");
+        assert!(config.get("tags").is_none());
+    }
+
+    #[test]
+    fn test_validate_config_reports_all_errors() {
+        let manifest = validated_manifest();
+        let errors = manifest
+            .validate_config(&serde_json::json!({
+                "endpoint": "ftp://example.com",
+                "mode": "reckless",
+                "tags": ["ok", 3]
+            }))
+            .unwrap_err();
+
+        let fields: Vec<_> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["endpoint", "mode", "tags"]);
+        assert!(errors[0].message.contains("does not match pattern"));
+        assert!(errors[1].message.contains("fast, safe"));
+    }
+
+    #[test]
+    fn test_validate_config_missing_required() {
+        let manifest = validated_manifest();
+        let errors = manifest.validate_config(&serde_json::Value::Null).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "endpoint: missing required field");
+    }
+
+    #[test]
+    fn test_secret_not_echoed() {
+        let field = ConfigFieldBuilder::new("token", ConfigFieldType::Secret)
+            .validation("[a-f0-9]{8}")
+            .build();
+
+        let message = field.check(&serde_json::json!("hunter2")).unwrap_err();
+        assert!(!message.contains("hunter2"));
+    }
 }
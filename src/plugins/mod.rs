@@ -56,24 +56,33 @@
 //! # Built-in Plugins
 //!
 //! - `filesystem` - Read, write, and list files
+//! - `api` - Call REST APIs described in configuration
+//! - `database` - Inspect and query SQLite and Postgres databases
+//! - `office` - Read and create Excel workbooks
+//! - `email` - Search, read, draft and send email over IMAP and SMTP
 
 pub mod api;
+pub mod database;
+pub mod email;
 pub mod filesystem;
 pub mod loader;
 pub mod manifest;
+pub mod office;
 pub mod traits;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use thiserror::Error;
 
 // Re-exports for convenience
 pub use loader::{PluginLoader, SharedPluginLoader, shared_loader};
 pub use manifest::{
-    ConfigField, ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest, Version,
+    ConfigField, ConfigFieldBuilder, ConfigFieldError, ConfigFieldType, PluginCategory,
+    PluginManifest, Version,
 };
 pub use traits::{Plugin, PluginContext, PluginState, PluginExt};
 
@@ -88,6 +97,19 @@ pub mod prelude {
     pub use serde_json::{json, Value};
 }
 
+/// Manifests of the plugins that ship with Moxie
+///
+/// Used to validate client configurations without starting the server.
+pub fn builtin_manifests() -> Vec<PluginManifest> {
+    vec![
+        filesystem::FilesystemPlugin::default_plugin().manifest(),
+        api::ApiPlugin::default_plugin().manifest(),
+        database::DatabasePlugin::default_plugin().manifest(),
+        office::OfficePlugin::default_plugin().manifest(),
+        email::EmailPlugin::default_plugin().manifest(),
+    ]
+}
+
 /// Errors that can occur during plugin operations
 #[derive(Debug, Error)]
 pub enum PluginError {
@@ -117,6 +139,9 @@ pub enum PluginError {
 
     #[error("Configuration error: {0}")]
     ConfigError(String),
+
+    #[error("Tool execution cancelled: {0}")]
+    Cancelled(String),
 }
 
 /// Definition of a tool that an AI can call
@@ -191,6 +216,20 @@ pub struct ToolResult {
     /// Execution metadata
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<ToolResultMetadata>,
+
+    /// Files the tool produced for the user to download
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+/// A file written by a tool, offered to the user as a download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// Where the file was written
+    pub path: PathBuf,
+
+    /// MIME type of the file
+    pub mime_type: String,
 }
 
 /// Metadata about tool execution
@@ -213,6 +252,7 @@ impl ToolResult {
             output: output.into(),
             error: None,
             metadata: None,
+            artifacts: Vec::new(),
         }
     }
 
@@ -223,6 +263,7 @@ impl ToolResult {
             output: Value::Null,
             error: Some(error.into()),
             metadata: None,
+            artifacts: Vec::new(),
         }
     }
 
@@ -241,6 +282,25 @@ impl ToolResult {
         metadata.duration_ms = Some(duration_ms);
         self
     }
+
+    /// Add the ID of the plugin that executed the tool
+    pub fn with_plugin(mut self, plugin_id: impl Into<String>) -> Self {
+        let metadata = self.metadata.get_or_insert(ToolResultMetadata {
+            duration_ms: None,
+            plugin_id: None,
+        });
+        metadata.plugin_id = Some(plugin_id.into());
+        self
+    }
+
+    /// Offer a file the tool wrote as a download
+    pub fn with_artifact(mut self, path: impl Into<PathBuf>, mime_type: impl Into<String>) -> Self {
+        self.artifacts.push(Artifact {
+            path: path.into(),
+            mime_type: mime_type.into(),
+        });
+        self
+    }
 }
 
 // ============================================================================
@@ -0,0 +1,485 @@
+//! Excel workbooks
+//!
+//! Reading goes through calamine (xlsx, xlsm, xls, xlsb and ods) and writing
+//! through rust_xlsxwriter, so neither needs Office. Workbooks are parsed
+//! from and written to memory; the plugin reads and writes the files.
+//! Everything here is blocking and runs on the blocking thread pool.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use calamine::{open_workbook_auto_from_rs, Data, Range, Reader};
+use rust_xlsxwriter::{Table, TableColumn, TableStyle, Workbook, XlsxError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Errors reading or writing workbooks
+#[derive(Debug, thiserror::Error)]
+pub enum ExcelError {
+    #[error("Could not open workbook: {0}")]
+    Open(#[from] calamine::Error),
+
+    #[error("Sheet not found: {0}")]
+    SheetNotFound(String),
+
+    #[error("Invalid cell range '{0}' (expected e.g. A1:D20)")]
+    InvalidRange(String),
+
+    #[error("Column not found: {0}")]
+    ColumnNotFound(String),
+
+    #[error("Invalid workbook: {0}")]
+    InvalidInput(String),
+
+    #[error("Could not write workbook: {0}")]
+    Write(#[from] XlsxError),
+}
+
+/// A sheet to write, as produced by the model
+#[derive(Debug, Clone, Deserialize)]
+pub struct SheetSpec {
+    pub name: String,
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// Names and used ranges of all sheets
+pub fn list_sheets(content: &[u8]) -> Result<Value, ExcelError> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(content))?;
+    let mut sheets = Vec::new();
+
+    for name in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&name)?;
+        let (rows, columns) = range.get_size();
+        sheets.push(json!({
+            "name": name,
+            "range": range_reference(&range),
+            "rows": rows,
+            "columns": columns
+        }));
+    }
+
+    Ok(json!({ "sheets": sheets }))
+}
+
+/// Read a range as a table, at most `max_rows` data rows
+///
+/// With `header`, the first row names the columns and rows are objects;
+/// otherwise rows are arrays.
+pub fn read_range(
+    content: &[u8],
+    sheet: Option<&str>,
+    reference: Option<&str>,
+    header: bool,
+    max_rows: usize,
+) -> Result<Value, ExcelError> {
+    let (sheet, range) = open_sheet(content, sheet)?;
+    let range = match reference {
+        Some(reference) => {
+            let (start, end) = parse_range(reference)
+                .ok_or_else(|| ExcelError::InvalidRange(reference.to_string()))?;
+            range.range(start, end)
+        }
+        None => range,
+    };
+
+    let mut rows = range.rows();
+    let columns = if header {
+        rows.next().map(|row| column_names(&range, row))
+    } else {
+        None
+    };
+
+    let mut table = Vec::new();
+    let mut truncated = false;
+    for row in rows {
+        if table.len() == max_rows {
+            truncated = true;
+            break;
+        }
+        let values = row.iter().map(cell_value);
+        table.push(match &columns {
+            Some(columns) => Value::Object(columns.iter().cloned().zip(values).collect()),
+            None => Value::Array(values.collect()),
+        });
+    }
+
+    Ok(json!({
+        "sheet": sheet,
+        "range": range_reference(&range),
+        "columns": columns,
+        "row_count": table.len(),
+        "truncated": truncated,
+        "rows": table
+    }))
+}
+
+/// Count, sum, average, min and max of a column, optionally per group
+///
+/// Columns are named by their header (first row of the sheet) or by letter.
+pub fn aggregate(
+    content: &[u8],
+    sheet: Option<&str>,
+    column: &str,
+    group_by: Option<&str>,
+) -> Result<Value, ExcelError> {
+    let (sheet, range) = open_sheet(content, sheet)?;
+    let mut rows = range.rows();
+    let headers = rows
+        .next()
+        .map(|row| column_names(&range, row))
+        .unwrap_or_default();
+
+    let value_index = column_index(&range, &headers, column)?;
+    let group_index = group_by
+        .map(|group| column_index(&range, &headers, group))
+        .transpose()?;
+
+    let mut groups: BTreeMap<String, Stats> = BTreeMap::new();
+    for row in rows {
+        let key = match group_index {
+            Some(i) => row.get(i).map(cell_text).unwrap_or_default(),
+            None => String::new(),
+        };
+        groups
+            .entry(key)
+            .or_default()
+            .add(row.get(value_index).unwrap_or(&Data::Empty));
+    }
+
+    let result = match group_by {
+        Some(group_by) => json!({
+            "sheet": sheet,
+            "column": column,
+            "group_by": group_by,
+            "groups": groups
+                .into_iter()
+                .map(|(key, stats)| {
+                    let mut value = stats.to_json();
+                    value["group"] = Value::String(key);
+                    value
+                })
+                .collect::<Vec<_>>()
+        }),
+        None => {
+            let mut value = groups.remove("").unwrap_or_default().to_json();
+            value["sheet"] = Value::String(sheet);
+            value["column"] = Value::String(column.to_string());
+            value
+        }
+    };
+    Ok(result)
+}
+
+/// Write sheets as formatted Excel tables, returning the file content
+pub fn create_workbook(sheets: &[SheetSpec]) -> Result<(Vec<u8>, Value), ExcelError> {
+    if sheets.is_empty() {
+        return Err(ExcelError::InvalidInput(
+            "at least one sheet is required".into(),
+        ));
+    }
+
+    let mut workbook = Workbook::new();
+    let mut total_rows = 0;
+
+    for spec in sheets {
+        if spec.columns.is_empty() {
+            return Err(ExcelError::InvalidInput(format!(
+                "sheet '{}' has no columns",
+                spec.name
+            )));
+        }
+        if let Some(row) = spec.rows.iter().find(|row| row.len() > spec.columns.len()) {
+            return Err(ExcelError::InvalidInput(format!(
+                "sheet '{}' has a row with {} values but only {} columns",
+                spec.name,
+                row.len(),
+                spec.columns.len()
+            )));
+        }
+
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(&spec.name)?;
+
+        for (r, row) in spec.rows.iter().enumerate() {
+            let r = r as u32 + 1;
+            for (c, value) in row.iter().enumerate() {
+                let c = c as u16;
+                match value {
+                    Value::Null => {}
+                    Value::Bool(b) => {
+                        worksheet.write_boolean(r, c, *b)?;
+                    }
+                    Value::Number(n) => {
+                        worksheet.write_number(r, c, n.as_f64().unwrap_or_default())?;
+                    }
+                    Value::String(s) => {
+                        worksheet.write_string(r, c, s)?;
+                    }
+                    other => {
+                        worksheet.write_string(r, c, other.to_string())?;
+                    }
+                }
+            }
+        }
+
+        // A table needs at least one data row, even if it's empty
+        let last_row = spec.rows.len().max(1) as u32;
+        let columns: Vec<_> = spec
+            .columns
+            .iter()
+            .map(|name| TableColumn::new().set_header(name))
+            .collect();
+        let table = Table::new()
+            .set_columns(&columns)
+            .set_style(TableStyle::Medium2);
+        worksheet.add_table(0, 0, last_row, spec.columns.len() as u16 - 1, &table)?;
+        worksheet.set_freeze_panes(1, 0)?;
+        worksheet.autofit();
+
+        total_rows += spec.rows.len();
+    }
+
+    let content = workbook.save_to_buffer()?;
+
+    Ok((
+        content,
+        json!({
+            "sheets": sheets.len(),
+            "rows": total_rows
+        }),
+    ))
+}
+
+fn open_sheet(content: &[u8], sheet: Option<&str>) -> Result<(String, Range<Data>), ExcelError> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(content))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        Some(sheet) => names
+            .into_iter()
+            .find(|name| name.eq_ignore_ascii_case(sheet))
+            .ok_or_else(|| ExcelError::SheetNotFound(sheet.to_string()))?,
+        None => names
+            .into_iter()
+            .next()
+            .ok_or_else(|| ExcelError::SheetNotFound("(workbook has no sheets)".into()))?,
+    };
+    let range = workbook.worksheet_range(&name)?;
+    Ok((name, range))
+}
+
+/// Header texts, falling back to the column letter for empty headers
+fn column_names(range: &Range<Data>, row: &[Data]) -> Vec<String> {
+    let first_column = range.start().map(|(_, c)| c).unwrap_or_default();
+    row.iter()
+        .enumerate()
+        .map(|(i, cell)| match cell_text(cell) {
+            text if text.is_empty() => column_letter(first_column + i as u32),
+            text => text,
+        })
+        .collect()
+}
+
+/// Position of a column within the range, by header or letter
+fn column_index(
+    range: &Range<Data>,
+    headers: &[String],
+    column: &str,
+) -> Result<usize, ExcelError> {
+    if let Some(i) = headers.iter().position(|h| h.eq_ignore_ascii_case(column)) {
+        return Ok(i);
+    }
+    let first_column = range.start().map(|(_, c)| c).unwrap_or_default();
+    parse_column(column)
+        .and_then(|c| c.checked_sub(first_column))
+        .map(|c| c as usize)
+        .filter(|&c| c < range.width())
+        .ok_or_else(|| ExcelError::ColumnNotFound(column.to_string()))
+}
+
+/// Running statistics of a column
+#[derive(Debug, Default)]
+struct Stats {
+    count: usize,
+    numeric: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Stats {
+    fn add(&mut self, cell: &Data) {
+        if matches!(cell, Data::Empty) {
+            return;
+        }
+        self.count += 1;
+        let number = match cell {
+            Data::Int(i) => *i as f64,
+            Data::Float(f) => *f,
+            _ => return,
+        };
+        self.numeric += 1;
+        self.sum += number;
+        self.min = Some(self.min.map_or(number, |min| min.min(number)));
+        self.max = Some(self.max.map_or(number, |max| max.max(number)));
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "numeric_count": self.numeric,
+            "sum": self.sum,
+            "average": (self.numeric > 0).then(|| self.sum / self.numeric as f64),
+            "min": self.min,
+            "max": self.max
+        })
+    }
+}
+
+fn cell_value(cell: &Data) -> Value {
+    match cell {
+        Data::Empty => Value::Null,
+        Data::Int(i) => json!(i),
+        Data::Float(f) => json!(f),
+        Data::Bool(b) => json!(b),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => json!(s),
+        Data::DateTime(_) | Data::Error(_) => Value::String(cell_text(cell)),
+    }
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(dt) => match dt.as_datetime() {
+            Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => {
+                datetime.date().to_string()
+            }
+            Some(datetime) => datetime.format("%Y-%m-%dT%H:%M:%S").to_string(),
+            None => dt.as_f64().to_string(),
+        },
+        Data::Empty => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// `A1:D20` style reference of a range
+fn range_reference(range: &Range<Data>) -> Option<String> {
+    let (start, end) = (range.start()?, range.end()?);
+    Some(format!(
+        "{}{}:{}{}",
+        column_letter(start.1),
+        start.0 + 1,
+        column_letter(end.1),
+        end.0 + 1
+    ))
+}
+
+/// Zero-based column index to letters (0 → A, 27 → AB)
+fn column_letter(mut column: u32) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'A' + (column % 26) as u8);
+        if column < 26 {
+            break;
+        }
+        column = column / 26 - 1;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// Letters to a zero-based column index (A → 0, AB → 27)
+fn parse_column(letters: &str) -> Option<u32> {
+    if letters.is_empty() || letters.len() > 3 || !letters.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return None;
+    }
+    let number = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0u32, |n, b| n * 26 + u32::from(b - b'A' + 1));
+    Some(number - 1)
+}
+
+/// `B2` to zero-based (row, column)
+fn parse_cell(reference: &str) -> Option<(u32, u32)> {
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    let row: u32 = digits.parse().ok()?;
+    Some((row.checked_sub(1)?, parse_column(letters)?))
+}
+
+/// `A1:D20` (or a single cell) to zero-based start and end
+fn parse_range(reference: &str) -> Option<((u32, u32), (u32, u32))> {
+    let reference = reference.trim().replace('$', "");
+    let (start, end) = reference
+        .split_once(':')
+        .unwrap_or((&reference, &reference));
+    let (start, end) = (parse_cell(start)?, parse_cell(end)?);
+    (start.0 <= end.0 && start.1 <= end.1).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_references() {
+        assert_eq!(column_letter(0), "A");
+        assert_eq!(column_letter(27), "AB");
+        assert_eq!(parse_column("ab"), Some(27));
+        assert_eq!(parse_range("B2:$D$20"), Some(((1, 1), (19, 3))));
+        assert_eq!(parse_range("C3"), Some(((2, 2), (2, 2))));
+        assert_eq!(parse_range("D20:B2"), None);
+        assert_eq!(parse_range("sales"), None);
+    }
+
+    #[test]
+    fn test_write_then_read() {
+        let sheets = vec![SheetSpec {
+            name: "Sales".into(),
+            columns: vec!["Region".into(), "Amount".into(), "Closed".into()],
+            rows: vec![
+                vec![json!("North"), json!(120), json!(true)],
+                vec![json!("South"), json!(80.5), json!(false)],
+                vec![json!("North"), json!(30), json!(null)],
+            ],
+        }];
+
+        let (workbook, written) = create_workbook(&sheets).unwrap();
+        assert_eq!(written["rows"], 3);
+
+        let listed = list_sheets(&workbook).unwrap();
+        assert_eq!(listed["sheets"][0]["name"], "Sales");
+        assert_eq!(listed["sheets"][0]["range"], "A1:C4");
+
+        let table = read_range(&workbook, Some("sales"), None, true, 2).unwrap();
+        assert_eq!(table["columns"], json!(["Region", "Amount", "Closed"]));
+        assert_eq!(
+            table["rows"][0],
+            json!({"Region": "North", "Amount": 120.0, "Closed": true})
+        );
+        assert_eq!(table["truncated"], true);
+
+        let cells = read_range(&workbook, None, Some("A2:B3"), false, 10).unwrap();
+        assert_eq!(cells["rows"], json!([["North", 120.0], ["South", 80.5]]));
+
+        let total = aggregate(&workbook, None, "amount", None).unwrap();
+        assert_eq!(total["sum"], 230.5);
+        assert_eq!(total["max"], 120.0);
+
+        let by_region = aggregate(&workbook, None, "B", Some("Region")).unwrap();
+        assert_eq!(by_region["groups"][0]["group"], "North");
+        assert_eq!(by_region["groups"][0]["sum"], 150.0);
+        assert_eq!(by_region["groups"][1]["count"], 1);
+
+        assert!(matches!(
+            aggregate(&workbook, None, "Profit", None),
+            Err(ExcelError::ColumnNotFound(_))
+        ));
+        assert!(matches!(
+            read_range(&workbook, Some("Costs"), None, true, 10),
+            Err(ExcelError::SheetNotFound(_))
+        ));
+    }
+}
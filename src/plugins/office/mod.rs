@@ -0,0 +1,1015 @@
+//! Office plugin for Excel workbooks, Word reports and PowerPoint decks
+//!
+//! Reads and writes Office documents in pure Rust, so it runs on servers
+//! without Office installed. Like the filesystem plugin, access is restricted
+//! to configured allowed paths and goes through the same sandbox, with deny
+//! patterns and a hidden-file policy. A document that replaces an existing
+//! file is journaled, with the old content kept in the plugin data directory.
+//!
+//! # Tools
+//!
+//! - `list_sheets` - List the sheets of a workbook
+//! - `read_range` - Read a sheet or cell range as a table
+//! - `aggregate_column` - Count, sum, average, min and max of a column
+//! - `create_workbook` - Write tables to a new workbook (if enabled)
+//! - `create_document` - Write a Word report (if enabled)
+//! - `create_presentation` - Write a PowerPoint deck (if enabled)
+//!
+//! Created files are returned as artifacts the user can download.
+//!
+//! # Configuration
+//!
+//! ```toml
+//! [plugins.office]
+//! excel_enabled = true
+//! word_enabled = true
+//! powerpoint_enabled = true
+//! allowed_paths = ["C:\\Reports"]
+//! deny_patterns = ["**/payroll/**"]
+//! allow_write = false
+//! max_rows = 500
+//! ```
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::plugins::filesystem::{
+    digest, Change, DenyPattern, HiddenFiles, Journal, Operation, Sandbox, SandboxError,
+};
+use crate::plugins::manifest::{
+    ConfigFieldBuilder, ConfigFieldType, PluginCategory, PluginManifest,
+};
+use crate::plugins::traits::{Plugin, PluginContext};
+use crate::plugins::{LegacyPlugin, PluginError, ToolDefinition, ToolResult};
+
+pub mod excel;
+pub mod package;
+pub mod powerpoint;
+pub mod word;
+
+const XLSX_MIME: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+const DOCX_MIME: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+const PPTX_MIME: &str = "application/vnd.openxmlformats-officedocument.presentationml.presentation";
+
+/// Configuration for the office plugin
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OfficeConfig {
+    /// Enable the Excel tools
+    #[serde(default = "default_true")]
+    pub excel_enabled: bool,
+
+    /// Enable the Word tools
+    #[serde(default = "default_true")]
+    pub word_enabled: bool,
+
+    /// Enable the PowerPoint tools
+    #[serde(default)]
+    pub powerpoint_enabled: bool,
+
+    /// Unused; mail is handled by the email plugin (`moxie.email`)
+    #[serde(default)]
+    pub outlook_enabled: bool,
+
+    /// Paths that documents can be read from and written to
+    #[serde(default)]
+    pub allowed_paths: Vec<PathBuf>,
+
+    /// Paths refused even inside the allowed paths, as in the filesystem
+    /// plugin
+    #[serde(default)]
+    pub deny_patterns: Vec<String>,
+
+    /// Whether hidden files are treated normally or refused; "hide" is
+    /// refused, as the plugin lists no directories to hide them from
+    #[serde(default)]
+    pub hidden_files: HiddenFiles,
+
+    /// Whether to allow creating documents
+    #[serde(default)]
+    pub allow_write: bool,
+
+    /// Maximum number of rows read from a sheet
+    #[serde(default = "default_max_rows")]
+    pub max_rows: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_rows() -> usize {
+    500
+}
+
+impl Default for OfficeConfig {
+    fn default() -> Self {
+        Self {
+            excel_enabled: true,
+            word_enabled: true,
+            powerpoint_enabled: false,
+            outlook_enabled: false,
+            allowed_paths: vec![],
+            deny_patterns: vec![],
+            hidden_files: HiddenFiles::default(),
+            allow_write: false,
+            max_rows: default_max_rows(),
+        }
+    }
+}
+
+impl OfficeConfig {
+    /// Parse configuration from a JSON Value
+    pub fn from_value(value: &Value) -> Result<Self, PluginError> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = serde_json::from_value(value.clone())
+            .map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        for pattern in &config.deny_patterns {
+            DenyPattern::new(pattern).map_err(|e| PluginError::ConfigError(e.to_string()))?;
+        }
+        if config.hidden_files == HiddenFiles::Hide {
+            return Err(PluginError::ConfigError(
+                "hidden_files = \"hide\" is not supported by the office plugin; use \"allow\" or \"deny\""
+                    .to_string(),
+            ));
+        }
+        Ok(config)
+    }
+}
+
+/// The sandbox for a configuration; one that allows nothing if the allowed
+/// directories can't be opened
+fn sandbox(config: &OfficeConfig) -> Arc<Sandbox> {
+    let sandbox = Sandbox::new(
+        &config.allowed_paths,
+        &config.deny_patterns,
+        config.hidden_files,
+    )
+    .unwrap_or_else(|e| {
+        tracing::error!("Office sandbox: {}", e);
+        Sandbox::empty()
+    });
+    Arc::new(sandbox)
+}
+
+/// Office plugin for documents
+pub struct OfficePlugin {
+    config: OfficeConfig,
+    sandbox: Arc<Sandbox>,
+    journal: Journal,
+}
+
+impl OfficePlugin {
+    /// Plugin ID constant
+    pub const ID: &'static str = "moxie.office";
+
+    /// Create a new office plugin with the given configuration
+    pub fn new(config: OfficeConfig) -> Self {
+        Self {
+            sandbox: sandbox(&config),
+            config,
+            journal: Journal::new(PluginContext::default().data_dir.join(Self::ID)),
+        }
+    }
+
+    /// Keep the journal and backups of replaced documents in `dir`
+    pub fn with_data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.journal = Journal::new(dir);
+        self
+    }
+
+    /// Create with default configuration
+    pub fn default_plugin() -> Self {
+        Self::new(OfficeConfig::default())
+    }
+
+    /// Run a sandbox operation on `path`
+    async fn sandboxed<T, F>(&self, path: &Path, f: F) -> Result<T, SandboxError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Sandbox, &Path) -> Result<T, SandboxError> + Send + 'static,
+    {
+        let path = path.to_path_buf();
+        self.sandbox.run(move |sandbox| f(sandbox, &path)).await
+    }
+
+    /// Read a document, or explain why it can't be
+    async fn read(&self, path: &str) -> Result<(PathBuf, Vec<u8>), String> {
+        let path = PathBuf::from(path);
+        let content = self
+            .sandboxed(&path, |sandbox, path| match sandbox.metadata(path)? {
+                Some(metadata) if metadata.is_file() => sandbox.read(path).map(Some),
+                _ => Ok(None),
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+        match content {
+            Some(content) => Ok((path, content)),
+            None => Err(format!("File not found: {}", path.display())),
+        }
+    }
+
+    /// Resolve a document to create, or explain why it can't be
+    fn writable(&self, path: &str, extension: &str) -> Result<PathBuf, String> {
+        if !self.config.allow_write {
+            return Err("Write operations are disabled for this plugin".to_string());
+        }
+
+        let path = PathBuf::from(path);
+        if !path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        {
+            return Err(format!("Path must end in .{}", extension));
+        }
+        self.sandbox.check(&path).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
+    /// Write a document, keeping a backup of the file it replaces
+    async fn save(&self, path: &Path, content: Vec<u8>) -> Result<Change, PluginError> {
+        let failed = |e: SandboxError| match e {
+            SandboxError::Io(e) => PluginError::IoError(e),
+            e => PluginError::ExecutionFailed(e.to_string()),
+        };
+        let previous = self
+            .sandboxed(path, |sandbox, path| match sandbox.metadata(path)? {
+                Some(metadata) if metadata.is_file() => sandbox.read(path).map(Some),
+                _ => Ok(None),
+            })
+            .await
+            .map_err(failed)?;
+
+        let mut change = Change::new(Operation::Write, path);
+        if let Some(previous) = previous {
+            change.before = Some(digest(&previous));
+            self.journal.backup(&mut change, &previous).await?;
+        }
+        change.after = Some(digest(&content));
+        self.sandboxed(path, move |sandbox, path| sandbox.write(path, &content))
+            .await
+            .map_err(failed)?;
+        self.journal.record(&change).await?;
+        Ok(change)
+    }
+
+    /// Build a document off the async runtime and write it to `path`,
+    /// offering it as a download
+    async fn create<F, E>(
+        &self,
+        path: PathBuf,
+        mime_type: &str,
+        build: F,
+    ) -> Result<ToolResult, PluginError>
+    where
+        F: FnOnce() -> Result<(Vec<u8>, Value), E> + Send + 'static,
+        E: Display + Send + 'static,
+    {
+        let built = tokio::task::spawn_blocking(build)
+            .await
+            .map_err(|e| PluginError::ExecutionFailed(e.to_string()))?;
+        // A failed build writes and offers nothing
+        let (content, mut output) = match built {
+            Ok(built) => built,
+            Err(e) => return Ok(ToolResult::failure(e.to_string())),
+        };
+
+        let change = self.save(&path, content).await?;
+        output["path"] = Value::String(path.to_string_lossy().into_owned());
+        output["change_id"] = Value::String(change.id);
+        Ok(ToolResult::success(output).with_artifact(path, mime_type))
+    }
+
+    async fn list_sheets(&self, path: &str) -> Result<ToolResult, PluginError> {
+        let (path, content) = match self.read(path).await {
+            Ok(document) => document,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        run_blocking(path, move || excel::list_sheets(&content)).await
+    }
+
+    async fn read_range(
+        &self,
+        path: &str,
+        sheet: Option<String>,
+        range: Option<String>,
+        header: bool,
+    ) -> Result<ToolResult, PluginError> {
+        let (path, content) = match self.read(path).await {
+            Ok(document) => document,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        let max_rows = self.config.max_rows;
+        run_blocking(path, move || {
+            excel::read_range(&content, sheet.as_deref(), range.as_deref(), header, max_rows)
+        })
+        .await
+    }
+
+    async fn aggregate_column(
+        &self,
+        path: &str,
+        sheet: Option<String>,
+        column: String,
+        group_by: Option<String>,
+    ) -> Result<ToolResult, PluginError> {
+        let (path, content) = match self.read(path).await {
+            Ok(document) => document,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        run_blocking(path, move || {
+            excel::aggregate(&content, sheet.as_deref(), &column, group_by.as_deref())
+        })
+        .await
+    }
+
+    async fn create_workbook(
+        &self,
+        path: &str,
+        sheets: Vec<excel::SheetSpec>,
+    ) -> Result<ToolResult, PluginError> {
+        let path = match self.writable(path, "xlsx") {
+            Ok(path) => path,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        self.create(path, XLSX_MIME, move || excel::create_workbook(&sheets))
+            .await
+    }
+
+    async fn create_document(
+        &self,
+        path: &str,
+        spec: word::DocumentSpec,
+    ) -> Result<ToolResult, PluginError> {
+        let path = match self.writable(path, "docx") {
+            Ok(path) => path,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        self.create(path, DOCX_MIME, move || word::create_document(&spec))
+            .await
+    }
+
+    async fn create_presentation(
+        &self,
+        path: &str,
+        spec: powerpoint::DeckSpec,
+    ) -> Result<ToolResult, PluginError> {
+        let path = match self.writable(path, "pptx") {
+            Ok(path) => path,
+            Err(message) => return Ok(ToolResult::failure(message)),
+        };
+        self.create(path, PPTX_MIME, move || {
+            powerpoint::create_presentation(&spec)
+        })
+        .await
+    }
+
+    /// Build tools list based on configuration
+    fn build_tools(&self) -> Vec<ToolDefinition> {
+        let mut tools = Vec::new();
+
+        if self.config.excel_enabled {
+            tools.extend([
+                ToolDefinition::new("list_sheets", "List the sheets of an Excel workbook")
+                    .with_parameters(json!({
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "Path to the workbook (.xlsx, .xls, .xlsb or .ods)"
+                            }
+                        },
+                        "required": ["path"]
+                    }))
+                    .from_plugin(Self::ID),
+                ToolDefinition::new(
+                    "read_range",
+                    "Read a sheet or cell range of an Excel workbook as a table",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the workbook"
+                        },
+                        "sheet": {
+                            "type": "string",
+                            "description": "Sheet name (default: the first sheet)"
+                        },
+                        "range": {
+                            "type": "string",
+                            "description": "Cell range such as A1:D20 (default: the used range)"
+                        },
+                        "header": {
+                            "type": "boolean",
+                            "description": "Whether the first row holds column names (default: true)"
+                        }
+                    },
+                    "required": ["path"]
+                }))
+                .from_plugin(Self::ID),
+                ToolDefinition::new(
+                    "aggregate_column",
+                    "Count, sum, average, min and max of a sheet column, optionally grouped by another column",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path to the workbook"
+                        },
+                        "sheet": {
+                            "type": "string",
+                            "description": "Sheet name (default: the first sheet)"
+                        },
+                        "column": {
+                            "type": "string",
+                            "description": "Column header or letter to aggregate"
+                        },
+                        "group_by": {
+                            "type": "string",
+                            "description": "Column header or letter to group by"
+                        }
+                    },
+                    "required": ["path", "column"]
+                }))
+                .from_plugin(Self::ID),
+            ]);
+
+            if self.config.allow_write {
+                tools.push(
+                    ToolDefinition::new(
+                        "create_workbook",
+                        "Create an Excel workbook with one formatted table per sheet",
+                    )
+                    .with_parameters(json!({
+                        "type": "object",
+                        "properties": {
+                            "path": {
+                                "type": "string",
+                                "description": "Path of the .xlsx file to create"
+                            },
+                            "sheets": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "name": { "type": "string" },
+                                        "columns": {
+                                            "type": "array",
+                                            "items": { "type": "string" }
+                                        },
+                                        "rows": {
+                                            "type": "array",
+                                            "items": { "type": "array" }
+                                        }
+                                    },
+                                    "required": ["name", "columns", "rows"]
+                                }
+                            }
+                        },
+                        "required": ["path", "sheets"]
+                    }))
+                    .with_confirmation()
+                    .from_plugin(Self::ID),
+                );
+            }
+        }
+
+        if self.config.word_enabled && self.config.allow_write {
+            tools.push(
+                ToolDefinition::new(
+                    "create_document",
+                    "Create a Word report from headings, paragraphs, bullet lists and tables",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the .docx file to create"
+                        },
+                        "title": {
+                            "type": "string",
+                            "description": "Document title"
+                        },
+                        "blocks": {
+                            "type": "array",
+                            "description": "Content in order. Each block has a type: heading (text, level 1-3), paragraph (text), bullets (items) or table (columns, rows)",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "type": {
+                                        "type": "string",
+                                        "enum": ["heading", "paragraph", "bullets", "table"]
+                                    },
+                                    "text": { "type": "string" },
+                                    "level": { "type": "integer", "minimum": 1, "maximum": 3 },
+                                    "items": {
+                                        "type": "array",
+                                        "items": { "type": "string" }
+                                    },
+                                    "columns": {
+                                        "type": "array",
+                                        "items": { "type": "string" }
+                                    },
+                                    "rows": {
+                                        "type": "array",
+                                        "items": { "type": "array" }
+                                    }
+                                },
+                                "required": ["type"]
+                            }
+                        }
+                    },
+                    "required": ["path", "blocks"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            );
+        }
+
+        if self.config.powerpoint_enabled && self.config.allow_write {
+            tools.push(
+                ToolDefinition::new(
+                    "create_presentation",
+                    "Create a PowerPoint deck with a title slide and bullet-point slides",
+                )
+                .with_parameters(json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "Path of the .pptx file to create"
+                        },
+                        "title": {
+                            "type": "string",
+                            "description": "Title of the opening slide"
+                        },
+                        "subtitle": {
+                            "type": "string",
+                            "description": "Subtitle of the opening slide"
+                        },
+                        "slides": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "title": { "type": "string" },
+                                    "bullets": {
+                                        "type": "array",
+                                        "items": { "type": "string" }
+                                    }
+                                },
+                                "required": ["title"]
+                            }
+                        }
+                    },
+                    "required": ["path", "slides"]
+                }))
+                .with_confirmation()
+                .from_plugin(Self::ID),
+            );
+        }
+
+        tools
+    }
+}
+
+/// Run blocking document work off the async runtime
+async fn run_blocking<F, E>(path: PathBuf, work: F) -> Result<ToolResult, PluginError>
+where
+    F: FnOnce() -> Result<Value, E> + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || {
+        work().map(|mut output| {
+            output["path"] = Value::String(path.to_string_lossy().into_owned());
+            output
+        })
+    })
+    .await
+    .map_err(|e| PluginError::ExecutionFailed(e.to_string()))?;
+
+    Ok(match result {
+        Ok(output) => ToolResult::success(output),
+        Err(e) => ToolResult::failure(e.to_string()),
+    })
+}
+
+// ============================================================================
+// New Plugin trait implementation
+// ============================================================================
+
+#[async_trait]
+impl Plugin for OfficePlugin {
+    fn manifest(&self) -> PluginManifest {
+        PluginManifest::new(
+            Self::ID,
+            "Office",
+            "Read and create Excel workbooks, Word reports and PowerPoint decks without Office installed",
+        )
+        .with_version(1, 0, 0)
+        .with_author("Moxie AI")
+        .with_category(PluginCategory::Office)
+        .with_keywords(vec![
+            "office",
+            "excel",
+            "xlsx",
+            "spreadsheet",
+            "word",
+            "docx",
+            "report",
+            "powerpoint",
+            "pptx",
+        ])
+        .with_config_field(
+            ConfigFieldBuilder::new("excel_enabled", ConfigFieldType::Boolean)
+                .label("Excel")
+                .description("Enable the Excel tools")
+                .default_value(json!(true))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("word_enabled", ConfigFieldType::Boolean)
+                .label("Word")
+                .description("Enable creating Word reports (needs Allow Write)")
+                .default_value(json!(true))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("powerpoint_enabled", ConfigFieldType::Boolean)
+                .label("PowerPoint")
+                .description("Enable creating PowerPoint decks (needs Allow Write)")
+                .default_value(json!(false))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("allowed_paths", ConfigFieldType::PathArray)
+                .label("Allowed Paths")
+                .description("Directories documents can be read from and written to")
+                .required()
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("deny_patterns", ConfigFieldType::StringArray)
+                .label("Deny Patterns")
+                .description("Documents refused even inside the allowed paths (e.g. **/payroll/**)")
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new(
+                "hidden_files",
+                ConfigFieldType::Select(vec!["allow".into(), "deny".into()]),
+            )
+            .label("Hidden Files")
+            .description("Whether documents starting with '.' are accessible (allow) or refused (deny)")
+            .default_value(json!("allow"))
+            .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("allow_write", ConfigFieldType::Boolean)
+                .label("Allow Write")
+                .description("Enable creating documents")
+                .default_value(json!(false))
+                .build(),
+        )
+        .with_config_field(
+            ConfigFieldBuilder::new("max_rows", ConfigFieldType::Number)
+                .label("Max Rows")
+                .description("Maximum number of rows read from a sheet")
+                .default_value(json!(500))
+                .build(),
+        )
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        let path = params["path"]
+            .as_str()
+            .ok_or_else(|| PluginError::InvalidParameters("path is required".into()));
+        let text = |name: &str| params[name].as_str().map(String::from);
+
+        match tool {
+            "list_sheets" if self.config.excel_enabled => self.list_sheets(path?).await,
+            "read_range" if self.config.excel_enabled => {
+                let header = params["header"].as_bool().unwrap_or(true);
+                self.read_range(path?, text("sheet"), text("range"), header)
+                    .await
+            }
+            "aggregate_column" if self.config.excel_enabled => {
+                let column = text("column")
+                    .ok_or_else(|| PluginError::InvalidParameters("column is required".into()))?;
+                self.aggregate_column(path?, text("sheet"), column, text("group_by"))
+                    .await
+            }
+            "create_workbook" if self.config.excel_enabled => {
+                let sheets = serde_json::from_value(params["sheets"].clone())
+                    .map_err(|e| PluginError::InvalidParameters(format!("sheets: {}", e)))?;
+                self.create_workbook(path?, sheets).await
+            }
+            "create_document" if self.config.word_enabled => {
+                let spec = serde_json::from_value(params.clone())
+                    .map_err(|e| PluginError::InvalidParameters(e.to_string()))?;
+                self.create_document(path?, spec).await
+            }
+            "create_presentation" if self.config.powerpoint_enabled => {
+                let spec = serde_json::from_value(params.clone())
+                    .map_err(|e| PluginError::InvalidParameters(e.to_string()))?;
+                self.create_presentation(path?, spec).await
+            }
+            _ => Err(PluginError::ToolNotFound(tool.to_string())),
+        }
+    }
+
+    async fn on_init(&mut self, ctx: &PluginContext) -> Result<(), PluginError> {
+        // Update config from context if provided
+        if !ctx.config.is_null() {
+            self.config = OfficeConfig::from_value(&ctx.config)?;
+        }
+        self.sandbox = sandbox(&self.config);
+        self.journal = Journal::new(&ctx.data_dir);
+
+        tracing::info!(
+            "Office plugin initialized with {} allowed paths",
+            self.config.allowed_paths.len()
+        );
+
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// ============================================================================
+// Legacy Plugin trait implementation (backwards compatibility)
+// ============================================================================
+
+#[async_trait]
+impl LegacyPlugin for OfficePlugin {
+    fn name(&self) -> &str {
+        "office"
+    }
+
+    fn description(&self) -> &str {
+        "Reads and creates Office documents"
+    }
+
+    fn tools(&self) -> Vec<ToolDefinition> {
+        self.build_tools()
+    }
+
+    async fn execute(&self, tool: &str, params: Value) -> Result<ToolResult, PluginError> {
+        Plugin::execute(self, tool, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::traits::Plugin as NewPlugin;
+
+    fn test_config(dir: &Path) -> OfficeConfig {
+        OfficeConfig {
+            allowed_paths: vec![dir.to_path_buf()],
+            allow_write: true,
+            ..OfficeConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_manifest_and_tools() {
+        let plugin = OfficePlugin::default_plugin();
+        let manifest = NewPlugin::manifest(&plugin);
+        assert_eq!(manifest.id, "moxie.office");
+        assert_eq!(manifest.category, PluginCategory::Office);
+
+        let names: Vec<_> = plugin.build_tools().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["list_sheets", "read_range", "aggregate_column"]);
+
+        let plugin = OfficePlugin::new(OfficeConfig {
+            excel_enabled: false,
+            powerpoint_enabled: true,
+            allow_write: true,
+            ..OfficeConfig::default()
+        });
+        let names: Vec<_> = plugin.build_tools().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["create_document", "create_presentation"]);
+    }
+
+    #[tokio::test]
+    async fn test_create_and_read_workbook() {
+        let dir = std::env::temp_dir().join(format!("moxie-office-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plugin = OfficePlugin::new(test_config(&dir)).with_data_dir(dir.join(".journal"));
+        let path = dir.join("q3.xlsx");
+        let path = path.to_string_lossy();
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "create_workbook",
+            json!({
+                "path": path,
+                "sheets": [{
+                    "name": "Q3",
+                    "columns": ["Month", "Revenue"],
+                    "rows": [["July", 1200], ["August", 900], ["September", 1500]]
+                }]
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.artifacts[0].mime_type, XLSX_MIME);
+
+        let result = plugin
+            .aggregate_column(&path, None, "Revenue".into(), None)
+            .await
+            .unwrap();
+        assert_eq!(result.output["sum"], 3600.0);
+        assert_eq!(result.output["path"], path.as_ref());
+
+        // Only .xlsx files inside the allowed paths
+        let result = plugin
+            .create_workbook(&dir.join("q3.csv").to_string_lossy(), vec![])
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().contains(".xlsx"));
+        let result = plugin.list_sheets("/etc/passwd").await.unwrap();
+        assert!(result.error.unwrap().contains("Access denied"));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_create_report_and_deck() {
+        let dir = std::env::temp_dir().join(format!("moxie-office-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let plugin = OfficePlugin::new(OfficeConfig {
+            powerpoint_enabled: true,
+            ..test_config(&dir)
+        })
+        .with_data_dir(dir.join(".journal"));
+
+        let report = dir.join("q3.docx");
+        let result = NewPlugin::execute(
+            &plugin,
+            "create_document",
+            json!({
+                "path": report,
+                "title": "Q3",
+                "blocks": [{ "type": "paragraph", "text": "Revenue grew." }]
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(result.success, "{:?}", result.error);
+        assert_eq!(result.artifacts[0].path, report);
+        assert_eq!(result.artifacts[0].mime_type, DOCX_MIME);
+
+        let result = NewPlugin::execute(
+            &plugin,
+            "create_presentation",
+            json!({
+                "path": dir.join("q3.pptx"),
+                "slides": [{ "title": "Highlights", "bullets": ["Revenue grew"] }]
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.output["slides"], 1);
+        assert_eq!(result.artifacts[0].mime_type, PPTX_MIME);
+
+        // A failed write offers nothing
+        let result = NewPlugin::execute(
+            &plugin,
+            "create_document",
+            json!({ "path": dir.join("empty.docx"), "blocks": [] }),
+        )
+        .await
+        .unwrap();
+        assert!(!result.success);
+        assert!(result.artifacts.is_empty());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_is_journaled() {
+        let dir = std::env::temp_dir().join(format!("moxie-office-{}", uuid::Uuid::new_v4()));
+        let root = dir.join("reports");
+        std::fs::create_dir_all(&root).unwrap();
+        let plugin = OfficePlugin::new(test_config(&root)).with_data_dir(dir.join("data"));
+        let path = root.join("q3.xlsx");
+        let sheets = |revenue: i64| {
+            vec![excel::SheetSpec {
+                name: "Q3".into(),
+                columns: vec!["Revenue".into()],
+                rows: vec![vec![json!(revenue)]],
+            }]
+        };
+
+        let created = plugin
+            .create_workbook(&path.to_string_lossy(), sheets(1200))
+            .await
+            .unwrap();
+        assert!(created.success, "{:?}", created.error);
+        let original = std::fs::read(&path).unwrap();
+
+        let replaced = plugin
+            .create_workbook(&path.to_string_lossy(), sheets(900))
+            .await
+            .unwrap();
+        assert!(replaced.success, "{:?}", replaced.error);
+
+        let changes = plugin.journal.changes().await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].before, None);
+        let change = &changes[1];
+        assert_eq!(change.id, replaced.output["change_id"].as_str().unwrap());
+        assert_eq!(change.before.as_deref(), Some(digest(&original).as_str()));
+        assert_eq!(std::fs::read(change.backup.as_ref().unwrap()).unwrap(), original);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_deny_patterns_and_links() {
+        let dir = std::env::temp_dir().join(format!("moxie-office-{}", uuid::Uuid::new_v4()));
+        let root = dir.join("reports");
+        std::fs::create_dir_all(root.join("payroll")).unwrap();
+        assert!(OfficeConfig::from_value(&json!({"deny_patterns": ["["]})).is_err());
+        assert!(matches!(
+            OfficeConfig::from_value(&json!({"hidden_files": "hide"})),
+            Err(PluginError::ConfigError(e)) if e.contains("hide")
+        ));
+
+        let plugin = OfficePlugin::new(OfficeConfig {
+            deny_patterns: vec!["**/payroll/**".into()],
+            hidden_files: HiddenFiles::Deny,
+            ..test_config(&root)
+        })
+        .with_data_dir(dir.join("data"));
+
+        let result = plugin
+            .create_document(
+                &root.join("payroll/q3.docx").to_string_lossy(),
+                word::DocumentSpec {
+                    title: None,
+                    blocks: vec![],
+                },
+            )
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().contains("deny pattern"));
+        let result = plugin
+            .list_sheets(&root.join(".q3.xlsx").to_string_lossy())
+            .await
+            .unwrap();
+        assert!(result.error.unwrap().contains("hidden"));
+
+        #[cfg(unix)]
+        {
+            std::fs::write(dir.join("outside.xlsx"), b"secret").unwrap();
+            std::os::unix::fs::symlink("../outside.xlsx", root.join("linked.xlsx")).unwrap();
+            std::os::unix::fs::symlink("payroll", root.join("people")).unwrap();
+
+            let linked = root.join("linked.xlsx");
+            let result = plugin.list_sheets(&linked.to_string_lossy()).await.unwrap();
+            assert!(result.error.unwrap().contains("outside the allowed paths"));
+            let result = plugin
+                .create_workbook(&linked.to_string_lossy(), vec![])
+                .await
+                .unwrap();
+            assert!(!result.success);
+            assert_eq!(std::fs::read(dir.join("outside.xlsx")).unwrap(), b"secret");
+
+            // A link inside the root still can't reach a denied path
+            let result = plugin
+                .list_sheets(&root.join("people/q3.xlsx").to_string_lossy())
+                .await
+                .unwrap();
+            assert!(result.error.unwrap().contains("deny pattern"));
+        }
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,98 @@
+//! Writing Office Open XML packages
+//!
+//! `.docx` and `.pptx` files are zip archives of XML parts. The Word and
+//! PowerPoint writers produce the parts; this module zips them up.
+
+use std::io::{Cursor, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Errors writing a package
+#[derive(Debug, thiserror::Error)]
+pub enum PackageError {
+    #[error("Invalid document: {0}")]
+    InvalidInput(String),
+
+    #[error("Could not write document: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not write document: {0}")]
+    Zip(#[from] zip::result::ZipError),
+}
+
+/// Zip `parts` (name, XML) into a package, returning its content
+pub fn write_package(parts: &[(String, String)]) -> Result<Vec<u8>, PackageError> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, xml) in parts {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(xml.as_bytes())?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Escape text for XML content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than tab and newlines are not valid XML
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The `docProps/core.xml` part: title, author and creation time
+pub fn core_properties(title: Option<&str>) -> String {
+    let title = title
+        .map(|title| format!("<dc:title>{}</dc:title>", escape(title)))
+        .unwrap_or_default();
+    let created = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">{title}<dc:creator>Moxie</dc:creator><dcterms:created xsi:type="dcterms:W3CDTF">{created}</dcterms:created></cp:coreProperties>"#
+    )
+}
+
+/// Text of a JSON cell value, as the model wrote it
+pub fn cell_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn read_part(content: &[u8], name: &str) -> String {
+    use std::io::Read;
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(content)).unwrap();
+    let mut part = archive.by_name(name).unwrap();
+    let mut xml = String::new();
+    part.read_to_string(&mut xml).unwrap();
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("Q3 <draft> & \"final\"\u{1}"),
+            "Q3 &lt;draft&gt; &amp; &quot;final&quot;"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! PowerPoint decks
+//!
+//! Writes simple 16:9 `.pptx` decks: an optional title slide followed by one
+//! slide per entry, each with a title and bullet points. Slides use plain
+//! text boxes on a blank layout, so the deck needs no template.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::package::{self, escape, PackageError};
+
+const NAMESPACES: &str = r#"xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:p="http://schemas.openxmlformats.org/presentationml/2006/main""#;
+
+/// Slide size in EMU (13.333 x 7.5 inches)
+const SLIDE_WIDTH: i64 = 12_192_000;
+const SLIDE_HEIGHT: i64 = 6_858_000;
+const MARGIN: i64 = 609_600;
+
+/// A deck to write
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeckSpec {
+    /// Title of the opening slide and the document properties
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Shown under the title on the opening slide
+    #[serde(default)]
+    pub subtitle: Option<String>,
+
+    /// Content slides, in order
+    pub slides: Vec<SlideSpec>,
+}
+
+/// One content slide
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlideSpec {
+    pub title: String,
+
+    #[serde(default)]
+    pub bullets: Vec<String>,
+}
+
+/// Write `spec` as a `.pptx`, returning the file content
+pub fn create_presentation(spec: &DeckSpec) -> Result<(Vec<u8>, Value), PackageError> {
+    let mut slides = Vec::new();
+    if let Some(title) = &spec.title {
+        slides.push(title_slide(title, spec.subtitle.as_deref()));
+    }
+    slides.extend(spec.slides.iter().map(content_slide));
+    if slides.is_empty() {
+        return Err(PackageError::InvalidInput(
+            "a presentation needs a title or at least one slide".into(),
+        ));
+    }
+
+    let slide_overrides: String = (1..=slides.len())
+        .map(|n| format!(r#"<Override PartName="/ppt/slides/slide{n}.xml" ContentType="application/vnd.openxmlformats-officedocument.presentationml.slide+xml"/>"#))
+        .collect();
+    let content_types = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/ppt/presentation.xml" ContentType="application/vnd.openxmlformats-officedocument.presentationml.presentation.main+xml"/><Override PartName="/ppt/slideMasters/slideMaster1.xml" ContentType="application/vnd.openxmlformats-officedocument.presentationml.slideMaster+xml"/><Override PartName="/ppt/slideLayouts/slideLayout1.xml" ContentType="application/vnd.openxmlformats-officedocument.presentationml.slideLayout+xml"/><Override PartName="/ppt/theme/theme1.xml" ContentType="application/vnd.openxmlformats-officedocument.theme+xml"/>{slide_overrides}<Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/></Types>"#
+    );
+
+    // rId1 is the master and rId2 the theme; slides follow
+    let slide_ids: String = (1..=slides.len())
+        .map(|n| format!(r#"<p:sldId id="{}" r:id="rId{}"/>"#, 255 + n, n + 2))
+        .collect();
+    let presentation = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<p:presentation {NAMESPACES}><p:sldMasterIdLst><p:sldMasterId id="2147483648" r:id="rId1"/></p:sldMasterIdLst><p:sldIdLst>{slide_ids}</p:sldIdLst><p:sldSz cx="{SLIDE_WIDTH}" cy="{SLIDE_HEIGHT}"/><p:notesSz cx="6858000" cy="9144000"/></p:presentation>"#
+    );
+    let slide_rels: String = (1..=slides.len())
+        .map(|n| format!(r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slide" Target="slides/slide{}.xml"/>"#, n + 2, n))
+        .collect();
+    let presentation_rels = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideMaster" Target="slideMasters/slideMaster1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/theme" Target="theme/theme1.xml"/>{slide_rels}</Relationships>"#
+    );
+
+    let mut parts = vec![
+        ("[Content_Types].xml".to_string(), content_types),
+        ("_rels/.rels".to_string(), ROOT_RELS.to_string()),
+        (
+            "docProps/core.xml".to_string(),
+            package::core_properties(spec.title.as_deref()),
+        ),
+        ("ppt/presentation.xml".to_string(), presentation),
+        (
+            "ppt/_rels/presentation.xml.rels".to_string(),
+            presentation_rels,
+        ),
+        (
+            "ppt/slideMasters/slideMaster1.xml".to_string(),
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<p:sldMaster {NAMESPACES}><p:cSld><p:spTree>{EMPTY_GROUP}</p:spTree></p:cSld><p:clrMap bg1="lt1" tx1="dk1" bg2="lt2" tx2="dk2" accent1="accent1" accent2="accent2" accent3="accent3" accent4="accent4" accent5="accent5" accent6="accent6" hlink="hlink" folHlink="folHlink"/><p:sldLayoutIdLst><p:sldLayoutId id="2147483649" r:id="rId1"/></p:sldLayoutIdLst></p:sldMaster>"#
+            ),
+        ),
+        (
+            "ppt/slideMasters/_rels/slideMaster1.xml.rels".to_string(),
+            MASTER_RELS.to_string(),
+        ),
+        (
+            "ppt/slideLayouts/slideLayout1.xml".to_string(),
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<p:sldLayout {NAMESPACES} type="blank" preserve="1"><p:cSld name="Blank"><p:spTree>{EMPTY_GROUP}</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sldLayout>"#
+            ),
+        ),
+        (
+            "ppt/slideLayouts/_rels/slideLayout1.xml.rels".to_string(),
+            LAYOUT_RELS.to_string(),
+        ),
+        ("ppt/theme/theme1.xml".to_string(), THEME.to_string()),
+    ];
+    for (i, slide) in slides.into_iter().enumerate() {
+        parts.push((format!("ppt/slides/slide{}.xml", i + 1), slide));
+        parts.push((
+            format!("ppt/slides/_rels/slide{}.xml.rels", i + 1),
+            SLIDE_RELS.to_string(),
+        ));
+    }
+    let content = package::write_package(&parts)?;
+
+    let count = spec.slides.len() + usize::from(spec.title.is_some());
+    Ok((content, json!({ "slides": count })))
+}
+
+fn title_slide(title: &str, subtitle: Option<&str>) -> String {
+    let width = SLIDE_WIDTH - 2 * MARGIN;
+    let mut shapes = text_box(
+        2,
+        "Title",
+        (MARGIN, 2_130_000, width, 1_470_000),
+        "b",
+        &run_paragraph(title, 4400, true, true),
+    );
+    if let Some(subtitle) = subtitle {
+        shapes.push_str(&text_box(
+            3,
+            "Subtitle",
+            (MARGIN, 3_700_000, width, 1_000_000),
+            "t",
+            &run_paragraph(subtitle, 2400, false, true),
+        ));
+    }
+    slide(&shapes)
+}
+
+fn content_slide(spec: &SlideSpec) -> String {
+    let width = SLIDE_WIDTH - 2 * MARGIN;
+    let mut shapes = text_box(
+        2,
+        "Title",
+        (MARGIN, 365_125, width, 1_325_563),
+        "ctr",
+        &run_paragraph(&spec.title, 3600, true, false),
+    );
+    if !spec.bullets.is_empty() {
+        let paragraphs: String = spec
+            .bullets
+            .iter()
+            .map(|item| {
+                format!(
+                    r#"<a:p><a:pPr marL="342900" indent="-342900"><a:spcBef><a:spcPts val="600"/></a:spcBef><a:buFont typeface="Arial"/><a:buChar char="•"/></a:pPr><a:r><a:rPr lang="en-US" sz="2000" dirty="0"/><a:t>{}</a:t></a:r></a:p>"#,
+                    escape(item)
+                )
+            })
+            .collect();
+        shapes.push_str(&text_box(
+            3,
+            "Content",
+            (MARGIN, 1_825_625, width, 4_351_338),
+            "t",
+            &paragraphs,
+        ));
+    }
+    slide(&shapes)
+}
+
+fn run_paragraph(text: &str, size: u32, bold: bool, centered: bool) -> String {
+    format!(
+        r#"<a:p>{}<a:r><a:rPr lang="en-US" sz="{}" b="{}" dirty="0"/><a:t>{}</a:t></a:r></a:p>"#,
+        if centered {
+            r#"<a:pPr algn="ctr"/>"#
+        } else {
+            ""
+        },
+        size,
+        u8::from(bold),
+        escape(text)
+    )
+}
+
+/// A text box at (x, y, width, height) in EMU
+fn text_box(
+    id: u32,
+    name: &str,
+    (x, y, cx, cy): (i64, i64, i64, i64),
+    anchor: &str,
+    paragraphs: &str,
+) -> String {
+    format!(
+        r#"<p:sp><p:nvSpPr><p:cNvPr id="{id}" name="{name}"/><p:cNvSpPr txBox="1"/><p:nvPr/></p:nvSpPr><p:spPr><a:xfrm><a:off x="{x}" y="{y}"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></p:spPr><p:txBody><a:bodyPr wrap="square" anchor="{anchor}"><a:normAutofit/></a:bodyPr><a:lstStyle/>{paragraphs}</p:txBody></p:sp>"#
+    )
+}
+
+fn slide(shapes: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<p:sld {NAMESPACES}><p:cSld><p:spTree>{EMPTY_GROUP}{shapes}</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sld>"#
+    )
+}
+
+const EMPTY_GROUP: &str =
+    r#"<p:nvGrpSpPr><p:cNvPr id="1" name=""/><p:cNvGrpSpPr/><p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="ppt/presentation.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/></Relationships>"#;
+
+const MASTER_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideLayout" Target="../slideLayouts/slideLayout1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/theme" Target="../theme/theme1.xml"/></Relationships>"#;
+
+const LAYOUT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideMaster" Target="../slideMasters/slideMaster1.xml"/></Relationships>"#;
+
+const SLIDE_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideLayout" Target="../slideLayouts/slideLayout1.xml"/></Relationships>"#;
+
+const THEME: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<a:theme xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" name="Moxie"><a:themeElements><a:clrScheme name="Office"><a:dk1><a:sysClr val="windowText" lastClr="000000"/></a:dk1><a:lt1><a:sysClr val="window" lastClr="FFFFFF"/></a:lt1><a:dk2><a:srgbClr val="1F3864"/></a:dk2><a:lt2><a:srgbClr val="E7E6E6"/></a:lt2><a:accent1><a:srgbClr val="4472C4"/></a:accent1><a:accent2><a:srgbClr val="ED7D31"/></a:accent2><a:accent3><a:srgbClr val="A5A5A5"/></a:accent3><a:accent4><a:srgbClr val="FFC000"/></a:accent4><a:accent5><a:srgbClr val="5B9BD5"/></a:accent5><a:accent6><a:srgbClr val="70AD47"/></a:accent6><a:hlink><a:srgbClr val="0563C1"/></a:hlink><a:folHlink><a:srgbClr val="954F72"/></a:folHlink></a:clrScheme><a:fontScheme name="Office"><a:majorFont><a:latin typeface="Calibri Light"/><a:ea typeface=""/><a:cs typeface=""/></a:majorFont><a:minorFont><a:latin typeface="Calibri"/><a:ea typeface=""/><a:cs typeface=""/></a:minorFont></a:fontScheme><a:fmtScheme name="Office"><a:fillStyleLst><a:solidFill><a:schemeClr val="phClr"/></a:solidFill><a:solidFill><a:schemeClr val="phClr"/></a:solidFill><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:fillStyleLst><a:lnStyleLst><a:ln w="6350"><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:ln><a:ln w="12700"><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:ln><a:ln w="19050"><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:ln></a:lnStyleLst><a:effectStyleLst><a:effectStyle><a:effectLst/></a:effectStyle><a:effectStyle><a:effectLst/></a:effectStyle><a:effectStyle><a:effectLst/></a:effectStyle></a:effectStyleLst><a:bgFillStyleLst><a:solidFill><a:schemeClr val="phClr"/></a:solidFill><a:solidFill><a:schemeClr val="phClr"/></a:solidFill><a:solidFill><a:schemeClr val="phClr"/></a:solidFill></a:bgFillStyleLst></a:fmtScheme></a:themeElements></a:theme>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_presentation() {
+        let spec: DeckSpec = serde_json::from_value(json!({
+            "title": "Q3 Review",
+            "subtitle": "Sales & margins",
+            "slides": [
+                { "title": "Highlights", "bullets": ["Revenue up 12%", "Churn <2%"] },
+                { "title": "Next steps" }
+            ]
+        }))
+        .unwrap();
+
+        let (pptx, output) = create_presentation(&spec).unwrap();
+        assert_eq!(output["slides"], 3);
+
+        let presentation = package::read_part(&pptx, "ppt/presentation.xml");
+        assert_eq!(presentation.matches("<p:sldId ").count(), 3);
+        assert!(package::read_part(&pptx, "ppt/slides/slide1.xml").contains("Sales &amp; margins"));
+        let highlights = package::read_part(&pptx, "ppt/slides/slide2.xml");
+        assert!(highlights.contains("Churn &lt;2%"));
+        assert_eq!(highlights.matches("<a:buChar").count(), 2);
+        assert!(
+            package::read_part(&pptx, "ppt/slides/_rels/slide3.xml.rels")
+                .contains("slideLayout1.xml")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Word documents
+//!
+//! Writes `.docx` reports from a list of blocks: headings, paragraphs,
+//! bullet lists and tables. Styling comes from a small built-in stylesheet
+//! (Title, Heading 1-3, List Paragraph and Table Grid) so the document looks
+//! like one written in Word and can be restyled there.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::package::{self, cell_text, escape, PackageError};
+
+const WORD_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+
+/// A report to write
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentSpec {
+    /// Title shown at the top and in the document properties
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Content, in order
+    pub blocks: Vec<Block>,
+}
+
+/// One piece of document content
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Heading {
+        text: String,
+        /// 1 to 3
+        #[serde(default = "default_level")]
+        level: u8,
+    },
+    Paragraph {
+        text: String,
+    },
+    Bullets {
+        items: Vec<String>,
+    },
+    Table {
+        columns: Vec<String>,
+        #[serde(default)]
+        rows: Vec<Vec<Value>>,
+    },
+}
+
+fn default_level() -> u8 {
+    1
+}
+
+/// Write `spec` as a `.docx`, returning the file content
+pub fn create_document(spec: &DocumentSpec) -> Result<(Vec<u8>, Value), PackageError> {
+    if spec.blocks.is_empty() && spec.title.is_none() {
+        return Err(PackageError::InvalidInput(
+            "a document needs a title or at least one block".into(),
+        ));
+    }
+
+    let mut body = String::new();
+    if let Some(title) = &spec.title {
+        body.push_str(&paragraph(Some("Title"), title));
+    }
+    for block in &spec.blocks {
+        match block {
+            Block::Heading { text, level } => {
+                let style = format!("Heading{}", (*level).clamp(1, 3));
+                body.push_str(&paragraph(Some(&style), text));
+            }
+            Block::Paragraph { text } => body.push_str(&paragraph(None, text)),
+            Block::Bullets { items } => {
+                for item in items {
+                    body.push_str(&bullet(item));
+                }
+            }
+            Block::Table { columns, rows } => {
+                if columns.is_empty() {
+                    return Err(PackageError::InvalidInput("a table needs columns".into()));
+                }
+                body.push_str(&table(columns, rows));
+            }
+        }
+    }
+
+    let document = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="{WORD_NS}"><w:body>{body}<w:sectPr><w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1440" w:right="1440" w:bottom="1440" w:left="1440" w:header="708" w:footer="708" w:gutter="0"/></w:sectPr></w:body></w:document>"#
+    );
+
+    let parts = vec![
+        ("[Content_Types].xml".to_string(), CONTENT_TYPES.to_string()),
+        ("_rels/.rels".to_string(), ROOT_RELS.to_string()),
+        (
+            "docProps/core.xml".to_string(),
+            package::core_properties(spec.title.as_deref()),
+        ),
+        ("word/document.xml".to_string(), document),
+        ("word/styles.xml".to_string(), STYLES.to_string()),
+        ("word/numbering.xml".to_string(), NUMBERING.to_string()),
+        (
+            "word/_rels/document.xml.rels".to_string(),
+            DOCUMENT_RELS.to_string(),
+        ),
+    ];
+    let content = package::write_package(&parts)?;
+
+    Ok((content, json!({ "blocks": spec.blocks.len() })))
+}
+
+/// Runs for `text`, with line breaks kept
+fn runs(text: &str, bold: bool) -> String {
+    let properties = if bold { "<w:rPr><w:b/></w:rPr>" } else { "" };
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| format!(r#"<w:t xml:space="preserve">{}</w:t>"#, escape(line)))
+        .collect();
+    format!("<w:r>{}{}</w:r>", properties, lines.join("<w:br/>"))
+}
+
+fn paragraph(style: Option<&str>, text: &str) -> String {
+    let properties = style
+        .map(|style| format!(r#"<w:pPr><w:pStyle w:val="{}"/></w:pPr>"#, style))
+        .unwrap_or_default();
+    format!("<w:p>{}{}</w:p>", properties, runs(text, false))
+}
+
+fn bullet(text: &str) -> String {
+    format!(
+        r#"<w:p><w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr>{}</w:p>"#,
+        runs(text, false)
+    )
+}
+
+fn table(columns: &[String], rows: &[Vec<Value>]) -> String {
+    let grid = "<w:gridCol/>".repeat(columns.len());
+
+    let header: String = columns
+        .iter()
+        .map(|column| {
+            format!(
+                r#"<w:tc><w:tcPr><w:shd w:val="clear" w:color="auto" w:fill="D9E2F3"/></w:tcPr><w:p>{}</w:p></w:tc>"#,
+                runs(column, true)
+            )
+        })
+        .collect();
+
+    // Rows are padded or cut to the number of columns
+    let body: String = rows
+        .iter()
+        .map(|row| {
+            let cells: String = (0..columns.len())
+                .map(|i| {
+                    let text = row.get(i).map(cell_text).unwrap_or_default();
+                    format!("<w:tc><w:p>{}</w:p></w:tc>", runs(&text, false))
+                })
+                .collect();
+            format!("<w:tr>{}</w:tr>", cells)
+        })
+        .collect();
+
+    // Word expects a paragraph between a table and what follows it
+    format!(
+        r#"<w:tbl><w:tblPr><w:tblStyle w:val="TableGrid"/><w:tblW w:w="5000" w:type="pct"/></w:tblPr><w:tblGrid>{grid}</w:tblGrid><w:tr><w:trPr><w:tblHeader/></w:trPr>{header}</w:tr>{body}</w:tbl><w:p/>"#
+    )
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/word/numbering.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml"/><Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/></Relationships>"#;
+
+const DOCUMENT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering" Target="numbering.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii="Calibri" w:hAnsi="Calibri" w:cs="Calibri"/><w:sz w:val="22"/></w:rPr></w:rPrDefault><w:pPrDefault><w:pPr><w:spacing w:after="120" w:line="264" w:lineRule="auto"/></w:pPr></w:pPrDefault></w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/></w:style><w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:spacing w:after="240"/></w:pPr><w:rPr><w:b/><w:color w:val="1F3864"/><w:sz w:val="48"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="360" w:after="120"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:color w:val="2F5496"/><w:sz w:val="32"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="240" w:after="80"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:color w:val="2F5496"/><w:sz w:val="26"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="200" w:after="60"/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:color w:val="1F3763"/><w:sz w:val="24"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/><w:basedOn w:val="Normal"/><w:pPr><w:spacing w:after="40"/><w:ind w:left="720"/></w:pPr></w:style><w:style w:type="table" w:styleId="TableGrid"><w:name w:val="Table Grid"/><w:tblPr><w:tblBorders><w:top w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:left w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:bottom w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:right w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:insideH w:val="single" w:sz="4" w:space="0" w:color="auto"/><w:insideV w:val="single" w:sz="4" w:space="0" w:color="auto"/></w:tblBorders><w:tblCellMar><w:left w:w="108" w:type="dxa"/><w:right w:w="108" w:type="dxa"/></w:tblCellMar></w:tblPr></w:style></w:styles>"#;
+
+const NUMBERING: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:numbering xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:abstractNum w:abstractNumId="0"><w:multiLevelType w:val="hybridMultilevel"/><w:lvl w:ilvl="0"><w:start w:val="1"/><w:numFmt w:val="bullet"/><w:lvlText w:val="•"/><w:lvlJc w:val="left"/><w:pPr><w:ind w:left="720" w:hanging="360"/></w:pPr></w:lvl></w:abstractNum><w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num></w:numbering>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_document() {
+        let spec: DocumentSpec = serde_json::from_value(json!({
+            "title": "Q3 Sales",
+            "blocks": [
+                { "type": "heading", "text": "Summary" },
+                { "type": "paragraph", "text": "Revenue grew & margins held." },
+                { "type": "bullets", "items": ["July", "August"] },
+                { "type": "table", "columns": ["Month", "Revenue"], "rows": [["July", 1200], ["August"]] }
+            ]
+        }))
+        .unwrap();
+
+        let (docx, output) = create_document(&spec).unwrap();
+        assert_eq!(output["blocks"], 4);
+
+        let document = package::read_part(&docx, "word/document.xml");
+        assert!(document.contains(r#"<w:pStyle w:val="Title"/>"#));
+        assert!(document.contains(r#"<w:pStyle w:val="Heading1"/>"#));
+        assert!(document.contains("Revenue grew &amp; margins held."));
+        assert_eq!(document.matches("<w:numId w:val=\"1\"/>").count(), 2);
+        // The short row is padded to two cells
+        assert_eq!(document.matches("<w:tc>").count(), 6);
+        assert!(package::read_part(&docx, "docProps/core.xml").contains("Q3 Sales"));
+    }
+
+    #[test]
+    fn test_rejects_empty() {
+        let spec = DocumentSpec {
+            title: None,
+            blocks: vec![],
+        };
+        assert!(matches!(
+            create_document(&spec),
+            Err(PackageError::InvalidInput(_))
+        ));
+    }
+}
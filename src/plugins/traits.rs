@@ -7,7 +7,7 @@ use async_trait::async_trait;
 use serde_json::Value;
 use std::any::Any;
 
-use super::manifest::PluginManifest;
+use super::manifest::{ConfigFieldError, PluginManifest};
 use super::{PluginError, ToolDefinition, ToolResult};
 
 /// Plugin state for lifecycle management
@@ -27,6 +27,19 @@ pub enum PluginState {
     ShuttingDown,
 }
 
+impl PluginState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PluginState::Registered => "registered",
+            PluginState::Initializing => "initializing",
+            PluginState::Active => "active",
+            PluginState::Disabled => "disabled",
+            PluginState::Error => "error",
+            PluginState::ShuttingDown => "shutting_down",
+        }
+    }
+}
+
 /// Context provided to plugins during lifecycle events
 pub struct PluginContext {
     /// Plugin configuration from client TOML
@@ -169,27 +182,10 @@ pub trait Plugin: Send + Sync {
 /// Extension trait for easier plugin development
 pub trait PluginExt: Plugin {
     /// Validate configuration against the manifest schema
-    fn validate_config(&self, config: &Value) -> Result<(), Vec<String>> {
-        let manifest = self.manifest();
-        let mut errors = Vec::new();
-
-        for field in &manifest.config_schema {
-            let value = config.get(&field.name);
-
-            // Check required fields
-            if field.required && value.is_none() {
-                errors.push(format!("Missing required field: {}", field.name));
-                continue;
-            }
-
-            // Additional validation could go here
-        }
-
-        if errors.is_empty() {
-            Ok(())
-        } else {
-            Err(errors)
-        }
+    ///
+    /// Returns the configuration with defaults applied, or every problem found.
+    fn validate_config(&self, config: &Value) -> Result<Value, Vec<ConfigFieldError>> {
+        self.manifest().validate_config(config)
     }
 }
 
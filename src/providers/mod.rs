@@ -22,12 +22,19 @@
 
 mod ollama;
 mod openai_compat;
+mod streaming;
 
 use std::env;
+use std::pin::Pin;
+use std::time::Instant;
+
+use futures::{Stream, StreamExt};
+use serde_json::Value;
 use thiserror::Error;
 
 use crate::config::Config;
 use crate::conversation::Message;
+use crate::metrics;
 
 pub use openai_compat::{OpenAICompatConfig, OpenAICompatProvider, ToolDef, FunctionDef};
 
@@ -46,6 +53,26 @@ pub enum ProviderError {
     InvalidResponse(String),
 }
 
+/// Stream of text deltas from a streaming completion
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<String, ProviderError>> + Send>>;
+
+/// Structured output requested from the model
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseFormat {
+    /// Any valid JSON object
+    Json,
+    /// JSON matching a schema
+    JsonSchema { name: String, schema: Value },
+}
+
+/// Per-request generation options
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatOptions {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub response_format: Option<ResponseFormat>,
+}
+
 /// Supported LLM providers
 pub enum Provider {
     /// Local Ollama server
@@ -120,23 +147,99 @@ impl Provider {
         }
     }
 
+    /// Names of the providers set up in the config or the environment
+    ///
+    /// Ollama only counts when its URL is configured.
+    pub fn configured(config: &Config) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if config.ollama_url.is_some() {
+            names.push("ollama");
+        }
+        if config.openai_api_key.is_some() || env::var("OPENAI_API_KEY").is_ok() {
+            names.push("openai");
+        }
+        if env::var("GROQ_API_KEY").is_ok() {
+            names.push("groq");
+        }
+        if env::var("OPENAI_BASE_URL").is_ok() {
+            names.push("local");
+        }
+        names
+    }
+
+    /// Check that the provider is reachable and accepts the credentials
+    pub async fn ping(&self) -> Result<(), ProviderError> {
+        match self {
+            Provider::Ollama(p) => p.ping().await,
+            Provider::OpenAICompat(p) => p.list_models().await.map(drop),
+        }
+    }
+
     /// Send a chat completion request
     pub async fn chat(
         &self,
         messages: &[Message],
         model: &str,
     ) -> Result<Message, ProviderError> {
-        match self {
-            Provider::Ollama(p) => p.chat(messages, model).await,
-            Provider::OpenAICompat(p) => p.chat(messages, model).await,
-        }
+        self.chat_with_options(messages, model, &ChatOptions::default())
+            .await
+    }
+
+    /// Send a chat completion request with generation options
+    pub async fn chat_with_options(
+        &self,
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+    ) -> Result<Message, ProviderError> {
+        let started = Instant::now();
+        let result = match self {
+            Provider::Ollama(p) => p.chat(messages, model, options).await,
+            Provider::OpenAICompat(p) => p.complete(messages, model, None, options).await,
+        };
+
+        metrics::global().observe_llm(self.name(), model, started.elapsed(), result.is_ok());
+        result
+    }
+
+    /// Send a chat completion request and stream the reply as text deltas
+    pub async fn chat_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+    ) -> Result<TokenStream, ProviderError> {
+        let started = Instant::now();
+        let result = match self {
+            Provider::Ollama(p) => p.chat_stream(messages, model, options).await,
+            Provider::OpenAICompat(p) => p.chat_stream(messages, model, options).await,
+        };
+
+        let mut deltas = match result {
+            Ok(deltas) => deltas,
+            Err(e) => {
+                metrics::global().observe_llm(self.name(), model, started.elapsed(), false);
+                return Err(e);
+            }
+        };
+
+        // Timed until the stream ends; an abandoned stream is not recorded
+        let (provider, model) = (self.name(), model.to_string());
+        Ok(Box::pin(async_stream::stream! {
+            let mut ok = true;
+            while let Some(delta) = deltas.next().await {
+                ok &= delta.is_ok();
+                yield delta;
+            }
+            metrics::global().observe_llm(provider, &model, started.elapsed(), ok);
+        }))
     }
 
     /// Get the provider name
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &'static str {
         match self {
-            Provider::Ollama(_) => "ollama",
-            Provider::OpenAICompat(_) => "openai-compatible",
+            Provider::Ollama(_) => ollama::NAME,
+            Provider::OpenAICompat(_) => openai_compat::NAME,
         }
     }
 }
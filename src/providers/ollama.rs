@@ -1,11 +1,18 @@
 //! Ollama provider implementation
 
+use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::conversation::{Message, Role};
+use crate::metrics;
 
-use super::ProviderError;
+use super::streaming;
+use super::{ChatOptions, ProviderError, ResponseFormat, TokenStream};
+
+/// Provider name, e.g. in metrics
+pub(super) const NAME: &str = "ollama";
 
 pub struct OllamaProvider {
     client: Client,
@@ -17,6 +24,19 @@ struct OllamaRequest {
     model: String,
     messages: Vec<OllamaMessage>,
     stream: bool,
+    /// "json" or a JSON schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +48,41 @@ struct OllamaMessage {
 #[derive(Debug, Deserialize)]
 struct OllamaResponse {
     message: OllamaMessage,
+    #[serde(flatten)]
+    usage: OllamaUsage,
+}
+
+/// One line of a streaming response; the last one carries the usage
+#[derive(Debug, Deserialize)]
+struct OllamaChunk {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(flatten)]
+    usage: OllamaUsage,
+}
+
+/// Token counts of a finished request
+#[derive(Debug, Default, Deserialize)]
+struct OllamaUsage {
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+}
+
+impl OllamaUsage {
+    fn record(&self, model: &str) {
+        if self.prompt_eval_count.is_some() || self.eval_count.is_some() {
+            metrics::global().record_tokens(
+                NAME,
+                model,
+                self.prompt_eval_count.unwrap_or(0),
+                self.eval_count.unwrap_or(0),
+            );
+        }
+    }
 }
 
 impl OllamaProvider {
@@ -38,8 +93,100 @@ impl OllamaProvider {
         }
     }
 
-    pub async fn chat(&self, messages: &[Message], model: &str) -> Result<Message, ProviderError> {
-        let ollama_messages: Vec<OllamaMessage> = messages
+    pub async fn chat(
+        &self,
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+    ) -> Result<Message, ProviderError> {
+        let request = Self::request(messages, model, options, false);
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+        let response = streaming::check_status(response).await?;
+
+        let ollama_response: OllamaResponse = response.json().await?;
+        ollama_response.usage.record(model);
+
+        Ok(Message {
+            role: Role::Assistant,
+            content: ollama_response.message.content,
+        })
+    }
+
+    /// Check that the server answers, by listing its models
+    pub async fn ping(&self) -> Result<(), ProviderError> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await?;
+        streaming::check_status(response).await?;
+        Ok(())
+    }
+
+    /// Stream a chat completion as text deltas
+    pub async fn chat_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+    ) -> Result<TokenStream, ProviderError> {
+        let request = Self::request(messages, model, options, true);
+
+        let response = self
+            .client
+            .post(format!("{}/api/chat", self.base_url))
+            .json(&request)
+            .send()
+            .await?;
+        let response = streaming::check_status(response).await?;
+
+        let model = model.to_string();
+        let deltas = streaming::lines(response).filter_map(move |line| {
+            futures::future::ready(match line {
+                Ok(line) => Self::delta(&line, &model),
+                Err(e) => Some(Err(e)),
+            })
+        });
+
+        Ok(Box::pin(deltas))
+    }
+
+    /// Parse one line of a stream into a text delta, if it has one
+    fn delta(line: &str, model: &str) -> Option<Result<String, ProviderError>> {
+        let chunk = serde_json::from_str::<OllamaChunk>(line);
+        if let Ok(chunk) = &chunk {
+            chunk.usage.record(model);
+        }
+
+        match chunk {
+            Ok(OllamaChunk {
+                error: Some(error), ..
+            }) => Some(Err(ProviderError::InvalidResponse(error))),
+            Ok(OllamaChunk {
+                message: Some(message),
+                ..
+            }) if !message.content.is_empty() => Some(Ok(message.content)),
+            Ok(_) => None,
+            Err(e) => Some(Err(ProviderError::InvalidResponse(format!(
+                "Bad stream chunk: {}",
+                e
+            )))),
+        }
+    }
+
+    fn request(
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+        stream: bool,
+    ) -> OllamaRequest {
+        let messages = messages
             .iter()
             .map(|m| OllamaMessage {
                 role: match m.role {
@@ -51,33 +198,114 @@ impl OllamaProvider {
             })
             .collect();
 
-        let request = OllamaRequest {
+        let format = options.response_format.as_ref().map(|format| match format {
+            ResponseFormat::Json => Value::String("json".to_string()),
+            ResponseFormat::JsonSchema { schema, .. } => schema.clone(),
+        });
+
+        let generation = (options.temperature.is_some() || options.max_tokens.is_some()).then_some(
+            OllamaOptions {
+                temperature: options.temperature,
+                num_predict: options.max_tokens,
+            },
+        );
+
+        OllamaRequest {
             model: model.to_string(),
-            messages: ollama_messages,
-            stream: false,
+            messages,
+            stream,
+            format,
+            options: generation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+    use serde_json::json;
+
+    /// Serve `body` for every `/api/chat` request and return the base URL
+    async fn fake_ollama(body: &'static str) -> String {
+        let app = Router::new().route("/api/chat", post(move || async move { body }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    fn user(content: &str) -> Vec<Message> {
+        vec![Message {
+            role: Role::User,
+            content: content.to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_request_options() {
+        let options = ChatOptions {
+            temperature: Some(0.5),
+            max_tokens: None,
+            response_format: Some(ResponseFormat::Json),
         };
+        let request = OllamaProvider::request(&user("hi"), "llama3.2", &options, true);
 
-        let response = self
-            .client
-            .post(format!("{}/api/chat", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "model": "llama3.2",
+                "messages": [{"role": "user", "content": "hi"}],
+                "stream": true,
+                "format": "json",
+                "options": {"temperature": 0.5},
+            })
+        );
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(ProviderError::InvalidResponse(format!(
-                "{}: {}",
-                status, body
-            )));
-        }
+    #[tokio::test]
+    async fn test_chat_stream() {
+        let url = fake_ollama(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n\
+             {\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":false}\n\
+             {\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true}\n",
+        )
+        .await;
 
-        let ollama_response: OllamaResponse = response.json().await?;
+        let provider = OllamaProvider::new(url);
+        let deltas: Vec<String> = provider
+            .chat_stream(&user("hi"), "llama3.2", &ChatOptions::default())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
 
-        Ok(Message {
-            role: Role::Assistant,
-            content: ollama_response.message.content,
-        })
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+    }
+
+    #[tokio::test]
+    async fn test_chat() {
+        let app = Router::new().route(
+            "/api/chat",
+            post(|Json(request): Json<Value>| async move {
+                Json(json!({
+                    "message": {"role": "assistant", "content": format!("format={}", request["format"])},
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let options = ChatOptions {
+            response_format: Some(ResponseFormat::Json),
+            ..ChatOptions::default()
+        };
+        let reply = OllamaProvider::new(url)
+            .chat(&user("hi"), "llama3.2", &options)
+            .await
+            .unwrap();
+        assert_eq!(reply.content, "format=\"json\"");
     }
 }
@@ -20,13 +20,16 @@
 //! model = "gpt-4o-mini"
 //! ```
 
+use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
 use crate::conversation::{Message, Role};
+use crate::metrics;
 
-use super::ProviderError;
+use super::streaming;
+use super::{ChatOptions, ProviderError, ResponseFormat, TokenStream};
 
 /// OpenAI-compatible chat message
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +63,7 @@ pub struct ToolDef {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDef {
     pub name: String,
+    #[serde(default)]
     pub description: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parameters: Option<Value>,
@@ -78,10 +82,37 @@ struct ChatCompletionRequest {
     tools: Option<Vec<ToolDef>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<Value>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+/// One server-sent event of a streaming completion
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    /// Sent in the last chunk by servers that report streaming usage
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunkChoice {
+    #[serde(default)]
+    delta: ChunkDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
 }
 
 /// Chat completion response
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 struct ChatCompletionResponse {
     choices: Vec<Choice>,
     #[serde(default)]
@@ -89,6 +120,7 @@ struct ChatCompletionResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 struct Choice {
     message: ResponseMessage,
     #[serde(default)]
@@ -96,6 +128,7 @@ struct Choice {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 struct ResponseMessage {
     role: String,
     #[serde(default)]
@@ -105,6 +138,7 @@ struct ResponseMessage {
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 pub struct ToolCallResponse {
     pub id: String,
     #[serde(rename = "type")]
@@ -119,12 +153,24 @@ pub struct FunctionCallResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
     total_tokens: u32,
 }
 
+impl Usage {
+    fn record(&self, model: &str) {
+        metrics::global().record_tokens(
+            NAME,
+            model,
+            self.prompt_tokens.into(),
+            self.completion_tokens.into(),
+        );
+    }
+}
+
 /// Error response from API
 #[derive(Debug, Deserialize)]
 struct ErrorResponse {
@@ -132,12 +178,16 @@ struct ErrorResponse {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // Deserialized for completeness
 struct ApiError {
     message: String,
     #[serde(rename = "type")]
     error_type: Option<String>,
 }
 
+/// Provider name, e.g. in metrics
+pub(super) const NAME: &str = "openai-compatible";
+
 /// OpenAI-compatible provider configuration
 #[derive(Debug, Clone)]
 pub struct OpenAICompatConfig {
@@ -244,40 +294,20 @@ impl OpenAICompatProvider {
         model: &str,
         tools: Option<Vec<ToolDef>>,
     ) -> Result<Message, ProviderError> {
-        let url = format!("{}/chat/completions", self.config.base_url);
-
-        let chat_messages: Vec<ChatMessage> = messages.iter().map(ChatMessage::from).collect();
-
-        let request = ChatCompletionRequest {
-            model: if model.is_empty() {
-                self.config.default_model.clone()
-            } else {
-                model.to_string()
-            },
-            messages: chat_messages,
-            temperature: Some(0.7),
-            max_tokens: Some(4096),
-            tools,
-            tool_choice: None,
-        };
-
-        let mut req_builder = self.client.post(&url);
-
-        // Add authorization if API key is provided
-        if let Some(ref api_key) = self.config.api_key {
-            req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
-        }
-
-        // Add organization header if provided (OpenAI specific)
-        if let Some(ref org) = self.config.organization {
-            req_builder = req_builder.header("OpenAI-Organization", org);
-        }
+        self.complete(messages, model, tools, &ChatOptions::default())
+            .await
+    }
 
-        let response = req_builder
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    /// Send a chat completion request with tools and generation options
+    pub async fn complete(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<Vec<ToolDef>>,
+        options: &ChatOptions,
+    ) -> Result<Message, ProviderError> {
+        let request = self.request(messages, model, tools, options, false);
+        let response = self.post(&request).await?;
 
         let status = response.status();
         let body = response.text().await?;
@@ -299,6 +329,9 @@ impl OpenAICompatProvider {
         let completion: ChatCompletionResponse = serde_json::from_str(&body).map_err(|e| {
             ProviderError::InvalidResponse(format!("Failed to parse response: {} - Body: {}", e, body))
         })?;
+        if let Some(usage) = &completion.usage {
+            usage.record(model);
+        }
 
         let choice = completion
             .choices
@@ -335,7 +368,116 @@ impl OpenAICompatProvider {
         })
     }
 
+    /// Send a chat completion request and stream the reply as text deltas
+    pub async fn chat_stream(
+        &self,
+        messages: &[Message],
+        model: &str,
+        options: &ChatOptions,
+    ) -> Result<TokenStream, ProviderError> {
+        let request = self.request(messages, model, None, options, true);
+        let response = streaming::check_status(self.post(&request).await?).await?;
+
+        let model = model.to_string();
+        let deltas = async_stream::try_stream! {
+            let lines = streaming::lines(response);
+            futures::pin_mut!(lines);
+
+            while let Some(line) = lines.next().await {
+                let line = line?;
+                // Comments and other SSE fields (event:, id:) carry no content
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    break;
+                }
+
+                if let Ok(error) = serde_json::from_str::<ErrorResponse>(data) {
+                    Err(ProviderError::InvalidResponse(format!(
+                        "API error: {}",
+                        error.error.message
+                    )))?;
+                }
+
+                let chunk: ChatCompletionChunk = serde_json::from_str(data).map_err(|e| {
+                    ProviderError::InvalidResponse(format!("Bad stream chunk: {}", e))
+                })?;
+                if let Some(usage) = &chunk.usage {
+                    usage.record(&model);
+                }
+                for choice in chunk.choices {
+                    if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                        yield content;
+                    }
+                }
+            }
+        };
+
+        Ok(Box::pin(deltas))
+    }
+
+    fn request(
+        &self,
+        messages: &[Message],
+        model: &str,
+        tools: Option<Vec<ToolDef>>,
+        options: &ChatOptions,
+        stream: bool,
+    ) -> ChatCompletionRequest {
+        let response_format = options.response_format.as_ref().map(|format| match format {
+            ResponseFormat::Json => json!({"type": "json_object"}),
+            ResponseFormat::JsonSchema { name, schema } => json!({
+                "type": "json_schema",
+                "json_schema": {"name": name, "schema": schema},
+            }),
+        });
+
+        ChatCompletionRequest {
+            model: if model.is_empty() {
+                self.config.default_model.clone()
+            } else {
+                model.to_string()
+            },
+            messages: messages.iter().map(ChatMessage::from).collect(),
+            temperature: Some(options.temperature.unwrap_or(0.7)),
+            max_tokens: Some(options.max_tokens.unwrap_or(4096)),
+            tools,
+            tool_choice: None,
+            response_format,
+            stream,
+        }
+    }
+
+    async fn post(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<reqwest::Response, ProviderError> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+
+        let mut req_builder = self.client.post(&url);
+
+        // Add authorization if API key is provided
+        if let Some(ref api_key) = self.config.api_key {
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        // Add organization header if provided (OpenAI specific)
+        if let Some(ref org) = self.config.organization {
+            req_builder = req_builder.header("OpenAI-Organization", org);
+        }
+
+        Ok(req_builder
+            .header("Content-Type", "application/json")
+            .json(request)
+            .send()
+            .await?)
+    }
+
     /// List available models (if supported by the API)
+    ///
+    /// Fails if the API key is rejected.
     pub async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
         let url = format!("{}/models", self.config.base_url);
 
@@ -347,7 +489,12 @@ impl OpenAICompatProvider {
 
         let response = req_builder.send().await?;
 
-        if !response.status().is_success() {
+        // Rejected credentials are an error, unlike missing model listing
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+            return streaming::check_status(response).await.map(|_| Vec::new());
+        }
+        if !status.is_success() {
             return Ok(vec![]); // Some servers don't support model listing
         }
 
@@ -394,4 +541,65 @@ mod tests {
         assert_eq!(chat_msg.role, "user");
         assert_eq!(chat_msg.content, "Hello");
     }
+
+    #[test]
+    fn test_request_options() {
+        let provider = OpenAICompatProvider::local("http://localhost:8000/v1", "llama-3");
+        let messages = [Message {
+            role: Role::User,
+            content: "Hello".to_string(),
+        }];
+
+        let defaults = provider.request(&messages, "", None, &ChatOptions::default(), false);
+        let defaults = serde_json::to_value(&defaults).unwrap();
+        assert_eq!(defaults["model"], "llama-3");
+        assert_eq!(defaults["max_tokens"], 4096);
+        assert!(defaults.get("stream").is_none());
+        assert!(defaults.get("response_format").is_none());
+
+        let options = ChatOptions {
+            temperature: Some(0.0),
+            max_tokens: Some(100),
+            response_format: Some(ResponseFormat::JsonSchema {
+                name: "answer".to_string(),
+                schema: json!({"type": "object"}),
+            }),
+        };
+        let request = provider.request(&messages, "other", None, &options, true);
+        let request = serde_json::to_value(&request).unwrap();
+        assert_eq!(request["temperature"], 0.0);
+        assert_eq!(request["max_tokens"], 100);
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["response_format"]["type"], "json_schema");
+        assert_eq!(request["response_format"]["json_schema"]["name"], "answer");
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream() {
+        use axum::{routing::post, Router};
+
+        let body = "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+                    : keep-alive\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n\
+                    data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+                    data: [DONE]\n\n";
+        let app = Router::new().route("/v1/chat/completions", post(move || async move { body }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let messages = [Message {
+            role: Role::User,
+            content: "Hello".to_string(),
+        }];
+        let deltas: Vec<String> = OpenAICompatProvider::local(url, "llama-3")
+            .chat_stream(&messages, "", &ChatOptions::default())
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+    }
 }
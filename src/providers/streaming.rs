@@ -0,0 +1,51 @@
+//! Helpers for streaming provider responses
+
+use futures::StreamExt;
+
+use super::ProviderError;
+
+/// Split a response body into lines, as they arrive
+///
+/// Ollama streams newline-delimited JSON and OpenAI-compatible servers stream
+/// server-sent events; both are line-oriented. Empty lines are skipped.
+pub(super) fn lines(
+    response: reqwest::Response,
+) -> impl futures::Stream<Item = Result<String, ProviderError>> + Send {
+    async_stream::try_stream! {
+        let mut body = response.bytes_stream();
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = body.next().await {
+            buffer.extend_from_slice(&chunk?);
+
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if !line.is_empty() {
+                    yield line;
+                }
+            }
+        }
+
+        let rest = String::from_utf8_lossy(&buffer).trim().to_string();
+        if !rest.is_empty() {
+            yield rest;
+        }
+    }
+}
+
+/// Turn an error status into a `ProviderError` with the response body
+pub(super) async fn check_status(
+    response: reqwest::Response,
+) -> Result<reqwest::Response, ProviderError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(ProviderError::InvalidResponse(format!(
+        "{}: {}",
+        status, body
+    )))
+}
@@ -0,0 +1,345 @@
+//! Token-bucket rate limiting
+//!
+//! Chat requests are limited per API key, per client IP, per user and per
+//! conversation; tool executions have their own per-caller limit, enforced
+//! by the chat engine. Limits come from `[security.rate_limits]` in the
+//! client config.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::auth::Principal;
+use crate::config::client::{RateLimit, RateLimitConfig};
+use crate::routes::ApiError;
+use crate::AppState;
+
+/// Buckets kept before idle (full) ones are dropped
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// A request was rejected by a rate limit
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Rate limit exceeded ({scope}); retry in {}s", self.retry_after_secs())]
+pub struct RateLimited {
+    /// Which limit was hit ("key", "ip", "user", "conversation" or "tools")
+    pub scope: &'static str,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// Whole seconds to wait, as sent in `Retry-After`
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl IntoResponse for RateLimited {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// A single token bucket
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets for one limit, keyed by caller
+#[derive(Debug)]
+pub struct KeyedLimiter {
+    scope: &'static str,
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl KeyedLimiter {
+    /// Create a limiter, or `None` if the limit is disabled
+    pub fn new(scope: &'static str, limit: RateLimit) -> Option<Self> {
+        if limit.is_disabled() {
+            return None;
+        }
+
+        Some(Self {
+            scope,
+            capacity: f64::from(limit.burst.max(1)),
+            refill_per_sec: f64::from(limit.requests_per_minute) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Take a token for `key`
+    pub fn check(&self, key: &str) -> Result<(), RateLimited> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), RateLimited> {
+        check_all(&[(self, key)], now)
+    }
+
+    /// The refilled bucket of `key`
+    fn bucket<'a>(
+        &self,
+        buckets: &'a mut HashMap<String, TokenBucket>,
+        key: &str,
+        now: Instant,
+    ) -> &'a mut TokenBucket {
+        if buckets.len() >= PRUNE_THRESHOLD {
+            let (capacity, rate) = (self.capacity, self.refill_per_sec);
+            buckets.retain(|_, b| b.tokens + elapsed(b.updated, now) * rate < capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        bucket.tokens =
+            (bucket.tokens + elapsed(bucket.updated, now) * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+        bucket
+    }
+}
+
+/// Take a token from each limiter's bucket, or from none if one is empty
+///
+/// Callers list limiters in a fixed order (key, IP, user), in which their
+/// locks are held together, so a request limited by one bucket doesn't
+/// spend the others.
+fn check_all(limits: &[(&KeyedLimiter, &str)], now: Instant) -> Result<(), RateLimited> {
+    let mut locked: Vec<_> = limits
+        .iter()
+        .map(|&(limiter, key)| (limiter, key, limiter.buckets.lock().unwrap()))
+        .collect();
+
+    for (limiter, key, buckets) in &mut locked {
+        let bucket = limiter.bucket(buckets, key, now);
+        if bucket.tokens < 1.0 {
+            let missing = 1.0 - bucket.tokens;
+            return Err(RateLimited {
+                scope: limiter.scope,
+                retry_after: Duration::from_secs_f64(missing / limiter.refill_per_sec),
+            });
+        }
+    }
+    for (_, key, buckets) in &mut locked {
+        if let Some(bucket) = buckets.get_mut(*key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+    Ok(())
+}
+
+fn elapsed(since: Instant, now: Instant) -> f64 {
+    now.saturating_duration_since(since).as_secs_f64()
+}
+
+/// All rate limits for a deployment
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    pub per_key: Option<KeyedLimiter>,
+    pub per_ip: Option<KeyedLimiter>,
+    pub per_user: Option<KeyedLimiter>,
+    pub per_conversation: Option<KeyedLimiter>,
+
+    /// Shared with the chat engine, which checks it before each tool call
+    pub tool_executions: Option<Arc<KeyedLimiter>>,
+
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        if !config.enabled {
+            return Self::default();
+        }
+
+        Self {
+            per_key: KeyedLimiter::new("key", config.per_key),
+            per_ip: KeyedLimiter::new("ip", config.per_ip),
+            per_user: KeyedLimiter::new("user", config.per_user),
+            per_conversation: KeyedLimiter::new("conversation", config.per_conversation),
+            tool_executions: KeyedLimiter::new("tools", config.tool_executions).map(Arc::new),
+            trust_forwarded_for: config.trust_forwarded_for,
+        }
+    }
+
+    /// Limit a chat request by API key, client IP and user
+    pub fn check_request(
+        &self,
+        principal: Option<&Principal>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), RateLimited> {
+        let ip = ip.map(|ip| ip.to_string());
+        let limits: Vec<(&KeyedLimiter, &str)> = [
+            (&self.per_key, principal.and_then(Principal::key_id)),
+            (&self.per_ip, ip.as_deref()),
+            (&self.per_user, principal.and_then(|p| p.user_id.as_deref())),
+        ]
+        .into_iter()
+        .filter_map(|(limiter, key)| Some((limiter.as_ref()?, key?)))
+        .collect();
+
+        check_all(&limits, Instant::now())
+    }
+
+    /// Limit messages to an existing conversation
+    pub fn check_conversation(&self, conversation_id: &str) -> Result<(), RateLimited> {
+        match &self.per_conversation {
+            Some(limiter) => limiter.check(conversation_id),
+            None => Ok(()),
+        }
+    }
+
+    /// The client IP: the socket address, or `X-Forwarded-For` if trusted
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok());
+
+        forwarded.or(peer.map(|addr| addr.ip()))
+    }
+}
+
+/// Identity used for the tool execution limit: the key, else the IP
+pub fn caller_id(principal: Option<&Principal>, ip: Option<IpAddr>) -> Option<String> {
+    match (principal.and_then(Principal::key_id), ip) {
+        (Some(key_id), _) => Some(format!("key:{}", key_id)),
+        (None, Some(ip)) => Some(format!("ip:{}", ip)),
+        (None, None) => None,
+    }
+}
+
+/// Client IP of a request, as seen by the rate limiter
+pub fn request_ip(state: &AppState, request: &Request) -> Option<IpAddr> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    state.limits.client_ip(request.headers(), peer)
+}
+
+/// Middleware limiting requests per API key, IP and user
+///
+/// Runs after authentication so the key is known.
+pub async fn limit_requests(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, RateLimited> {
+    let ip = request_ip(&state, &request);
+    state
+        .limits
+        .check_request(request.extensions().get::<Principal>(), ip)?;
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let limiter = KeyedLimiter::new("key", RateLimit::new(60, 2)).unwrap();
+        let start = Instant::now();
+
+        assert!(limiter.check_at("a", start).is_ok());
+        assert!(limiter.check_at("a", start).is_ok());
+
+        let limited = limiter.check_at("a", start).unwrap_err();
+        assert_eq!(limited.scope, "key");
+        assert_eq!(limited.retry_after, Duration::from_secs(1));
+
+        // Other callers have their own bucket
+        assert!(limiter.check_at("b", start).is_ok());
+
+        // One token per second at 60/min
+        assert!(limiter
+            .check_at("a", start + Duration::from_secs(1))
+            .is_ok());
+        assert!(limiter
+            .check_at("a", start + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn test_limited_requests_spend_no_tokens() {
+        let per_key = KeyedLimiter::new("key", RateLimit::new(60, 2)).unwrap();
+        let per_ip = KeyedLimiter::new("ip", RateLimit::new(60, 1)).unwrap();
+        let now = Instant::now();
+
+        assert!(check_all(&[(&per_key, "k"), (&per_ip, "a")], now).is_ok());
+        // Turned away by the IP limit, so the key keeps its last token
+        let limited = check_all(&[(&per_key, "k"), (&per_ip, "a")], now).unwrap_err();
+        assert_eq!(limited.scope, "ip");
+        assert!(check_all(&[(&per_key, "k"), (&per_ip, "b")], now).is_ok());
+
+        let limited = check_all(&[(&per_key, "k"), (&per_ip, "c")], now).unwrap_err();
+        assert_eq!(limited.scope, "key");
+        assert!(per_ip.check_at("c", now).is_ok());
+    }
+
+    #[test]
+    fn test_disabled_limits() {
+        assert!(KeyedLimiter::new("key", RateLimit::new(0, 10)).is_none());
+
+        let limiter = RateLimiter::from_config(&RateLimitConfig {
+            enabled: false,
+            ..RateLimitConfig::default()
+        });
+        assert!(limiter.per_ip.is_none());
+        assert!(limiter.tool_executions.is_none());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let peer: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7, 10.0.0.1".parse().unwrap());
+
+        let direct = RateLimiter::from_config(&RateLimitConfig::default());
+        assert_eq!(
+            direct.client_ip(&headers, Some(peer)),
+            Some(peer.ip()),
+            "X-Forwarded-For must be ignored unless trusted"
+        );
+
+        let proxied = RateLimiter::from_config(&RateLimitConfig {
+            trust_forwarded_for: true,
+            ..RateLimitConfig::default()
+        });
+        assert_eq!(
+            proxied.client_ip(&headers, Some(peer)),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_response_has_retry_after() {
+        use axum::http::{header, StatusCode};
+
+        let response = RateLimited {
+            scope: "ip",
+            retry_after: Duration::from_millis(1500),
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    }
+}
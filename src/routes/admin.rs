@@ -0,0 +1,95 @@
+//! Admin endpoints for API key management and the audit log
+//!
+//! All routes here require the `admin` scope (applied in `routes::router`).
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::{delete, get},
+    Router,
+};
+use serde::Serialize;
+
+use super::{ApiError, ApiJson, ApiQuery};
+use crate::audit::{AuditEntry, AuditQuery};
+use crate::auth::{ApiKey, NewApiKey};
+use crate::AppState;
+
+/// A newly created key; the secret is only ever returned here
+#[derive(Debug, Serialize)]
+pub struct CreatedKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeysResponse {
+    pub keys: Vec<ApiKey>,
+}
+
+async fn list_keys(State(state): State<AppState>) -> Result<Json<KeysResponse>, ApiError> {
+    Ok(Json(KeysResponse {
+        keys: state.keys.list().await?,
+    }))
+}
+
+async fn create_key(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<NewApiKey>,
+) -> Result<(StatusCode, Json<CreatedKeyResponse>), ApiError> {
+    let (key, secret) = state.keys.create(request).await?;
+    tracing::info!("Created API key {} ({})", key.id, key.name);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedKeyResponse { key, secret }),
+    ))
+}
+
+async fn get_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKey>, ApiError> {
+    Ok(Json(state.keys.get(&id).await?))
+}
+
+async fn revoke_key(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiKey>, ApiError> {
+    let key = state.keys.revoke(&id).await?;
+    tracing::info!("Revoked API key {} ({})", key.id, key.name);
+
+    Ok(Json(key))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    pub entries: Vec<AuditEntry>,
+}
+
+/// `GET /v2/admin/audit`: tool calls from the audit log, newest first
+async fn query_audit(
+    State(state): State<AppState>,
+    ApiQuery(query): ApiQuery<AuditQuery>,
+) -> Result<Json<AuditResponse>, ApiError> {
+    let audit_log = state.chat_engine.audit_log().ok_or_else(|| {
+        ApiError::not_found(
+            "audit_log_disabled",
+            "No audit log is configured (set security.log_tool_calls or audit_log_path)",
+        )
+    })?;
+
+    Ok(Json(AuditResponse {
+        entries: audit_log.query(&query).await?,
+    }))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/v2/admin/keys", get(list_keys).post(create_key))
+        .route("/v2/admin/keys/:id", delete(revoke_key).get(get_key))
+        .route("/v2/admin/audit", get(query_audit))
+}
@@ -0,0 +1,72 @@
+//! Downloads of files that tools produced
+//!
+//! Artifacts follow the ownership rules of the conversation they were made
+//! in, so other users' files are not found.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+
+use super::ApiError;
+use crate::auth::Principal;
+use crate::AppState;
+
+/// `GET /v2/artifacts/:id` - the file, as an attachment
+async fn download_artifact(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let not_found =
+        || ApiError::not_found("artifact_not_found", format!("Artifact '{}' not found", id));
+
+    let Some(store) = state.chat_engine.artifacts() else {
+        return Err(not_found());
+    };
+    let artifact = store
+        .get(&id, principal.owner())
+        .await
+        .map_err(|e| ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e))?
+        .ok_or_else(not_found)?;
+
+    // The file may have been moved or deleted since the tool wrote it
+    let body = tokio::fs::read(&artifact.path)
+        .await
+        .map_err(|_| not_found())?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, artifact.info.mime_type),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}\"",
+                    header_safe(&artifact.info.name)
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// A file name that can go in a quoted header value
+fn header_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ' ') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/v2/artifacts/:id", get(download_artifact))
+}
@@ -0,0 +1,92 @@
+//! The caller's stored conversations
+//!
+//! Listing needs a user (a signed user token, or `X-Moxie-User` from a
+//! secret key); single conversations follow the same ownership rules as
+//! `/v2/chat`, so other users' conversations are not found.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+    routing::get,
+    Extension, Router,
+};
+use serde::Serialize;
+
+use super::error::conversation_not_found;
+use super::ApiError;
+use crate::auth::Principal;
+use crate::core::{ConversationSummary, StoredMessage};
+use crate::AppState;
+
+#[derive(Debug, Serialize)]
+pub struct ConversationsResponse {
+    pub conversations: Vec<ConversationSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationResponse {
+    pub id: String,
+    pub messages: Vec<StoredMessage>,
+}
+
+/// `GET /v2/conversations` - the user's conversations, most recent first
+async fn list_conversations(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<ConversationsResponse>, ApiError> {
+    if principal.user_id.is_none() {
+        return Err(ApiError::bad_request(
+            "user_required",
+            "Listing conversations needs a user; send X-Moxie-User-Token",
+        ));
+    }
+
+    let conversations = state
+        .chat_engine
+        .memory()
+        .list_conversations(principal.owner())
+        .await?;
+    Ok(Json(ConversationsResponse { conversations }))
+}
+
+/// `GET /v2/conversations/:id` - the messages of a conversation
+async fn get_conversation(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> Result<Json<ConversationResponse>, ApiError> {
+    let messages = state
+        .chat_engine
+        .memory()
+        .get_messages(&id, principal.owner())
+        .await?;
+    if messages.is_empty() {
+        return Err(conversation_not_found(&id));
+    }
+
+    Ok(Json(ConversationResponse { id, messages }))
+}
+
+/// `DELETE /v2/conversations/:id`
+async fn delete_conversation(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let memory = state.chat_engine.memory();
+    if !memory.delete_conversation(&id, principal.owner()).await? {
+        return Err(conversation_not_found(&id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/v2/conversations", get(list_conversations))
+        .route(
+            "/v2/conversations/:id",
+            get(get_conversation).delete(delete_conversation),
+        )
+}
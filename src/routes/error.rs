@@ -0,0 +1,489 @@
+//! API error responses
+//!
+//! Every failure is returned as JSON:
+//!
+//! ```json
+//! {"code": "unknown_provider", "message": "Unknown provider: foo", "details": null, "request_id": "..."}
+//! ```
+//!
+//! `code` is stable and machine-readable; `message` is for humans. Server-side
+//! failures (5xx) get a generic message, and the underlying error is logged
+//! with the request ID so it can be found from a client report.
+
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Query, Request,
+    },
+    http::{header, request::Parts, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+
+use crate::audit::AuditError;
+use crate::auth::{AuthError, KeyError};
+use crate::config::ConfigError;
+use crate::core::{ChatError, MemoryError};
+use crate::metrics;
+use crate::plugins::PluginError;
+use crate::providers::ProviderError;
+use crate::rate_limit::RateLimited;
+
+/// Header carrying the request ID (accepted from clients, always returned)
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request being handled, if inside [`request_id`]
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Run `future` with the current request ID, e.g. in a spawned task
+pub fn in_current_request<F: std::future::Future>(
+    future: F,
+) -> impl std::future::Future<Output = F::Output> {
+    REQUEST_ID.scope(current_request_id().unwrap_or_default(), future)
+}
+
+/// An error returned from a handler or middleware
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<Value>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// JSON body of an error response
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    message: &'a str,
+    details: &'a Option<Value>,
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    /// A server-side failure: the cause is logged, not returned
+    pub fn internal(status: StatusCode, code: &'static str, cause: impl std::fmt::Display) -> Self {
+        metrics::global().record_error(code);
+        tracing::error!(
+            request_id = current_request_id().as_deref().unwrap_or("-"),
+            code,
+            "{}",
+            cause
+        );
+
+        let message = match code {
+            "provider_unavailable" => "The LLM provider could not be reached",
+            "provider_timeout" => "The LLM provider did not respond in time",
+            "provider_error" => "The LLM provider returned an error",
+            "storage_error" => "Conversation storage failed",
+            "plugin_error" => "A plugin failed",
+            _ => "Internal server error",
+        };
+        Self::new(status, code, message)
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    /// The JSON body, for errors sent where a response can't be (mid-stream)
+    pub fn to_json(&self) -> Value {
+        json!(ErrorBody {
+            code: self.code,
+            message: &self.message,
+            details: &self.details,
+            request_id: current_request_id(),
+        })
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.code, self.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut response = (self.status, Json(self.to_json())).into_response();
+        response.headers_mut().extend(self.headers);
+        response
+    }
+}
+
+impl From<ProviderError> for ApiError {
+    fn from(error: ProviderError) -> Self {
+        match &error {
+            ProviderError::UnknownProvider(_) => {
+                ApiError::bad_request("unknown_provider", error.to_string())
+            }
+            ProviderError::NotConfigured(_) => {
+                ApiError::bad_request("provider_not_configured", error.to_string())
+            }
+            ProviderError::RequestFailed(e) if e.is_timeout() => {
+                ApiError::internal(StatusCode::GATEWAY_TIMEOUT, "provider_timeout", &error)
+            }
+            ProviderError::RequestFailed(e) if e.is_connect() => {
+                ApiError::internal(StatusCode::BAD_GATEWAY, "provider_unavailable", &error)
+            }
+            ProviderError::RequestFailed(e) => {
+                let status = e.status().map(|s| s.as_u16());
+                ApiError::internal(StatusCode::BAD_GATEWAY, "provider_error", &error)
+                    .with_details(json!({ "upstream_status": status }))
+            }
+            ProviderError::InvalidResponse(_) => {
+                ApiError::internal(StatusCode::BAD_GATEWAY, "provider_error", &error)
+            }
+        }
+    }
+}
+
+impl From<PluginError> for ApiError {
+    fn from(error: PluginError) -> Self {
+        match &error {
+            PluginError::ToolNotFound(_) => {
+                ApiError::not_found("tool_not_found", error.to_string())
+            }
+            PluginError::PluginNotFound(_) => {
+                ApiError::not_found("plugin_not_found", error.to_string())
+            }
+            PluginError::InvalidParameters(_) => {
+                ApiError::bad_request("invalid_parameters", error.to_string())
+            }
+            PluginError::PluginDisabled(_) => {
+                ApiError::new(StatusCode::CONFLICT, "plugin_disabled", error.to_string())
+            }
+            PluginError::Cancelled(_) => {
+                ApiError::new(StatusCode::CONFLICT, "cancelled", error.to_string())
+            }
+            PluginError::ExecutionFailed(_)
+            | PluginError::IoError(_)
+            | PluginError::JsonError(_)
+            | PluginError::InitFailed(_)
+            | PluginError::ConfigError(_) => {
+                ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "plugin_error", &error)
+            }
+        }
+    }
+}
+
+impl From<ChatError> for ApiError {
+    fn from(error: ChatError) -> Self {
+        match error {
+            ChatError::Provider(e) => e.into(),
+            ChatError::Plugin(e) => e.into(),
+            ChatError::RateLimited(e) => e.into(),
+            ChatError::Memory(e) => {
+                ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e)
+            }
+            ChatError::ConversationNotFound(id) => conversation_not_found(&id),
+            ChatError::Cancelled => ApiError::new(
+                StatusCode::CONFLICT,
+                "cancelled",
+                "The reply was cancelled",
+            ),
+            ChatError::ShuttingDown => ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "shutting_down",
+                "The server is shutting down; retry shortly",
+            ),
+            ChatError::MaxIterationsExceeded => ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                "max_tool_iterations",
+                "The model kept calling tools without producing an answer",
+            ),
+        }
+    }
+}
+
+impl From<ConfigError> for ApiError {
+    fn from(error: ConfigError) -> Self {
+        match error {
+            ConfigError::Validation(issues) => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_config",
+                format!("Configuration has {} problem(s)", issues.len()),
+            )
+            .with_details(json!({
+                "issues": issues
+                    .iter()
+                    .map(|i| json!({ "path": i.path, "message": i.message }))
+                    .collect::<Vec<_>>(),
+            })),
+            ConfigError::Toml(e) => ApiError::bad_request("invalid_config", e.to_string()),
+            ConfigError::Io(e) => {
+                ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "config_error", e)
+            }
+        }
+    }
+}
+
+impl From<KeyError> for ApiError {
+    fn from(error: KeyError) -> Self {
+        match &error {
+            KeyError::NotFound(_) => ApiError::not_found("key_not_found", error.to_string()),
+            KeyError::Invalid(_) => ApiError::bad_request("invalid_key_request", error.to_string()),
+            KeyError::Database(_) => {
+                ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", &error)
+            }
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::MissingKey | AuthError::InvalidKey => {
+                ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", error.to_string())
+                    .with_header(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))
+            }
+            AuthError::MissingScope(scope) => {
+                ApiError::new(StatusCode::FORBIDDEN, "forbidden", error.to_string())
+                    .with_details(json!({ "required_scope": scope }))
+            }
+            AuthError::Forbidden(message) => {
+                ApiError::new(StatusCode::FORBIDDEN, "forbidden", message)
+            }
+            AuthError::UserToken(_) => {
+                ApiError::new(StatusCode::UNAUTHORIZED, "invalid_user_token", error.to_string())
+            }
+            AuthError::Store(e) => e.into(),
+        }
+    }
+}
+
+impl From<RateLimited> for ApiError {
+    fn from(error: RateLimited) -> Self {
+        let secs = error.retry_after_secs();
+        ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limited",
+            error.to_string(),
+        )
+        .with_details(json!({ "limit": error.scope, "retry_after": secs }))
+        .with_header(header::RETRY_AFTER, secs.into())
+    }
+}
+
+impl From<MemoryError> for ApiError {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::NotFound(id) => conversation_not_found(&id),
+            MemoryError::Database(_) => {
+                ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", &error)
+            }
+        }
+    }
+}
+
+/// A conversation that doesn't exist, or belongs to someone else
+pub(super) fn conversation_not_found(id: &str) -> ApiError {
+    ApiError::not_found(
+        "conversation_not_found",
+        format!("Conversation '{}' not found", id),
+    )
+}
+
+impl From<AuditError> for ApiError {
+    fn from(error: AuditError) -> Self {
+        ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "audit_log_error", &error)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_request", rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_request", rejection.body_text())
+    }
+}
+
+/// `Json` extractor whose rejections are [`ApiError`]s
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// `Query` extractor whose rejections are [`ApiError`]s
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiQuery<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// Fallback for unknown routes
+pub async fn not_found() -> ApiError {
+    ApiError::not_found("not_found", "No such endpoint")
+}
+
+/// Middleware assigning each request an ID
+///
+/// A well-formed `X-Request-Id` from the client is kept; otherwise a UUID is
+/// generated. The ID is echoed in the response header and in error bodies.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128 && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ValidationIssue;
+
+    #[test]
+    fn test_status_mapping() {
+        let cases: Vec<(ApiError, StatusCode, &str)> = vec![
+            (
+                ProviderError::UnknownProvider("foo".into()).into(),
+                StatusCode::BAD_REQUEST,
+                "unknown_provider",
+            ),
+            (
+                ProviderError::InvalidResponse("garbage".into()).into(),
+                StatusCode::BAD_GATEWAY,
+                "provider_error",
+            ),
+            (
+                ChatError::MaxIterationsExceeded.into(),
+                StatusCode::BAD_GATEWAY,
+                "max_tool_iterations",
+            ),
+            (
+                ChatError::Memory("disk full".into()).into(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "storage_error",
+            ),
+            (
+                PluginError::ToolNotFound("rm".into()).into(),
+                StatusCode::NOT_FOUND,
+                "tool_not_found",
+            ),
+            (
+                AuthError::MissingKey.into(),
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!((error.status, error.code), (status, code));
+        }
+    }
+
+    #[test]
+    fn test_internal_errors_hide_cause() {
+        let error: ApiError = ChatError::Memory("no such table: messages".into()).into();
+        assert!(!error.message.contains("messages"));
+    }
+
+    #[test]
+    fn test_validation_details() {
+        let error: ApiError =
+            ConfigError::Validation(vec![ValidationIssue::new("llm.provider", "invalid value")])
+                .into();
+
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.details.unwrap()["issues"][0]["path"],
+            json!("llm.provider")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_includes_request_id() {
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                ApiError::bad_request("invalid_request", "nope").into_response()
+            })
+            .await;
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "code": "invalid_request",
+                "message": "nope",
+                "details": null,
+                "request_id": "req-1",
+            })
+        );
+    }
+}
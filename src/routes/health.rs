@@ -0,0 +1,191 @@
+//! Liveness and readiness checks
+//!
+//! `/health` only tells that the process answers. `/health/ready` checks
+//! what replies depend on: the database, the LLM providers and the plugins.
+//! The database and the default provider are critical; if either fails the
+//! response is `503`. Other providers and failed plugins make the status
+//! `degraded`.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::Json, routing::get, Router};
+use futures::future::join_all;
+use serde::Serialize;
+
+use super::ApiError;
+use crate::plugins::PluginState;
+use crate::providers::Provider;
+use crate::AppState;
+
+/// How long a single check may take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+}
+
+/// `GET /health/ready` response
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    /// `ok`, `degraded` or `unavailable`
+    status: &'static str,
+    version: &'static str,
+    database: Check,
+    providers: Vec<ProviderCheck>,
+    plugins: Vec<PluginCheck>,
+}
+
+/// Result of one component check
+#[derive(Debug, Serialize)]
+struct Check {
+    /// `ok` or `error`
+    status: &'static str,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<CheckError>,
+}
+
+#[derive(Debug, Serialize)]
+struct CheckError {
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ProviderCheck {
+    name: String,
+    /// The default provider; without it no request can be answered
+    critical: bool,
+    #[serde(flatten)]
+    check: Check,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginCheck {
+    id: String,
+    state: &'static str,
+}
+
+impl Check {
+    fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Run a check with the timeout, reporting failures as API error codes
+async fn check<E: Into<ApiError>>(future: impl Future<Output = Result<(), E>>) -> Check {
+    let started = Instant::now();
+    let error = match tokio::time::timeout(CHECK_TIMEOUT, future).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            let error: ApiError = e.into();
+            Some(CheckError {
+                code: error.code,
+                message: error.message,
+            })
+        }
+        Err(_) => Some(CheckError {
+            code: "timeout",
+            message: format!("No answer within {} seconds", CHECK_TIMEOUT.as_secs()),
+        }),
+    };
+
+    Check {
+        status: if error.is_none() { "ok" } else { "error" },
+        latency_ms: started.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+/// `GET /health`
+async fn health() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// `GET /health/ready`
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let memory = state.chat_engine.memory().clone();
+    let database = check(async move {
+        memory.ping().await.map_err(|e| {
+            ApiError::internal(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", e)
+        })
+    });
+
+    // The default provider first, then the others that are set up
+    let mut names = vec![state.llm.provider.clone()];
+    for name in Provider::configured(&state.config) {
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name.to_string());
+        }
+    }
+    let providers = join_all(names.into_iter().enumerate().map(|(i, name)| {
+        let config = state.config.clone();
+        async move {
+            let check = check(async {
+                let provider = Provider::from_name(&name, &config)?;
+                provider.ping().await
+            })
+            .await;
+            ProviderCheck {
+                name,
+                critical: i == 0,
+                check,
+            }
+        }
+    }));
+
+    let (database, providers) = futures::join!(database, providers);
+
+    let plugins: Vec<PluginCheck> = state
+        .chat_engine
+        .plugins()
+        .read()
+        .await
+        .states()
+        .into_iter()
+        .map(|(id, state)| PluginCheck {
+            id,
+            state: state.as_str(),
+        })
+        .collect();
+
+    let critical_ok = database.is_ok()
+        && providers
+            .iter()
+            .filter(|p| p.critical)
+            .all(|p| p.check.is_ok());
+    let all_ok = providers.iter().all(|p| p.check.is_ok())
+        && plugins
+            .iter()
+            .all(|p| p.state != PluginState::Error.as_str());
+
+    let (status, code) = match (critical_ok, all_ok) {
+        (false, _) => ("unavailable", StatusCode::SERVICE_UNAVAILABLE),
+        (true, false) => ("degraded", StatusCode::OK),
+        (true, true) => ("ok", StatusCode::OK),
+    };
+
+    (
+        code,
+        Json(ReadinessResponse {
+            status,
+            version: env!("CARGO_PKG_VERSION"),
+            database,
+            providers,
+            plugins,
+        }),
+    )
+}
+
+/// Public health routes
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/health/ready", get(ready))
+}
@@ -0,0 +1,752 @@
+//! OpenAI-compatible chat completions
+//!
+//! `POST /v1/chat/completions` and `GET /v1/models` accept and return the
+//! OpenAI Chat Completions shapes, so existing SDKs and tools can talk to
+//! Moxie by changing their base URL. Requests run through the chat engine:
+//!
+//! - `model` picks the persona or provider: `moxie` (configured defaults),
+//!   `moxie/<persona>`, `<provider>:<model>`, or a bare model name for the
+//!   default provider.
+//! - `X-Moxie-Persona`, `X-Moxie-Provider` and `X-Moxie-Plugins` override
+//!   the persona, provider and plugin set; plugins are limited to those the
+//!   API key allows.
+//! - Requests are stateless by default: the client sends the whole history.
+//!   With `X-Moxie-Conversation`, only the last user message is used and the
+//!   conversation is kept in Moxie's memory instead. Keys that can't set
+//!   their own system prompt can't send assistant messages or tool results
+//!   either, and tool results are passed on as user content.
+//! - `tools` are client tools: Moxie's plugin tools run server-side, calls to
+//!   client tools are returned as `tool_calls` for the client to execute.
+//!
+//! Errors use Moxie's JSON error format ([`ApiError`]).
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post},
+    Extension, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use super::{in_current_request, ApiError, ApiJson};
+use crate::auth::{AuthError, Principal};
+use crate::config::client::LlmConfig;
+use crate::config::prompts_builtin;
+use crate::conversation::{Message, Role};
+use crate::core::{ChatEvent, ChatRequest, ChatResponse, ToolCall};
+use crate::plugins::ToolDefinition;
+use crate::providers::{ChatOptions, ResponseFormat, ToolDef};
+use crate::rate_limit;
+use crate::AppState;
+
+/// Persona to use, overriding the model name
+pub const PERSONA_HEADER: &str = "x-moxie-persona";
+
+/// Provider to use, overriding the model name
+pub const PROVIDER_HEADER: &str = "x-moxie-provider";
+
+/// Comma-separated plugins whose tools the model may use
+pub const PLUGINS_HEADER: &str = "x-moxie-plugins";
+
+/// Conversation kept in Moxie's memory (returned on every response)
+pub const CONVERSATION_HEADER: &str = "x-moxie-conversation";
+
+/// Model name for the configured defaults
+const MOXIE_MODEL: &str = "moxie";
+
+/// Chat completion request
+#[derive(Debug, Deserialize)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub messages: Vec<CompletionMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub tools: Vec<ToolDef>,
+    #[serde(default)]
+    pub response_format: Option<ResponseFormatParam>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Newer name for `max_tokens`
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+}
+
+/// A message in a completion request
+#[derive(Debug, Deserialize)]
+pub struct CompletionMessage {
+    /// "system", "developer", "user", "assistant" or "tool"
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<MessageContent>,
+    #[serde(default)]
+    pub tool_calls: Vec<CompletionToolCall>,
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+}
+
+/// Message content: a string or a list of parts
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentPart {
+    #[serde(rename = "type")]
+    pub part_type: String,
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+impl MessageContent {
+    /// The text of the message; non-text parts are ignored
+    fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter(|p| p.part_type == "text")
+                .filter_map(|p| p.text.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// A tool call made by the assistant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletionToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments
+    pub arguments: String,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+impl From<ToolCall> for CompletionToolCall {
+    fn from(call: ToolCall) -> Self {
+        Self {
+            id: format!("call_{}", call.id.replace('-', "")),
+            call_type: function_type(),
+            function: FunctionCall {
+                name: call.name,
+                arguments: call.arguments.to_string(),
+            },
+        }
+    }
+}
+
+/// Requested output format
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormatParam {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: JsonSchemaParam },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JsonSchemaParam {
+    pub name: String,
+    #[serde(default)]
+    pub schema: Value,
+}
+
+impl From<ResponseFormatParam> for Option<ResponseFormat> {
+    fn from(format: ResponseFormatParam) -> Self {
+        match format {
+            ResponseFormatParam::Text => None,
+            ResponseFormatParam::JsonObject => Some(ResponseFormat::Json),
+            ResponseFormatParam::JsonSchema { json_schema } => Some(ResponseFormat::JsonSchema {
+                name: json_schema.name,
+                schema: json_schema.schema,
+            }),
+        }
+    }
+}
+
+/// Non-streaming response
+#[derive(Debug, Serialize)]
+pub struct Completion {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<CompletionChoice>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompletionChoice {
+    pub index: u32,
+    pub message: AssistantMessage,
+    pub finish_reason: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssistantMessage {
+    pub role: &'static str,
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<CompletionToolCall>,
+}
+
+/// Model list entry
+#[derive(Debug, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    pub object: &'static str,
+    pub created: u64,
+    pub owned_by: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+    pub object: &'static str,
+    pub data: Vec<ModelInfo>,
+}
+
+/// Provider, model and persona selected by a model name
+#[derive(Debug, PartialEq)]
+struct Target {
+    provider: String,
+    model: String,
+    persona: Option<String>,
+}
+
+fn resolve_model(name: &str, llm: &LlmConfig) -> Target {
+    let defaults = |persona: Option<&str>| Target {
+        provider: llm.provider.clone(),
+        model: llm.model.clone(),
+        persona: persona.map(str::to_string),
+    };
+
+    if name == MOXIE_MODEL {
+        return defaults(None);
+    }
+    if let Some(persona) = name.strip_prefix("moxie/") {
+        return defaults(Some(persona));
+    }
+
+    match name.split_once(':') {
+        // "ollama:llama3.2:8b" - model names may contain colons themselves
+        Some((provider, model)) if is_provider(provider) => Target {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            persona: None,
+        },
+        _ => Target {
+            provider: llm.provider.clone(),
+            model: name.to_string(),
+            persona: None,
+        },
+    }
+}
+
+fn is_provider(name: &str) -> bool {
+    matches!(
+        name.to_lowercase().as_str(),
+        "ollama" | "openai" | "gpt" | "gpt4" | "groq" | "local" | "vllm" | "lmstudio" | "localai"
+    )
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// Requested plugins, limited to those the key allows
+fn plugins_for(principal: &Principal, requested: Option<String>) -> Option<Vec<String>> {
+    let requested = requested.map(|list| {
+        list.split(',')
+            .map(|p| p.trim().to_string())
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>()
+    });
+
+    match (principal.allowed_plugins(), requested) {
+        (Some(allowed), Some(requested)) => Some(
+            requested
+                .into_iter()
+                .filter(|p| allowed.contains(p))
+                .collect(),
+        ),
+        (allowed, None) => allowed,
+        (None, requested) => requested,
+    }
+}
+
+/// The conversation in the engine's message format
+struct Conversation {
+    /// Client system messages, joined
+    system_prompt: Option<String>,
+    history: Vec<Message>,
+    /// The trailing user message, if the conversation ends with one
+    message: String,
+    /// Whether the history includes assistant messages or tool results
+    has_replies: bool,
+}
+
+fn convert_messages(messages: Vec<CompletionMessage>) -> Result<Conversation, ApiError> {
+    let mut system = Vec::new();
+    let mut history = Vec::new();
+    let mut tool_names: HashMap<String, String> = HashMap::new();
+    let mut has_replies = false;
+    let mut ends_with_user = false;
+
+    for message in messages {
+        let content = message
+            .content
+            .as_ref()
+            .map(MessageContent::text)
+            .unwrap_or_default();
+
+        if message.role != "system" && message.role != "developer" {
+            ends_with_user = message.role == "user";
+        }
+        match message.role.as_str() {
+            "system" | "developer" => system.push(content),
+            "user" => history.push(Message {
+                role: Role::User,
+                content,
+            }),
+            "assistant" => {
+                has_replies = true;
+                // Tool calls go back into the text format the engine parses
+                let mut parts: Vec<String> = Vec::new();
+                if !content.is_empty() {
+                    parts.push(content);
+                }
+                for call in message.tool_calls {
+                    let arguments = serde_json::from_str(&call.function.arguments)
+                        .unwrap_or(Value::String(call.function.arguments));
+                    let block = json!({"name": call.function.name, "arguments": arguments});
+                    parts.push(format!("```tool_call\n{}\n```", block));
+                    tool_names.insert(call.id, call.function.name);
+                }
+                history.push(Message {
+                    role: Role::Assistant,
+                    content: parts.join("\n\n"),
+                });
+            }
+            "tool" => {
+                has_replies = true;
+                let id = message.tool_call_id.unwrap_or_default();
+                let name = tool_names.get(&id).cloned().unwrap_or(id);
+                history.push(Message {
+                    role: Role::User,
+                    content: format!("Tool result for {}: {}", name, content),
+                });
+            }
+            other => {
+                return Err(ApiError::bad_request(
+                    "invalid_request",
+                    format!("Unsupported message role '{}'", other),
+                ))
+            }
+        }
+    }
+
+    if history.is_empty() {
+        return Err(ApiError::bad_request(
+            "invalid_request",
+            "messages must include at least one user message",
+        ));
+    }
+
+    // Tool results are user messages too, but not the one to reply to
+    let message = if ends_with_user {
+        history.pop().map(|m| m.content).unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    Ok(Conversation {
+        system_prompt: (!system.is_empty()).then(|| system.join("\n\n")),
+        history,
+        message,
+        has_replies,
+    })
+}
+
+fn client_tool(tool: ToolDef) -> ToolDefinition {
+    ToolDefinition {
+        name: tool.function.name,
+        description: tool.function.description,
+        parameters: tool
+            .function
+            .parameters
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+        requires_confirmation: false,
+        plugin_id: None,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Create a chat completion
+async fn chat_completions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    ApiJson(request): ApiJson<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    let target = resolve_model(&request.model, &state.llm);
+    let conversation_id = header(&headers, CONVERSATION_HEADER);
+    let conversation = convert_messages(request.messages)?;
+    if conversation.has_replies && !principal.allows_custom_prompts() {
+        return Err(AuthError::Forbidden(
+            "This API key cannot send assistant messages or tool results".to_string(),
+        )
+        .into());
+    }
+
+    let persona = principal.persona_for(
+        header(&headers, PERSONA_HEADER).or(target.persona),
+        conversation.system_prompt.is_some(),
+    )?;
+
+    // Stored conversations come from memory; only the new message is used
+    let history = match &conversation_id {
+        Some(id) => {
+            if conversation.message.is_empty() {
+                return Err(ApiError::bad_request(
+                    "invalid_request",
+                    "With X-Moxie-Conversation the last message must be a user message; \
+                     send the full history without it to return tool results",
+                ));
+            }
+            super::check_conversation(&state, &principal, id).await?;
+            None
+        }
+        None => Some(conversation.history),
+    };
+
+    let ip = state
+        .limits
+        .client_ip(&headers, peer.map(|ConnectInfo(addr)| addr));
+
+    let engine_request = ChatRequest {
+        message: conversation.message,
+        conversation_id: conversation_id.clone(),
+        system_prompt: conversation.system_prompt,
+        persona,
+        provider: header(&headers, PROVIDER_HEADER).unwrap_or(target.provider),
+        model: target.model,
+        allowed_plugins: plugins_for(&principal, header(&headers, PLUGINS_HEADER)),
+        caller: rate_limit::caller_id(Some(&principal), ip),
+        user_id: principal.user_id.clone(),
+        key_id: principal.key_id().map(str::to_string),
+        history,
+        client_tools: request.tools.into_iter().map(client_tool).collect(),
+        options: ChatOptions {
+            temperature: request.temperature,
+            max_tokens: request.max_completion_tokens.or(request.max_tokens),
+            response_format: request.response_format.and_then(Into::into),
+        },
+        ..ChatRequest::default()
+    };
+
+    let mut response = if request.stream {
+        stream_completion(state, engine_request, request.model).into_response()
+    } else {
+        let response = super::run_chat(&state, engine_request).await?;
+        Json(completion(response, request.model)).into_response()
+    };
+
+    // Stored conversations echo their ID (streams only know it up front)
+    if let Some(value) = conversation_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(CONVERSATION_HEADER), value);
+    }
+
+    Ok(response)
+}
+
+fn completion(response: ChatResponse, model: String) -> Completion {
+    let tool_calls: Vec<CompletionToolCall> = response
+        .pending_tool_calls
+        .into_iter()
+        .map(CompletionToolCall::from)
+        .collect();
+
+    Completion {
+        id: completion_id(),
+        object: "chat.completion",
+        created: now(),
+        model,
+        choices: vec![CompletionChoice {
+            index: 0,
+            finish_reason: if tool_calls.is_empty() {
+                "stop"
+            } else {
+                "tool_calls"
+            },
+            message: AssistantMessage {
+                role: "assistant",
+                content: Some(response.message).filter(|m| !m.is_empty()),
+                tool_calls,
+            },
+        }],
+    }
+}
+
+/// Stream a completion as `chat.completion.chunk` server-sent events
+///
+/// Errors after the stream has started are sent as an `{"error": ...}` event
+/// before `[DONE]`.
+fn stream_completion(
+    state: AppState,
+    request: ChatRequest,
+    model: String,
+) -> Sse<impl futures::Stream<Item = Result<Event, Infallible>>> {
+    let (events, mut received) = mpsc::channel(64);
+    let cancel = request.cancel.clone();
+    let engine = state.chat_engine.clone();
+    let run = tokio::spawn(in_current_request(async move {
+        engine
+            .chat_stream(request, events)
+            .await
+            .map_err(|e| ApiError::from(e).to_json())
+    }));
+
+    let id = completion_id();
+    let created = now();
+    let chunk = move |delta: Value, finish_reason: Option<&str>| {
+        let chunk = json!({
+            "id": id,
+            "object": "chat.completion.chunk",
+            "created": created,
+            "model": model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        });
+        Ok(Event::default().data(chunk.to_string()))
+    };
+
+    let stream = async_stream::stream! {
+        // A dropped stream means the client went away
+        let _cancel_on_drop = cancel.drop_guard();
+
+        yield chunk(json!({"role": "assistant", "content": ""}), None);
+
+        // The channel closes when the engine is done
+        while let Some(event) = received.recv().await {
+            if let ChatEvent::Delta { content } = event {
+                yield chunk(json!({"content": content}), None);
+            }
+        }
+
+        match run.await {
+            Ok(Ok(response)) if !response.pending_tool_calls.is_empty() => {
+                let tool_calls: Vec<Value> = response
+                    .pending_tool_calls
+                    .into_iter()
+                    .map(CompletionToolCall::from)
+                    .enumerate()
+                    .map(|(index, call)| {
+                        let mut call = json!(call);
+                        call["index"] = json!(index);
+                        call
+                    })
+                    .collect();
+                yield chunk(json!({"tool_calls": tool_calls}), None);
+                yield chunk(json!({}), Some("tool_calls"));
+            }
+            Ok(Ok(_)) => yield chunk(json!({}), Some("stop")),
+            Ok(Err(error)) => {
+                yield Ok(Event::default().data(json!({"error": error}).to_string()));
+            }
+            Err(e) => {
+                tracing::error!("Chat stream task failed: {}", e);
+                let error = ApiError::new(
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal_error",
+                    "Internal server error",
+                );
+                yield Ok(Event::default().data(json!({"error": error.to_json()}).to_string()));
+            }
+        }
+
+        yield Ok(Event::default().data("[DONE]"));
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+/// List the model names this deployment accepts
+async fn list_models(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Json<ModelList> {
+    let model = |id: String| ModelInfo {
+        id,
+        object: "model",
+        created: 0,
+        owned_by: "moxie",
+    };
+
+    let mut data = Vec::new();
+    if principal.persona_for(None, false).ok() == Some(None) {
+        data.push(model(MOXIE_MODEL.to_string()));
+    }
+    for (persona, _) in prompts_builtin::PERSONAS {
+        if principal
+            .persona_for(Some(persona.to_string()), false)
+            .is_ok()
+        {
+            data.push(model(format!("moxie/{}", persona)));
+        }
+    }
+    data.push(model(format!("{}:{}", state.llm.provider, state.llm.model)));
+
+    Json(ModelList {
+        object: "list",
+        data,
+    })
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/models", get(list_models))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(value: Value) -> Vec<CompletionMessage> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_model() {
+        let llm = LlmConfig::default();
+
+        assert_eq!(
+            resolve_model("moxie", &llm),
+            Target {
+                provider: "ollama".into(),
+                model: "llama3.2".into(),
+                persona: None
+            }
+        );
+        assert_eq!(
+            resolve_model("moxie/tech_support", &llm).persona.as_deref(),
+            Some("tech_support")
+        );
+
+        let target = resolve_model("groq:llama-3.3-70b-versatile", &llm);
+        assert_eq!(target.provider, "groq");
+        assert_eq!(target.model, "llama-3.3-70b-versatile");
+
+        // Ollama tags contain colons
+        let target = resolve_model("llama3.2:8b", &llm);
+        assert_eq!(target.provider, "ollama");
+        assert_eq!(target.model, "llama3.2:8b");
+    }
+
+    #[test]
+    fn test_convert_messages() {
+        let conversation = convert_messages(messages(json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": [{"type": "text", "text": "Weather in Oslo?"}]},
+            {"role": "assistant", "content": null, "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": {"name": "get_weather", "arguments": "{\"city\":\"Oslo\"}"}
+            }]},
+            {"role": "tool", "tool_call_id": "call_1", "content": "12C, rain"},
+        ])))
+        .unwrap();
+
+        assert_eq!(conversation.system_prompt.as_deref(), Some("Be brief."));
+        assert_eq!(conversation.message, "", "history ends with a tool result");
+        assert!(conversation.has_replies);
+        assert_eq!(conversation.history.len(), 3);
+        assert!(matches!(conversation.history[2].role, Role::User));
+        assert_eq!(
+            conversation.history[1].content,
+            "```tool_call\n{\"arguments\":{\"city\":\"Oslo\"},\"name\":\"get_weather\"}\n```"
+        );
+        assert_eq!(
+            conversation.history[2].content,
+            "Tool result for get_weather: 12C, rain"
+        );
+
+        let conversation = convert_messages(messages(json!([
+            {"role": "user", "content": "Hi"},
+        ])))
+        .unwrap();
+        assert_eq!(conversation.message, "Hi");
+        assert!(conversation.history.is_empty());
+        assert!(!conversation.has_replies);
+
+        assert!(convert_messages(messages(json!([{"role": "system", "content": "x"}]))).is_err());
+    }
+
+    #[test]
+    fn test_completion_shape() {
+        let response = ChatResponse {
+            message: String::new(),
+            conversation_id: "c1".into(),
+            tool_calls: Vec::new(),
+            pending_tool_calls: vec![ToolCall {
+                id: "1234-5678".into(),
+                name: "get_weather".into(),
+                arguments: json!({"city": "Oslo"}),
+            }],
+            artifacts: Vec::new(),
+        };
+
+        let body = json!(completion(response, "moxie".into()));
+        assert_eq!(body["object"], "chat.completion");
+        assert_eq!(body["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(body["choices"][0]["message"]["content"], Value::Null);
+
+        let call = &body["choices"][0]["message"]["tool_calls"][0];
+        assert_eq!(call["id"], "call_12345678");
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(call["function"]["arguments"], "{\"city\":\"Oslo\"}");
+    }
+}
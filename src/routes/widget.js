@@ -0,0 +1,279 @@
+/*
+ * Moxie chat widget
+ *
+ * <script src="https://moxie.example.com/widget.js" data-key="moxie_..." async></script>
+ *
+ * Optional attributes: data-server (defaults to the script's origin),
+ * data-persona (overrides the configured persona) and data-user-token (a
+ * signed user token from the site's backend, giving the signed-in user
+ * their own conversations).
+ */
+(function () {
+  "use strict";
+
+  var script = document.currentScript;
+  if (!script || window.__moxieWidget) return;
+  window.__moxieWidget = true;
+
+  var key = script.getAttribute("data-key");
+  var userToken = script.getAttribute("data-user-token");
+  var server = (script.getAttribute("data-server") || new URL(script.src).origin).replace(/\/$/, "");
+  var storageKey = "moxie-widget:" + (key || "anonymous").slice(0, 12);
+  if (userToken) storageKey += ":" + tokenUser(userToken);
+  var MAX_STORED_MESSAGES = 50;
+
+  function headers() {
+    var h = { "Content-Type": "application/json" };
+    if (key) h["X-API-Key"] = key;
+    if (userToken) h["X-Moxie-User-Token"] = userToken;
+    return h;
+  }
+
+  /* The user a token was issued for, so users of one browser don't share
+     stored conversations (the server checks the signature) */
+  function tokenUser(token) {
+    try {
+      var payload = token.split(".")[0].replace(/-/g, "+").replace(/_/g, "/");
+      return JSON.parse(atob(payload)).sub || "";
+    } catch (e) {
+      return "";
+    }
+  }
+
+  function load() {
+    try {
+      return JSON.parse(localStorage.getItem(storageKey)) || {};
+    } catch (e) {
+      return {};
+    }
+  }
+
+  function save(state) {
+    try {
+      state.messages = state.messages.slice(-MAX_STORED_MESSAGES);
+      localStorage.setItem(storageKey, JSON.stringify(state));
+    } catch (e) {
+      /* storage full or disabled: the conversation just won't persist */
+    }
+  }
+
+  function el(tag, className, text) {
+    var node = document.createElement(tag);
+    if (className) node.className = className;
+    if (text) node.textContent = text;
+    return node;
+  }
+
+  function styles(config) {
+    var side = config.position === "left" ? "left" : "right";
+    return (
+      ":host{all:initial}" +
+      "*{box-sizing:border-box;font-family:system-ui,-apple-system,Segoe UI,Roboto,sans-serif}" +
+      ".launcher{position:fixed;bottom:20px;" + side + ":20px;width:56px;height:56px;border-radius:50%;" +
+      "border:none;cursor:pointer;background:" + config.primary_color + ";color:#fff;font-size:26px;" +
+      "box-shadow:0 4px 14px rgba(0,0,0,.25);z-index:2147483646}" +
+      ".panel{position:fixed;bottom:88px;" + side + ":20px;width:360px;max-width:calc(100vw - 40px);" +
+      "height:520px;max-height:calc(100vh - 120px);display:none;flex-direction:column;background:#fff;" +
+      "border-radius:12px;box-shadow:0 8px 30px rgba(0,0,0,.25);overflow:hidden;z-index:2147483647}" +
+      ".panel.open{display:flex}" +
+      ".header{display:flex;align-items:center;justify-content:space-between;padding:12px 16px;" +
+      "background:" + config.primary_color + ";color:#fff;font-weight:600;font-size:15px}" +
+      ".header button{background:none;border:none;color:#fff;cursor:pointer;font-size:13px;opacity:.85}" +
+      ".messages{flex:1;overflow-y:auto;padding:12px;display:flex;flex-direction:column;gap:8px;background:#f7f7f8}" +
+      ".msg{max-width:85%;padding:8px 12px;border-radius:12px;font-size:14px;line-height:1.4;" +
+      "white-space:pre-wrap;word-wrap:break-word;color:#111}" +
+      ".msg.user{align-self:flex-end;background:" + config.primary_color + ";color:#fff}" +
+      ".msg.assistant{align-self:flex-start;background:#fff;border:1px solid #e5e5e5}" +
+      ".msg.error{align-self:center;background:#fdecea;color:#8a1c14;font-size:13px}" +
+      ".status{font-size:12px;color:#666;padding:0 12px 6px;min-height:18px;background:#f7f7f8}" +
+      "form{display:flex;border-top:1px solid #e5e5e5}" +
+      "input{flex:1;border:none;padding:12px;font-size:14px;outline:none}" +
+      "form button{border:none;background:none;color:" + config.primary_color + ";font-weight:600;" +
+      "padding:0 16px;cursor:pointer}" +
+      "form button:disabled{opacity:.4;cursor:default}"
+    );
+  }
+
+  /* Read a server-sent event stream, calling onEvent(name, data) per event */
+  function readEvents(response, onEvent) {
+    var reader = response.body.getReader();
+    var decoder = new TextDecoder();
+    var buffer = "";
+
+    function dispatch(frame) {
+      var name = "message";
+      var data = [];
+      frame.split("\n").forEach(function (line) {
+        if (line.indexOf("event:") === 0) name = line.slice(6).trim();
+        else if (line.indexOf("data:") === 0) data.push(line.slice(5).replace(/^ /, ""));
+      });
+      if (data.length) onEvent(name, JSON.parse(data.join("\n")));
+    }
+
+    function pump() {
+      return reader.read().then(function (result) {
+        if (result.done) {
+          if (buffer.trim()) dispatch(buffer);
+          return;
+        }
+        buffer += decoder.decode(result.value, { stream: true }).replace(/\r\n/g, "\n");
+        var end;
+        while ((end = buffer.indexOf("\n\n")) !== -1) {
+          dispatch(buffer.slice(0, end));
+          buffer = buffer.slice(end + 2);
+        }
+        return pump();
+      });
+    }
+
+    return pump();
+  }
+
+  function mount(config) {
+    var state = load();
+    state.messages = state.messages || [];
+
+    var host = el("div");
+    host.id = "moxie-widget";
+    var root = host.attachShadow({ mode: "open" });
+    var style = el("style");
+    style.textContent = styles(config);
+
+    var launcher = el("button", "launcher", "\u{1F4AC}");
+    launcher.setAttribute("aria-label", "Open chat");
+
+    var panel = el("div", "panel");
+    var header = el("div", "header");
+    var title = el("span", null, config.title || "Chat");
+    var reset = el("button", null, "New chat");
+    header.appendChild(title);
+    header.appendChild(reset);
+
+    var list = el("div", "messages");
+    var status = el("div", "status");
+    var form = el("form");
+    var input = el("input");
+    input.placeholder = config.placeholder || "";
+    input.setAttribute("aria-label", "Message");
+    var send = el("button", null, "Send");
+    send.type = "submit";
+    form.appendChild(input);
+    form.appendChild(send);
+
+    panel.appendChild(header);
+    panel.appendChild(list);
+    panel.appendChild(status);
+    panel.appendChild(form);
+    root.appendChild(style);
+    root.appendChild(panel);
+    root.appendChild(launcher);
+    document.body.appendChild(host);
+
+    function bubble(role, text) {
+      var node = el("div", "msg " + role, text);
+      list.appendChild(node);
+      list.scrollTop = list.scrollHeight;
+      return node;
+    }
+
+    function render() {
+      list.textContent = "";
+      if (config.greeting) bubble("assistant", config.greeting);
+      state.messages.forEach(function (m) {
+        bubble(m.role, m.content);
+      });
+    }
+
+    launcher.addEventListener("click", function () {
+      panel.classList.toggle("open");
+      if (panel.classList.contains("open")) input.focus();
+    });
+
+    reset.addEventListener("click", function () {
+      state = { messages: [] };
+      save(state);
+      render();
+    });
+
+    form.addEventListener("submit", function (event) {
+      event.preventDefault();
+      var text = input.value.trim();
+      if (!text || send.disabled) return;
+
+      input.value = "";
+      send.disabled = true;
+      bubble("user", text);
+      state.messages.push({ role: "user", content: text });
+      save(state);
+
+      var reply = bubble("assistant", "");
+      var content = "";
+
+      fetch(server + "/v2/chat/stream", {
+        method: "POST",
+        headers: headers(),
+        body: JSON.stringify({
+          message: text,
+          conversation_id: state.conversation_id || null,
+          persona: script.getAttribute("data-persona") || config.persona || null
+        })
+      })
+        .then(function (response) {
+          if (!response.ok) {
+            return response.json().then(function (error) {
+              // Started by another user (or deleted): start over
+              if (error.code === "conversation_not_found") {
+                state.conversation_id = null;
+                save(state);
+              }
+              throw new Error(error.message || "Request failed");
+            });
+          }
+          return readEvents(response, function (name, data) {
+            if (name === "delta") {
+              content += data.content;
+              reply.textContent = content;
+              list.scrollTop = list.scrollHeight;
+            } else if (name === "tool_call") {
+              status.textContent = "Using " + data.name + "…";
+            } else if (name === "tool_result") {
+              status.textContent = "";
+            } else if (name === "done") {
+              state.conversation_id = data.conversation_id;
+              reply.textContent = content = data.message;
+            } else if (name === "error") {
+              throw new Error(data.message || "Something went wrong");
+            }
+          });
+        })
+        .then(function () {
+          state.messages.push({ role: "assistant", content: content });
+          save(state);
+        })
+        .catch(function (error) {
+          if (!content) list.removeChild(reply);
+          bubble("error", error.message);
+        })
+        .then(function () {
+          status.textContent = "";
+          send.disabled = false;
+          input.focus();
+        });
+    });
+
+    render();
+  }
+
+  fetch(server + "/v2/widget/config", { headers: headers() })
+    .then(function (response) {
+      if (!response.ok) throw new Error("Moxie widget config: HTTP " + response.status);
+      return response.json();
+    })
+    .then(function (config) {
+      if (document.body) mount(config);
+      else document.addEventListener("DOMContentLoaded", function () { mount(config); });
+    })
+    .catch(function (error) {
+      console.error(error);
+    });
+})();
@@ -0,0 +1,55 @@
+//! Embeddable website chat widget
+//!
+//! Sites add one script tag with a publishable key:
+//!
+//! ```html
+//! <script src="https://moxie.example.com/widget.js" data-key="moxie_..." async></script>
+//! ```
+//!
+//! The script loads its theme from `/v2/widget/config`, streams replies from
+//! `/v2/chat/stream` and keeps the conversation ID in local storage. Both
+//! endpoints are called cross-origin, so the site's origin must also be in
+//! `security.cors_allowed_origins`.
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Json},
+    routing::get,
+    Router,
+};
+
+use crate::config::client::WidgetConfig;
+use crate::AppState;
+
+/// The widget script, self-contained and dependency-free
+const WIDGET_JS: &str = include_str!("widget.js");
+
+/// `GET /widget.js`
+async fn script() -> impl IntoResponse {
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/javascript; charset=utf-8",
+            ),
+            (header::CACHE_CONTROL, "public, max-age=300"),
+        ],
+        WIDGET_JS,
+    )
+}
+
+/// `GET /v2/widget/config` - theme and texts for the widget
+async fn config(State(state): State<AppState>) -> Json<WidgetConfig> {
+    Json(state.widget.as_ref().clone())
+}
+
+/// The public script route
+pub fn script_router() -> Router<AppState> {
+    Router::new().route("/widget.js", get(script))
+}
+
+/// Authenticated widget routes
+pub fn router() -> Router<AppState> {
+    Router::new().route("/v2/widget/config", get(config))
+}
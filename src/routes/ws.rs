@@ -0,0 +1,569 @@
+//! WebSocket chat transport (`/v2/ws`)
+//!
+//! Clients send JSON messages tagged by `type`:
+//!
+//! - `chat`: a `/v2/chat` request body
+//! - `confirm`: `{"id": ..., "approved": true}` answering a confirmation prompt
+//! - `cancel`: stop the running reply
+//! - `resume`: `{"conversation_id": ..., "after": 12}` re-attach to a session
+//! - `ping`: answered with `pong`
+//!
+//! The server answers with `started`, then `delta`, `tool_call`,
+//! `tool_result` and `confirmation_required` events, and finally `done`,
+//! `error` or `cancelled`. Every event of a session carries a `seq` number.
+//!
+//! A reply keeps running when the socket drops. Its events are buffered for a
+//! while, so a client can reconnect and `resume` with the last `seq` it saw.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
+    },
+    http::HeaderMap,
+    response::Response,
+    routing::get,
+    Extension, Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::error::conversation_not_found;
+use super::{engine_request, in_current_request, ApiError, ChatRequest, ChatResponse};
+use crate::auth::Principal;
+use crate::core::{ChatError, ToolCall, ToolConfirmer};
+use crate::AppState;
+
+/// How often the server pings an idle socket
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A socket that sent nothing (not even a pong) for this long is closed
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How long a confirmation prompt waits before the tool is declined
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long a finished session can still be resumed
+const SESSION_TTL: Duration = Duration::from_secs(300);
+
+/// Events kept per session for resuming
+const MAX_BUFFERED_EVENTS: usize = 1000;
+
+/// A message from the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Chat(ChatRequest),
+    Confirm {
+        id: String,
+        approved: bool,
+    },
+    Cancel,
+    Resume {
+        conversation_id: String,
+        #[serde(default)]
+        after: u64,
+    },
+    Ping,
+}
+
+/// Sequence numbers and buffered events of a session
+#[derive(Default)]
+struct Events {
+    next_seq: u64,
+    buffer: VecDeque<(u64, Value)>,
+}
+
+/// One chat reply, running independently of any socket
+struct Session {
+    conversation_id: String,
+    owner: Option<String>,
+    events: Mutex<Events>,
+    live: broadcast::Sender<(u64, Value)>,
+    confirmations: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+    cancel: CancellationToken,
+    finished_at: Mutex<Option<Instant>>,
+}
+
+impl Session {
+    fn new(conversation_id: String, owner: Option<String>) -> Self {
+        let (live, _) = broadcast::channel(256);
+        Self {
+            conversation_id,
+            owner,
+            events: Mutex::new(Events::default()),
+            live,
+            confirmations: Mutex::new(HashMap::new()),
+            cancel: CancellationToken::new(),
+            finished_at: Mutex::new(None),
+        }
+    }
+
+    /// Number an event, buffer it and send it to attached sockets
+    fn publish(&self, mut event: Value) {
+        let mut events = self.events.lock().unwrap();
+        events.next_seq += 1;
+        let seq = events.next_seq;
+        event["seq"] = json!(seq);
+
+        if events.buffer.len() == MAX_BUFFERED_EVENTS {
+            events.buffer.pop_front();
+        }
+        events.buffer.push_back((seq, event.clone()));
+        // Sent under the lock, so subscribers see no gaps or duplicates
+        let _ = self.live.send((seq, event));
+    }
+
+    /// Buffered events after `after`, and a receiver for the ones to come
+    fn subscribe(&self, after: u64) -> (Vec<Value>, broadcast::Receiver<(u64, Value)>) {
+        let events = self.events.lock().unwrap();
+        let replay = events
+            .buffer
+            .iter()
+            .filter(|(seq, _)| *seq > after)
+            .map(|(_, event)| event.clone())
+            .collect();
+        (replay, self.live.subscribe())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished_at.lock().unwrap().is_some()
+    }
+
+    /// Publish the final event, unless the session already ended
+    fn finish(&self, event: Value) {
+        {
+            let mut finished_at = self.finished_at.lock().unwrap();
+            if finished_at.is_some() {
+                return;
+            }
+            *finished_at = Some(Instant::now());
+        }
+        self.confirmations.lock().unwrap().clear();
+        self.publish(event);
+    }
+
+    /// Stop the running reply; it ends with a `cancelled` event
+    fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Answer a confirmation prompt; false if there is no such prompt
+    fn answer(&self, id: &str, approved: bool) -> bool {
+        match self.confirmations.lock().unwrap().remove(id) {
+            Some(sender) => sender.send(approved).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Asks the user through the session's sockets
+struct SessionConfirmer(Arc<Session>);
+
+#[async_trait::async_trait]
+impl ToolConfirmer for SessionConfirmer {
+    async fn confirm(&self, call: &ToolCall) -> bool {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        self.0
+            .confirmations
+            .lock()
+            .unwrap()
+            .insert(id.clone(), sender);
+
+        self.0.publish(json!({
+            "type": "confirmation_required",
+            "id": id,
+            "name": call.name,
+            "arguments": call.arguments,
+        }));
+
+        match tokio::time::timeout(CONFIRMATION_TIMEOUT, receiver).await {
+            Ok(Ok(approved)) => approved,
+            _ => {
+                self.0.confirmations.lock().unwrap().remove(&id);
+                false
+            }
+        }
+    }
+}
+
+/// Chat sessions by conversation ID
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a new session; fails if one is still running, or if the
+    /// last one was started by someone else
+    fn start(
+        &self,
+        conversation_id: &str,
+        owner: Option<String>,
+    ) -> Result<Arc<Session>, ApiError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| {
+            session
+                .finished_at
+                .lock()
+                .unwrap()
+                .is_none_or(|at| at.elapsed() < SESSION_TTL)
+        });
+
+        match sessions.get(conversation_id) {
+            Some(session) if session.owner != owner => {
+                return Err(conversation_not_found(conversation_id))
+            }
+            Some(session) if !session.is_finished() => return Err(busy()),
+            _ => {}
+        }
+
+        let session = Arc::new(Session::new(conversation_id.to_string(), owner));
+        sessions.insert(conversation_id.to_string(), session.clone());
+        Ok(session)
+    }
+
+    /// The session of a conversation, if `owner` started it
+    fn get(&self, conversation_id: &str, owner: Option<&str>) -> Option<Arc<Session>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(conversation_id)
+            .filter(|session| session.owner.as_deref() == owner)
+            .cloned()
+    }
+}
+
+/// Who may resume a session: the user, else the key that started it
+fn session_owner(principal: &Principal) -> Option<String> {
+    match (&principal.user_id, principal.key_id()) {
+        (Some(user_id), _) => Some(format!("user:{}", user_id)),
+        (None, Some(key_id)) => Some(format!("key:{}", key_id)),
+        (None, None) => None,
+    }
+}
+
+fn busy() -> ApiError {
+    ApiError::new(
+        axum::http::StatusCode::CONFLICT,
+        "conversation_busy",
+        "A reply is already running in this conversation",
+    )
+}
+
+fn error_event(error: ApiError) -> Value {
+    let mut event = error.to_json();
+    event["type"] = json!("error");
+    event
+}
+
+/// `GET /v2/ws`
+async fn upgrade(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let ip = state
+        .limits
+        .client_ip(&headers, peer.map(|ConnectInfo(addr)| addr));
+    let connection = Connection {
+        state,
+        principal,
+        ip,
+        attached: None,
+    };
+    ws.on_upgrade(move |socket| in_current_request(connection.run(socket)))
+}
+
+/// One WebSocket connection
+struct Connection {
+    state: AppState,
+    principal: Principal,
+    ip: Option<std::net::IpAddr>,
+
+    /// The session this socket follows, and the task forwarding its events
+    attached: Option<(Arc<Session>, JoinHandle<()>)>,
+}
+
+impl Connection {
+    async fn run(mut self, socket: WebSocket) {
+        let (mut sink, mut stream) = socket.split();
+        let (outgoing, mut to_send) = mpsc::channel::<Message>(256);
+        let writer = tokio::spawn(async move {
+            while let Some(message) = to_send.recv().await {
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+
+        loop {
+            tokio::select! {
+                message = stream.next() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => {
+                            last_seen = Instant::now();
+                            continue;
+                        }
+                    };
+                    last_seen = Instant::now();
+
+                    if let Some(reply) = self.handle(&text, &outgoing).await {
+                        let _ = outgoing.send(Message::Text(reply.to_string())).await;
+                    }
+                }
+                _ = heartbeat.tick() => {
+                    if last_seen.elapsed() > CLIENT_TIMEOUT {
+                        break;
+                    }
+                    let _ = outgoing.send(Message::Ping(Vec::new())).await;
+                }
+            }
+        }
+
+        // The session keeps running for a later resume
+        self.detach();
+        drop(outgoing);
+        let _ = writer.await;
+    }
+
+    /// Handle a client message, returning a direct reply if there is one
+    async fn handle(&mut self, text: &str, outgoing: &mpsc::Sender<Message>) -> Option<Value> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(error_event(ApiError::bad_request(
+                    "invalid_message",
+                    format!("Invalid message: {}", e),
+                )))
+            }
+        };
+
+        match message {
+            ClientMessage::Chat(request) => match self.start(request).await {
+                Ok(session) => {
+                    self.attach(session, 0, outgoing);
+                    None
+                }
+                Err(error) => Some(error_event(error)),
+            },
+            ClientMessage::Confirm { id, approved } => {
+                let answered = self
+                    .attached
+                    .as_ref()
+                    .is_some_and(|(session, _)| session.answer(&id, approved));
+                (!answered).then(|| {
+                    error_event(ApiError::not_found(
+                        "confirmation_not_found",
+                        format!("No pending confirmation '{}'", id),
+                    ))
+                })
+            }
+            ClientMessage::Cancel => {
+                match &self.attached {
+                    Some((session, _)) if !session.is_finished() => session.cancel(),
+                    _ => {
+                        return Some(error_event(ApiError::bad_request(
+                            "nothing_to_cancel",
+                            "No reply is running",
+                        )))
+                    }
+                }
+                None
+            }
+            ClientMessage::Resume {
+                conversation_id,
+                after,
+            } => {
+                let owner = session_owner(&self.principal);
+                match self.state.sessions.get(&conversation_id, owner.as_deref()) {
+                    Some(session) => {
+                        self.attach(session, after, outgoing);
+                        None
+                    }
+                    None => Some(error_event(ApiError::not_found(
+                        "session_not_found",
+                        format!(
+                            "No resumable session for conversation '{}'",
+                            conversation_id
+                        ),
+                    ))),
+                }
+            }
+            ClientMessage::Ping => Some(json!({"type": "pong"})),
+        }
+    }
+
+    /// Check limits and ownership, then start a reply in its own task
+    async fn start(&mut self, mut request: ChatRequest) -> Result<Arc<Session>, ApiError> {
+        if self
+            .attached
+            .as_ref()
+            .is_some_and(|(session, _)| !session.is_finished())
+        {
+            return Err(busy());
+        }
+
+        self.state
+            .limits
+            .check_request(Some(&self.principal), self.ip)?;
+        let conversation_id = request
+            .conversation_id
+            .get_or_insert_with(|| uuid::Uuid::new_v4().to_string())
+            .clone();
+        // Checks ownership before the session is registered, so another
+        // owner's conversation looks like it doesn't exist and its session
+        // stays theirs
+        let mut engine_request =
+            engine_request(&self.state, &self.principal, self.ip, request).await?;
+
+        let owner = session_owner(&self.principal);
+        let session = self.state.sessions.start(&conversation_id, owner)?;
+        session.publish(json!({"type": "started", "conversation_id": conversation_id}));
+        engine_request.cancel = session.cancel.clone();
+
+        let engine = self.state.chat_engine.clone();
+        let running = session.clone();
+        tokio::spawn(in_current_request(async move {
+            let (events, mut received) = mpsc::channel(64);
+            let confirmer = SessionConfirmer(running.clone());
+            let forward = async {
+                while let Some(event) = received.recv().await {
+                    running.publish(json!(event));
+                }
+            };
+
+            let (result, ()) = tokio::join!(
+                engine.chat_interactive(engine_request, events, &confirmer),
+                forward
+            );
+
+            running.finish(match result {
+                Ok(response) => {
+                    let mut done = json!(ChatResponse::from(response));
+                    done["type"] = json!("done");
+                    done
+                }
+                Err(ChatError::Cancelled) => {
+                    json!({"type": "cancelled", "conversation_id": running.conversation_id})
+                }
+                Err(e) => error_event(ApiError::from(e)),
+            });
+        }));
+
+        Ok(session)
+    }
+
+    /// Follow `session`, replaying its events after `after`
+    fn attach(&mut self, session: Arc<Session>, after: u64, outgoing: &mpsc::Sender<Message>) {
+        self.detach();
+
+        let (replay, mut live) = session.subscribe(after);
+        let outgoing = outgoing.clone();
+        let forwarder = tokio::spawn(async move {
+            let mut last = after;
+            for event in replay {
+                last = event["seq"].as_u64().unwrap_or(last);
+                if outgoing
+                    .send(Message::Text(event.to_string()))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            loop {
+                match live.recv().await {
+                    Ok((seq, _)) if seq <= last => continue,
+                    Ok((seq, event)) => {
+                        last = seq;
+                        if outgoing
+                            .send(Message::Text(event.to_string()))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!("WebSocket client skipped {} chat events", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+
+        self.attached = Some((session, forwarder));
+    }
+
+    fn detach(&mut self) {
+        if let Some((_, forwarder)) = self.attached.take() {
+            forwarder.abort();
+        }
+    }
+}
+
+/// WebSocket routes
+pub fn router() -> Router<AppState> {
+    Router::new().route("/v2/ws", get(upgrade))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_replays_after_seq() {
+        let session = Session::new("c1".to_string(), None);
+        session.publish(json!({"type": "started"}));
+        session.publish(json!({"type": "delta", "content": "Hi"}));
+
+        let (replay, mut live) = session.subscribe(1);
+        assert_eq!(
+            replay,
+            vec![json!({"type": "delta", "content": "Hi", "seq": 2})]
+        );
+
+        session.finish(json!({"type": "done"}));
+        session.finish(json!({"type": "error"}));
+        assert_eq!(live.try_recv().unwrap().1["type"], "done");
+        assert!(live.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_registry_rejects_concurrent_runs() {
+        let registry = SessionRegistry::new();
+        let key1 = || Some("key1".to_string());
+        let session = registry.start("c1", key1()).unwrap();
+        assert!(matches!(registry.start("c1", key1()), Err(e) if e.code == "conversation_busy"));
+        assert!(matches!(registry.start("c1", None), Err(e) if e.code == "conversation_not_found"));
+
+        assert!(registry.get("c1", Some("key1")).is_some());
+        assert!(registry.get("c1", Some("key2")).is_none());
+
+        session.finish(json!({"type": "done"}));
+        assert!(matches!(registry.start("c1", None), Err(e) if e.code == "conversation_not_found"));
+        assert!(registry.start("c1", key1()).is_ok());
+    }
+}
@@ -0,0 +1,601 @@
+//! Telemetry agent for the RMM dashboard
+//!
+//! When `[telemetry]` is enabled, the agent posts a [`Report`] to
+//! `{dashboard_url}/api/v1/telemetry` every `interval_secs`, with the key
+//! from the `api_key_env` variable as a bearer token. Each section is only
+//! collected when its `send_*` flag is set:
+//!
+//! - `system`: CPU, memory and uptime
+//! - `usage`: conversation, message, LLM, token and tool counts
+//! - `errors`: server-side API errors, by error code only
+//! - `conversations`: messages stored since the last report
+//!
+//! Message content is only ever read for `conversations`, and only when
+//! `send_conversations` is true; the other sections are counts.
+//!
+//! The LLM, token, tool and error counts are process-wide, so a server
+//! hosting several clients leaves them out (see
+//! [`TelemetryAgent::with_process_totals`]); each client's report then only
+//! holds its own stored counts.
+//!
+//! Reports are first written to `telemetry/outbox.jsonl` in the data
+//! directory and removed once the dashboard accepts them, so reports made
+//! while it is unreachable are sent later, oldest first.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sysinfo::System;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::client::TelemetryConfig;
+use crate::core::{MemoryStore, StoredMessage};
+use crate::metrics;
+
+/// Path of the report endpoint, below `dashboard_url`
+pub const REPORT_PATH: &str = "/api/v1/telemetry";
+
+/// Variable holding the dashboard key when `api_key_env` is not set
+pub const DEFAULT_API_KEY_ENV: &str = "MOXIE_DASHBOARD_KEY";
+
+/// Reports kept while the dashboard is unreachable; older ones are dropped
+const MAX_BUFFERED: usize = 1000;
+
+/// Messages sent per report when `send_conversations` is on
+const MAX_MESSAGES: usize = 500;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors from the telemetry agent
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("Telemetry buffer error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not reach the dashboard: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("The dashboard answered {0}")]
+    Rejected(StatusCode),
+
+    #[error("Could not read stored messages: {0}")]
+    Memory(#[from] sqlx::Error),
+}
+
+/// One report sent to the dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    /// Stable ID of this Moxie installation
+    pub instance_id: String,
+    pub client: String,
+    pub version: String,
+    pub timestamp: DateTime<Utc>,
+    /// Seconds since the server started
+    pub uptime_secs: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageReport>,
+    /// Server-side errors since start, by error code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<BTreeMap<String, u64>>,
+    /// Only present when `send_conversations` is true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversations: Option<Vec<StoredMessage>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemReport {
+    pub cpu_percent: f32,
+    pub memory_used_bytes: u64,
+    pub memory_total_bytes: u64,
+    /// Resident memory of the Moxie process
+    pub process_memory_bytes: u64,
+    /// Seconds since the host booted
+    pub host_uptime_secs: u64,
+}
+
+/// Counts since the server started, plus stored totals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub conversations: u64,
+    pub messages: u64,
+    /// Left out when the process hosts several clients
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub totals: Option<metrics::Totals>,
+}
+
+/// Background agent posting reports to the dashboard
+pub struct TelemetryAgent {
+    config: TelemetryConfig,
+    client: String,
+    instance_id: String,
+    endpoint: String,
+    api_key: Option<String>,
+    memory: Arc<MemoryStore>,
+    outbox: PathBuf,
+    http: reqwest::Client,
+    system: System,
+    started: Instant,
+    /// Messages up to this ID were already reported
+    last_message_id: i64,
+    /// Whether to report the process-wide counters
+    process_totals: bool,
+}
+
+impl TelemetryAgent {
+    /// Create the agent, or `None` when telemetry is disabled
+    ///
+    /// `data_dir` holds the outbox and the instance ID.
+    pub async fn new(
+        config: &TelemetryConfig,
+        client: &str,
+        data_dir: &Path,
+        memory: Arc<MemoryStore>,
+    ) -> Result<Option<Self>, TelemetryError> {
+        let Some(dashboard_url) = config.dashboard_url.as_deref().filter(|_| config.enabled) else {
+            return Ok(None);
+        };
+
+        let dir = data_dir.join("telemetry");
+        std::fs::create_dir_all(&dir)?;
+        let api_key_env = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+        let last_message_id = memory.last_message_id().await?;
+
+        Ok(Some(Self {
+            config: config.clone(),
+            client: client.to_string(),
+            instance_id: instance_id(&dir.join("instance_id"))?,
+            endpoint: format!("{}{}", dashboard_url.trim_end_matches('/'), REPORT_PATH),
+            api_key: std::env::var(api_key_env).ok(),
+            memory,
+            outbox: dir.join("outbox.jsonl"),
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            system: System::new(),
+            started: Instant::now(),
+            last_message_id,
+            process_totals: true,
+        }))
+    }
+
+    /// Whether reports include the process-wide LLM, token, tool and error
+    /// counts (default true); turn off when the process hosts other clients,
+    /// whose usage they would include
+    pub fn with_process_totals(mut self, enabled: bool) -> Self {
+        self.process_totals = enabled;
+        self
+    }
+
+    /// Report every `interval_secs` until `stop` is cancelled, then once more
+    pub async fn run(mut self, stop: CancellationToken) {
+        tracing::info!("📡 Sending telemetry to {}", self.endpoint);
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = stop.cancelled() => break,
+            }
+            self.tick().await;
+        }
+        self.tick().await;
+    }
+
+    /// Queue a report and send everything queued
+    pub async fn tick(&mut self) {
+        match self.report().await {
+            Ok(report) => {
+                if let Err(e) = self.enqueue(&report) {
+                    tracing::warn!("Failed to buffer telemetry report: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to collect telemetry: {}", e),
+        }
+
+        match self.flush().await {
+            Ok(0) => {}
+            Ok(sent) => tracing::debug!("Sent {} telemetry report(s)", sent),
+            Err(e) => tracing::debug!("Telemetry kept for later: {}", e),
+        }
+    }
+
+    /// Collect a report with the sections enabled in the config
+    pub async fn report(&mut self) -> Result<Report, TelemetryError> {
+        let system = self.config.send_metrics.then(|| self.system_report());
+
+        let usage = if self.config.send_usage {
+            let (conversations, messages) = self.memory.counts().await?;
+            Some(UsageReport {
+                conversations,
+                messages,
+                totals: self.process_totals.then(|| metrics::global().totals()),
+            })
+        } else {
+            None
+        };
+
+        let errors = (self.config.send_errors && self.process_totals)
+            .then(|| metrics::global().totals().server_errors);
+
+        // The only place message content is read
+        let conversations = if self.config.send_conversations {
+            let messages = self
+                .memory
+                .get_messages_after(self.last_message_id, MAX_MESSAGES)
+                .await?;
+            if let Some(last) = messages.last() {
+                self.last_message_id = last.id;
+            }
+            Some(messages)
+        } else {
+            None
+        };
+
+        Ok(Report {
+            instance_id: self.instance_id.clone(),
+            client: self.client.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: Utc::now(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            system,
+            usage,
+            errors,
+            conversations,
+        })
+    }
+
+    fn system_report(&mut self) -> SystemReport {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        let process_memory = sysinfo::get_current_pid()
+            .ok()
+            .filter(|pid| self.system.refresh_process(*pid))
+            .and_then(|pid| self.system.process(pid))
+            .map(|process| process.memory())
+            .unwrap_or(0);
+
+        SystemReport {
+            cpu_percent: self.system.global_cpu_info().cpu_usage(),
+            memory_used_bytes: self.system.used_memory(),
+            memory_total_bytes: self.system.total_memory(),
+            process_memory_bytes: process_memory,
+            host_uptime_secs: System::uptime(),
+        }
+    }
+
+    /// Append a report to the outbox, dropping the oldest beyond the limit
+    fn enqueue(&self, report: &Report) -> Result<(), TelemetryError> {
+        let mut lines = read_outbox(&self.outbox)?;
+        lines.push(serde_json::to_string(report).expect("reports serialize"));
+        if lines.len() > MAX_BUFFERED {
+            let dropped = lines.len() - MAX_BUFFERED;
+            tracing::warn!("Telemetry buffer full; dropping {} old report(s)", dropped);
+            lines.drain(..dropped);
+        }
+        write_outbox(&self.outbox, &lines)
+    }
+
+    /// Send queued reports in order until one fails
+    ///
+    /// Returns the number sent. Reports the dashboard rejects as invalid
+    /// (4xx other than 401, 408 and 429) are dropped rather than retried forever.
+    pub async fn flush(&self) -> Result<usize, TelemetryError> {
+        let lines = read_outbox(&self.outbox)?;
+        let mut done = 0;
+        let mut result = Ok(());
+
+        for line in &lines {
+            match self.send(line).await {
+                Ok(()) => done += 1,
+                Err(TelemetryError::Rejected(status)) if is_permanent(status) => {
+                    tracing::warn!(
+                        "Dashboard rejected a telemetry report ({}); dropping it",
+                        status
+                    );
+                    done += 1;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        if done > 0 {
+            write_outbox(&self.outbox, &lines[done..])?;
+        }
+        result.map(|()| done)
+    }
+
+    async fn send(&self, body: &str) -> Result<(), TelemetryError> {
+        let mut request = self
+            .http
+            .post(&self.endpoint)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(TelemetryError::Rejected(status)),
+        }
+    }
+}
+
+fn is_permanent(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+        && status != StatusCode::UNAUTHORIZED
+}
+
+/// Read the instance ID, creating it on first use
+fn instance_id(path: &Path) -> Result<String, TelemetryError> {
+    match std::fs::read_to_string(path) {
+        Ok(id) if !id.trim().is_empty() => Ok(id.trim().to_string()),
+        Ok(_) => write_instance_id(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => write_instance_id(path),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_instance_id(path: &Path) -> Result<String, TelemetryError> {
+    let id = uuid::Uuid::new_v4().to_string();
+    std::fs::write(path, &id)?;
+    Ok(id)
+}
+
+fn read_outbox(path: &Path) -> Result<Vec<String>, TelemetryError> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the outbox contents (via a temporary file, so a crash mid-write
+/// doesn't lose the buffer)
+fn write_outbox(path: &Path, lines: &[String]) -> Result<(), TelemetryError> {
+    let tmp = path.with_extension("jsonl.tmp");
+    let mut content = lines.join("\n");
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::{extract::State, http::HeaderMap, routing::post, Router};
+
+    use super::*;
+    use crate::conversation::{Message, Role};
+    use crate::core::Owner;
+
+    const SECRET: &str = "my salary is 123456";
+
+    /// Authorization header and body of each accepted report
+    type Received = Arc<Mutex<Vec<(Option<String>, String)>>>;
+
+    /// Stand-in dashboard recording report bodies; answers `status`
+    struct Dashboard {
+        url: String,
+        received: Received,
+        status: Arc<Mutex<StatusCode>>,
+    }
+
+    async fn dashboard() -> Dashboard {
+        type Shared = (Received, Arc<Mutex<StatusCode>>);
+
+        async fn receive(
+            State((received, status)): State<Shared>,
+            headers: HeaderMap,
+            body: String,
+        ) -> StatusCode {
+            let status = *status.lock().unwrap();
+            if status.is_success() {
+                let auth = headers
+                    .get("authorization")
+                    .map(|v| v.to_str().unwrap().to_string());
+                received.lock().unwrap().push((auth, body));
+            }
+            status
+        }
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let status = Arc::new(Mutex::new(StatusCode::OK));
+        let app = Router::new()
+            .route(REPORT_PATH, post(receive))
+            .with_state((received.clone(), status.clone()));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        Dashboard {
+            url,
+            received,
+            status,
+        }
+    }
+
+    async fn agent(config: TelemetryConfig, memory: Arc<MemoryStore>) -> (TelemetryAgent, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("moxie-telemetry-{}", uuid::Uuid::new_v4()));
+        let agent = TelemetryAgent::new(&config, "ACME", &dir, memory)
+            .await
+            .unwrap()
+            .unwrap();
+        (agent, dir)
+    }
+
+    fn config(url: &str) -> TelemetryConfig {
+        TelemetryConfig {
+            enabled: true,
+            dashboard_url: Some(url.to_string()),
+            api_key_env: Some("MOXIE_TEST_DASHBOARD_KEY".to_string()),
+            ..TelemetryConfig::default()
+        }
+    }
+
+    async fn memory_with_secret() -> Arc<MemoryStore> {
+        let memory = Arc::new(MemoryStore::new_in_memory_async().await.unwrap());
+        let message = Message {
+            role: Role::User,
+            content: SECRET.to_string(),
+        };
+        memory.save_message("private", Owner::Anonymous, &message).await.unwrap();
+        memory
+    }
+
+    #[tokio::test]
+    async fn test_disabled_without_dashboard() {
+        let memory = Arc::new(MemoryStore::new_in_memory_async().await.unwrap());
+        let dir = std::env::temp_dir();
+        let disabled = TelemetryConfig {
+            dashboard_url: Some("https://dashboard.example".to_string()),
+            ..TelemetryConfig::default()
+        };
+        assert!(TelemetryAgent::new(&disabled, "ACME", &dir, memory.clone())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reports_never_contain_messages_by_default() {
+        let dashboard = dashboard().await;
+        let memory = memory_with_secret().await;
+        let (mut agent, dir) = agent(config(&dashboard.url), memory.clone()).await;
+
+        // Written after the agent started, so it would be reported if allowed
+        let message = Message {
+            role: Role::Assistant,
+            content: SECRET.to_string(),
+        };
+        memory.save_message("private", Owner::Anonymous, &message).await.unwrap();
+        agent.tick().await;
+
+        let received = dashboard.received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        let body = &received[0].1;
+        assert!(!body.contains("123456"));
+        assert!(!body.contains("private"));
+
+        let report: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert!(report.get("conversations").is_none());
+        assert_eq!(report["client"], "ACME");
+        assert_eq!(report["usage"]["messages"], 2);
+        assert!(report["system"]["memory_total_bytes"].as_u64().unwrap() > 0);
+        assert!(report["errors"].is_object());
+        assert!(report["usage"]["llm_requests"].is_u64());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_shared_process_totals_are_left_out() {
+        let dashboard = dashboard().await;
+        let memory = memory_with_secret().await;
+        let (agent, dir) = agent(config(&dashboard.url), memory).await;
+        let mut agent = agent.with_process_totals(false);
+        agent.tick().await;
+
+        let received = dashboard.received.lock().unwrap().clone();
+        let report: serde_json::Value = serde_json::from_str(&received[0].1).unwrap();
+        assert_eq!(report["usage"]["messages"], 1);
+        assert!(report["usage"].get("llm_requests").is_none());
+        assert!(report.get("errors").is_none());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_conversations_only_when_enabled() {
+        let dashboard = dashboard().await;
+        let memory = Arc::new(MemoryStore::new_in_memory_async().await.unwrap());
+        let (mut agent, dir) = agent(
+            TelemetryConfig {
+                send_conversations: true,
+                ..config(&dashboard.url)
+            },
+            memory.clone(),
+        )
+        .await;
+
+        let message = Message {
+            role: Role::User,
+            content: SECRET.to_string(),
+        };
+        memory.save_message("shared", Owner::Anonymous, &message).await.unwrap();
+        agent.tick().await;
+        agent.tick().await;
+
+        let received = dashboard.received.lock().unwrap().clone();
+        let reports: Vec<Report> = received
+            .iter()
+            .map(|(_, body)| serde_json::from_str(body).unwrap())
+            .collect();
+        let sent = reports[0].conversations.as_ref().unwrap();
+        assert_eq!(sent[0].content, SECRET);
+        // Each message is only sent once
+        assert!(reports[1].conversations.as_ref().unwrap().is_empty());
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_reports_are_buffered_while_offline() {
+        std::env::set_var("MOXIE_TEST_DASHBOARD_KEY", "dash-key");
+        let dashboard = dashboard().await;
+        let memory = Arc::new(MemoryStore::new_in_memory_async().await.unwrap());
+        let (mut agent, dir) = agent(
+            TelemetryConfig {
+                send_metrics: false,
+                ..config(&dashboard.url)
+            },
+            memory,
+        )
+        .await;
+
+        *dashboard.status.lock().unwrap() = StatusCode::SERVICE_UNAVAILABLE;
+        agent.tick().await;
+        agent.tick().await;
+        assert!(dashboard.received.lock().unwrap().is_empty());
+        assert_eq!(read_outbox(&agent.outbox).unwrap().len(), 2);
+
+        *dashboard.status.lock().unwrap() = StatusCode::OK;
+        agent.tick().await;
+        assert!(read_outbox(&agent.outbox).unwrap().is_empty());
+
+        let received = dashboard.received.lock().unwrap().clone();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].0.as_deref(), Some("Bearer dash-key"));
+        let times: Vec<DateTime<Utc>> = received
+            .iter()
+            .map(|(_, body)| serde_json::from_str::<Report>(body).unwrap().timestamp)
+            .collect();
+        assert!(times.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(!received[0].1.contains("\"system\""));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,352 @@
+//! Hosting several clients in one process
+//!
+//! With `moxie serve --clients-dir DIR`, every `*.toml` in `DIR` is a client
+//! config. Each client (tenant) gets its own [`Bootstrap`] with the data
+//! directory `<data dir>/clients/<id>`, and so its own database, API keys,
+//! plugins, personas, provider settings, rate limits and audit log; the
+//! tenants share nothing but the process and the metrics.
+//!
+//! A request goes to the tenant named in the `X-Moxie-Client` header, else
+//! to the tenant that issued its API key, else to the only tenant allowing
+//! its `Origin` (for CORS preflights), else to the default tenant. Tenant
+//! IDs are the configs' `client.id`, or their file names.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Request, State},
+    http::{header, request::Parts},
+    response::{IntoResponse, Response},
+    Router,
+};
+use tower::ServiceExt;
+
+use crate::auth::{self, AuthError};
+use crate::bootstrap::Bootstrap;
+use crate::routes::{self, ApiError};
+use crate::AppState;
+
+/// Header naming the tenant of a request
+pub const CLIENT_HEADER: &str = "x-moxie-client";
+
+/// Routes that don't depend on the tenant, served by the default (or first)
+/// tenant when nothing identifies one; probes can't name a client, so
+/// `/health/ready` reports on that tenant
+const SHARED_PATHS: &[&str] = &["/health", "/health/ready", "/widget.js"];
+
+/// One hosted client
+pub struct Tenant {
+    pub id: String,
+    pub bootstrap: Bootstrap,
+    pub state: AppState,
+    router: Router,
+}
+
+/// The tenants of a multi-tenant server, by ID
+pub struct TenantRegistry {
+    tenants: BTreeMap<String, Tenant>,
+    default: Option<String>,
+}
+
+impl TenantRegistry {
+    /// Load every `*.toml` client config in `dir`
+    ///
+    /// Fails if a config is invalid, two configs share an ID, or the
+    /// directory holds no config.
+    pub async fn load(dir: &Path, data_dir: &Path) -> anyhow::Result<Self> {
+        let mut tenants = BTreeMap::new();
+        for path in client_configs(dir)? {
+            let bootstrap = tenant_bootstrap(&path, data_dir)?;
+            let id = tenant_id(&bootstrap, &path)?;
+            if tenants.contains_key(&id) {
+                anyhow::bail!("Two client configs use the ID '{}' ({})", id, path.display());
+            }
+
+            let state = bootstrap.app_state().await?;
+            let router = routes::router(state.clone())
+                .layer(routes::cors_layer(&state.security.cors_allowed_origins));
+            tracing::info!("🏢 Hosting client {} from {}", id, path.display());
+
+            tenants.insert(
+                id.clone(),
+                Tenant {
+                    id,
+                    bootstrap,
+                    state,
+                    router,
+                },
+            );
+        }
+
+        if tenants.is_empty() {
+            anyhow::bail!("No client configs (*.toml) in {}", dir.display());
+        }
+
+        Ok(Self {
+            tenants,
+            default: None,
+        })
+    }
+
+    /// Serve requests that don't identify a tenant from this one
+    pub fn with_default(mut self, id: Option<String>) -> anyhow::Result<Self> {
+        if let Some(id) = &id {
+            if !self.tenants.contains_key(id) {
+                anyhow::bail!("Unknown default client '{}'", id);
+            }
+        }
+        self.default = id;
+        Ok(self)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Tenant> {
+        self.tenants.get(id)
+    }
+
+    /// All tenants, by ID
+    pub fn tenants(&self) -> impl Iterator<Item = &Tenant> {
+        self.tenants.values()
+    }
+
+    /// Pick the tenant for a request
+    pub async fn resolve(&self, request: &Parts) -> Result<&Tenant, ApiError> {
+        let headers = &request.headers;
+
+        if let Some(id) = headers.get(CLIENT_HEADER).and_then(|v| v.to_str().ok()) {
+            return self.tenants.get(id.trim()).ok_or_else(|| {
+                ApiError::not_found("unknown_client", format!("Unknown client '{}'", id))
+            });
+        }
+
+        // Each tenant answers from its in-memory key index
+        let key = auth::request_key(headers, &request.uri);
+        if let Some(secret) = &key {
+            for tenant in self.tenants.values() {
+                if tenant.state.keys.issued(secret).await? {
+                    return Ok(tenant);
+                }
+            }
+        }
+
+        if let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) {
+            let mut allowing = self.tenants.values().filter(|tenant| {
+                let origins = &tenant.state.security.cors_allowed_origins;
+                origins.iter().any(|o| o == origin)
+            });
+            if let (Some(tenant), None) = (allowing.next(), allowing.next()) {
+                return Ok(tenant);
+            }
+        }
+
+        if let Some(tenant) = self.default.as_ref().and_then(|id| self.tenants.get(id)) {
+            return Ok(tenant);
+        }
+        if SHARED_PATHS.contains(&request.uri.path()) {
+            if let Some(tenant) = self.tenants.values().next() {
+                return Ok(tenant);
+            }
+        }
+
+        Err(match key {
+            Some(_) => AuthError::InvalidKey.into(),
+            None => ApiError::bad_request(
+                "client_required",
+                "Send an API key or the X-Moxie-Client header to choose the client",
+            ),
+        })
+    }
+
+    /// Router dispatching each request to its tenant's API
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new().fallback(dispatch).with_state(self)
+    }
+}
+
+async fn dispatch(State(registry): State<Arc<TenantRegistry>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    let router = match registry.resolve(&parts).await {
+        Ok(tenant) => tenant.router.clone(),
+        Err(error) => return error.into_response(),
+    };
+
+    match router.oneshot(Request::from_parts(parts, body)).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+/// The `*.toml` files in a clients directory, sorted
+fn client_configs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read clients directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|e| e == "toml"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+/// Load a tenant's config with the data directory `<data dir>/clients/<id>`
+fn tenant_bootstrap(path: &Path, data_dir: &Path) -> anyhow::Result<Bootstrap> {
+    let bootstrap = Bootstrap::load(Some(path), Some(data_dir.to_path_buf()))?;
+    let id = tenant_id(&bootstrap, path)?;
+    Ok(Bootstrap {
+        data_dir: data_dir.join("clients").join(id),
+        ..bootstrap
+    })
+}
+
+/// Load one tenant of a clients directory, for CLI commands (`--client`)
+pub fn load_tenant(dir: &Path, data_dir: &Path, id: &str) -> anyhow::Result<Bootstrap> {
+    for path in client_configs(dir)? {
+        let bootstrap = tenant_bootstrap(&path, data_dir)?;
+        if tenant_id(&bootstrap, &path)? == id {
+            return Ok(bootstrap);
+        }
+    }
+    anyhow::bail!("No client '{}' in {}", id, dir.display())
+}
+
+/// `client.id`, or the config's file name
+///
+/// IDs name directories, so only letters, digits, `-` and `_` are allowed.
+fn tenant_id(bootstrap: &Bootstrap, path: &Path) -> anyhow::Result<String> {
+    let id = bootstrap
+        .client
+        .as_ref()
+        .and_then(|c| c.client.id.clone())
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Client ID '{}' of {} may only contain letters, digits, '-' and '_'",
+            id,
+            path.display()
+        );
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::auth::{NewApiKey, Scope};
+
+    fn write_client(dir: &Path, file: &str, body: &str) {
+        std::fs::write(
+            dir.join(file),
+            format!("[client]\nname = \"{}\"\n{}", file, body),
+        )
+        .unwrap();
+    }
+
+    async fn key(tenant: &Tenant, scope: Scope) -> String {
+        let new_key = NewApiKey {
+            name: "test".to_string(),
+            scopes: vec![scope],
+            allowed_personas: Vec::new(),
+            allowed_plugins: Vec::new(),
+            allowed_origins: Vec::new(),
+        };
+        tenant.state.keys.create(new_key).await.unwrap().1
+    }
+
+    async fn status(app: &Router, request: axum::http::request::Builder) -> StatusCode {
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        let root = std::env::temp_dir().join(format!("moxie-tenants-{}", uuid::Uuid::new_v4()));
+        let clients = root.join("clients.d");
+        std::fs::create_dir_all(&clients).unwrap();
+        write_client(&clients, "acme.toml", "\n[llm]\nmodel = \"acme-model\"\n");
+        write_client(
+            &clients,
+            "globex.toml",
+            "id = \"globex-inc\"\n\n[security]\ncors_allowed_origins = [\"https://globex.example\"]\n",
+        );
+        std::fs::write(clients.join("notes.txt"), "not a config").unwrap();
+
+        let registry = TenantRegistry::load(&clients, &root).await.unwrap();
+        let ids: Vec<&str> = registry.tenants().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["acme", "globex-inc"]);
+
+        let acme = registry.get("acme").unwrap();
+        let globex = registry.get("globex-inc").unwrap();
+        assert_eq!(acme.state.llm.model, "acme-model");
+        assert_eq!(acme.bootstrap.data_dir, root.join("clients/acme"));
+        assert!(root.join("clients/globex-inc/moxie.db").exists());
+
+        let acme_admin = key(acme, Scope::Admin).await;
+        let globex_admin = key(globex, Scope::Admin).await;
+        assert!(!globex.state.keys.issued(&acme_admin).await.unwrap());
+
+        let app = Arc::new(registry).router();
+        let keys = |key: &str| {
+            axum::http::Request::get("/v2/admin/keys")
+                .header("Authorization", format!("Bearer {}", key))
+        };
+
+        // Routed by key
+        assert_eq!(status(&app, keys(&acme_admin)).await, StatusCode::OK);
+        assert_eq!(status(&app, keys(&globex_admin)).await, StatusCode::OK);
+        // A key only works for its own client
+        let crossed = keys(&acme_admin).header(CLIENT_HEADER, "globex-inc");
+        assert_eq!(status(&app, crossed).await, StatusCode::UNAUTHORIZED);
+        let unknown = keys(&acme_admin).header(CLIENT_HEADER, "initech");
+        assert_eq!(status(&app, unknown).await, StatusCode::NOT_FOUND);
+        assert_eq!(status(&app, keys("moxie_nope")).await, StatusCode::UNAUTHORIZED);
+
+        // Without a key: by origin, else a client must be named
+        let preflight = axum::http::Request::options("/v2/chat")
+            .header("Origin", "https://globex.example")
+            .header("Access-Control-Request-Method", "POST");
+        let response = app
+            .clone()
+            .oneshot(preflight.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "https://globex.example"
+        );
+        let anonymous = axum::http::Request::get("/v2/tools");
+        assert_eq!(status(&app, anonymous).await, StatusCode::BAD_REQUEST);
+        let health = axum::http::Request::get("/health");
+        assert_eq!(status(&app, health).await, StatusCode::OK);
+        // Readiness comes from the first client, whether or not its
+        // provider is up here
+        let ready = axum::http::Request::get("/health/ready");
+        let ready = status(&app, ready).await;
+        assert!(
+            [StatusCode::OK, StatusCode::SERVICE_UNAVAILABLE].contains(&ready),
+            "{}",
+            ready
+        );
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_invalid_client_id() {
+        let bootstrap = Bootstrap::load(None, None).unwrap();
+        let error = tenant_id(&bootstrap, Path::new("clients/acme corp.toml")).unwrap_err();
+        assert!(error.to_string().contains("may only contain"));
+    }
+}